@@ -0,0 +1,257 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_client::{types::chat::Chat, Client};
+use grammers_tl_types as tl;
+use indicatif::ProgressBar;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flood::{self, invoke},
+    output::{Destination, Format},
+    ratelimit, shutdown, Username, UsernameType,
+};
+
+/// Most admins listed for a chat, Telegram's limit for one request.
+const LIMIT: i32 = 200;
+
+/// Whether the admins of resolved chats are listed, for `--admins`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// An admin or the creator of a group or channel.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Admin {
+    pub id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// Whether the admin created the chat.
+    #[serde(default)]
+    pub creator: bool,
+}
+
+/// Someone who admins several of the resolved chats.
+#[derive(Debug, Serialize)]
+pub struct SharedAdmin {
+    pub id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    /// The chats the admin runs, by the links they were found by.
+    pub chats: Vec<LinkType>,
+}
+
+/// Lists the admins of a channel or supergroup. Channels only list them to
+/// their own admins, so for most of them the list is empty. Bots are left
+/// out, since the same moderation bots admin thousands of unrelated chats.
+pub async fn fetch(client: &Client, chat: &Chat) -> Vec<Admin> {
+    let Some(channel) = chat.pack().try_to_input_channel() else {
+        return Vec::new();
+    };
+    let request = tl::functions::channels::GetParticipants {
+        channel,
+        filter: tl::types::ChannelParticipantsAdmins {}.into(),
+        offset: 0,
+        limit: LIMIT,
+        hash: 0,
+    };
+    match invoke(client, &request).await {
+        Ok(tl::enums::channels::ChannelParticipants::Participants(list)) => admins(list),
+        Ok(tl::enums::channels::ChannelParticipants::NotModified) => Vec::new(),
+        Err(e) => {
+            tracing::debug!(chat_id = chat.id(), error = %e, "could not list admins");
+            Vec::new()
+        }
+    }
+}
+
+fn admins(list: tl::types::channels::ChannelParticipants) -> Vec<Admin> {
+    let users: HashMap<_, _> = list
+        .users
+        .into_iter()
+        .filter_map(|user| match user {
+            tl::enums::User::User(user) => Some((user.id, user)),
+            tl::enums::User::Empty(_) => None,
+        })
+        .collect();
+
+    list.participants
+        .into_iter()
+        .filter_map(|participant| {
+            let (user_id, creator) = match participant {
+                tl::enums::ChannelParticipant::Creator(creator) => (creator.user_id, true),
+                tl::enums::ChannelParticipant::Admin(admin) => (admin.user_id, false),
+                _ => return None,
+            };
+            let user = users.get(&user_id);
+            if user.is_some_and(|user| user.bot) {
+                return None;
+            }
+            let name = user.map(|user| {
+                [&user.first_name, &user.last_name]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            Some(Admin {
+                id: user_id,
+                username: user.and_then(|user| user.username.clone()),
+                name: name.unwrap_or_default(),
+                creator,
+            })
+        })
+        .collect()
+}
+
+/// Lists the admins of the resolved groups and channels that came from the
+/// cache or an earlier results file without them, looking their usernames
+/// up again.
+pub async fn fill(client: &Client, usernames: &mut [Username]) {
+    let pending: Vec<_> = usernames
+        .iter_mut()
+        .filter(|u| matches!(u.username, LinkType::Username(_) | LinkType::Mention(_)))
+        .filter(|u| {
+            u.metadata.as_ref().is_some_and(|metadata| {
+                metadata.type_ != UsernameType::User && metadata.admins.is_none()
+            })
+        })
+        .collect();
+    if pending.is_empty() {
+        return;
+    }
+
+    let progress_bar = ProgressBar::new(pending.len() as u64);
+    for username in pending {
+        if shutdown::requested() || ratelimit::over_budget() {
+            break;
+        }
+        let (LinkType::Username(ref name) | LinkType::Mention(ref name)) = username.username else {
+            unreachable!("only usernames and mentions are looked up");
+        };
+        match flood::resolve_username(client, name).await {
+            Ok(Some(chat)) => {
+                let admins = fetch(client, &chat).await;
+                if let Some(ref mut metadata) = username.metadata {
+                    metadata.admins = Some(admins);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!(username = name, error = %e, "could not resolve username"),
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish();
+}
+
+/// The admins of more than one of the resolved chats, the ones running the
+/// most chats first.
+pub fn shared<'a>(usernames: impl IntoIterator<Item = &'a Username>) -> Vec<SharedAdmin> {
+    let mut by_admin: BTreeMap<i64, SharedAdmin> = BTreeMap::new();
+    let mut chats = Vec::new();
+    for username in usernames {
+        let Some(ref metadata) = username.metadata else {
+            continue;
+        };
+        // The same chat is found in the results of several crawled chats.
+        if chats.contains(&metadata.id) {
+            continue;
+        }
+        chats.push(metadata.id);
+        for admin in metadata.admins.iter().flatten() {
+            by_admin
+                .entry(admin.id)
+                .or_insert_with(|| SharedAdmin {
+                    id: admin.id,
+                    username: admin.username.clone(),
+                    name: admin.name.clone(),
+                    chats: Vec::new(),
+                })
+                .chats
+                .push(username.username.clone());
+        }
+    }
+
+    let mut shared: Vec<_> = by_admin
+        .into_values()
+        .filter(|admin| admin.chats.len() > 1)
+        .collect();
+    shared.sort_by_key(|admin| std::cmp::Reverse(admin.chats.len()));
+    shared
+}
+
+/// Writes the shared admins next to the results as `admins.json`, or
+/// whatever `--out-name` makes of that name.
+pub fn write(shared: &[SharedAdmin], destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path("admins", Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(shared)?)?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsernameMetadata;
+
+    #[test]
+    fn test_shared() {
+        let admin = |id: i64| Admin {
+            id,
+            username: None,
+            name: format!("Admin {id}"),
+            creator: false,
+        };
+        let chat = |name: &str, id: i64, admins: Vec<Admin>| Username {
+            metadata: Some(UsernameMetadata {
+                id,
+                name: name.to_string(),
+                type_: UsernameType::Channel,
+                about: None,
+                members: None,
+                date: None,
+                restriction: None,
+                language: None,
+                label: None,
+                risk: None,
+                linked_chat: None,
+                admins: Some(admins),
+            }),
+            ..Username::new(LinkType::Username(name.to_string()))
+        };
+        let usernames = [
+            chat("rust", 1, vec![admin(10), admin(11)]),
+            chat("rustlang", 2, vec![admin(10)]),
+            // Found again in the results of another crawled chat.
+            chat("rust", 1, vec![admin(10), admin(11)]),
+            chat("golang", 3, vec![admin(12)]),
+        ];
+
+        let shared = shared(&usernames);
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].id, 10);
+        assert_eq!(
+            shared[0].chats,
+            [
+                LinkType::Username("rust".to_string()),
+                LinkType::Username("rustlang".to_string())
+            ]
+        );
+    }
+}
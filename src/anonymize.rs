@@ -0,0 +1,278 @@
+use hmac::{Hmac, KeyInit, Mac};
+use rampilo::LinkType;
+use sha2::Sha256;
+
+use crate::{
+    admins::SharedAdmin, cooccurrence::Pair, crosspost::Cluster, events::Events, members::Member,
+    senders::Sender, timeseries::Series, topics::Topic, Username,
+};
+
+/// Replaces the names in results with keyed hashes, so reports can be shared
+/// without exposing who is in them. The same key always gives the same
+/// hashes, so reports anonymized with it can still be compared.
+#[derive(Clone)]
+pub struct Anonymizer {
+    key: Vec<u8>,
+}
+
+impl Anonymizer {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    fn digest(&self, value: &str) -> [u8; 32] {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    /// First 64 bits of the keyed hash of `value`, in hex.
+    pub fn hash(&self, value: &str) -> String {
+        self.digest(value)[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn hash_id(&self, id: i64) -> i64 {
+        let digest = self.digest(&id.to_string());
+        let bytes: [u8; 8] = digest[..8].try_into().unwrap();
+        // Positive and below 2^53, so JSON readers keep it exact.
+        (u64::from_be_bytes(bytes) >> 11) as i64
+    }
+
+    /// The link with its value hashed. A username gets the same hash whether
+    /// it was linked or mentioned.
+    pub fn link(&self, link: &LinkType) -> LinkType {
+        let key = link.key();
+        let value = key.split_once(':').map_or(key.as_str(), |(_, value)| value);
+        let hash = self.hash(value);
+        match link {
+            LinkType::Username(_) => LinkType::Username(hash),
+            LinkType::Hash(_) => LinkType::Hash(hash),
+            LinkType::Mention(_) => LinkType::Mention(hash),
+            LinkType::Hashtag(_) => LinkType::Hashtag(hash),
+            LinkType::Url(_) => LinkType::Url(hash),
+            LinkType::Folder(_) => LinkType::Folder(hash),
+            LinkType::Proxy(_) => LinkType::Proxy(hash),
+            LinkType::Invoice(_) => LinkType::Invoice(hash),
+            LinkType::StickerSet(_) => LinkType::StickerSet(hash),
+            LinkType::EmojiSet(_) => LinkType::EmojiSet(hash),
+            LinkType::Theme(_) => LinkType::Theme(hash),
+            LinkType::UserId(id) => LinkType::UserId(self.hash_id(*id)),
+            LinkType::Phone(_) => LinkType::Phone(hash),
+            LinkType::Story(_) => LinkType::Story(hash),
+            LinkType::Boost(_) => LinkType::Boost(hash),
+        }
+    }
+
+    /// Hashes the links, aliases, chat names and ids and the admins of the
+    /// results, and drops the descriptions, message links and snippets, which
+    /// can't be hashed without losing what they are for. Counts, dates and
+    /// types are kept.
+    pub fn apply(&self, usernames: &mut [Username]) {
+        for username in usernames {
+            username.username = self.link(&username.username);
+            username.aliases = username.aliases.iter().map(|a| self.link(a)).collect();
+            username.messages.clear();
+            username.snippets.clear();
+            if let Some(ref mut metadata) = username.metadata {
+                metadata.name = self.hash(&metadata.name);
+                if metadata.id != 0 {
+                    metadata.id = self.hash_id(metadata.id);
+                }
+                metadata.about = None;
+                metadata.linked_chat = metadata.linked_chat.map(|id| self.hash_id(id));
+                for admin in metadata.admins.iter_mut().flatten() {
+                    admin.id = self.hash_id(admin.id);
+                    admin.username = admin
+                        .username
+                        .as_deref()
+                        .map(|u| self.hash(&u.to_lowercase()));
+                    admin.name = self.hash(&admin.name);
+                }
+            }
+        }
+    }
+
+    /// Hashes the shared admins like the admins of the results, and the chats
+    /// they run like the links of the results.
+    pub fn apply_shared_admins(&self, shared: &mut [SharedAdmin]) {
+        for admin in shared {
+            admin.id = self.hash_id(admin.id);
+            admin.username = admin
+                .username
+                .as_deref()
+                .map(|u| self.hash(&u.to_lowercase()));
+            admin.name = self.hash(&admin.name);
+            admin.chats = admin.chats.iter().map(|chat| self.link(chat)).collect();
+        }
+    }
+
+    /// Hashes the ids and usernames of the members and their names, like
+    /// the names of chats. Roles are kept.
+    pub fn apply_members(&self, members: &mut [Member]) {
+        for member in members {
+            member.id = self.hash_id(member.id);
+            member.username = member
+                .username
+                .as_deref()
+                .map(|u| self.hash(&u.to_lowercase()));
+            member.name = self.hash(&member.name);
+            member.rank = None;
+        }
+    }
+
+    /// Hashes the ids, names and usernames of the senders like those of
+    /// members, and the links they posted like the results.
+    pub fn apply_senders(&self, senders: &mut [Sender]) {
+        for sender in senders {
+            sender.id = self.hash_id(sender.id);
+            sender.username = sender
+                .username
+                .as_deref()
+                .map(|u| self.hash(&u.to_lowercase()));
+            sender.name = sender.name.as_deref().map(|name| self.hash(name));
+            for promoted in &mut sender.links {
+                promoted.link = self.link(&promoted.link);
+            }
+        }
+    }
+
+    /// Hashes the links posted together like the results.
+    pub fn apply_cooccurrence(&self, edges: &mut [Pair]) {
+        for pair in edges {
+            pair.a = self.link(&pair.a);
+            pair.b = self.link(&pair.b);
+        }
+    }
+
+    /// Hashes the links counted over time like the results.
+    pub fn apply_timeseries(&self, series: &mut [Series]) {
+        for series in series {
+            series.link = self.link(&series.link);
+        }
+    }
+
+    /// Hashes the links found in the topics like the results and drops the
+    /// links to the topics, which name the chat.
+    pub fn apply_topics(&self, topics: &mut [Topic]) {
+        for topic in topics {
+            topic.link = None;
+            for promoted in &mut topic.top {
+                promoted.link = self.link(&promoted.link);
+            }
+        }
+    }
+
+    /// Hashes the chats the posts were found in, like the crawled chats, and
+    /// drops the links to the posts. The texts are kept.
+    pub fn apply_crossposts(&self, clusters: &mut [Cluster]) {
+        for cluster in clusters {
+            for chat in &mut cluster.chats {
+                *chat = self.hash(&chat.to_lowercase());
+            }
+            for post in &mut cluster.posts {
+                post.chat = self.hash(&post.chat.to_lowercase());
+                post.message = None;
+            }
+        }
+    }
+
+    /// Hashes the ids of the inviters and of the chats upgraded from or to.
+    pub fn apply_events(&self, events: &mut Events) {
+        events.joined_by_link = std::mem::take(&mut events.joined_by_link)
+            .into_iter()
+            .map(|(id, joins)| (self.hash_id(id), joins))
+            .collect();
+        events.migrated_to = events.migrated_to.map(|id| self.hash_id(id));
+        events.migrated_from = events.migrated_from.map(|id| self.hash_id(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{members::Role, UsernameMetadata, UsernameType};
+
+    #[test]
+    fn test_anonymize() {
+        let mut usernames = vec![
+            Username {
+                count: 4,
+                metadata: Some(UsernameMetadata {
+                    id: 1234,
+                    name: "Grammers".to_string(),
+                    type_: UsernameType::Group,
+                    about: Some("Rust MTProto".to_string()),
+                    members: Some(100),
+                    date: None,
+                    restriction: None,
+                    language: None,
+                    label: None,
+                    risk: None,
+                    linked_chat: None,
+                    admins: None,
+                }),
+                messages: vec!["https://t.me/codenight/1".to_string()],
+                ..Username::new(LinkType::Username("Grammers".to_string()))
+            },
+            Username::new(LinkType::Mention("grammers".to_string())),
+        ];
+
+        let anonymizer = Anonymizer::new("secret");
+        anonymizer.apply(&mut usernames);
+
+        let LinkType::Username(ref hash) = usernames[0].username else {
+            panic!("the link type changed");
+        };
+        assert_eq!(hash.len(), 16);
+        assert_eq!(
+            usernames[1].username,
+            LinkType::Mention(hash.clone()),
+            "links and mentions of a username hash the same"
+        );
+        assert_eq!(usernames[0].count, 4);
+        assert!(usernames[0].messages.is_empty());
+
+        let metadata = usernames[0].metadata.as_ref().unwrap();
+        assert_ne!(metadata.name, "Grammers");
+        assert_ne!(metadata.id, 1234);
+        assert_eq!(
+            (metadata.about.as_deref(), metadata.members),
+            (None, Some(100))
+        );
+
+        assert_ne!(Anonymizer::new("other").hash("grammers"), hash.as_str());
+    }
+
+    #[test]
+    fn test_anonymize_members() {
+        let mut members = vec![Member {
+            id: 1234,
+            username: Some("Lonami".to_string()),
+            name: "Lonami Exo".to_string(),
+            bot: false,
+            role: Role::Admin,
+            rank: Some("maintainer".to_string()),
+        }];
+
+        let anonymizer = Anonymizer::new("secret");
+        anonymizer.apply_members(&mut members);
+
+        let LinkType::Username(hash) = anonymizer.link(&LinkType::Username("lonami".to_string()))
+        else {
+            panic!("the link type changed");
+        };
+        assert_eq!(members[0].username.as_deref(), Some(hash.as_str()));
+        assert_ne!(members[0].id, 1234);
+        assert_ne!(members[0].name, "Lonami Exo");
+        assert_eq!(
+            (members[0].role, members[0].rank.as_deref()),
+            (Role::Admin, None)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use color_eyre::eyre::Result;
+use grammers_client::{types::User, Client, Config, SignInError};
+use inquire::{Password, Text};
+
+use crate::{
+    credentials::{self, ApiCredentials},
+    proxy,
+};
+
+/// Connects with the saved session, without signing in.
+pub async fn open_client(bot: bool, encrypt: bool) -> Result<(Client, ApiCredentials)> {
+    let session = credentials::load_session(bot, encrypt)?;
+    let credentials = ApiCredentials::load()?;
+
+    let client = Client::connect(Config {
+        session,
+        api_id: credentials.api_id(),
+        api_hash: credentials.api_hash().to_owned(),
+        params: proxy::params()?,
+    })
+    .await?;
+
+    Ok((client, credentials))
+}
+
+/// Connects with the saved session, signing in first if there is none.
+pub async fn connect(bot_token: Option<&str>, encrypt_session: bool) -> Result<Client> {
+    status!("Connecting to Telegram servers...");
+    let (client, credentials) = open_client(bot_token.is_some(), encrypt_session).await?;
+    status!("Connected!");
+
+    let is_authorized = client.is_authorized().await?;
+
+    if !is_authorized {
+        match bot_token {
+            Some(token) => bot_sign_in(&client, token, &credentials).await?,
+            None => sign_in(&client, credentials.api_id(), credentials.api_hash()).await?,
+        }
+        credentials.save()?;
+    }
+
+    Ok(client)
+}
+
+async fn sign_in(client: &Client, api_id: i32, app_hash: &str) -> Result<()> {
+    status!("Signing in...");
+
+    let phone = Text::new("Enter your phone number: ").prompt()?;
+    let token = client.request_login_code(&phone, api_id, app_hash).await?;
+    let code = Text::new("Enter the code: ").prompt()?;
+    let sign_in = client.sign_in(&token, &code).await;
+
+    match sign_in {
+        Ok(user) => {
+            status!("Signed in as {}!", user.first_name());
+        }
+        Err(SignInError::PasswordRequired(password_token)) => {
+            let password = Password::new("Enter the password: ").prompt()?;
+
+            client
+                .check_password(password_token, password.trim())
+                .await?;
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    credentials::save_session(client.session(), false)?;
+
+    Ok(())
+}
+
+async fn bot_sign_in(client: &Client, token: &str, credentials: &ApiCredentials) -> Result<()> {
+    status!("Signing in as a bot...");
+
+    let user = client
+        .bot_sign_in(token, credentials.api_id(), credentials.api_hash())
+        .await?;
+    status!("Signed in as {}!", user.first_name());
+
+    credentials::save_session(client.session(), true)?;
+
+    Ok(())
+}
+
+/// Signs in if the saved session isn't signed in yet, and tells who it is
+/// signed in as.
+pub async fn login(bot_token: Option<&str>, encrypt_session: bool) -> Result<()> {
+    let client = connect(bot_token, encrypt_session).await?;
+    let me = client.get_me().await?;
+    status!("{}, the session is saved", describe(&me));
+    Ok(())
+}
+
+/// Logs the saved session out on Telegram's side, so a copy of it can't be
+/// used either, and forgets it.
+pub async fn logout(bot: bool, encrypt_session: bool) -> Result<()> {
+    let (client, _) = open_client(bot, encrypt_session).await?;
+    if client.is_authorized().await? {
+        client.sign_out().await?;
+        status!("Logged out, the session was revoked");
+    } else {
+        status!("The session wasn't signed in");
+    }
+    credentials::forget_session(bot)?;
+    Ok(())
+}
+
+/// Tells who the saved session is signed in as, without signing in.
+pub async fn whoami(bot: bool, encrypt_session: bool) -> Result<()> {
+    let (client, _) = open_client(bot, encrypt_session).await?;
+    if !client.is_authorized().await? {
+        status!("Not signed in, run `rampilo login` to sign in");
+        return Ok(());
+    }
+    let me = client.get_me().await?;
+    status!("{}", describe(&me));
+    Ok(())
+}
+
+fn describe(me: &User) -> String {
+    signed_in_as(&me.full_name(), me.username(), me.id(), me.is_bot())
+}
+
+/// `Signed in as <name> (@<username>), id <id>`, mentioning bots.
+fn signed_in_as(name: &str, username: Option<&str>, id: i64, bot: bool) -> String {
+    let mut line = format!("Signed in as {name}");
+    if let Some(username) = username {
+        line.push_str(&format!(" (@{username})"));
+    }
+    line.push_str(&format!(", id {id}"));
+    if bot {
+        line.push_str(", a bot");
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_in_as() {
+        assert_eq!(
+            signed_in_as("Lonami Exo", Some("lonami"), 1234, false),
+            "Signed in as Lonami Exo (@lonami), id 1234"
+        );
+        assert_eq!(
+            signed_in_as("Crawler", None, 5678, true),
+            "Signed in as Crawler, id 5678, a bot"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::IsTerminal;
+
+use color_eyre::eyre::Result;
+use grammers_client::{types::chat::Chat, Client};
+use grammers_tl_types as tl;
+use inquire::Confirm;
+
+use crate::{flood, target::Target};
+
+/// Joining the private chats found during `--depth` crawls.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct Options {
+    /// Join the private chats whose invite links are found with `--depth`,
+    /// crawl them and leave them again. Every chat is confirmed first unless
+    /// it is allowed with `--join-allow`. Joining shows in the chat and is
+    /// refused with `--strict-passive`
+    #[arg(long)]
+    pub auto_join: bool,
+
+    /// Invite link or hash of a chat `--auto-join` may join without asking,
+    /// given once per chat
+    #[arg(long, value_name = "INVITE", value_parser = invite_hash)]
+    pub join_allow: Vec<String>,
+}
+
+impl Options {
+    /// Whether to join the chat behind the invite link with `hash`, asking
+    /// the operator when it isn't allowed up front. Without a terminal to
+    /// ask on, only allowed chats are joined.
+    pub fn confirm(&self, hash: &str, invite: &tl::types::ChatInvite) -> Result<bool> {
+        if invite.request_needed {
+            tracing::info!(
+                hash,
+                title = invite.title,
+                "not joining a chat that needs an admin's approval"
+            );
+            return Ok(false);
+        }
+        if self.join_allow.iter().any(|allowed| allowed == hash) {
+            return Ok(true);
+        }
+        if !std::io::stdin().is_terminal() {
+            tracing::info!(
+                hash,
+                title = invite.title,
+                "not joining a chat that isn't allowed"
+            );
+            return Ok(false);
+        }
+
+        let kind = match (invite.channel, invite.broadcast) {
+            (true, true) => "channel",
+            _ => "group",
+        };
+        let question = format!(
+            "Join the {kind} {:?} ({} members) to crawl it, and leave it afterwards?",
+            invite.title, invite.participants_count
+        );
+        Ok(Confirm::new(&question)
+            .with_default(false)
+            .with_help_message("joining shows in the chat")
+            .prompt_skippable()?
+            .unwrap_or(false))
+    }
+}
+
+/// Leaves a chat joined with `--auto-join`.
+pub async fn leave(client: &Client, chat: &Chat) -> Result<()> {
+    let packed = chat.pack();
+    if let Some(channel) = packed.try_to_input_channel() {
+        flood::invoke(client, &tl::functions::channels::LeaveChannel { channel }).await?;
+    } else if let Some(chat_id) = packed.try_to_chat_id() {
+        let request = tl::functions::messages::DeleteChatUser {
+            chat_id,
+            user_id: tl::enums::InputUser::UserSelf,
+            revoke_history: false,
+        };
+        flood::invoke(client, &request).await?;
+    }
+    Ok(())
+}
+
+/// The hash of an invite link, or the hash itself.
+fn invite_hash(value: &str) -> Result<String, String> {
+    match value.parse::<Target>() {
+        Ok(Target::Invite(hash)) => Ok(hash),
+        _ if value.contains('/') => Err(format!("{value:?} is not an invite link")),
+        _ => Ok(value.trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invite_hash() {
+        assert_eq!(invite_hash("https://t.me/+AbC-12").unwrap(), "AbC-12");
+        assert_eq!(invite_hash("t.me/joinchat/AbC").unwrap(), "AbC");
+        assert_eq!(invite_hash("AbC-12").unwrap(), "AbC-12");
+        assert!(invite_hash("https://t.me/codenight").is_err());
+    }
+}
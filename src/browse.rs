@@ -0,0 +1,230 @@
+use std::{fmt, io::IsTerminal};
+
+use color_eyre::eyre::{self, Result};
+use inquire::{InquireError, Select};
+
+use crate::{
+    anonymize::Anonymizer, output::date, provenance::Provenance, results_name, storage::Discard,
+    write_results, Args, Username,
+};
+
+/// Entries shown per page.
+const PAGE_SIZE: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Mark {
+    #[default]
+    Unmarked,
+    Interesting,
+    Ignored,
+}
+
+/// What can be picked from the list of entries.
+enum Choice<'a> {
+    Entry(usize, &'a Username, Mark),
+    ShowIgnored(usize),
+    HideIgnored,
+    Export(usize),
+    Quit,
+}
+
+impl fmt::Display for Choice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Choice::Entry(_, username, mark) => {
+                let marker = match mark {
+                    Mark::Unmarked => "[ ]",
+                    Mark::Interesting => "[+]",
+                    Mark::Ignored => "[-]",
+                };
+                write!(f, "{marker} {} ×{}", username.username, username.count)?;
+                if let Some(ref metadata) = username.metadata {
+                    write!(f, " {} ({})", metadata.name, metadata.type_.as_str())?;
+                }
+                Ok(())
+            }
+            Choice::ShowIgnored(count) => write!(f, "Show the {count} ignored entries"),
+            Choice::HideIgnored => write!(f, "Hide the ignored entries"),
+            Choice::Export(count) => write!(f, "Export the {count} interesting entries"),
+            Choice::Quit => write!(f, "Quit"),
+        }
+    }
+}
+
+/// Lets the operator page through the results of `chat`, look at the
+/// messages an entry was found in, mark entries as interesting or ignored
+/// and write the interesting ones to their own results file.
+pub fn run(chat: &str, usernames: &[Username], args: &Args) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        eyre::bail!("Browsing the results needs a terminal");
+    }
+
+    let mut marks = vec![Mark::Unmarked; usernames.len()];
+    let mut show_ignored = false;
+    let mut cursor = 0;
+    loop {
+        let choices = choices(usernames, &marks, show_ignored);
+        let message = format!("{chat}: {} entries", usernames.len());
+        let cursor_at = cursor.min(choices.len() - 1);
+        let picked = Select::new(&message, choices)
+            .with_page_size(PAGE_SIZE)
+            .with_starting_cursor(cursor_at)
+            .with_help_message("↑↓ to move, enter to open, type to filter, esc to quit")
+            .raw_prompt();
+        let picked = match picked {
+            Ok(picked) => picked,
+            Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+                return Ok(())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        cursor = picked.index;
+
+        match picked.value {
+            Choice::Entry(i, username, mark) => {
+                if let Some(mark) = open(username, mark)? {
+                    marks[i] = mark;
+                }
+            }
+            Choice::ShowIgnored(_) => show_ignored = true,
+            Choice::HideIgnored => show_ignored = false,
+            Choice::Export(_) => export(chat, usernames, &marks, args)?,
+            Choice::Quit => return Ok(()),
+        }
+    }
+}
+
+fn choices<'a>(usernames: &'a [Username], marks: &[Mark], show_ignored: bool) -> Vec<Choice<'a>> {
+    let interesting = marks.iter().filter(|&&m| m == Mark::Interesting).count();
+    let ignored = marks.iter().filter(|&&m| m == Mark::Ignored).count();
+
+    let mut choices = vec![Choice::Export(interesting), Choice::Quit];
+    match (show_ignored, ignored) {
+        (_, 0) => {}
+        (false, _) => choices.push(Choice::ShowIgnored(ignored)),
+        (true, _) => choices.push(Choice::HideIgnored),
+    }
+    choices.extend(
+        usernames
+            .iter()
+            .zip(marks)
+            .enumerate()
+            .filter(|(_, (_, &mark))| show_ignored || mark != Mark::Ignored)
+            .map(|(i, (username, &mark))| Choice::Entry(i, username, mark)),
+    );
+    choices
+}
+
+/// Shows everything known about the entry and asks how to mark it, `None`
+/// to leave the mark as it is.
+fn open(username: &Username, mark: Mark) -> Result<Option<Mark>> {
+    status!("\n{} ({})", username.username, username.username.kind());
+    if let Some(ref metadata) = username.metadata {
+        status!("  {} ({})", metadata.name, metadata.type_.as_str());
+        if let Some(members) = metadata.members {
+            status!("  {members} members");
+        }
+        if let Some(ref about) = metadata.about {
+            status!("  {}", about.replace('\n', "\n  "));
+        }
+    }
+    status!(
+        "  Found {} times, first on {}, last on {}",
+        username.count,
+        username.first_seen.map_or("?".to_string(), date),
+        username.last_seen.map_or("?".to_string(), date),
+    );
+    for message in &username.messages {
+        status!("  {message}");
+    }
+    if username.messages.is_empty() {
+        status!("  The messages it was found in can't be linked to");
+    }
+
+    let actions = [
+        ("Mark as interesting", Mark::Interesting),
+        ("Ignore", Mark::Ignored),
+        ("Clear the mark", Mark::Unmarked),
+    ];
+    let options: Vec<_> = actions
+        .iter()
+        .filter(|(_, action)| *action != mark)
+        .map(|(label, _)| *label)
+        .chain(["Back"])
+        .collect();
+    let picked = match Select::new("Mark it?", options).prompt_skippable() {
+        Ok(picked) => picked,
+        Err(InquireError::OperationInterrupted) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(picked.and_then(|picked| {
+        actions
+            .iter()
+            .find(|(label, _)| *label == picked)
+            .map(|&(_, mark)| mark)
+    }))
+}
+
+/// Writes the interesting entries next to the results as
+/// `<chat>.marked.<ext>`.
+fn export(chat: &str, usernames: &[Username], marks: &[Mark], args: &Args) -> Result<()> {
+    let mut marked: Vec<_> = usernames
+        .iter()
+        .zip(marks)
+        .filter(|(_, &mark)| mark == Mark::Interesting)
+        .map(|(username, _)| username.clone())
+        .collect();
+    if marked.is_empty() {
+        status!("Mark some entries as interesting first");
+        return Ok(());
+    }
+
+    let anonymizer = args.anonymize.as_deref().map(Anonymizer::new);
+    let name = format!("{}.marked", results_name(chat, anonymizer.as_ref()));
+    let provenance = Provenance::new(chat);
+    let files = write_results(
+        &mut marked,
+        chat,
+        &name,
+        anonymizer.as_ref(),
+        provenance,
+        &mut Discard,
+        args,
+    )?;
+    if files.is_empty() {
+        status!("Nothing was saved, `--summary-only` writes no files");
+    } else {
+        status!("Saved {} entries to {}", marked.len(), files.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rampilo::LinkType;
+
+    use super::*;
+
+    #[test]
+    fn test_choices() {
+        let usernames: Vec<_> = ["a", "b", "c"]
+            .map(|name| Username::new(LinkType::Username(name.to_string())))
+            .into();
+        let marks = [Mark::Interesting, Mark::Ignored, Mark::Unmarked];
+
+        let choices = choices(&usernames, &marks, false);
+        let shown: Vec<_> = choices.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            shown,
+            [
+                "Export the 1 interesting entries",
+                "Quit",
+                "Show the 1 ignored entries",
+                "[+] a ×1",
+                "[ ] c ×1"
+            ]
+        );
+        assert_eq!(super::choices(&usernames, &marks, true).len(), 6);
+    }
+}
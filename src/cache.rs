@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{Resolution, Unresolved, UsernameMetadata};
+
+/// Usernames resolved by earlier runs, including the ones that couldn't be
+/// resolved and why, so crawls of related chats don't look them all up again.
+pub struct ResolveCache {
+    path: PathBuf,
+    ttl: Duration,
+    entries: HashMap<String, Cached>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Cached {
+    /// Unix timestamp of the lookup.
+    resolved_at: u64,
+    /// `None` if the username couldn't be resolved.
+    metadata: Option<UsernameMetadata>,
+    /// Why the username couldn't be resolved, missing from caches written
+    /// before the reasons were kept, when it always meant not found.
+    #[serde(default)]
+    unresolved: Option<Unresolved>,
+}
+
+impl ResolveCache {
+    /// Loads the cache, starting an empty one if the file doesn't exist yet.
+    /// Entries older than `ttl` are looked up again.
+    pub fn load(path: &Path, ttl: Duration) -> Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            ttl,
+            entries,
+        })
+    }
+
+    /// The cached lookup of `username`, if there is one that hasn't expired.
+    pub fn get(&self, username: &str) -> Option<Resolution> {
+        let cached = self.entries.get(&username.to_lowercase())?;
+        if now().saturating_sub(cached.resolved_at) >= self.ttl.as_secs() {
+            return None;
+        }
+        match cached.metadata {
+            Some(ref metadata) => Some(Ok(metadata.clone())),
+            None => Some(Err(cached.unresolved.unwrap_or(Unresolved::NotFound))),
+        }
+    }
+
+    pub fn insert(&mut self, username: &str, resolution: &Resolution) {
+        let cached = Cached {
+            resolved_at: now(),
+            metadata: resolution.as_ref().ok().cloned(),
+            unresolved: resolution.as_ref().err().copied(),
+        };
+        self.entries.insert(username.to_lowercase(), cached);
+    }
+
+    /// Writes the cache back, leaving out the expired entries.
+    pub fn save(&mut self) -> Result<()> {
+        let now = now();
+        let ttl = self.ttl.as_secs();
+        self.entries
+            .retain(|_, cached| now.saturating_sub(cached.resolved_at) < ttl);
+
+        // Written next to the cache and renamed over it, like the seen
+        // database.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&self.entries)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parses `--cache-ttl`, given in hours.
+pub fn parse_ttl(hours: &str) -> Result<Duration, String> {
+    let hours: f64 = hours
+        .parse()
+        .map_err(|_| format!("{hours:?} is not a number of hours"))?;
+    if !hours.is_finite() || hours < 0.0 {
+        return Err("must be zero or more hours".to_string());
+    }
+    Ok(Duration::from_secs_f64(hours * 3600.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UsernameType;
+
+    #[test]
+    fn test_resolve_cache() {
+        let path = std::env::temp_dir().join("rampilo-test-resolve-cache.json");
+        let _ = fs::remove_file(&path);
+
+        let metadata = UsernameMetadata {
+            id: 42,
+            name: "Grammers".to_string(),
+            type_: UsernameType::Group,
+            about: None,
+            members: Some(100),
+            date: None,
+            restriction: None,
+            language: None,
+            label: None,
+            risk: None,
+            linked_chat: None,
+            admins: None,
+        };
+        let mut cache = ResolveCache::load(&path, Duration::from_secs(3600)).unwrap();
+        cache.insert("Grammers", &Ok(metadata));
+        cache.insert("deleted", &Err(Unresolved::Deleted));
+        cache.save().unwrap();
+
+        let cache = ResolveCache::load(&path, Duration::from_secs(3600)).unwrap();
+        assert_eq!(cache.get("grammers").unwrap().unwrap().id, 42);
+        assert_eq!(
+            cache.get("deleted").unwrap().unwrap_err(),
+            Unresolved::Deleted
+        );
+        assert!(cache.get("unknown").is_none());
+
+        let mut expired = ResolveCache::load(&path, Duration::ZERO).unwrap();
+        assert!(expired.get("grammers").is_none());
+        expired.save().unwrap();
+        assert!(expired.entries.is_empty());
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("24").unwrap(), Duration::from_secs(86400));
+        assert_eq!(parse_ttl("0.5").unwrap(), Duration::from_secs(1800));
+        assert!(parse_ttl("-1").is_err());
+        assert!(parse_ttl("week").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+
+/// Compression of the files written, picked with `--compress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The compression a file name's extension stands for.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Adds the extension of the compression to `path`, unless it already
+    /// ends with it.
+    pub fn path(self, path: PathBuf) -> PathBuf {
+        if Self::from_path(&path) == Some(self) {
+            return path;
+        }
+        let mut path = path.into_os_string();
+        path.push(".");
+        path.push(self.extension());
+        path.into()
+    }
+
+    /// Starts a compressed stream written to `out`. Streams written one
+    /// after the other to the same file decompress as a single one.
+    pub fn encoder<W: Write>(self, out: W) -> io::Result<Encoder<W>> {
+        Ok(match self {
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(out, flate2::Compression::default())),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(out, 0)?),
+        })
+    }
+}
+
+pub enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Ends the stream, returning where it was written.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writes `contents` to `path`, compressed if asked to.
+pub fn write(
+    path: &Path,
+    contents: impl AsRef<[u8]>,
+    compression: Option<Compression>,
+) -> io::Result<()> {
+    let Some(compression) = compression else {
+        return fs::write(path, contents);
+    };
+    let mut encoder = compression.encoder(File::create(path)?)?;
+    encoder.write_all(contents.as_ref())?;
+    encoder.finish()?.flush()
+}
+
+/// Reads a file written by [`write`], decompressing it if its extension
+/// says it is compressed.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    match Compression::from_path(path) {
+        None => return fs::read_to_string(path),
+        Some(Compression::Gzip) => {
+            MultiGzDecoder::new(File::open(path)?).read_to_string(&mut text)?
+        }
+        Some(Compression::Zstd) => {
+            zstd::Decoder::new(File::open(path)?)?.read_to_string(&mut text)?
+        }
+    };
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let path = compression.path(std::env::temp_dir().join("rampilo-test-compress.json"));
+            assert_eq!(Compression::from_path(&path), Some(compression));
+            assert_eq!(compression.path(path.clone()), path);
+
+            // Streams appended to each other read back as one.
+            write(&path, "[1, 2]\n", Some(compression)).unwrap();
+            let mut encoder = compression
+                .encoder(fs::OpenOptions::new().append(true).open(&path).unwrap())
+                .unwrap();
+            encoder.write_all(b"[3]\n").unwrap();
+            encoder.finish().unwrap();
+
+            assert_eq!(read_to_string(&path).unwrap(), "[1, 2]\n[3]\n");
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{fs, path::Path};
+
+use color_eyre::eyre::{self, Result};
+use rampilo::LinkType;
+use serde::Deserialize;
+
+/// Settings read from `rampilo.toml`, every one of them optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub resolve: Strategies,
+    pub notify: Notify,
+    /// Proxy Telegram is reached through, overridden by `--proxy`.
+    pub proxy: Option<String>,
+}
+
+/// How the operator of a long running `watch` is alerted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notify {
+    /// Program and arguments to run, the message is appended as the last
+    /// argument, e.g. `["notify-send", "rampilo"]`.
+    pub command: Vec<String>,
+}
+
+/// How the entries of a link type are looked up after the crawl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Resolve the username to its chat.
+    Resolve,
+    /// Check the invite hash without joining the chat.
+    CheckInvite,
+    /// Fetch the page to get its title, needs the `http` feature.
+    Probe,
+    /// Keep the entry as it is.
+    Skip,
+}
+
+/// Strategy of every link type, by default only usernames and mentions,
+/// including mentions by id, are resolved.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strategies {
+    pub username: Strategy,
+    pub mention: Strategy,
+    pub hash: Strategy,
+    pub hashtag: Strategy,
+    pub url: Strategy,
+    pub folder: Strategy,
+    pub proxy: Strategy,
+    pub invoice: Strategy,
+    pub stickers: Strategy,
+    pub emoji: Strategy,
+    pub theme: Strategy,
+    pub user_id: Strategy,
+    pub phone: Strategy,
+    pub story: Strategy,
+    pub boost: Strategy,
+}
+
+impl Default for Strategies {
+    fn default() -> Self {
+        Self {
+            username: Strategy::Resolve,
+            mention: Strategy::Resolve,
+            hash: Strategy::Skip,
+            hashtag: Strategy::Skip,
+            url: Strategy::Skip,
+            folder: Strategy::Skip,
+            proxy: Strategy::Skip,
+            invoice: Strategy::Skip,
+            stickers: Strategy::Skip,
+            emoji: Strategy::Skip,
+            theme: Strategy::Skip,
+            user_id: Strategy::Resolve,
+            phone: Strategy::Skip,
+            story: Strategy::Skip,
+            boost: Strategy::Skip,
+        }
+    }
+}
+
+impl Strategies {
+    pub fn for_link(&self, link: &LinkType) -> Strategy {
+        match link {
+            LinkType::Username(_) => self.username,
+            LinkType::Mention(_) => self.mention,
+            LinkType::Hash(_) => self.hash,
+            LinkType::Hashtag(_) => self.hashtag,
+            LinkType::Url(_) => self.url,
+            LinkType::Folder(_) => self.folder,
+            LinkType::Proxy(_) => self.proxy,
+            LinkType::Invoice(_) => self.invoice,
+            LinkType::StickerSet(_) => self.stickers,
+            LinkType::EmojiSet(_) => self.emoji,
+            LinkType::Theme(_) => self.theme,
+            LinkType::UserId(_) => self.user_id,
+            LinkType::Phone(_) => self.phone,
+            LinkType::Story(_) => self.story,
+            LinkType::Boost(_) => self.boost,
+        }
+    }
+
+    /// Rejects strategies that don't apply to their link type, such as
+    /// resolving a hashtag.
+    fn validate(&self) -> Result<()> {
+        let strategies = [
+            ("username", self.username, Strategy::Resolve),
+            ("mention", self.mention, Strategy::Resolve),
+            ("hash", self.hash, Strategy::CheckInvite),
+            ("hashtag", self.hashtag, Strategy::Skip),
+            ("url", self.url, Strategy::Probe),
+            ("folder", self.folder, Strategy::Skip),
+            ("proxy", self.proxy, Strategy::Skip),
+            ("invoice", self.invoice, Strategy::Skip),
+            ("stickers", self.stickers, Strategy::Skip),
+            ("emoji", self.emoji, Strategy::Skip),
+            ("theme", self.theme, Strategy::Skip),
+            ("user_id", self.user_id, Strategy::Resolve),
+            ("phone", self.phone, Strategy::Skip),
+            ("story", self.story, Strategy::Skip),
+            ("boost", self.boost, Strategy::Skip),
+        ];
+
+        for (kind, strategy, supported) in strategies {
+            if strategy != Strategy::Skip && strategy != supported {
+                eyre::bail!("{kind} links can't use the {strategy:?} strategy");
+            }
+        }
+
+        if cfg!(not(feature = "http")) && self.url == Strategy::Probe {
+            eyre::bail!("Probing urls needs rampilo to be built with the `http` feature");
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads the config file, using the defaults if it doesn't exist.
+pub fn load(path: &Path) -> Result<Config> {
+    let config: Config = match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    config.resolve.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategies() {
+        let config: Config = toml::from_str(
+            "[resolve]\n\
+             mention = \"skip\"\n\
+             hash = \"check-invite\"\n",
+        )
+        .unwrap();
+        config.resolve.validate().unwrap();
+
+        let strategy = |link| config.resolve.for_link(&link);
+        assert_eq!(
+            strategy(LinkType::Username("a".to_string())),
+            Strategy::Resolve
+        );
+        assert_eq!(strategy(LinkType::Mention("a".to_string())), Strategy::Skip);
+        assert_eq!(
+            strategy(LinkType::Hash("a".to_string())),
+            Strategy::CheckInvite
+        );
+
+        let config: Config = toml::from_str("[resolve]\nhashtag = \"resolve\"\n").unwrap();
+        assert!(config.resolve.validate().is_err());
+        assert!(toml::from_str::<Config>("[resolve]\nusernames = \"skip\"\n").is_err());
+    }
+}
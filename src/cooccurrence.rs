@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Destination, Format};
+
+/// Fewest messages two links must be posted together in to be written, so
+/// links that happened to meet once don't drown the bundles.
+const MIN_MESSAGES: usize = 2;
+
+/// Which links are posted in the same messages, for `--cooccurrence`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Cooccurrence {
+    /// Whether the pairs are counted at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    /// By the keys of both links, in order, separated by a newline.
+    pub pairs: HashMap<String, Pair>,
+}
+
+/// Two links and the messages that posted both.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Pair {
+    pub a: LinkType,
+    pub b: LinkType,
+    pub messages: usize,
+}
+
+impl Cooccurrence {
+    /// Counts every pair of distinct links of a message once.
+    pub fn record(&mut self, links: &[LinkType]) {
+        if !self.enabled || links.len() < 2 {
+            return;
+        }
+        let mut links: Vec<_> = links.iter().map(|link| (link.key(), link)).collect();
+        links.sort_by(|a, b| a.0.cmp(&b.0));
+        links.dedup_by(|a, b| a.0 == b.0);
+
+        for (i, (key_a, a)) in links.iter().enumerate() {
+            for (key_b, b) in &links[i + 1..] {
+                self.pairs
+                    .entry(format!("{key_a}\n{key_b}"))
+                    .or_insert_with(|| Pair {
+                        a: (*a).clone(),
+                        b: (*b).clone(),
+                        messages: 0,
+                    })
+                    .messages += 1;
+            }
+        }
+    }
+
+    /// The edge list of the pairs posted together in at least
+    /// [`MIN_MESSAGES`] messages, the most frequent first.
+    pub fn edges(&self) -> Vec<Pair> {
+        let mut edges: Vec<_> = self
+            .pairs
+            .values()
+            .filter(|pair| pair.messages >= MIN_MESSAGES)
+            .cloned()
+            .collect();
+        edges.sort_by(|x, y| {
+            y.messages
+                .cmp(&x.messages)
+                .then_with(|| (&x.a, &x.b).cmp(&(&y.a, &y.b)))
+        });
+        edges
+    }
+}
+
+/// Number of links found in at least one of the edges.
+pub fn linked(edges: &[Pair]) -> usize {
+    let mut keys: Vec<_> = edges
+        .iter()
+        .flat_map(|pair| [pair.a.key(), pair.b.key()])
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys.len()
+}
+
+/// Writes the edges next to the results as `<chat>.cooccurrence.json`, or
+/// whatever `--out-name` makes of that name.
+pub fn write(edges: &[Pair], chat: &str, destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(&format!("{chat}.cooccurrence"), Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(edges)?)?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edges() {
+        let link = |name: &str| LinkType::Username(name.to_string());
+        let mut cooccurrence = Cooccurrence {
+            enabled: true,
+            ..Default::default()
+        };
+        cooccurrence.record(&[link("pump"), link("dump"), link("Pump")]);
+        cooccurrence.record(&[link("dump"), link("pump"), link("scam")]);
+        cooccurrence.record(&[link("dump"), link("pump"), link("rustlang")]);
+        cooccurrence.record(&[link("rustlang")]);
+
+        let edges = cooccurrence.edges();
+        assert_eq!(
+            edges,
+            [Pair {
+                a: link("dump"),
+                b: link("pump"),
+                messages: 3
+            }]
+        );
+        assert_eq!(linked(&edges), 2);
+        assert_eq!(cooccurrence.pairs.len(), 5);
+    }
+}
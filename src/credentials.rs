@@ -1,7 +1,41 @@
-use color_eyre::eyre::Result;
-use inquire::{validator::Validation, Text};
+use clap::Subcommand;
+use color_eyre::eyre::{self, Result, WrapErr};
+use grammers_session::Session;
+use inquire::{validator::Validation, Password, PasswordDisplayMode, Text};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::paths;
+
+const API_INFO_FILE: &str = "api_info.json";
+const SESSION_FILE: &str = "crawler.session";
+const BOT_SESSION_FILE: &str = "bot.session";
+const PASSPHRASE_ENV: &str = "RAMPILO_SESSION_PASSPHRASE";
+const EXPORT_PASSPHRASE_ENV: &str = "RAMPILO_EXPORT_PASSPHRASE";
+
+/// Passphrase the session files are encrypted with, once it is known.
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write the saved session and API credentials to a file sealed with a
+    /// passphrase, to sign in somewhere else without a phone
+    Export {
+        /// File to write, `-` for stdout
+        file: PathBuf,
+    },
+    /// Save the session and API credentials of a file written by `session
+    /// export`
+    Import {
+        /// File to read, `-` for stdin
+        file: PathBuf,
+    },
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct ApiCredentials {
@@ -11,7 +45,7 @@ pub struct ApiCredentials {
 
 impl ApiCredentials {
     fn load_from_file() -> Result<Self> {
-        let contents = fs::read_to_string("api_info.json")?;
+        let contents = fs::read_to_string(paths::config_file(API_INFO_FILE))?;
         let api_info: Self = serde_json::from_str(&contents)?;
         Ok(api_info)
     }
@@ -32,12 +66,29 @@ impl ApiCredentials {
         let api_hash = Text::new("Enter your API hash: ").prompt()?;
 
         let api_info = Self { api_id, api_hash };
-        let json = serde_json::to_string_pretty(&api_info)?;
-        fs::write("api_info.json", json)?;
+        api_info.save()?;
 
         Ok(api_info)
     }
 
+    #[cfg(feature = "keyring")]
+    pub fn load() -> Result<Self> {
+        if let Some(api_info) = keyring::get(keyring::API_INFO)? {
+            return Ok(serde_json::from_slice(&api_info)?);
+        }
+
+        match Self::load_from_file() {
+            Ok(api_info) => {
+                if keyring::set(keyring::API_INFO, &serde_json::to_vec(&api_info)?)? {
+                    keyring::forget_file(&paths::config_file(API_INFO_FILE));
+                }
+                Ok(api_info)
+            }
+            Err(_) => Self::load_from_input(),
+        }
+    }
+
+    #[cfg(not(feature = "keyring"))]
     pub fn load() -> Result<Self> {
         if let Ok(api_info) = Self::load_from_file() {
             Ok(api_info)
@@ -48,7 +99,13 @@ impl ApiCredentials {
 
     pub fn save(&self) -> Result<()> {
         let json = serde_json::to_string_pretty(&self)?;
-        fs::write("api_info.json", json)?;
+
+        #[cfg(feature = "keyring")]
+        if keyring::set(keyring::API_INFO, json.as_bytes())? {
+            return Ok(());
+        }
+
+        fs::write(paths::config_file(API_INFO_FILE), json)?;
 
         Ok(())
     }
@@ -61,3 +118,453 @@ impl ApiCredentials {
         &self.api_hash
     }
 }
+
+/// Bots and users are signed in with separate sessions, so using a bot token
+/// doesn't sign out the user account.
+fn session_file(bot: bool) -> PathBuf {
+    if bot {
+        paths::config_file(BOT_SESSION_FILE)
+    } else {
+        paths::config_file(SESSION_FILE)
+    }
+}
+
+#[cfg(feature = "keyring")]
+pub fn load_session(bot: bool, encrypt: bool) -> Result<Session> {
+    let entry = keyring::session(bot);
+    if let Some(session) = keyring::get(entry)? {
+        return Ok(Session::load(&session)?);
+    }
+
+    let session = load_session_file(bot, encrypt)?;
+    if session.signed_in() && keyring::set(entry, &session.save())? {
+        keyring::forget_file(&session_file(bot));
+    }
+
+    Ok(session)
+}
+
+/// Loads the saved session. With `encrypt`, a session file that isn't
+/// encrypted yet is encrypted right away.
+#[cfg(not(feature = "keyring"))]
+pub fn load_session(bot: bool, encrypt: bool) -> Result<Session> {
+    load_session_file(bot, encrypt)
+}
+
+pub fn save_session(session: &Session, bot: bool) -> Result<()> {
+    #[cfg(feature = "keyring")]
+    if keyring::set(keyring::session(bot), &session.save())? {
+        return Ok(());
+    }
+
+    save_session_file(session, bot)
+}
+
+/// Deletes the saved session, from the keyring as well as the file.
+pub fn forget_session(bot: bool) -> Result<()> {
+    #[cfg(feature = "keyring")]
+    keyring::delete(keyring::session(bot))?;
+
+    match fs::remove_file(session_file(bot)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn load_session_file(bot: bool, encrypt: bool) -> Result<Session> {
+    let path = session_file(bot);
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if encrypt {
+                passphrase(true)?;
+            }
+            return Ok(Session::new());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if sealed::is_sealed(&contents) {
+        let session = sealed::open(&contents, passphrase(false)?)?;
+        return Ok(Session::load(&session)?);
+    }
+
+    let session = Session::load(&contents)?;
+    if encrypt {
+        passphrase(true)?;
+        save_session_file(&session, bot)?;
+        status!("Encrypted {}", path.display());
+    }
+    Ok(session)
+}
+
+/// Writes the session file, encrypted if it was read with a passphrase.
+fn save_session_file(session: &Session, bot: bool) -> Result<()> {
+    let path = session_file(bot);
+    match PASSPHRASE.get() {
+        Some(passphrase) => fs::write(&path, sealed::seal(&session.save(), passphrase)?)?,
+        None => session.save_to_file(&path)?,
+    }
+
+    Ok(())
+}
+
+/// The passphrase of the session files, taken from
+/// `RAMPILO_SESSION_PASSPHRASE` or asked for once, twice for a `new` one.
+fn passphrase(new: bool) -> Result<&'static str> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase);
+    }
+
+    let passphrase = ask_passphrase(PASSPHRASE_ENV, "session", new)?;
+    Ok(PASSPHRASE.get_or_init(|| passphrase))
+}
+
+/// A passphrase taken from the `env` variable or asked for, twice for a
+/// `new` one.
+fn ask_passphrase(env: &str, what: &str, new: bool) -> Result<String> {
+    let passphrase = match env::var(env) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let message = format!("Enter the {what} passphrase: ");
+            let prompt = Password::new(&message).with_display_mode(PasswordDisplayMode::Masked);
+            match new {
+                true => prompt.with_custom_confirmation_message("Repeat the passphrase: "),
+                false => prompt.without_confirmation(),
+            }
+            .prompt()?
+        }
+    };
+    if passphrase.is_empty() {
+        eyre::bail!("The {what} passphrase can't be empty");
+    }
+
+    Ok(passphrase)
+}
+
+/// What `session export` writes: everything a headless run needs to connect
+/// as the account without signing in.
+#[derive(Deserialize, Serialize)]
+struct Bundle {
+    api_id: i32,
+    api_hash: String,
+    /// Whether it is the session of a bot.
+    bot: bool,
+    session: Vec<u8>,
+}
+
+impl Bundle {
+    fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        sealed::seal_as(sealed::BUNDLE_MAGIC, &serde_json::to_vec(self)?, passphrase)
+    }
+
+    fn open(contents: &[u8], passphrase: &str) -> Result<Self> {
+        if !contents.starts_with(sealed::BUNDLE_MAGIC) {
+            eyre::bail!("Not a file written by `rampilo session export`");
+        }
+        let bundle = sealed::open_as(sealed::BUNDLE_MAGIC, contents, passphrase, "the file")?;
+        Ok(serde_json::from_slice(&bundle)?)
+    }
+}
+
+pub fn run(command: &Command, bot: bool, encrypt: bool) -> Result<()> {
+    match command {
+        Command::Export { file } => export(file, bot),
+        Command::Import { file } => import(file, encrypt),
+    }
+}
+
+/// Seals the saved session of the user, or of the bot with `bot`, with the
+/// API credentials into `file`.
+fn export(file: &Path, bot: bool) -> Result<()> {
+    let session = load_session(bot, false)?;
+    if !session.signed_in() {
+        eyre::bail!("Not signed in, run `rampilo login` first");
+    }
+    let credentials = ApiCredentials::load()?;
+    let bundle = Bundle {
+        api_id: credentials.api_id,
+        api_hash: credentials.api_hash,
+        bot,
+        session: session.save(),
+    };
+    let contents = bundle.seal(&ask_passphrase(EXPORT_PASSPHRASE_ENV, "export", true)?)?;
+
+    if file == Path::new("-") {
+        io::stdout().lock().write_all(&contents)?;
+        eprintln!(
+            "Exported the session, anyone with the file and its passphrase can use the account"
+        );
+    } else {
+        fs::write(file, contents)?;
+        status!(
+            "Exported the session to {}, anyone with it and its passphrase can use the account",
+            file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Saves the session and API credentials sealed in `file`, replacing the
+/// saved ones. With `encrypt`, the session file is encrypted.
+fn import(file: &Path, encrypt: bool) -> Result<()> {
+    let contents = if file == Path::new("-") {
+        let mut contents = Vec::new();
+        io::stdin().lock().read_to_end(&mut contents)?;
+        contents
+    } else {
+        fs::read(file).wrap_err_with(|| format!("Could not read {}", file.display()))?
+    };
+    let bundle = Bundle::open(
+        &contents,
+        &ask_passphrase(EXPORT_PASSPHRASE_ENV, "export", false)?,
+    )?;
+    let session = Session::load(&bundle.session)?;
+
+    if encrypt {
+        passphrase(true)?;
+    }
+    ApiCredentials {
+        api_id: bundle.api_id,
+        api_hash: bundle.api_hash,
+    }
+    .save()?;
+    save_session(&session, bundle.bot)?;
+    match bundle.bot {
+        true => status!("Imported the bot session, it is used when given `--bot-token`"),
+        false => status!("Imported the session, run `rampilo whoami` to check it"),
+    }
+    Ok(())
+}
+
+/// Encryption of the session files at rest, which otherwise give anyone
+/// who copies them full access to the account.
+///
+/// The key is derived from the passphrase with Argon2id and the session
+/// sealed with XChaCha20-Poly1305, behind a header and the salt and nonce.
+mod sealed {
+    use argon2::Argon2;
+    use chacha20poly1305::{
+        aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+        XChaCha20Poly1305, XNonce,
+    };
+    use color_eyre::eyre::{self, eyre, Result};
+
+    /// Start of an encrypted session file. Plaintext sessions never start
+    /// like this.
+    const MAGIC: &[u8] = b"rampilo-sealed-session-v1\n";
+    /// Start of a file written by `session export`.
+    pub const BUNDLE_MAGIC: &[u8] = b"rampilo-session-export-v1\n";
+    const SALT_LEN: usize = 16;
+    const NONCE_LEN: usize = 24;
+
+    pub fn is_sealed(contents: &[u8]) -> bool {
+        contents.starts_with(MAGIC)
+    }
+
+    pub fn seal(session: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+        seal_as(MAGIC, session, passphrase)
+    }
+
+    pub fn open(contents: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+        open_as(MAGIC, contents, passphrase, "the session file")
+    }
+
+    /// Seals `data` behind the `magic` header.
+    pub fn seal_as(magic: &[u8], data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = cipher(passphrase, &salt)?
+            .encrypt(&nonce, data)
+            .map_err(|_| eyre!("Could not encrypt the session"))?;
+
+        Ok([magic, &salt, &nonce, &sealed].concat())
+    }
+
+    /// Opens what `seal_as` sealed behind `magic`, naming the file `what` in
+    /// errors.
+    pub fn open_as(magic: &[u8], contents: &[u8], passphrase: &str, what: &str) -> Result<Vec<u8>> {
+        let rest = contents.strip_prefix(magic).unwrap_or_default();
+        if rest.len() < SALT_LEN + NONCE_LEN {
+            eyre::bail!("Could not open {what}, it is damaged");
+        }
+        let (salt, rest) = rest.split_at(SALT_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        cipher(passphrase, salt)?
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| eyre!("Wrong passphrase, or {what} is damaged"))
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| eyre!("Could not derive the session key: {e}"))?;
+
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+/// Secrets stored in the OS keyring (Keychain, Credential Manager or the
+/// kernel keyutils store).
+///
+/// When no keyring is available the plaintext files are used instead.
+#[cfg(feature = "keyring")]
+mod keyring {
+    use std::{fs, path::Path};
+
+    use ::keyring::{Entry, Error};
+    use color_eyre::eyre::Result;
+
+    use crate::paths;
+
+    /// Service of the entries of builds from before profiles, which belong
+    /// to the default profile.
+    const SERVICE: &str = "rampilo";
+    pub const API_INFO: &str = "api_info";
+    const SESSION: &str = "session";
+    const BOT_SESSION: &str = "bot_session";
+
+    pub fn session(bot: bool) -> &'static str {
+        if bot {
+            BOT_SESSION
+        } else {
+            SESSION
+        }
+    }
+
+    /// Service the entries of the active profile are stored under, e.g.
+    /// `rampilo/work`.
+    fn service() -> String {
+        service_of(paths::profile())
+    }
+
+    pub fn service_of(profile: &str) -> String {
+        format!("{SERVICE}/{profile}")
+    }
+
+    /// Reads the secret of the active profile, moving the default profile's
+    /// one from where builds before profiles left it.
+    pub fn get(name: &str) -> Result<Option<Vec<u8>>> {
+        let secret = read(&service(), name)?;
+        if secret.is_some() || paths::profile() != paths::DEFAULT_PROFILE {
+            return Ok(secret);
+        }
+
+        let Some(secret) = read(SERVICE, name)? else {
+            return Ok(None);
+        };
+        if write(&service(), name, &secret)? {
+            remove(SERVICE, name)?;
+        }
+        Ok(Some(secret))
+    }
+
+    /// Stores the secret, returning `false` if there is no usable keyring.
+    pub fn set(name: &str, secret: &[u8]) -> Result<bool> {
+        write(&service(), name, secret)
+    }
+
+    /// Removes the secret, if there is one.
+    pub fn delete(name: &str) -> Result<()> {
+        remove(&service(), name)
+    }
+
+    fn read(service: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        match Entry::new(service, name).and_then(|entry| entry.get_secret()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(Error::NoEntry) => Ok(None),
+            Err(Error::PlatformFailure(e)) | Err(Error::NoStorageAccess(e)) => {
+                tracing::warn!(error = %e, "keyring unavailable, falling back to files");
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(service: &str, name: &str, secret: &[u8]) -> Result<bool> {
+        match Entry::new(service, name).and_then(|entry| entry.set_secret(secret)) {
+            Ok(()) => Ok(true),
+            Err(Error::PlatformFailure(e)) | Err(Error::NoStorageAccess(e)) => {
+                tracing::warn!(error = %e, "keyring unavailable, falling back to files");
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn remove(service: &str, name: &str) -> Result<()> {
+        match Entry::new(service, name).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(Error::PlatformFailure(e)) | Err(Error::NoStorageAccess(e)) => {
+                tracing::warn!(error = %e, "keyring unavailable, falling back to files");
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes a legacy plaintext file once its contents live in the keyring.
+    pub fn forget_file(path: &Path) {
+        match fs::remove_file(path) {
+            Ok(()) => status!("Moved {} into the OS keyring", path.display()),
+            Err(e) => tracing::warn!(
+                path = %path.display(),
+                error = %e,
+                "could not remove file after moving it to the keyring"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_session() {
+        let session = Session::new().save();
+        let contents = sealed::seal(&session, "correct horse").unwrap();
+
+        assert!(sealed::is_sealed(&contents));
+        assert!(!sealed::is_sealed(&session));
+        assert_eq!(sealed::open(&contents, "correct horse").unwrap(), session);
+        assert!(sealed::open(&contents, "battery staple").is_err());
+        assert!(sealed::open(&contents[..30], "correct horse").is_err());
+    }
+
+    #[test]
+    fn test_bundle() {
+        let bundle = Bundle {
+            api_id: 12345,
+            api_hash: "0123456789abcdef".to_string(),
+            bot: false,
+            session: Session::new().save(),
+        };
+        let contents = bundle.seal("correct horse").unwrap();
+
+        let opened = Bundle::open(&contents, "correct horse").unwrap();
+        assert_eq!(opened.api_id, 12345);
+        assert_eq!(opened.session, bundle.session);
+        assert!(Bundle::open(&contents, "battery staple").is_err());
+        // Encrypted session files aren't exports, even with the passphrase.
+        let session = sealed::seal(&bundle.session, "correct horse").unwrap();
+        assert!(Bundle::open(&session, "correct horse").is_err());
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_keyring_service() {
+        assert_eq!(keyring::service_of("default"), "rampilo/default");
+        assert_ne!(keyring::service_of("work"), keyring::service_of("default"));
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_keyring_session() {
+        assert_eq!(keyring::session(false), "session");
+        assert_ne!(keyring::session(true), keyring::session(false));
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Destination, Format};
+
+/// Fewest words a message needs to be fingerprinted, so greetings and lone
+/// links don't cluster every chat together.
+const MIN_WORDS: usize = 8;
+/// Words hashed together into one feature of the fingerprint.
+const SHINGLE: usize = 3;
+/// Most bits two fingerprints may differ in to count as the same post.
+const MAX_DISTANCE: u32 = 3;
+/// The fingerprints are split into this many bands. Two within
+/// [`MAX_DISTANCE`] bits of each other share at least one band exactly.
+const BANDS: u32 = MAX_DISTANCE + 1;
+/// Characters of a post kept to show what a cluster is about.
+const SAMPLE: usize = 200;
+
+/// Fingerprints of the messages of one chat, for `--crossposts`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Fingerprints {
+    /// Whether messages are fingerprinted at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    pub posts: Vec<Post>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Post {
+    pub simhash: u64,
+    pub date: i64,
+    /// Link to the message, if the chat can be linked to.
+    pub message: Option<String>,
+    pub text: String,
+}
+
+impl Fingerprints {
+    pub fn record(&mut self, text: &str, date: i64, permalink: Option<&str>) {
+        if !self.enabled {
+            return;
+        }
+        let Some(simhash) = simhash(text) else {
+            return;
+        };
+        self.posts.push(Post {
+            simhash,
+            date,
+            message: permalink.map(str::to_string),
+            text: text.chars().take(SAMPLE).collect(),
+        });
+    }
+}
+
+/// The fingerprints of every chat crawled in one run.
+#[derive(Debug, Default)]
+pub struct Index {
+    posts: Vec<(String, Post)>,
+}
+
+/// Near-identical posts found in more than one chat.
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    /// Text of the earliest post.
+    pub text: String,
+    pub chats: Vec<String>,
+    /// Every post of the cluster, oldest first.
+    pub posts: Vec<Posted>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Posted {
+    pub chat: String,
+    pub date: i64,
+    pub message: Option<String>,
+}
+
+impl Index {
+    pub fn add(&mut self, chat: &str, fingerprints: Fingerprints) {
+        self.posts.extend(
+            fingerprints
+                .posts
+                .into_iter()
+                .map(|post| (chat.to_string(), post)),
+        );
+    }
+
+    /// Groups the posts whose fingerprints are at most [`MAX_DISTANCE`] bits
+    /// apart and keeps the groups that span several chats, largest first.
+    pub fn clusters(&self) -> Vec<Cluster> {
+        let mut sets = DisjointSets::new(self.posts.len());
+        for band in 0..BANDS {
+            let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+            for (i, (_, post)) in self.posts.iter().enumerate() {
+                buckets
+                    .entry(band_of(post.simhash, band))
+                    .or_default()
+                    .push(i);
+            }
+            for bucket in buckets.values() {
+                for (n, &a) in bucket.iter().enumerate() {
+                    for &b in &bucket[n + 1..] {
+                        let distance =
+                            (self.posts[a].1.simhash ^ self.posts[b].1.simhash).count_ones();
+                        if distance <= MAX_DISTANCE {
+                            sets.union(a, b);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..self.posts.len() {
+            groups.entry(sets.find(i)).or_default().push(i);
+        }
+
+        let mut clusters: Vec<_> = groups
+            .into_values()
+            .filter_map(|mut members| {
+                let chats: BTreeSet<_> = members.iter().map(|&i| self.posts[i].0.clone()).collect();
+                if chats.len() < 2 {
+                    return None;
+                }
+                members.sort_by_key(|&i| self.posts[i].1.date);
+                Some(Cluster {
+                    text: self.posts[members[0]].1.text.clone(),
+                    chats: chats.into_iter().collect(),
+                    posts: members
+                        .iter()
+                        .map(|&i| {
+                            let (chat, post) = &self.posts[i];
+                            Posted {
+                                chat: chat.clone(),
+                                date: post.date,
+                                message: post.message.clone(),
+                            }
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        clusters.sort_by(|a, b| {
+            (b.chats.len(), b.posts.len())
+                .cmp(&(a.chats.len(), a.posts.len()))
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        clusters
+    }
+}
+
+/// Writes the clusters to `crossposts.json`, or whatever `--out-name` makes
+/// of that name.
+pub fn write(clusters: &[Cluster], destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path("crossposts", Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(clusters)?)?;
+    Ok(path.display().to_string())
+}
+
+/// 64-bit SimHash of the word shingles of `text`, ignoring case and
+/// punctuation. `None` for messages too short to tell apart.
+fn simhash(text: &str) -> Option<u64> {
+    let text = text.to_lowercase();
+    let words: Vec<_> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut weights = [0i32; 64];
+    for shingle in words.windows(SHINGLE) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, &weight)| weight > 0)
+            .fold(0, |simhash, (bit, _)| simhash | 1 << bit),
+    )
+}
+
+/// FNV-1a, which unlike the std hasher gives the same fingerprints on every
+/// build, so they can be kept in partial results.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn band_of(simhash: u64, band: u32) -> u64 {
+    let width = 64 / BANDS;
+    (simhash >> (band * width)) & ((1 << width) - 1)
+}
+
+struct DisjointSets {
+    parents: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprints(texts: &[&str]) -> Fingerprints {
+        let mut fingerprints = Fingerprints {
+            enabled: true,
+            ..Default::default()
+        };
+        for (date, text) in texts.iter().enumerate() {
+            fingerprints.record(text, date as i64, None);
+        }
+        fingerprints
+    }
+
+    #[test]
+    fn test_clusters() {
+        let promo = "Join the best crypto signals channel today, free entry for the first hundred members only";
+        let mut index = Index::default();
+        index.add(
+            "alpha",
+            fingerprints(&[
+                promo,
+                "Our weekly meetup moves to Thursday evening this time, see you all there",
+            ]),
+        );
+        index.add("beta", fingerprints(&[&format!("{promo}!!"), "hello"]));
+        index.add("gamma", fingerprints(&[&promo.to_uppercase()]));
+
+        let clusters = index.clusters();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].chats, ["alpha", "beta", "gamma"]);
+        assert_eq!(clusters[0].text, promo);
+        assert_eq!(clusters[0].posts.len(), 3);
+    }
+
+    #[test]
+    fn test_simhash() {
+        assert_eq!(simhash("too short to count"), None);
+        let a = simhash("the quick brown fox jumps over the lazy dog near the river bank").unwrap();
+        let b =
+            simhash("The quick brown fox jumps over the lazy dog, near the river bank.").unwrap();
+        let c = simhash("completely different words make up this other message about rust crates")
+            .unwrap();
+        assert_eq!(a, b);
+        assert!((a ^ c).count_ones() > MAX_DISTANCE);
+    }
+}
@@ -0,0 +1,190 @@
+use grammers_client::{client::messages::InvocationError, types::chat::Chat, Client};
+use grammers_tl_types as tl;
+
+use crate::{flood::invoke, risk::Label};
+
+/// Information about a chat that only its "full" request returns.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChatDetails {
+    /// Description of a group or channel, or the bio of a user.
+    pub about: Option<String>,
+    pub members: Option<usize>,
+    /// Unix timestamp of when the chat was created, or when the crawling
+    /// account joined it if it is a member.
+    pub date: Option<i64>,
+    /// Why Telegram restricts the chat, as set by `restriction`.
+    pub restriction: Option<String>,
+    /// Whether the user's account was deleted.
+    pub deleted: bool,
+    pub label: Option<Label>,
+    /// Id of the discussion group of a channel, or of the channel a group
+    /// discusses.
+    pub linked_chat: Option<i64>,
+}
+
+/// Fetches the description, member count and date of a resolved chat.
+pub async fn fetch(client: &Client, chat: &Chat) -> Result<ChatDetails, InvocationError> {
+    let packed = chat.pack();
+
+    if let Some(channel) = packed.try_to_input_channel() {
+        let request = tl::functions::channels::GetFullChannel { channel };
+        let tl::enums::messages::ChatFull::Full(full) = invoke(client, &request).await?;
+        Ok(chat_details(full, chat.id()))
+    } else if let Some(chat_id) = packed.try_to_chat_id() {
+        let request = tl::functions::messages::GetFullChat { chat_id };
+        let tl::enums::messages::ChatFull::Full(full) = invoke(client, &request).await?;
+        Ok(chat_details(full, chat.id()))
+    } else if let Some(id) = packed.try_to_input_user() {
+        let request = tl::functions::users::GetFullUser { id };
+        let tl::enums::users::UserFull::Full(full) = invoke(client, &request).await?;
+        let tl::enums::UserFull::Full(about) = full.full_user;
+        let mut details = ChatDetails {
+            about: about.about.and_then(non_empty),
+            ..Default::default()
+        };
+        if let Some(user) = find_user(full.users, chat.id()) {
+            details.restriction = user
+                .restricted
+                .then(|| restriction(user.restriction_reason));
+            details.deleted = user.deleted;
+            details.label = label(user.scam, user.fake);
+        }
+        Ok(details)
+    } else {
+        Ok(ChatDetails::default())
+    }
+}
+
+/// Fetches the name and bio of a user known only by id, as mentioned by
+/// name without a username. `None` if Telegram doesn't return the user.
+pub async fn fetch_user(
+    client: &Client,
+    user_id: i64,
+    access_hash: i64,
+) -> Result<Option<(String, ChatDetails)>, InvocationError> {
+    let request = tl::functions::users::GetFullUser {
+        id: tl::types::InputUser {
+            user_id,
+            access_hash,
+        }
+        .into(),
+    };
+    let tl::enums::users::UserFull::Full(full) = invoke(client, &request).await?;
+    let tl::enums::UserFull::Full(about) = full.full_user;
+
+    Ok(find_user(full.users, user_id).map(|user| {
+        let name = [user.first_name, user.last_name];
+        let details = ChatDetails {
+            about: about.about.and_then(non_empty),
+            restriction: user
+                .restricted
+                .then(|| restriction(user.restriction_reason)),
+            deleted: user.deleted,
+            label: label(user.scam, user.fake),
+            ..Default::default()
+        };
+        (
+            name.into_iter().flatten().collect::<Vec<_>>().join(" "),
+            details,
+        )
+    }))
+}
+
+fn find_user(users: Vec<tl::enums::User>, id: i64) -> Option<tl::types::User> {
+    users.into_iter().find_map(|user| match user {
+        tl::enums::User::User(user) if user.id == id => Some(user),
+        _ => None,
+    })
+}
+
+fn chat_details(full: tl::types::messages::ChatFull, id: i64) -> ChatDetails {
+    let mut details = match full.full_chat {
+        tl::enums::ChatFull::Full(full) => ChatDetails {
+            about: non_empty(full.about),
+            ..Default::default()
+        },
+        tl::enums::ChatFull::ChannelFull(full) => ChatDetails {
+            about: non_empty(full.about),
+            members: full.participants_count.map(|count| count as usize),
+            linked_chat: full.linked_chat_id,
+            ..Default::default()
+        },
+    };
+
+    for chat in full.chats {
+        match chat {
+            tl::enums::Chat::Chat(chat) if chat.id == id => {
+                details.members = Some(chat.participants_count as usize);
+                details.date = Some(chat.date as i64);
+            }
+            tl::enums::Chat::Channel(channel) if channel.id == id => {
+                details.members = details
+                    .members
+                    .or(channel.participants_count.map(|count| count as usize));
+                details.date = Some(channel.date as i64);
+                details.restriction = channel
+                    .restricted
+                    .then(|| restriction(channel.restriction_reason));
+                details.label = label(channel.scam, channel.fake);
+            }
+            _ => {}
+        }
+    }
+
+    details
+}
+
+/// The reasons Telegram gives for restricting a chat, e.g. `terms: This
+/// channel can't be displayed because it violated Telegram's Terms of
+/// Service.`, once each as they are repeated for every platform.
+fn restriction(reasons: Option<Vec<tl::enums::RestrictionReason>>) -> String {
+    let mut texts: Vec<String> = Vec::new();
+    for tl::enums::RestrictionReason::Reason(reason) in reasons.unwrap_or_default() {
+        let text = format!("{}: {}", reason.reason, reason.text);
+        if !texts.contains(&text) {
+            texts.push(text);
+        }
+    }
+
+    if texts.is_empty() {
+        "restricted".to_string()
+    } else {
+        texts.join("; ")
+    }
+}
+
+fn label(scam: bool, fake: bool) -> Option<Label> {
+    match (scam, fake) {
+        (true, _) => Some(Label::Scam),
+        (false, true) => Some(Label::Fake),
+        (false, false) => None,
+    }
+}
+
+fn non_empty(about: String) -> Option<String> {
+    let about = about.trim();
+    (!about.is_empty()).then(|| about.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restriction() {
+        let reason = |platform: &str| {
+            tl::types::RestrictionReason {
+                platform: platform.to_string(),
+                reason: "terms".to_string(),
+                text: "This channel can't be displayed.".to_string(),
+            }
+            .into()
+        };
+
+        assert_eq!(
+            restriction(Some(vec![reason("ios"), reason("android")])),
+            "terms: This channel can't be displayed."
+        );
+        assert_eq!(restriction(None), "restricted");
+    }
+}
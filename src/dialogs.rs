@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::Result;
+use grammers_client::{types::chat::Chat, Client};
+use regex::Regex;
+
+use crate::{flood, target::Target, Username};
+
+/// Name of the results of all the chats crawled with `--all-dialogs`.
+pub const RESULTS_NAME: &str = "all-dialogs";
+
+#[derive(clap::Args)]
+#[group(id = "DialogOptions")]
+pub struct Options {
+    /// Crawl every group and channel the account is in, and write the links
+    /// of all of them to `all-dialogs.json` as well
+    #[arg(long)]
+    pub all_dialogs: bool,
+
+    /// Only crawl the chats of `--all-dialogs` whose title or username
+    /// matches this regular expression, can be given more than once
+    #[arg(long, value_name = "REGEX", requires = "all_dialogs")]
+    include_dialogs: Vec<Regex>,
+
+    /// Leave out the chats of `--all-dialogs` whose title or username
+    /// matches this regular expression, can be given more than once
+    #[arg(long, value_name = "REGEX", requires = "all_dialogs")]
+    exclude_dialogs: Vec<Regex>,
+}
+
+impl Options {
+    /// Whether a chat with `title` and `username` is to be crawled.
+    fn matches(&self, title: &str, username: Option<&str>) -> bool {
+        let matches = |regex: &Regex| {
+            regex.is_match(title) || username.is_some_and(|username| regex.is_match(username))
+        };
+        (self.include_dialogs.is_empty() || self.include_dialogs.iter().any(matches))
+            && !self.exclude_dialogs.iter().any(matches)
+    }
+}
+
+/// The groups and channels the account is in that `options` keep, as
+/// targets to crawl.
+pub async fn chats(client: &Client, options: &Options) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    let mut dialogs = flood::iter_dialogs(client)?;
+    while let Some(dialog) = dialogs.next().await? {
+        let chat = dialog.chat();
+        if matches!(chat, Chat::User(_)) || !options.matches(chat.name(), chat.username()) {
+            continue;
+        }
+        targets.push(match chat.username() {
+            Some(username) => Target::Username(username.to_string()),
+            None => Target::Id(chat.id()),
+        });
+    }
+    tracing::debug!(chats = targets.len(), "listed dialogs");
+    Ok(targets)
+}
+
+/// Adds up the results of several chats, entry by entry.
+pub fn aggregate<'a>(results: impl IntoIterator<Item = &'a [Username]>) -> Vec<Username> {
+    let mut merged: HashMap<String, Username> = HashMap::new();
+    for username in results.into_iter().flatten() {
+        let Some(existing) = merged.get_mut(&username.username.key()) else {
+            merged.insert(username.username.key(), username.clone());
+            continue;
+        };
+        existing.absorb(username);
+        for alias in &username.aliases {
+            if !existing.aliases.contains(alias) {
+                existing.aliases.push(alias.clone());
+            }
+        }
+        if existing.metadata.is_none() {
+            existing.metadata.clone_from(&username.metadata);
+            existing.unresolved = username.unresolved;
+        }
+    }
+    merged.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rampilo::LinkType;
+
+    #[test]
+    fn test_matches() {
+        let options = Options {
+            all_dialogs: true,
+            include_dialogs: vec![Regex::new("(?i)rust").unwrap()],
+            exclude_dialogs: vec![Regex::new("offtopic").unwrap()],
+        };
+
+        assert!(options.matches("Rust Lang", None));
+        assert!(options.matches("Programming", Some("rustlang")));
+        assert!(!options.matches("Go", Some("golang")));
+        assert!(!options.matches("Rust", Some("rust_offtopic")));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let username = |name: &str, count| Username {
+            count,
+            ..Username::new(LinkType::Username(name.to_string()))
+        };
+        let first = [username("grammers", 2), username("rampilo", 1)];
+        let second = [
+            Username {
+                aliases: vec![LinkType::Mention("grammers".to_string())],
+                ..username("grammers", 3)
+            },
+            username("tokio", 4),
+        ];
+
+        let mut aggregated = aggregate([&first[..], &second[..]]);
+        aggregated.sort_by_key(|u| u.username.key());
+        let counts: Vec<_> = aggregated.iter().map(|u| u.count).collect();
+        assert_eq!(counts, [5, 1, 4]);
+        assert_eq!(
+            aggregated[0].aliases,
+            [LinkType::Mention("grammers".to_string())]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, WrapErr};
+use rampilo::LinkType;
+use serde::Serialize;
+
+use crate::{locale::Formatter, Username};
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// JSON results file of the earlier crawl
+    old: PathBuf,
+
+    /// JSON results file of the later crawl
+    new: PathBuf,
+
+    /// Print the report as JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// How the links of two crawls differ.
+#[derive(Debug, Default, Serialize)]
+struct Diff {
+    /// Links only the later crawl found, by descending count.
+    appeared: Vec<Change>,
+    /// Links only the earlier crawl found, by descending count.
+    disappeared: Vec<Change>,
+    /// Links whose count changed, by descending change.
+    changed: Vec<Change>,
+    unchanged: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Change {
+    username: LinkType,
+    old: usize,
+    new: usize,
+}
+
+impl Change {
+    fn delta(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+pub fn run(options: &Options, fmt: Formatter) -> Result<()> {
+    let diff = diff(&load(&options.old)?, &load(&options.new)?);
+
+    if options.json {
+        status!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    status!(
+        "{} appeared, {} disappeared, {} changed, {} unchanged",
+        fmt.number(diff.appeared.len()),
+        fmt.number(diff.disappeared.len()),
+        fmt.number(diff.changed.len()),
+        fmt.number(diff.unchanged)
+    );
+    for change in &diff.appeared {
+        status!("+ {} ({})", change.username.key(), fmt.number(change.new));
+    }
+    for change in &diff.disappeared {
+        status!("- {} ({})", change.username.key(), fmt.number(change.old));
+    }
+    for change in &diff.changed {
+        let sign = if change.delta() > 0 { "+" } else { "-" };
+        status!(
+            "~ {} {} -> {} ({sign}{})",
+            change.username.key(),
+            fmt.number(change.old),
+            fmt.number(change.new),
+            fmt.number(change.delta().unsigned_abs() as usize)
+        );
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Vec<Username>> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("{} is not a JSON results file", path.display()))
+}
+
+/// Matches the entries of both crawls by link, counting an entry as the same
+/// when a link merged into it on one side is its main link on the other.
+fn diff(old: &[Username], new: &[Username]) -> Diff {
+    let mut index = HashMap::new();
+    for (i, username) in old.iter().enumerate() {
+        for link in std::iter::once(&username.username).chain(&username.aliases) {
+            index.entry(link.key()).or_insert(i);
+        }
+    }
+
+    let mut diff = Diff::default();
+    let mut matched = HashSet::new();
+    for username in new {
+        let found = std::iter::once(&username.username)
+            .chain(&username.aliases)
+            .find_map(|link| index.get(&link.key()).copied());
+
+        let change = Change {
+            username: username.username.clone(),
+            old: found.map_or(0, |i| old[i].count),
+            new: username.count,
+        };
+        match found {
+            Some(i) if matched.insert(i) => match change.delta() {
+                0 => diff.unchanged += 1,
+                _ => diff.changed.push(change),
+            },
+            // Already matched by another entry of the later crawl.
+            Some(_) => {}
+            None => diff.appeared.push(change),
+        }
+    }
+
+    diff.disappeared = old
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !matched.contains(i))
+        .map(|(_, username)| Change {
+            username: username.username.clone(),
+            old: username.count,
+            new: 0,
+        })
+        .collect();
+
+    diff.appeared
+        .sort_by_key(|change| std::cmp::Reverse(change.new));
+    diff.disappeared
+        .sort_by_key(|change| std::cmp::Reverse(change.old));
+    diff.changed
+        .sort_by_key(|change| std::cmp::Reverse(change.delta().abs()));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn username(link: LinkType, count: usize) -> Username {
+        Username {
+            count,
+            ..Username::new(link)
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            username(LinkType::Username("grammers".to_string()), 4),
+            username(LinkType::Username("rampilo".to_string()), 2),
+            username(LinkType::Hashtag("rust".to_string()), 1),
+            username(LinkType::Username("codenight_old".to_string()), 3),
+        ];
+        let new = vec![
+            username(LinkType::Username("Grammers".to_string()), 9),
+            username(LinkType::Hashtag("rust".to_string()), 1),
+            username(LinkType::Mention("tokio".to_string()), 5),
+            Username {
+                aliases: vec![LinkType::Username("codenight_old".to_string())],
+                ..username(LinkType::Username("codenight".to_string()), 1)
+            },
+        ];
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.appeared,
+            [Change {
+                username: LinkType::Mention("tokio".to_string()),
+                old: 0,
+                new: 5
+            }]
+        );
+        assert_eq!(
+            diff.disappeared,
+            [Change {
+                username: LinkType::Username("rampilo".to_string()),
+                old: 2,
+                new: 0
+            }]
+        );
+        let changed: Vec<_> = diff.changed.iter().map(Change::delta).collect();
+        assert_eq!(changed, [5, -2]);
+        assert_eq!(diff.unchanged, 1);
+    }
+}
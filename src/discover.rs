@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_client::Client;
+use grammers_tl_types as tl;
+use serde::Serialize;
+
+use crate::{
+    flood,
+    locale::Formatter,
+    output::{Destination, Format},
+    target::Target,
+    UsernameType,
+};
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// Keywords to search the public groups and channels for
+    #[arg(value_name = "KEYWORD", required = true)]
+    keywords: Vec<String>,
+
+    /// Most chats Telegram is asked for per keyword
+    #[arg(long, default_value_t = 50)]
+    limit: i32,
+
+    /// Leave out the chats with fewer members than this
+    #[arg(long, default_value_t = 0)]
+    min_members: usize,
+
+    /// Crawl the chats found, as if they were given on the command line
+    #[arg(long)]
+    pub crawl: bool,
+}
+
+/// A public chat found by searching for a keyword.
+#[derive(Debug, Serialize)]
+struct Discovered {
+    username: String,
+    id: i64,
+    title: String,
+    #[serde(rename = "type")]
+    type_: UsernameType,
+    members: Option<usize>,
+    /// Keywords the chat was found for.
+    keywords: Vec<String>,
+}
+
+/// Searches Telegram for the public chats matching every keyword and writes
+/// them to `discovered.json`, most members first. Returns them as targets
+/// to crawl.
+pub async fn run(
+    client: &Client,
+    options: &Options,
+    destination: &Destination,
+    fmt: Formatter,
+) -> Result<Vec<Target>> {
+    let mut found: HashMap<String, Discovered> = HashMap::new();
+    for keyword in &options.keywords {
+        let request = tl::functions::contacts::Search {
+            q: keyword.clone(),
+            limit: options.limit,
+        };
+        let tl::enums::contacts::Found::Found(results) = flood::invoke(client, &request).await?;
+        tracing::debug!(keyword, chats = results.chats.len(), "searched");
+
+        for chat in public_chats(results.chats, options.min_members) {
+            found
+                .entry(chat.username.to_lowercase())
+                .or_insert(chat)
+                .keywords
+                .push(keyword.clone());
+        }
+    }
+
+    let mut found: Vec<_> = found.into_values().collect();
+    found.sort_by(|a, b| b.members.cmp(&a.members).then(a.username.cmp(&b.username)));
+    for chat in &found {
+        status!(
+            "@{} ({}, {} members): {}",
+            chat.username,
+            chat.type_.as_str(),
+            chat.members.map_or("?".to_string(), |n| fmt.number(n)),
+            chat.title
+        );
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path("discovered", Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(&found)?)?;
+    status!(
+        "Found {} public chats, saved to {}",
+        fmt.number(found.len()),
+        path.display()
+    );
+
+    Ok(found
+        .into_iter()
+        .map(|chat| Target::Username(chat.username))
+        .collect())
+}
+
+/// The groups and channels with a username, the only ones that can be
+/// crawled without being invited.
+fn public_chats(chats: Vec<tl::enums::Chat>, min_members: usize) -> Vec<Discovered> {
+    chats
+        .into_iter()
+        .filter_map(|chat| match chat {
+            tl::enums::Chat::Channel(channel) if !channel.min => Some(channel),
+            _ => None,
+        })
+        .filter_map(|channel| {
+            let members = channel.participants_count.map(|n| n.max(0) as usize);
+            if members.unwrap_or_default() < min_members {
+                return None;
+            }
+            Some(Discovered {
+                username: channel.username?,
+                id: channel.id,
+                title: channel.title,
+                type_: match channel.broadcast {
+                    true => UsernameType::Channel,
+                    false => UsernameType::Group,
+                },
+                members,
+                keywords: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(id: i64, username: Option<&str>, members: i32) -> tl::enums::Chat {
+        tl::types::Channel {
+            creator: false,
+            left: true,
+            broadcast: id % 2 == 0,
+            verified: false,
+            megagroup: id % 2 == 1,
+            restricted: false,
+            signatures: false,
+            min: false,
+            scam: false,
+            has_link: false,
+            has_geo: false,
+            slowmode_enabled: false,
+            call_active: false,
+            call_not_empty: false,
+            fake: false,
+            gigagroup: false,
+            noforwards: false,
+            join_to_send: false,
+            join_request: false,
+            id,
+            access_hash: Some(1),
+            title: format!("Chat {id}"),
+            username: username.map(str::to_string),
+            photo: tl::types::ChatPhotoEmpty {}.into(),
+            date: 0,
+            restriction_reason: None,
+            admin_rights: None,
+            banned_rights: None,
+            default_banned_rights: None,
+            participants_count: Some(members),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_public_chats() {
+        let chats = vec![
+            channel(1, Some("rustgroup"), 500),
+            channel(2, Some("rustnews"), 20),
+            channel(3, None, 1000),
+        ];
+
+        let found = public_chats(chats, 100);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].username, "rustgroup");
+        assert_eq!(found[0].type_.as_str(), UsernameType::Group.as_str());
+        assert_eq!(found[0].members, Some(500));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{Result, WrapErr};
+use grammers_client::Client;
+use indicatif::ProgressBar;
+
+use crate::{
+    admins,
+    cache::ResolveCache,
+    compress::{self, Compression},
+    config::Config,
+    detect_languages, merge_resolved, ratelimit, resolve_all, risk, shutdown, Unresolved, Username,
+};
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// JSON results file of an earlier crawl, compressed or not
+    results: PathBuf,
+
+    /// Write the enriched results to this file instead of over the results
+    /// file, compressed if its name ends in `.gz` or `.zst`
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+/// Looks up the entries of a results file that have no metadata yet, as a
+/// crawl would, and writes the results back.
+pub async fn run(
+    client: &Client,
+    args: &crate::Args,
+    options: &Options,
+    config: &Config,
+) -> Result<()> {
+    let mut usernames = load(&options.results)?;
+    let cache = Mutex::new(ResolveCache::load(&args.resolve_cache(), args.cache_ttl)?);
+    ratelimit::init(
+        args.requests_per_second,
+        Duration::from_millis(args.resolve_delay_ms),
+    );
+    shutdown::listen();
+    args.stop.start();
+
+    let (mut pending, mut complete): (Vec<_>, Vec<_>) =
+        usernames.drain(..).partition(is_missing_metadata);
+    status!(
+        "Resolving {} of {} entries of {}...",
+        args.locale.number(pending.len()),
+        args.locale.number(pending.len() + complete.len()),
+        options.results.display()
+    );
+
+    // The access hashes of users mentioned by id came with the crawled
+    // messages, so those entries can't be looked up again.
+    let progress_bar = ProgressBar::new(pending.len() as u64);
+    resolve_all(
+        client,
+        &mut pending,
+        &config.resolve,
+        &HashMap::new(),
+        &cache,
+        progress_bar,
+    )
+    .await;
+    cache.lock().unwrap().save()?;
+    // Entries resolved before `--admins` was given get their admins too.
+    if args.admins {
+        admins::fill(client, &mut pending).await;
+        admins::fill(client, &mut complete).await;
+    }
+    if shutdown::requested() {
+        status!("Interrupted while resolving, some entries are left unresolved");
+    } else if ratelimit::over_budget() {
+        status!("Request budget used up while resolving, some entries are left unresolved");
+    }
+
+    let resolved = pending.iter().filter(|u| u.metadata.is_some()).count();
+    usernames.extend(pending);
+    usernames.extend(complete);
+    if args.languages {
+        detect_languages(&mut usernames);
+    }
+    let mut usernames = merge_resolved(usernames);
+    if args.risk_score {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        risk::score(&mut usernames, now);
+    }
+
+    let path = options.output.as_ref().unwrap_or(&options.results);
+    compress::write(
+        path,
+        serde_json::to_string_pretty(&usernames)?,
+        Compression::from_path(path),
+    )?;
+    status!(
+        "Resolved {} entries, saved {} usernames to {}",
+        args.locale.number(resolved),
+        args.locale.number(usernames.len()),
+        path.display()
+    );
+    if args.admins {
+        let shared = admins::shared(&usernames);
+        let filename = admins::write(&shared, &args.destination)?;
+        status!(
+            "{} people admin several of the chats found, saved to {filename}",
+            args.locale.number(shared.len())
+        );
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<Vec<Username>> {
+    let contents = compress::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("{} is not a JSON results file", path.display()))
+}
+
+/// Whether the entry was never looked up, or the lookup failed in a way a
+/// later one may not.
+fn is_missing_metadata(username: &Username) -> bool {
+    username.metadata.is_none() && matches!(username.unresolved, None | Some(Unresolved::Failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rampilo::LinkType;
+
+    #[test]
+    fn test_is_missing_metadata() {
+        let username = || Username::new(LinkType::Username("grammers".to_string()));
+
+        assert!(is_missing_metadata(&username()));
+        assert!(is_missing_metadata(&Username {
+            unresolved: Some(Unresolved::Failed),
+            ..username()
+        }));
+        assert!(!is_missing_metadata(&Username {
+            unresolved: Some(Unresolved::NotFound),
+            ..username()
+        }));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_tl_types as tl;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    locale::Formatter,
+    output::{self, Destination, Format},
+};
+
+/// What the service messages of a chat tell about its growth.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Events {
+    /// Members who joined through an invite link, by id of the user whose
+    /// link it was. Telegram doesn't say which of their links was used.
+    pub joined_by_link: BTreeMap<i64, usize>,
+    /// Members whose join request was approved.
+    pub joined_by_request: usize,
+    /// Members who joined a public group by themselves.
+    pub joined: usize,
+    /// Members added by someone else.
+    pub added: usize,
+    pub left: usize,
+    pub removed: usize,
+    /// Joins of every kind by `YYYY-MM` month.
+    pub joins_by_month: BTreeMap<String, usize>,
+    pub pinned: usize,
+    /// Id of the supergroup this group was upgraded to.
+    pub migrated_to: Option<i64>,
+    /// Id of the group this supergroup was upgraded from.
+    pub migrated_from: Option<i64>,
+}
+
+impl Events {
+    /// Counts a service message, ignoring the actions that say nothing about
+    /// the members.
+    pub fn record(&mut self, message: &tl::types::MessageService) {
+        let from = match message.from_id {
+            Some(tl::enums::Peer::User(ref user)) => Some(user.user_id),
+            _ => None,
+        };
+
+        let joins = match message.action {
+            tl::enums::MessageAction::ChatAddUser(ref action) => {
+                for &user in &action.users {
+                    if Some(user) == from {
+                        self.joined += 1;
+                    } else {
+                        self.added += 1;
+                    }
+                }
+                action.users.len()
+            }
+            tl::enums::MessageAction::ChatJoinedByLink(ref action) => {
+                *self.joined_by_link.entry(action.inviter_id).or_default() += 1;
+                1
+            }
+            tl::enums::MessageAction::ChatJoinedByRequest => {
+                self.joined_by_request += 1;
+                1
+            }
+            tl::enums::MessageAction::ChatDeleteUser(ref action) => {
+                if Some(action.user_id) == from {
+                    self.left += 1;
+                } else {
+                    self.removed += 1;
+                }
+                0
+            }
+            tl::enums::MessageAction::PinMessage => {
+                self.pinned += 1;
+                0
+            }
+            tl::enums::MessageAction::ChatMigrateTo(ref action) => {
+                self.migrated_to = Some(action.channel_id);
+                0
+            }
+            tl::enums::MessageAction::ChannelMigrateFrom(ref action) => {
+                self.migrated_from = Some(action.chat_id);
+                0
+            }
+            _ => 0,
+        };
+
+        if joins > 0 {
+            let month = output::date(message.date as i64)[..7].to_string();
+            *self.joins_by_month.entry(month).or_default() += joins;
+        }
+    }
+
+    pub fn joins(&self) -> usize {
+        self.joined_by_link.values().sum::<usize>()
+            + self.joined_by_request
+            + self.joined
+            + self.added
+    }
+
+    /// One line on how members joined and left, naming the users whose
+    /// invite links brought the most of them.
+    pub fn summary(&self, fmt: Formatter) -> String {
+        let by_link = self.joined_by_link.values().sum();
+        let mut summary = format!(
+            "{} joins ({} through invite links, {} by request, {} added by others), {} left, {} removed, {} pinned messages",
+            fmt.number(self.joins()),
+            fmt.number(by_link),
+            fmt.number(self.joined_by_request),
+            fmt.number(self.added),
+            fmt.number(self.left),
+            fmt.number(self.removed),
+            fmt.number(self.pinned),
+        );
+
+        let mut inviters: Vec<_> = self.joined_by_link.iter().collect();
+        inviters.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        if !inviters.is_empty() {
+            let top = inviters
+                .iter()
+                .take(3)
+                .map(|(id, count)| format!("tg://user?id={id} ({})", fmt.number(**count)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            summary.push_str(&format!(", most joins through the links of {top}"));
+        }
+        if let Some(id) = self.migrated_to {
+            summary.push_str(&format!(", upgraded to the supergroup {id}"));
+        }
+        if let Some(id) = self.migrated_from {
+            summary.push_str(&format!(", upgraded from the group {id}"));
+        }
+        summary
+    }
+
+    /// Writes the statistics next to the results as `<chat>.events.json`, or
+    /// whatever `--out-name` makes of that name.
+    pub fn write(&self, chat: &str, destination: &Destination) -> Result<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let path = destination.path(&format!("{chat}.events"), Format::Json, now);
+        fs::create_dir_all(&destination.out_dir)?;
+        destination.write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(
+        from: i64,
+        date: i32,
+        action: tl::enums::MessageAction,
+    ) -> tl::types::MessageService {
+        tl::types::MessageService {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            legacy: false,
+            id: 1,
+            from_id: Some(tl::types::PeerUser { user_id: from }.into()),
+            peer_id: tl::types::PeerChannel { channel_id: 1 }.into(),
+            reply_to: None,
+            date,
+            action,
+            ttl_period: None,
+        }
+    }
+
+    #[test]
+    fn test_record_events() {
+        // 2023-01-15 and 2023-02-15
+        let (january, february) = (1673740800, 1676419200);
+        let by_link = |inviter_id| {
+            tl::enums::MessageAction::ChatJoinedByLink(tl::types::MessageActionChatJoinedByLink {
+                inviter_id,
+            })
+        };
+
+        let mut events = Events::default();
+        for message in [
+            service(10, january, by_link(1)),
+            service(11, january, by_link(1)),
+            service(12, february, by_link(2)),
+            service(
+                13,
+                february,
+                tl::types::MessageActionChatAddUser {
+                    users: vec![13, 14],
+                }
+                .into(),
+            ),
+            service(
+                10,
+                february,
+                tl::types::MessageActionChatDeleteUser { user_id: 10 }.into(),
+            ),
+            service(1, february, tl::enums::MessageAction::PinMessage),
+            service(1, february, tl::enums::MessageAction::ChatDeletePhoto),
+        ] {
+            events.record(&message);
+        }
+
+        assert_eq!(events.joined_by_link, BTreeMap::from([(1, 2), (2, 1)]));
+        assert_eq!((events.joined, events.added), (1, 1));
+        assert_eq!((events.left, events.removed, events.pinned), (1, 0, 1));
+        assert_eq!(events.joins(), 5);
+        assert_eq!(
+            events.joins_by_month,
+            BTreeMap::from([("2023-01".to_string(), 2), ("2023-02".to_string(), 3)])
+        );
+        assert!(events
+            .summary(Formatter::default())
+            .contains("most joins through the links of tg://user?id=1 (2), tg://user?id=2 (1)"));
+    }
+}
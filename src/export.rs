@@ -0,0 +1,452 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{self, Result};
+use grammers_tl_types::{
+    enums::MessageEntity,
+    types::{MessageEntityMention, MessageEntityPhone, MessageEntityTextUrl},
+};
+use regex::Regex;
+use serde::Deserialize;
+
+/// A message read from a Telegram Desktop export.
+pub struct ExportedMessage {
+    pub text: String,
+    /// Only mentions, phone numbers and the urls of hyperlinked text are
+    /// recovered from exports.
+    pub entities: Vec<MessageEntity>,
+    /// Unix timestamp of when the message was sent.
+    pub date: Option<i64>,
+    /// Total of the reactions on the message.
+    pub reactions: usize,
+}
+
+impl ExportedMessage {
+    fn push_mention(&mut self, mention: &str) {
+        self.entities.push(
+            MessageEntityMention {
+                offset: utf16_len(&self.text),
+                length: utf16_len(mention),
+            }
+            .into(),
+        );
+        self.text.push_str(mention);
+    }
+
+    fn push_phone(&mut self, number: &str) {
+        self.entities.push(
+            MessageEntityPhone {
+                offset: utf16_len(&self.text),
+                length: utf16_len(number),
+            }
+            .into(),
+        );
+        self.text.push_str(number);
+    }
+
+    fn push_text_url(&mut self, text: &str, url: &str) {
+        self.entities.push(
+            MessageEntityTextUrl {
+                offset: utf16_len(&self.text),
+                length: utf16_len(text),
+                url: url.to_string(),
+            }
+            .into(),
+        );
+        self.text.push_str(text);
+    }
+}
+
+fn utf16_len(text: &str) -> i32 {
+    text.encode_utf16().count() as i32
+}
+
+/// Loads every message from a Telegram Desktop export.
+///
+/// `path` can point to a `result.json` file, a single exported HTML file or
+/// an export directory containing either of them.
+pub fn load(path: &Path) -> Result<Vec<ExportedMessage>> {
+    if path.is_dir() {
+        let json = path.join("result.json");
+        if json.is_file() {
+            return load_json(&json);
+        }
+
+        let mut files = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_html(path))
+            .collect::<Vec<_>>();
+        files.sort_by_key(|path| html_page_number(path));
+
+        if files.is_empty() {
+            return Err(eyre::eyre!(
+                "{} does not contain a result.json or any exported HTML files",
+                path.display()
+            ));
+        }
+
+        load_html(&files)
+    } else if is_html(path) {
+        load_html(&[path.to_path_buf()])
+    } else {
+        load_json(path)
+    }
+}
+
+/// Name used for the output file of an export crawl.
+pub fn name(path: &Path) -> String {
+    let path = if path.is_file() && path.file_name() == Some("result.json".as_ref()) {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    };
+
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "export".to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ResultJson {
+    Chat(ChatExport),
+    Account { chats: ChatList },
+}
+
+#[derive(Deserialize)]
+struct ChatList {
+    list: Vec<ChatExport>,
+}
+
+#[derive(Deserialize)]
+struct ChatExport {
+    messages: Vec<JsonMessage>,
+}
+
+#[derive(Deserialize)]
+struct JsonMessage {
+    #[serde(default)]
+    text: JsonText,
+    /// Exported as a string, e.g. `"1612137600"`.
+    date_unixtime: Option<String>,
+    #[serde(default)]
+    reactions: Vec<JsonReaction>,
+}
+
+#[derive(Deserialize)]
+struct JsonReaction {
+    count: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonText {
+    Plain(String),
+    Parts(Vec<JsonTextPart>),
+}
+
+impl Default for JsonText {
+    fn default() -> Self {
+        Self::Plain(String::new())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonTextPart {
+    Plain(String),
+    Entity {
+        #[serde(rename = "type")]
+        type_: String,
+        text: String,
+        /// Url of a `text_link`.
+        href: Option<String>,
+    },
+}
+
+impl From<JsonMessage> for ExportedMessage {
+    fn from(message: JsonMessage) -> Self {
+        let date = message.date_unixtime.and_then(|date| date.parse().ok());
+        let reactions = message.reactions.iter().map(|r| r.count).sum();
+
+        match message.text {
+            JsonText::Plain(text) => Self {
+                text,
+                entities: Vec::new(),
+                date,
+                reactions,
+            },
+            JsonText::Parts(parts) => {
+                let mut message = Self {
+                    text: String::new(),
+                    entities: Vec::new(),
+                    date,
+                    reactions,
+                };
+
+                for part in parts {
+                    match part {
+                        JsonTextPart::Plain(plain) => message.text.push_str(&plain),
+                        JsonTextPart::Entity { type_, text, .. } if type_ == "mention" => {
+                            message.push_mention(&text)
+                        }
+                        JsonTextPart::Entity { type_, text, .. } if type_ == "phone" => {
+                            message.push_phone(&text)
+                        }
+                        JsonTextPart::Entity {
+                            text,
+                            href: Some(href),
+                            ..
+                        } => message.push_text_url(&text, &href),
+                        JsonTextPart::Entity { text, .. } => message.text.push_str(&text),
+                    }
+                }
+
+                message
+            }
+        }
+    }
+}
+
+fn load_json(path: &Path) -> Result<Vec<ExportedMessage>> {
+    let contents = fs::read_to_string(path)?;
+    let export: ResultJson = serde_json::from_str(&contents)?;
+
+    let chats = match export {
+        ResultJson::Chat(chat) => vec![chat],
+        ResultJson::Account { chats } => chats.list,
+    };
+
+    Ok(chats
+        .into_iter()
+        .flat_map(|chat| chat.messages)
+        .map(ExportedMessage::from)
+        .collect())
+}
+
+fn load_html(files: &[PathBuf]) -> Result<Vec<ExportedMessage>> {
+    let text_regex = Regex::new(r#"(?s)<div class="text">(.*?)</div>"#).unwrap();
+    let part_regex = Regex::new(
+        r#"<a [^>]*>(@[a-zA-Z0-9_]+)</a>|<a [^>]*href="([^"]*)"[^>]*>([^<]*)</a>|<[^>]+>|[^<]+"#,
+    )
+    .unwrap();
+    let date_regex = Regex::new(r#"<div class="pull_right date details" title="([^"]+)""#).unwrap();
+
+    let mut messages = Vec::new();
+    for file in files {
+        let contents = fs::read_to_string(file)?;
+        let dates: Vec<_> = date_regex
+            .captures_iter(&contents)
+            .map(|captures| {
+                (
+                    captures.get(0).unwrap().start(),
+                    parse_html_date(&captures[1]),
+                )
+            })
+            .collect();
+
+        for captures in text_regex.captures_iter(&contents) {
+            // The date of a message comes right before its text.
+            let start = captures.get(0).unwrap().start();
+            let date = dates
+                .iter()
+                .take_while(|(offset, _)| *offset < start)
+                .last()
+                .and_then(|(_, date)| *date);
+
+            let html = captures[1].trim().replace("<br>", "\n");
+            let mut message = ExportedMessage {
+                text: String::new(),
+                entities: Vec::new(),
+                date,
+                // Reactions are only read from JSON exports.
+                reactions: 0,
+            };
+
+            for part in part_regex.captures_iter(&html) {
+                if let Some(mention) = part.get(1) {
+                    message.push_mention(mention.as_str());
+                } else if let (Some(href), Some(text)) = (part.get(2), part.get(3)) {
+                    message.push_text_url(
+                        &unescape_html(text.as_str()),
+                        &unescape_html(href.as_str()),
+                    );
+                } else if !part[0].starts_with('<') {
+                    message.text.push_str(&unescape_html(&part[0]));
+                }
+            }
+
+            messages.push(message);
+        }
+    }
+
+    Ok(messages)
+}
+
+fn is_html(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "html")
+}
+
+/// Telegram splits HTML exports into `messages.html`, `messages2.html`, ...
+fn html_page_number(path: &Path) -> usize {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.trim_start_matches("messages").parse().ok())
+        .unwrap_or(1)
+}
+
+/// Parses the `12.03.2021 15:04:05 UTC+03:00` dates of HTML exports into
+/// Unix timestamps.
+fn parse_html_date(date: &str) -> Option<i64> {
+    let (day, rest) = date.split_once('.')?;
+    let (month, rest) = rest.split_once('.')?;
+    let (year, rest) = rest.split_once(' ')?;
+    let (time, zone) = rest.split_once(' ').unwrap_or((rest, "UTC"));
+
+    let mut time = time.split(':').map(str::parse::<i64>);
+    let seconds = time.next()?.ok()? * 3600 + time.next()?.ok()? * 60 + time.next()?.ok()?;
+
+    let offset = match zone.strip_prefix("UTC") {
+        Some("") => 0,
+        Some(offset) => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+        }
+        None => return None,
+    };
+
+    let days = days_from_civil(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    Some(days * 86400 + seconds - offset)
+}
+
+/// Days between 1970-01-01 and the given date of the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_text_parts() {
+        let message: JsonMessage = serde_json::from_str(
+            r#"{"text": ["join ", {"type": "mention", "text": "@grammers"}, " or ", {"type": "link", "text": "https://t.me/rampilo"}]}"#,
+        )
+        .unwrap();
+        let message = ExportedMessage::from(message);
+
+        assert_eq!(message.text, "join @grammers or https://t.me/rampilo");
+        assert_eq!(
+            message.entities,
+            vec![MessageEntityMention {
+                offset: 5,
+                length: 9
+            }
+            .into()]
+        );
+    }
+
+    #[test]
+    fn test_account_export() {
+        let export: ResultJson = serde_json::from_str(
+            r#"{"chats": {"list": [{"name": "a", "messages": [{"text": "hi"}]}]}}"#,
+        )
+        .unwrap();
+        assert!(matches!(export, ResultJson::Account { .. }));
+    }
+
+    #[test]
+    fn test_text_links() {
+        let message: JsonMessage = serde_json::from_str(
+            r#"{"text": ["see ", {"type": "text_link", "text": "our group", "href": "https://t.me/rampilo"}]}"#,
+        )
+        .unwrap();
+        let message = ExportedMessage::from(message);
+        assert_eq!(message.text, "see our group");
+        assert_eq!(
+            message.entities,
+            vec![MessageEntityTextUrl {
+                offset: 4,
+                length: 9,
+                url: "https://t.me/rampilo".to_string()
+            }
+            .into()]
+        );
+
+        let path = std::env::temp_dir().join("rampilo-test-text-links.html");
+        fs::write(
+            &path,
+            r#"<div class="text">see <a href="https://t.me/rampilo?a=1&amp;b=2">our group</a> or <a href="" onclick="return ShowMentionName()">@grammers</a></div>"#,
+        )
+        .unwrap();
+        let messages = load_html(std::slice::from_ref(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(messages[0].text, "see our group or @grammers");
+        assert_eq!(
+            messages[0].entities,
+            vec![
+                MessageEntityTextUrl {
+                    offset: 4,
+                    length: 9,
+                    url: "https://t.me/rampilo?a=1&b=2".to_string()
+                }
+                .into(),
+                MessageEntityMention {
+                    offset: 17,
+                    length: 9
+                }
+                .into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_dates() {
+        let message: JsonMessage =
+            serde_json::from_str(r#"{"text": "hi", "date_unixtime": "1612137600"}"#).unwrap();
+        assert_eq!(ExportedMessage::from(message).date, Some(1612137600));
+
+        assert_eq!(
+            parse_html_date("01.02.2021 03:00:00 UTC+03:00"),
+            Some(1612137600)
+        );
+        assert_eq!(parse_html_date("01.02.2021 00:00:00"), Some(1612137600));
+        assert_eq!(parse_html_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_message_reactions() {
+        let message: JsonMessage = serde_json::from_str(
+            r#"{"text": "hi", "reactions": [{"type": "emoji", "count": 5, "emoji": "👍"}, {"type": "paid", "count": 2}]}"#,
+        )
+        .unwrap();
+        assert_eq!(ExportedMessage::from(message).reactions, 7);
+    }
+
+    #[test]
+    fn test_html_page_number() {
+        assert_eq!(html_page_number(Path::new("messages.html")), 1);
+        assert_eq!(html_page_number(Path::new("messages12.html")), 12);
+    }
+}
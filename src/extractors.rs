@@ -0,0 +1,906 @@
+use std::{collections::HashSet, fmt};
+
+use clap::ValueEnum;
+use grammers_tl_types::{enums::MessageEntity, types};
+use regex::Regex;
+
+use crate::{tme, LinkType};
+
+/// Text of a message together with its formatting entities and the link
+/// preview it was sent with. Messages fetched from Telegram and read from
+/// exports are both turned into one, so extraction never depends on where
+/// a message came from.
+pub struct MessageText<'a> {
+    pub text: &'a str,
+    pub entities: &'a [MessageEntity],
+    /// Url of the link preview, which the sender may have removed from the
+    /// text.
+    pub preview: Option<&'a str>,
+}
+
+impl<'a> MessageText<'a> {
+    /// Text without entities or link preview.
+    pub fn plain(text: &'a str) -> Self {
+        Self {
+            text,
+            entities: &[],
+            preview: None,
+        }
+    }
+}
+
+/// Builds the text and entities of a message piece by piece, counting the
+/// entity offsets in UTF-16 code units like Telegram does. Meant for tests
+/// and fuzzing, where writing the offsets by hand is error prone.
+///
+/// ```
+/// use rampilo::{extractors::{Kind, MessageBuilder, Pipeline}, LinkType};
+///
+/// let message = MessageBuilder::new()
+///     .text("🙂 ask ")
+///     .mention("grammers")
+///     .text(" or ")
+///     .text_url("here", "https://t.me/rampilo");
+/// assert_eq!(
+///     Pipeline::new(&Kind::DEFAULT).extract(&message.message()),
+///     vec![
+///         LinkType::Mention("grammers".to_string()),
+///         LinkType::Username("rampilo".to_string()),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MessageBuilder {
+    text: String,
+    entities: Vec<MessageEntity>,
+    preview: Option<String>,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends plain text.
+    pub fn text(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self
+    }
+
+    /// Appends `@username` as a mention.
+    pub fn mention(self, username: &str) -> Self {
+        let (offset, length) = self.span(&format!("@{username}"));
+        let entity = types::MessageEntityMention { offset, length };
+        self.push(&format!("@{username}"), entity.into())
+    }
+
+    /// Appends `name` as a mention of the user with `user_id`.
+    pub fn mention_name(self, name: &str, user_id: i64) -> Self {
+        let (offset, length) = self.span(name);
+        let entity = types::MessageEntityMentionName {
+            offset,
+            length,
+            user_id,
+        };
+        self.push(name, entity.into())
+    }
+
+    /// Appends `number` as a phone number.
+    pub fn phone(self, number: &str) -> Self {
+        let (offset, length) = self.span(number);
+        let entity = types::MessageEntityPhone { offset, length };
+        self.push(number, entity.into())
+    }
+
+    /// Appends `text` hyperlinked to `url`.
+    pub fn text_url(self, text: &str, url: &str) -> Self {
+        let (offset, length) = self.span(text);
+        let entity = types::MessageEntityTextUrl {
+            offset,
+            length,
+            url: url.to_string(),
+        };
+        self.push(text, entity.into())
+    }
+
+    /// Sets the url of the link preview.
+    pub fn preview(mut self, url: &str) -> Self {
+        self.preview = Some(url.to_string());
+        self
+    }
+
+    pub fn message(&self) -> MessageText<'_> {
+        MessageText {
+            text: &self.text,
+            entities: &self.entities,
+            preview: self.preview.as_deref(),
+        }
+    }
+
+    /// Offset and length of `text` once appended, in UTF-16 code units.
+    fn span(&self, text: &str) -> (i32, i32) {
+        let offset = self.text.encode_utf16().count() as i32;
+        (offset, text.encode_utf16().count() as i32)
+    }
+
+    fn push(mut self, text: &str, entity: MessageEntity) -> Self {
+        self.text.push_str(text);
+        self.entities.push(entity);
+        self
+    }
+}
+
+/// Text of a message indexed by UTF-16 code units, which Telegram counts
+/// entity offsets and lengths in.
+pub struct Utf16Text {
+    units: Vec<u16>,
+}
+
+impl Utf16Text {
+    pub fn new(text: &str) -> Self {
+        Self {
+            units: text.encode_utf16().collect(),
+        }
+    }
+
+    /// The text covered by an entity at `offset`, `length` units long.
+    /// Buggy clients and hand-made exports can send offsets that don't fit
+    /// the text, which are errors rather than panics.
+    pub fn slice(&self, offset: i32, length: i32) -> Result<String, OffsetError> {
+        let (Ok(start), Ok(length)) = (usize::try_from(offset), usize::try_from(length)) else {
+            return Err(OffsetError::Negative);
+        };
+        let units = start
+            .checked_add(length)
+            .and_then(|end| self.units.get(start..end))
+            .ok_or(OffsetError::OutOfBounds)?;
+        String::from_utf16(units).map_err(|_| OffsetError::SplitsCharacter)
+    }
+}
+
+/// Why an entity doesn't fit the text of its message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetError {
+    /// The offset or length is negative.
+    Negative,
+    /// The entity ends after the text.
+    OutOfBounds,
+    /// The entity starts or ends in the middle of a character.
+    SplitsCharacter,
+}
+
+impl fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OffsetError::Negative => "negative entity offset or length",
+            OffsetError::OutOfBounds => "entity ends after the text",
+            OffsetError::SplitsCharacter => "entity splits a character",
+        })
+    }
+}
+
+impl std::error::Error for OffsetError {}
+
+/// The text covered by an entity, skipping entities that don't fit.
+fn entity_text(text: &Utf16Text, offset: i32, length: i32) -> Option<String> {
+    text.slice(offset, length)
+        .inspect_err(|e| tracing::debug!(offset, length, error = %e, "skipping entity"))
+        .ok()
+}
+
+/// Finds one kind of link in a message.
+pub trait Extractor: Send + Sync {
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType>;
+}
+
+/// The built-in extractors, as selected with `--extract`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Kind {
+    /// `t.me/<username>` links, also on telegram.me and telegram.dog and as
+    /// `t.me/s/<username>` web previews
+    Links,
+    /// `t.me/+<hash>` and `t.me/joinchat/<hash>` invite links
+    Hashes,
+    /// `t.me/addlist/<slug>` chat folder links
+    Folders,
+    /// `t.me/proxy` and `t.me/socks` proxy links
+    Proxies,
+    /// `t.me/invoice/<slug>` and `t.me/$<slug>` payment links
+    Invoices,
+    /// `t.me/addstickers`, `t.me/addemoji` and `t.me/addtheme` links
+    Stickers,
+    /// `t.me/<username>/s/<id>` story links and `t.me/boost/<username>`
+    /// links of channels gathering boosts for giveaways
+    Stories,
+    /// `@username` mentions and mentions of users by name
+    Mentions,
+    /// `#hashtags`
+    Hashtags,
+    /// Phone numbers Telegram marks in the text
+    Phones,
+    /// Links to any site other than t.me
+    Urls,
+}
+
+impl Kind {
+    pub const DEFAULT: [Kind; 3] = [Kind::Links, Kind::Hashes, Kind::Mentions];
+
+    /// Every extractor of Telegram links that works on plain text.
+    pub const TELEGRAM: [Kind; 7] = [
+        Kind::Links,
+        Kind::Hashes,
+        Kind::Folders,
+        Kind::Proxies,
+        Kind::Invoices,
+        Kind::Stickers,
+        Kind::Stories,
+    ];
+
+    fn extractor(self) -> Box<dyn Extractor> {
+        match self {
+            Kind::Links => telegram(|link| matches!(link, LinkType::Username(_))),
+            Kind::Hashes => telegram(|link| matches!(link, LinkType::Hash(_))),
+            Kind::Folders => telegram(|link| matches!(link, LinkType::Folder(_))),
+            Kind::Proxies => telegram(|link| matches!(link, LinkType::Proxy(_))),
+            Kind::Invoices => telegram(|link| matches!(link, LinkType::Invoice(_))),
+            Kind::Stickers => telegram(|link| {
+                matches!(
+                    link,
+                    LinkType::StickerSet(_) | LinkType::EmojiSet(_) | LinkType::Theme(_)
+                )
+            }),
+            Kind::Stories => {
+                telegram(|link| matches!(link, LinkType::Story(_) | LinkType::Boost(_)))
+            }
+            Kind::Mentions => Box::new(Mentions),
+            Kind::Hashtags => Box::new(Hashtags::new()),
+            Kind::Phones => Box::new(Phones),
+            Kind::Urls => Box::new(Urls::new()),
+        }
+    }
+}
+
+fn telegram(keep: fn(&LinkType) -> bool) -> Box<dyn Extractor> {
+    Box::new(Telegram { keep })
+}
+
+/// The set of enabled extractors every message is run through.
+pub struct Pipeline {
+    extractors: Vec<Box<dyn Extractor>>,
+    usernames: UsernameFilter,
+}
+
+impl Pipeline {
+    pub fn new(kinds: &[Kind]) -> Self {
+        let mut kinds = kinds.to_vec();
+        kinds.sort();
+        kinds.dedup();
+
+        Self {
+            extractors: kinds.into_iter().map(Kind::extractor).collect(),
+            usernames: UsernameFilter::default(),
+        }
+    }
+
+    /// Drops the usernames and mentions `filter` doesn't keep.
+    pub fn usernames(mut self, filter: UsernameFilter) -> Self {
+        self.usernames = filter;
+        self
+    }
+
+    /// Runs every extractor over the message, and over the urls hidden
+    /// behind hyperlinked text or in a link preview, which aren't part of
+    /// the text itself.
+    pub fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        let mut links: Vec<_> = self
+            .extractors
+            .iter()
+            .flat_map(|extractor| extractor.extract(message))
+            .collect();
+
+        let hidden = hidden_urls(message);
+        if !hidden.is_empty() {
+            let hidden = MessageText::plain(&hidden);
+            links.extend(
+                self.extractors
+                    .iter()
+                    .flat_map(|extractor| extractor.extract(&hidden)),
+            );
+        }
+        links.retain(|link| self.usernames.keeps(link));
+        links
+    }
+}
+
+/// Usernames a [`Pipeline`] leaves out, such as the crawler's own channels
+/// or common bots, or the only ones it keeps. Links other than usernames
+/// and mentions are kept either way.
+#[derive(Debug, Default, Clone)]
+pub struct UsernameFilter {
+    ignore: HashSet<String>,
+    only: Option<HashSet<String>>,
+}
+
+impl UsernameFilter {
+    /// Reads a list of usernames, one a line, with or without an `@` or
+    /// `https://t.me/` in front. Blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn parse_list(list: &str) -> HashSet<String> {
+        list.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let username = ["https://t.me/", "t.me/", "@"]
+                    .iter()
+                    .find_map(|prefix| line.strip_prefix(prefix))
+                    .unwrap_or(line);
+                username.to_lowercase()
+            })
+            .collect()
+    }
+
+    /// Leaves out these lowercase usernames.
+    pub fn ignore(mut self, usernames: HashSet<String>) -> Self {
+        self.ignore = usernames;
+        self
+    }
+
+    /// Keeps only these lowercase usernames.
+    pub fn only(mut self, usernames: HashSet<String>) -> Self {
+        self.only = Some(usernames);
+        self
+    }
+
+    fn keeps(&self, link: &LinkType) -> bool {
+        let (LinkType::Username(username) | LinkType::Mention(username)) = link else {
+            return true;
+        };
+        let username = username.to_lowercase();
+        !self.ignore.contains(&username)
+            && self
+                .only
+                .as_ref()
+                .is_none_or(|only| only.contains(&username))
+    }
+}
+
+/// The urls of the `TextUrl` entities and the link preview that don't show
+/// in the text, one per line.
+fn hidden_urls(message: &MessageText<'_>) -> String {
+    let mut urls: Vec<_> = message
+        .entities
+        .iter()
+        .filter_map(|entity| match entity {
+            MessageEntity::TextUrl(e) => Some(e.url.as_str()),
+            _ => None,
+        })
+        .collect();
+    if let Some(preview) = message.preview {
+        if !urls.contains(&preview) {
+            urls.push(preview);
+        }
+    }
+    urls.retain(|url| !message.text.contains(url));
+    urls.join("\n")
+}
+
+/// The t.me and `tg://` links of the types `keep` selects.
+struct Telegram {
+    keep: fn(&LinkType) -> bool,
+}
+
+impl Extractor for Telegram {
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        tme::links(message.text)
+            .filter(|link| (self.keep)(link))
+            .collect()
+    }
+}
+
+struct Mentions;
+
+impl Extractor for Mentions {
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        let text = Utf16Text::new(message.text);
+
+        message
+            .entities
+            .iter()
+            .filter_map(|entity| match entity {
+                MessageEntity::Mention(e) => {
+                    let username = entity_text(&text, e.offset, e.length)?;
+                    let username = username.trim_start_matches('@').trim().to_lowercase();
+                    Some(LinkType::Mention(username))
+                }
+                // Mentions by name link to the user's id, not to a username.
+                MessageEntity::MentionName(e) => Some(LinkType::UserId(e.user_id)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct Hashtags {
+    regex: Regex,
+}
+
+impl Hashtags {
+    fn new() -> Self {
+        Self {
+            // A hashtag needs at least one non-digit, so "issue #1" isn't one.
+            regex: Regex::new(r"(?:^|[^\w&])#(\w*[^\W\d]\w*)").unwrap(),
+        }
+    }
+}
+
+impl Extractor for Hashtags {
+    /// Takes the hashtags Telegram marked, or finds them in the text of
+    /// messages without entities for them.
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        let marked: Vec<_> = message
+            .entities
+            .iter()
+            .filter_map(|entity| match entity {
+                MessageEntity::Hashtag(e) => Some((e.offset, e.length)),
+                _ => None,
+            })
+            .collect();
+        if marked.is_empty() {
+            return self
+                .regex
+                .captures_iter(message.text)
+                .map(|captures| LinkType::Hashtag(captures[1].to_lowercase()))
+                .collect();
+        }
+
+        let text = Utf16Text::new(message.text);
+        marked
+            .into_iter()
+            .filter_map(|(offset, length)| entity_text(&text, offset, length))
+            .map(|hashtag| hashtag.trim_start_matches('#').to_lowercase())
+            .filter(|hashtag| !hashtag.is_empty())
+            .map(LinkType::Hashtag)
+            .collect()
+    }
+}
+
+struct Phones;
+
+impl Extractor for Phones {
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        let text = Utf16Text::new(message.text);
+
+        message
+            .entities
+            .iter()
+            .filter_map(|entity| match entity {
+                MessageEntity::Phone(e) => entity_text(&text, e.offset, e.length),
+                _ => None,
+            })
+            .filter_map(|number| {
+                // Spaces, dashes and brackets are only formatting.
+                let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+                let plus = if number.trim_start().starts_with('+') {
+                    "+"
+                } else {
+                    ""
+                };
+                (!digits.is_empty()).then(|| LinkType::Phone(format!("{plus}{digits}")))
+            })
+            .collect()
+    }
+}
+
+struct Urls {
+    regex: Regex,
+}
+
+impl Urls {
+    fn new() -> Self {
+        Self {
+            regex: Regex::new(r#"https?://([^\s/<>"']+)[^\s<>"']*"#).unwrap(),
+        }
+    }
+}
+
+impl Extractor for Urls {
+    fn extract(&self, message: &MessageText<'_>) -> Vec<LinkType> {
+        self.regex
+            .captures_iter(message.text)
+            .filter(|captures| {
+                let domain = captures[1].to_lowercase();
+                !tme::HOSTS.contains(&domain.as_str())
+            })
+            .map(|captures| {
+                let url = captures[0].trim_end_matches(['.', ',', ')', '!', '?', ';', ':']);
+                LinkType::Url(url.to_string())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammers_tl_types::types::{
+        MessageEntityHashtag, MessageEntityMention, MessageEntityMentionName, MessageEntityTextUrl,
+    };
+
+    use super::*;
+
+    fn text(text: &str) -> MessageText<'_> {
+        MessageText::plain(text)
+    }
+
+    fn extract(link: &str) -> Option<LinkType> {
+        let message = text(link);
+
+        Kind::Links
+            .extractor()
+            .extract(&message)
+            .into_iter()
+            .chain(Kind::Hashes.extractor().extract(&message))
+            .next()
+    }
+
+    #[test]
+    fn test_extract_username() {
+        let link = "https://t.me/grammers";
+        let username = extract(link);
+        assert_eq!(username, Some(LinkType::Username("grammers".to_string())));
+    }
+
+    #[test]
+    fn test_extract_username_with_query() {
+        let link = "https://t.me/grammers?start=123";
+        let username = extract(link);
+        assert_eq!(username, Some(LinkType::Username("grammers".to_string())));
+    }
+
+    #[test]
+    fn test_joined_username() {
+        let link = "https://t.me/joinchat/USpx-sviNKIj408g";
+        let username = extract(link);
+        assert_eq!(
+            username,
+            Some(LinkType::Hash("USpx-sviNKIj408g".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invite_link() {
+        let link = "https://t.me/+_DGX2NIt9IhkNTVk";
+        let username = extract(link);
+        assert_eq!(
+            username,
+            Some(LinkType::Hash("_DGX2NIt9IhkNTVk".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_other_domains() {
+        let username = Some(LinkType::Username("grammers".to_string()));
+        assert_eq!(extract("https://t.me/s/grammers"), username);
+        assert_eq!(extract("https://telegram.me/grammers/12"), username);
+        assert_eq!(extract("https://telegram.dog/s/grammers"), username);
+        assert_eq!(extract("https://t.me/s"), None);
+        assert_eq!(
+            extract("https://telegram.me/joinchat/USpx-sviNKIj408g"),
+            Some(LinkType::Hash("USpx-sviNKIj408g".to_string()))
+        );
+        assert!(Urls::new()
+            .extract(&text(
+                "https://telegram.dog/grammers https://t.me/s/grammers"
+            ))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_tg_links() {
+        assert_eq!(
+            extract("tg://resolve?domain=grammers&start=1"),
+            Some(LinkType::Username("grammers".to_string()))
+        );
+        assert_eq!(
+            extract("tg://join?invite=USpx-sviNKIj408g"),
+            Some(LinkType::Hash("USpx-sviNKIj408g".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_folders() {
+        let folders = Kind::Folders
+            .extractor()
+            .extract(&text("https://t.me/addlist/AbCd-123 tg://addlist?slug=xyz"));
+        assert_eq!(
+            folders,
+            vec![
+                LinkType::Folder("AbCd-123".to_string()),
+                LinkType::Folder("xyz".to_string())
+            ]
+        );
+        assert!(Kind::Links
+            .extractor()
+            .extract(&text("https://t.me/addlist/AbCd-123"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_proxies() {
+        let proxies = Kind::Proxies.extractor().extract(&text(
+            "https://t.me/proxy?server=1.2.3.4&port=443&secret=ee00, \
+             tg://socks?server=example.org&port=1080 https://t.me/proxy?port=1",
+        ));
+        assert_eq!(
+            proxies,
+            vec![
+                LinkType::Proxy("1.2.3.4:443".to_string()),
+                LinkType::Proxy("example.org:1080".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invoices() {
+        let invoices = Kind::Invoices.extractor().extract(&text(
+            "pay https://t.me/$Ab-1 or https://t.me/invoice/xyz_2, tg://invoice?slug=q",
+        ));
+        assert_eq!(
+            invoices,
+            vec![
+                LinkType::Invoice("Ab-1".to_string()),
+                LinkType::Invoice("xyz_2".to_string()),
+                LinkType::Invoice("q".to_string())
+            ]
+        );
+        assert!(Kind::Links
+            .extractor()
+            .extract(&text("https://t.me/invoice/xyz_2"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stickers() {
+        let links = Kind::Stickers.extractor().extract(&text(
+            "https://t.me/addstickers/Cats_1 https://t.me/addemoji/Blobs \
+             tg://addtheme?slug=night-sky tg://addstickers?set=Dogs",
+        ));
+        assert_eq!(
+            links,
+            vec![
+                LinkType::StickerSet("Cats_1".to_string()),
+                LinkType::EmojiSet("Blobs".to_string()),
+                LinkType::Theme("night-sky".to_string()),
+                LinkType::StickerSet("Dogs".to_string())
+            ]
+        );
+        assert!(Kind::Links
+            .extractor()
+            .extract(&text("https://t.me/addstickers/Cats_1"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stories() {
+        let links = Kind::Stories.extractor().extract(&text(
+            "https://t.me/durov/s/12 https://t.me/boost/Codenight \
+             tg://resolve?domain=rust&story=3 tg://boost?domain=tokio tg://resolve?domain=rust",
+        ));
+        assert_eq!(
+            links,
+            vec![
+                LinkType::Story("durov/12".to_string()),
+                LinkType::Boost("Codenight".to_string()),
+                LinkType::Story("rust/3".to_string()),
+                LinkType::Boost("tokio".to_string())
+            ]
+        );
+        assert!(Kind::Links
+            .extractor()
+            .extract(&text("https://t.me/boost/Codenight"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mentions() {
+        let entities = [
+            MessageEntityMention {
+                offset: 3,
+                length: 9,
+            }
+            .into(),
+            MessageEntityMentionName {
+                offset: 17,
+                length: 4,
+                user_id: 777000,
+            }
+            .into(),
+        ];
+        let message = MessageText {
+            text: "🙂 @Grammers! and Lonami",
+            entities: &entities,
+            preview: None,
+        };
+
+        assert_eq!(
+            Mentions.extract(&message),
+            vec![
+                LinkType::Mention("grammers".to_string()),
+                LinkType::UserId(777000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_utf16_slice() {
+        let text = Utf16Text::new("🙂 @grammers");
+
+        assert_eq!(text.slice(3, 9).as_deref(), Ok("@grammers"));
+        assert_eq!(text.slice(0, 2).as_deref(), Ok("🙂"));
+        assert_eq!(text.slice(-1, 2), Err(OffsetError::Negative));
+        assert_eq!(text.slice(3, -9), Err(OffsetError::Negative));
+        assert_eq!(text.slice(3, 10), Err(OffsetError::OutOfBounds));
+        assert_eq!(
+            text.slice(i32::MAX, i32::MAX),
+            Err(OffsetError::OutOfBounds)
+        );
+        assert_eq!(text.slice(1, 2), Err(OffsetError::SplitsCharacter));
+        assert_eq!(text.slice(0, 1), Err(OffsetError::SplitsCharacter));
+    }
+
+    #[test]
+    fn test_malformed_entities() {
+        let entities = [
+            MessageEntityMention {
+                offset: -5,
+                length: 9,
+            }
+            .into(),
+            MessageEntityMention {
+                offset: 1,
+                length: i32::MAX,
+            }
+            .into(),
+            MessageEntityMention {
+                offset: 1,
+                length: 9,
+            }
+            .into(),
+        ];
+        let message = MessageText {
+            text: "🙂@grammers",
+            entities: &entities,
+            preview: None,
+        };
+
+        assert!(Mentions.extract(&message).is_empty());
+    }
+
+    #[test]
+    fn test_marked_hashtags() {
+        let entities = [MessageEntityHashtag {
+            offset: 3,
+            length: 5,
+        }
+        .into()];
+        let message = MessageText {
+            text: "🙂 #Rust #unmarked",
+            entities: &entities,
+            preview: None,
+        };
+
+        assert_eq!(
+            Hashtags::new().extract(&message),
+            vec![LinkType::Hashtag("rust".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_phones() {
+        let message = MessageBuilder::new()
+            .text("call ")
+            .phone("+1 (555) 010-9999")
+            .text(" or ")
+            .phone("555 0100");
+
+        assert_eq!(
+            Phones.extract(&message.message()),
+            vec![
+                LinkType::Phone("+15550109999".to_string()),
+                LinkType::Phone("5550100".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hidden_urls() {
+        let entities = [
+            MessageEntityTextUrl {
+                offset: 0,
+                length: 4,
+                url: "https://t.me/grammers".to_string(),
+            }
+            .into(),
+            MessageEntityTextUrl {
+                offset: 10,
+                length: 21,
+                url: "https://t.me/rampilo".to_string(),
+            }
+            .into(),
+        ];
+        let message = MessageText {
+            text: "join here: https://t.me/rampilo",
+            entities: &entities,
+            preview: None,
+        };
+
+        assert_eq!(
+            Pipeline::new(&Kind::DEFAULT).extract(&message),
+            vec![
+                LinkType::Username("rampilo".to_string()),
+                LinkType::Username("grammers".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hashtags() {
+        let hashtags = Hashtags::new().extract(&text("#Rust and issue #1 a#b #tg_bots"));
+        assert_eq!(
+            hashtags,
+            vec![
+                LinkType::Hashtag("rust".to_string()),
+                LinkType::Hashtag("tg_bots".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_urls() {
+        let urls = Urls::new().extract(&text(
+            "see https://example.com/a?b=1, https://t.me/grammers and (http://foo.org).",
+        ));
+        assert_eq!(
+            urls,
+            vec![
+                LinkType::Url("https://example.com/a?b=1".to_string()),
+                LinkType::Url("http://foo.org".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let pipeline = Pipeline::new(&[Kind::Hashes]);
+        let links = pipeline.extract(&text("https://t.me/grammers https://t.me/+abc"));
+        assert_eq!(links, vec![LinkType::Hash("abc".to_string())]);
+    }
+
+    #[test]
+    fn test_username_filter() {
+        let list = UsernameFilter::parse_list("# bots\n@BotFather\n\nhttps://t.me/SpamBot\n");
+        assert_eq!(
+            list,
+            HashSet::from(["botfather".to_string(), "spambot".to_string()])
+        );
+
+        let message = MessageBuilder::new()
+            .mention("BotFather")
+            .text(" https://t.me/grammers https://t.me/+abc");
+        let links = |filter: UsernameFilter| {
+            Pipeline::new(&Kind::DEFAULT)
+                .usernames(filter)
+                .extract(&message.message())
+        };
+        let grammers = LinkType::Username("grammers".to_string());
+        let hash = LinkType::Hash("abc".to_string());
+
+        assert_eq!(
+            links(UsernameFilter::default().ignore(list)),
+            vec![grammers.clone(), hash.clone()]
+        );
+        assert_eq!(
+            links(UsernameFilter::default().only(HashSet::from(["grammers".to_string()]))),
+            vec![grammers, hash]
+        );
+    }
+}
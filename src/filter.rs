@@ -0,0 +1,47 @@
+use regex::Regex;
+
+/// Decides which messages are scanned for links. Without any keywords or
+/// patterns every message is.
+pub struct Filter {
+    keywords: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl Filter {
+    pub fn new(keywords: &[String], regexes: &[Regex]) -> Self {
+        Self {
+            keywords: keywords.iter().map(|k| k.to_lowercase()).collect(),
+            regexes: regexes.to_vec(),
+        }
+    }
+
+    /// Whether the message contains any of the keywords (ignoring case) or
+    /// matches any of the patterns.
+    pub fn matches(&self, text: &str) -> bool {
+        if self.keywords.is_empty() && self.regexes.is_empty() {
+            return true;
+        }
+
+        let lowercase = text.to_lowercase();
+        self.keywords.iter().any(|k| lowercase.contains(k))
+            || self.regexes.iter().any(|r| r.is_match(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        assert!(Filter::new(&[], &[]).matches("anything"));
+
+        let filter = Filter::new(
+            &["Airdrop".to_string()],
+            &[Regex::new(r"\bgive ?away\b").unwrap()],
+        );
+        assert!(filter.matches("Free AIRDROP today"));
+        assert!(filter.matches("big giveaway"));
+        assert!(!filter.matches("https://t.me/grammers"));
+    }
+}
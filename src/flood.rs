@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use grammers_client::{
+    client::{chats::ParticipantIter, dialogs::DialogIter, messages::InvocationError},
+    types::{Chat, PackedChat},
+    Client,
+};
+use grammers_session::PackedType;
+use grammers_tl_types::{self as tl, Serializable};
+
+use crate::{metrics, passive, ratelimit, stats, usage};
+
+// Every request of a crawl goes through this module, the raw ones through
+// `invoke_once` and those of grammers' own helpers through the wrappers
+// below, so `--strict-passive` sees all of them. Only `login` and the other
+// account commands call the client directly.
+
+/// Invokes the request once, counting it, and refusing it first if passive
+/// mode forbids it.
+pub async fn invoke_once<R>(client: &Client, request: &R) -> Result<R::Return, InvocationError>
+where
+    R: tl::RemoteCall,
+    R::Return: Serializable,
+{
+    passive::check::<R>()?;
+    ratelimit::acquire().await;
+    let result = client.invoke(request).await;
+    if let Err(InvocationError::Rpc(_)) = result {
+        metrics::record_rpc_error();
+    }
+    usage::record(request, &result);
+    result
+}
+
+/// Invokes the request, sleeping off flood waits instead of failing.
+pub async fn invoke<R>(client: &Client, request: &R) -> Result<R::Return, InvocationError>
+where
+    R: tl::RemoteCall,
+    R::Return: Serializable,
+{
+    loop {
+        match invoke_once(client, request).await {
+            Err(InvocationError::Rpc(e)) if e.name == "FLOOD_WAIT" => {
+                let wait = Duration::from_secs(e.value.unwrap_or(1) as u64);
+                tracing::warn!(
+                    method = stats::method_name::<R>(),
+                    wait_secs = wait.as_secs(),
+                    "flood wait"
+                );
+                metrics::record_flood_wait(wait);
+                usage::record_flood_wait(wait);
+                ratelimit::pause(wait);
+                tokio::time::sleep(wait).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Resolves a username with grammers' helper, spacing resolutions out like
+/// the other lookups.
+pub async fn resolve_username(
+    client: &Client,
+    name: &str,
+) -> Result<Option<Chat>, InvocationError> {
+    passive::check::<tl::functions::contacts::ResolveUsername>()?;
+    ratelimit::acquire_resolve().await;
+    usage::record_resolve();
+    ratelimit::acquire().await;
+    client.resolve_username(name).await
+}
+
+/// Iterates over the chats the account is in.
+pub fn iter_dialogs(client: &Client) -> Result<DialogIter, InvocationError> {
+    passive::check::<tl::functions::messages::GetDialogs>()?;
+    Ok(client.iter_dialogs())
+}
+
+/// Iterates over the participants of the chat.
+pub fn iter_participants(client: &Client, chat: &Chat) -> Result<ParticipantIter, InvocationError> {
+    passive::check::<tl::functions::channels::GetParticipants>()?;
+    Ok(client.iter_participants(chat))
+}
+
+/// Fetches the chat a packed chat stands for.
+pub async fn unpack_chat(client: &Client, packed: PackedChat) -> Result<Chat, InvocationError> {
+    match packed.ty {
+        PackedType::User | PackedType::Bot => passive::check::<tl::functions::users::GetUsers>()?,
+        PackedType::Chat => passive::check::<tl::functions::messages::GetChats>()?,
+        PackedType::Megagroup | PackedType::Broadcast | PackedType::Gigagroup => {
+            passive::check::<tl::functions::channels::GetChannels>()?
+        }
+    }
+    ratelimit::acquire().await;
+    client.unpack_chat(packed).await
+}
+
+/// Whether the client's session is signed in.
+pub async fn is_authorized(client: &Client) -> Result<bool, InvocationError> {
+    passive::check::<tl::functions::updates::GetState>()?;
+    client.is_authorized().await
+}
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{self, Result};
+use grammers_client::{types::chat::Chat, Client};
+use grammers_tl_types as tl;
+
+use crate::{flood, target::Target, UsernameType};
+
+/// Chats of the account's chat folder named `title`, ignoring case, as
+/// targets to crawl.
+///
+/// A folder holds the chats added to it by hand, and with its group or
+/// channel options every group or channel the account is in, minus the
+/// chats excluded from it. Its options for people, bots, muted, read and
+/// archived chats aren't applied.
+pub async fn chats(client: &Client, title: &str) -> Result<Vec<Target>> {
+    let filters = flood::invoke(client, &tl::functions::messages::GetDialogFilters {}).await?;
+    let folders: Vec<_> = filters
+        .into_iter()
+        .filter_map(|filter| match filter {
+            tl::enums::DialogFilter::Filter(filter) => Some(filter),
+            tl::enums::DialogFilter::Default => None,
+        })
+        .collect();
+    let Some(folder) = folders.iter().find(|f| f.title.eq_ignore_ascii_case(title)) else {
+        let titles: Vec<_> = folders.iter().map(|f| format!("{:?}", f.title)).collect();
+        eyre::bail!(
+            "The account has no chat folder named {title:?}, its folders are: {}",
+            titles.join(", ")
+        );
+    };
+
+    let mut targets = Vec::new();
+    let mut dialogs = flood::iter_dialogs(client)?;
+    while let Some(dialog) = dialogs.next().await? {
+        let chat = dialog.chat();
+        let type_ = match chat {
+            Chat::User(_) => UsernameType::User,
+            Chat::Group(_) => UsernameType::Group,
+            Chat::Channel(_) => UsernameType::Channel,
+        };
+        if !selects(folder, chat.id(), type_) {
+            continue;
+        }
+        targets.push(match chat.username() {
+            Some(username) => Target::Username(username.to_string()),
+            None => Target::Id(chat.id()),
+        });
+    }
+    tracing::debug!(
+        folder = folder.title,
+        chats = targets.len(),
+        "listed folder"
+    );
+    Ok(targets)
+}
+
+/// Whether the chat with `id` is in the folder.
+fn selects(folder: &tl::types::DialogFilter, id: i64, type_: UsernameType) -> bool {
+    let ids = |peers: &[tl::enums::InputPeer]| -> HashSet<i64> {
+        peers.iter().filter_map(peer_id).collect()
+    };
+
+    if ids(&folder.exclude_peers).contains(&id) {
+        return false;
+    }
+    ids(&folder.include_peers).contains(&id)
+        || ids(&folder.pinned_peers).contains(&id)
+        || match type_ {
+            UsernameType::Group => folder.groups,
+            UsernameType::Channel => folder.broadcasts,
+            UsernameType::User | UsernameType::Website => false,
+        }
+}
+
+fn peer_id(peer: &tl::enums::InputPeer) -> Option<i64> {
+    match peer {
+        tl::enums::InputPeer::Chat(chat) => Some(chat.chat_id),
+        tl::enums::InputPeer::Channel(channel) => Some(channel.channel_id),
+        tl::enums::InputPeer::ChannelFromMessage(channel) => Some(channel.channel_id),
+        tl::enums::InputPeer::User(user) => Some(user.user_id),
+        tl::enums::InputPeer::UserFromMessage(user) => Some(user.user_id),
+        tl::enums::InputPeer::Empty | tl::enums::InputPeer::PeerSelf => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selects() {
+        let channel = |channel_id| {
+            tl::enums::InputPeer::Channel(tl::types::InputPeerChannel {
+                channel_id,
+                access_hash: 0,
+            })
+        };
+        let folder = tl::types::DialogFilter {
+            contacts: false,
+            non_contacts: false,
+            groups: true,
+            broadcasts: false,
+            bots: false,
+            exclude_muted: false,
+            exclude_read: false,
+            exclude_archived: false,
+            id: 2,
+            title: "Research".to_string(),
+            emoticon: None,
+            pinned_peers: vec![channel(1)],
+            include_peers: vec![channel(2)],
+            exclude_peers: vec![channel(3)],
+        };
+
+        assert!(selects(&folder, 1, UsernameType::Channel));
+        assert!(selects(&folder, 2, UsernameType::Channel));
+        assert!(!selects(&folder, 3, UsernameType::Group));
+        assert!(selects(&folder, 4, UsernameType::Group));
+        assert!(!selects(&folder, 5, UsernameType::Channel));
+    }
+}
@@ -0,0 +1,973 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use grammers_client::{client::messages::InvocationError, types::chat::Chat, Client};
+use grammers_tl_types::{self as tl, Serializable};
+
+use crate::{
+    flood, metrics, ratelimit,
+    stats::{self, Stats},
+    usage,
+};
+
+/// Telegram never returns more than 100 messages per `messages.getHistory`.
+pub const MAX_PAGE_SIZE: usize = 100;
+const MIN_PAGE_SIZE: usize = 10;
+const PAGE_SIZE_STEP: usize = 10;
+
+/// Responses slower than this shrink the next page.
+const SLOW_RESPONSE: Duration = Duration::from_secs(2);
+
+/// After this many pages failed in a row the crawl is aborted instead of
+/// skipping yet another page.
+const MAX_CONSECUTIVE_SKIPS: usize = 3;
+
+/// How many times skipped messages are requested again at the end of a crawl.
+pub const RETRY_ATTEMPTS: usize = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// `messages.getMessages` accepts at most this many ids per request.
+const MAX_IDS_PER_REQUEST: usize = 100;
+
+/// When reading by id, this many pages without a single message in a row
+/// are taken as the end of the chat.
+const MAX_EMPTY_PAGES: usize = 5;
+
+/// Size of the next history page, growing while responses are fast and
+/// halving when they slow down or a flood wait is hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageSize {
+    current: usize,
+    max: usize,
+    adaptive: bool,
+}
+
+impl PageSize {
+    pub fn new(max: usize, adaptive: bool) -> Self {
+        let max = max.clamp(1, MAX_PAGE_SIZE);
+        Self {
+            current: max,
+            max,
+            adaptive,
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        self.current
+    }
+
+    fn record_response(&mut self, elapsed: Duration) {
+        if !self.adaptive {
+            return;
+        }
+
+        if elapsed > SLOW_RESPONSE {
+            self.shrink();
+        } else {
+            self.current = (self.current + PAGE_SIZE_STEP).min(self.max);
+        }
+    }
+
+    fn shrink(&mut self) {
+        if self.adaptive {
+            self.current = (self.current / 2).max(MIN_PAGE_SIZE.min(self.max));
+        }
+    }
+}
+
+/// Name of a user who sent or was mentioned in a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub username: Option<String>,
+}
+
+impl Profile {
+    fn of(user: &tl::types::User) -> Self {
+        let name = [user.first_name.as_deref(), user.last_name.as_deref()];
+        Self {
+            name: name.into_iter().flatten().collect::<Vec<_>>().join(" "),
+            username: user.username.clone(),
+        }
+    }
+}
+
+/// Access hash a user mentioned by name can be resolved with later. Min users
+/// come with a hash that only works in this chat.
+fn access_hash(user: &tl::types::User) -> Option<i64> {
+    user.access_hash.filter(|_| !user.min)
+}
+
+/// Iterates over the history of a chat, newest message first.
+///
+/// Unlike grammers' own iterator, the number of messages requested per page
+/// follows [`PageSize`], and flood waits are slept off instead of aborting
+/// the crawl. Pages that fail with a transient error are skipped and their
+/// ids remembered, so they can be fetched again with
+/// [`History::retry_skipped`].
+pub struct History {
+    client: Client,
+    channel: Option<tl::enums::InputChannel>,
+    request: tl::functions::messages::GetHistory,
+    page_size: PageSize,
+    buffer: VecDeque<tl::enums::Message>,
+    last_page: bool,
+    stats: Option<Arc<Stats>>,
+    skipped: Vec<i32>,
+    consecutive_skips: usize,
+    by_id: bool,
+    pinned_only: bool,
+    /// Unix timestamp of the oldest message read, 0 for no limit.
+    min_date: i64,
+    /// Text the messages are searched for instead of reading all of them.
+    query: String,
+    /// Id of the forum topic the messages are read from.
+    topic: Option<i32>,
+    empty_pages: usize,
+    /// Access hashes of the users the fetched messages came with, by id.
+    users: HashMap<i64, i64>,
+    /// Names of the users the fetched messages came with, by id.
+    profiles: HashMap<i64, Profile>,
+    /// Start of the links to the chat's messages, if they have any.
+    permalink: Option<String>,
+    /// The group the chat was upgraded from or the supergroup it was
+    /// upgraded to, read once the chat's own history is done.
+    migration: Option<Migration>,
+    /// Whether the messages come from the chat of the migration now.
+    migrated: bool,
+}
+
+/// The other side of a group's upgrade to a supergroup, as told by the
+/// service message of the upgrade.
+#[derive(Debug, Clone, PartialEq)]
+struct Migration {
+    peer: tl::enums::InputPeer,
+    channel: Option<tl::enums::InputChannel>,
+    permalink: Option<String>,
+}
+
+impl History {
+    pub fn new(client: &Client, chat: &Chat, page_size: PageSize) -> Self {
+        Self {
+            client: client.clone(),
+            channel: chat.pack().try_to_input_channel(),
+            request: tl::functions::messages::GetHistory {
+                peer: chat.pack().to_input_peer(),
+                offset_id: 0,
+                offset_date: 0,
+                add_offset: 0,
+                limit: 0,
+                max_id: 0,
+                min_id: 0,
+                hash: 0,
+            },
+            page_size,
+            buffer: VecDeque::new(),
+            last_page: false,
+            stats: None,
+            skipped: Vec::new(),
+            consecutive_skips: 0,
+            by_id: false,
+            pinned_only: false,
+            min_date: 0,
+            query: String::new(),
+            topic: None,
+            empty_pages: 0,
+            users: HashMap::new(),
+            profiles: HashMap::new(),
+            permalink: permalink(chat),
+            migration: None,
+            migrated: false,
+        }
+    }
+
+    /// Reports flood waits to `stats`.
+    pub fn stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Starts iterating right before (older than) the message with this id.
+    pub fn offset_id(mut self, offset_id: i32) -> Self {
+        self.request.offset_id = offset_id;
+        self
+    }
+
+    /// Only reads the messages newer than the one with this id, which is
+    /// where reading by id starts as well.
+    pub fn min_id(mut self, min_id: i32) -> Self {
+        self.request.min_id = min_id;
+        self
+    }
+
+    /// Stops at the first message sent before this Unix timestamp. Reading by
+    /// id, oldest first, skips them instead.
+    pub fn min_date(mut self, min_date: Option<i64>) -> Self {
+        self.min_date = min_date.unwrap_or_default();
+        self
+    }
+
+    /// Ids skipped by an earlier, aborted run that still need to be fetched.
+    pub fn skipped(mut self, skipped: Vec<i32>) -> Self {
+        self.skipped = skipped;
+        self
+    }
+
+    /// Reads the messages by id, oldest first, instead of with
+    /// `messages.getHistory`, which bots may not call. The offset id is then
+    /// the last message already read.
+    pub fn by_id(mut self, by_id: bool) -> Self {
+        self.by_id = by_id;
+        self
+    }
+
+    /// Only reads the pinned messages, newest first, with `messages.search`.
+    pub fn pinned_only(mut self, pinned_only: bool) -> Self {
+        self.pinned_only = pinned_only;
+        self
+    }
+
+    /// Only reads the messages Telegram's search finds for `query`, newest
+    /// first, with `messages.search`.
+    pub fn search(mut self, query: Option<&str>) -> Self {
+        self.query = query.unwrap_or_default().to_string();
+        self
+    }
+
+    /// Only reads the messages of the forum topic with the id `topic`,
+    /// with `messages.search`.
+    pub fn topic(mut self, topic: Option<i32>) -> Self {
+        self.topic = topic;
+        self
+    }
+
+    /// Whether pages are fetched with `messages.search`.
+    fn searching(&self) -> bool {
+        self.pinned_only || !self.query.is_empty() || self.topic.is_some()
+    }
+
+    /// Whether the messages come from the group the chat was upgraded from,
+    /// or the supergroup it was upgraded to, rather than the chat itself.
+    pub fn migrated(&self) -> bool {
+        self.migrated
+    }
+
+    /// Ids of the messages in skipped pages that haven't been fetched yet.
+    pub fn skipped_ids(&self) -> &[i32] {
+        &self.skipped
+    }
+
+    /// Link to the message `id`. Links to chats without a username only
+    /// open for their members, and private chats and small groups have none.
+    pub fn permalink(&self, id: i32) -> Option<String> {
+        self.permalink.as_ref().map(|base| format!("{base}/{id}"))
+    }
+
+    /// Access hashes of the users seen so far, which mentions without a
+    /// username need to be resolved.
+    pub fn users(&self) -> &HashMap<i64, i64> {
+        &self.users
+    }
+
+    /// Names of the users seen so far.
+    pub fn profiles(&self) -> &HashMap<i64, Profile> {
+        &self.profiles
+    }
+
+    fn remember_users(&mut self, users: &[tl::enums::User]) {
+        for user in users {
+            if let tl::enums::User::User(user) = user {
+                self.profiles.insert(user.id, Profile::of(user));
+                if let Some(access_hash) = access_hash(user) {
+                    self.users.insert(user.id, access_hash);
+                }
+            }
+        }
+    }
+
+    pub async fn next(&mut self) -> Result<Option<tl::enums::Message>, InvocationError> {
+        // A skipped page leaves the buffer empty without reaching the end.
+        loop {
+            while self.buffer.is_empty() && !self.last_page {
+                self.fill_buffer().await?;
+            }
+
+            let Some(message) = self.buffer.pop_front() else {
+                if self.follow_migration() {
+                    continue;
+                }
+                return Ok(None);
+            };
+            if message_date(&message).is_none_or(|date| date >= self.min_date) {
+                return Ok(Some(message));
+            }
+            if !self.by_id {
+                self.buffer.clear();
+                self.last_page = true;
+                if self.follow_migration() {
+                    continue;
+                }
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Goes on with the chat on the other side of the upgrade of a group to
+    /// a supergroup, once. Their messages have ids of their own, so skipped
+    /// pages of the chat would be fetched from the wrong one afterwards.
+    fn follow_migration(&mut self) -> bool {
+        if self.migrated {
+            return false;
+        }
+        let Some(migration) = self.migration.take() else {
+            return false;
+        };
+        if !self.skipped.is_empty() {
+            tracing::warn!("not following the upgrade of the chat, some of its pages were skipped");
+            return false;
+        }
+
+        tracing::info!(peer = ?migration.peer, "following the upgrade of the chat");
+        self.migrated = true;
+        self.request.peer = migration.peer;
+        self.request.offset_id = 0;
+        self.request.min_id = 0;
+        self.channel = migration.channel;
+        self.permalink = migration.permalink;
+        self.last_page = false;
+        true
+    }
+
+    /// Requests the messages of skipped pages again, up to `attempts` times.
+    ///
+    /// Returns the messages that could be fetched, ids that still fail are
+    /// left in [`History::skipped_ids`].
+    pub async fn retry_skipped(&mut self, attempts: usize) -> Vec<tl::enums::Message> {
+        let mut recovered = Vec::new();
+
+        for attempt in 0..attempts {
+            if self.skipped.is_empty() {
+                break;
+            }
+            if attempt > 0 {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+
+            tracing::info!(
+                attempt,
+                remaining = self.skipped.len(),
+                "retrying skipped messages"
+            );
+            let ids = std::mem::take(&mut self.skipped);
+            for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+                match self.get_messages(chunk).await {
+                    Ok(messages) => recovered.extend(messages),
+                    Err(e) => {
+                        tracing::warn!(
+                            attempt,
+                            count = chunk.len(),
+                            error = %e,
+                            "could not fetch skipped messages"
+                        );
+                        self.skipped.extend_from_slice(chunk);
+                    }
+                }
+            }
+        }
+
+        recovered
+    }
+
+    async fn fill_buffer(&mut self) -> Result<(), InvocationError> {
+        use tl::enums::messages::Messages;
+
+        if self.by_id {
+            return self.fill_buffer_by_id().await;
+        }
+
+        let response = loop {
+            self.request.limit = self.page_size.get() as i32;
+
+            if let Some(ref stats) = self.stats {
+                stats.record_page_size(self.page_size.get(), self.page_size.max);
+            }
+
+            let start = Instant::now();
+            let result = match self.searching() {
+                true => {
+                    let request =
+                        search_request(&self.request, &self.query, self.pinned_only, self.topic);
+                    self.invoke(&request).await
+                }
+                false => self.invoke(&self.request).await,
+            };
+            match result {
+                Ok(response) => {
+                    tracing::debug!(
+                        offset_id = self.request.offset_id,
+                        limit = self.request.limit,
+                        elapsed_ms = start.elapsed().as_millis() as u64,
+                        "fetched history page"
+                    );
+                    self.page_size.record_response(start.elapsed());
+                    self.consecutive_skips = 0;
+                    break response;
+                }
+                Err(InvocationError::Rpc(e)) if e.name == "FLOOD_WAIT" => {
+                    self.flood_wait(e.value).await;
+                }
+                // Search results are too far apart to skip by id.
+                Err(e)
+                    if is_transient(&e)
+                        && !self.searching()
+                        && self.request.offset_id > 1
+                        && self.consecutive_skips < MAX_CONSECUTIVE_SKIPS =>
+                {
+                    self.skip_page(&e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let (messages, users, chats) = match response {
+            Messages::Messages(m) => {
+                self.last_page = true;
+                (m.messages, m.users, m.chats)
+            }
+            Messages::Slice(m) => (m.messages, m.users, m.chats),
+            Messages::ChannelMessages(m) => (m.messages, m.users, m.chats),
+            Messages::NotModified(_) => (Vec::new(), Vec::new(), Vec::new()),
+        };
+        self.remember_users(&users);
+        // Searches don't return the service message of an upgrade.
+        if !self.migrated && !self.searching() && self.migration.is_none() {
+            self.migration = messages.iter().find_map(|m| migration(m, &chats));
+        }
+
+        if is_last_page(&messages, self.request.limit, self.searching()) {
+            self.last_page = true;
+        }
+
+        if let Some(last) = messages.last() {
+            self.request.offset_id = message_id(last);
+        }
+
+        self.buffer.extend(messages);
+        Ok(())
+    }
+
+    async fn fill_buffer_by_id(&mut self) -> Result<(), InvocationError> {
+        if let Some(ref stats) = self.stats {
+            stats.record_page_size(self.page_size.get(), self.page_size.max);
+        }
+
+        let ids: Vec<i32> = next_ids(
+            self.request.offset_id,
+            self.request.min_id,
+            self.page_size.get() as i32,
+        )
+        .collect();
+        let first = ids[0];
+
+        let start = Instant::now();
+        let messages = self.get_messages(&ids).await?;
+        tracing::debug!(
+            first_id = first,
+            limit = ids.len(),
+            messages = messages.len(),
+            elapsed_ms = start.elapsed().as_millis() as u64,
+            "fetched messages by id"
+        );
+        self.page_size.record_response(start.elapsed());
+
+        if messages.is_empty() {
+            self.empty_pages += 1;
+            self.last_page = self.empty_pages >= MAX_EMPTY_PAGES;
+        } else {
+            self.empty_pages = 0;
+        }
+
+        self.request.offset_id = first + ids.len() as i32 - 1;
+        self.buffer.extend(messages);
+        Ok(())
+    }
+
+    /// Moves past the page that was just requested, remembering its ids.
+    fn skip_page(&mut self, error: &InvocationError) {
+        let ids = page_ids(self.request.offset_id, self.request.limit);
+        tracing::warn!(
+            from = ids.start,
+            to = ids.end - 1,
+            %error,
+            "skipping messages, they are retried at the end"
+        );
+
+        self.request.offset_id = ids.start;
+        self.last_page = ids.start <= 1;
+        self.consecutive_skips += 1;
+        self.skipped.extend(ids);
+    }
+
+    /// Fetches the messages with `ids` again, e.g. to see whether they were
+    /// edited. Deleted ones come back as [`tl::enums::Message::Empty`].
+    pub async fn refetch(
+        &mut self,
+        ids: &[i32],
+    ) -> Result<Vec<tl::enums::Message>, InvocationError> {
+        let mut messages = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+            messages.extend(self.request_messages(chunk).await?);
+        }
+        Ok(messages)
+    }
+
+    async fn get_messages(
+        &mut self,
+        ids: &[i32],
+    ) -> Result<Vec<tl::enums::Message>, InvocationError> {
+        // Deleted messages come back empty, there is nothing left to retry.
+        Ok(self
+            .request_messages(ids)
+            .await?
+            .into_iter()
+            .filter(|message| !matches!(message, tl::enums::Message::Empty(_)))
+            .collect())
+    }
+
+    async fn request_messages(
+        &mut self,
+        ids: &[i32],
+    ) -> Result<Vec<tl::enums::Message>, InvocationError> {
+        use tl::enums::messages::Messages;
+
+        let id: Vec<tl::enums::InputMessage> = ids
+            .iter()
+            .map(|&id| tl::types::InputMessageId { id }.into())
+            .collect();
+
+        let response = loop {
+            let result = match self.channel {
+                Some(ref channel) => {
+                    let request = tl::functions::channels::GetMessages {
+                        channel: channel.clone(),
+                        id: id.clone(),
+                    };
+                    self.invoke(&request).await
+                }
+                None => {
+                    let request = tl::functions::messages::GetMessages { id: id.clone() };
+                    self.invoke(&request).await
+                }
+            };
+
+            match result {
+                Ok(response) => break response,
+                Err(InvocationError::Rpc(e)) if e.name == "FLOOD_WAIT" => {
+                    self.flood_wait(e.value).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let (messages, users) = match response {
+            Messages::Messages(m) => (m.messages, m.users),
+            Messages::Slice(m) => (m.messages, m.users),
+            Messages::ChannelMessages(m) => (m.messages, m.users),
+            Messages::NotModified(_) => (Vec::new(), Vec::new()),
+        };
+        self.remember_users(&users);
+        Ok(messages)
+    }
+
+    /// Invokes the request, counting it and its size in `stats`.
+    async fn invoke<R>(&self, request: &R) -> Result<R::Return, InvocationError>
+    where
+        R: tl::RemoteCall,
+        R::Return: Serializable,
+    {
+        let result = flood::invoke_once(&self.client, request).await;
+
+        if let Some(ref stats) = self.stats {
+            let received = result
+                .as_ref()
+                .map_or(0, |response| response.to_bytes().len());
+            stats.record_request(
+                stats::method_name::<R>(),
+                request.to_bytes().len(),
+                received,
+            );
+        }
+
+        result
+    }
+
+    async fn flood_wait(&mut self, seconds: Option<u32>) {
+        let wait = Duration::from_secs(seconds.unwrap_or(1) as u64);
+        tracing::warn!(
+            wait_secs = wait.as_secs(),
+            page_size = self.page_size.get(),
+            "history flood wait, shrinking page size"
+        );
+        if let Some(ref stats) = self.stats {
+            stats.record_flood_wait(wait);
+        }
+        self.page_size.shrink();
+        metrics::record_flood_wait(wait);
+        usage::record_flood_wait(wait);
+        ratelimit::pause(wait);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Errors after which the same request may well succeed: responses that
+/// couldn't be decoded, dropped requests and internal server errors.
+fn is_transient(error: &InvocationError) -> bool {
+    match error {
+        InvocationError::Rpc(e) => e.code >= 500,
+        InvocationError::Dropped | InvocationError::Read(_) => true,
+    }
+}
+
+/// Base of the links to the chat's messages, if they can be linked to.
+fn permalink(chat: &Chat) -> Option<String> {
+    link_base(chat.username(), chat.id(), chat.pack().is_channel())
+}
+
+/// `https://t.me/<username>` for public channels and supergroups and
+/// `https://t.me/c/<id>` for private ones. Messages of small groups and
+/// private chats have no links.
+fn link_base(username: Option<&str>, id: i64, channel: bool) -> Option<String> {
+    match (username, channel) {
+        (Some(username), true) => Some(format!("https://t.me/{username}")),
+        (None, true) => Some(format!("https://t.me/c/{id}")),
+        (_, false) => None,
+    }
+}
+
+/// The chat on the other side of an upgrade, if `message` is the service
+/// message of one. A supergroup starts with the message naming the group it
+/// was upgraded from, and the group ends with the one naming the supergroup,
+/// whose access hash comes with it in `chats`.
+fn migration(message: &tl::enums::Message, chats: &[tl::enums::Chat]) -> Option<Migration> {
+    let tl::enums::Message::Service(service) = message else {
+        return None;
+    };
+    match service.action {
+        tl::enums::MessageAction::ChannelMigrateFrom(ref action) => Some(Migration {
+            peer: tl::types::InputPeerChat {
+                chat_id: action.chat_id,
+            }
+            .into(),
+            channel: None,
+            permalink: None,
+        }),
+        tl::enums::MessageAction::ChatMigrateTo(ref action) => {
+            let access_hash = chats.iter().find_map(|chat| match chat {
+                tl::enums::Chat::Channel(channel) if channel.id == action.channel_id => {
+                    channel.access_hash
+                }
+                _ => None,
+            })?;
+            Some(Migration {
+                peer: tl::types::InputPeerChannel {
+                    channel_id: action.channel_id,
+                    access_hash,
+                }
+                .into(),
+                channel: Some(
+                    tl::types::InputChannel {
+                        channel_id: action.channel_id,
+                        access_hash,
+                    }
+                    .into(),
+                ),
+                permalink: Some(format!("https://t.me/c/{}", action.channel_id)),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The page of search results `request` would fetch from the history,
+/// finding the pinned messages or the ones matching `query`, in `topic` if
+/// there is one.
+fn search_request(
+    request: &tl::functions::messages::GetHistory,
+    query: &str,
+    pinned_only: bool,
+    topic: Option<i32>,
+) -> tl::functions::messages::Search {
+    let filter = match pinned_only {
+        true => tl::types::InputMessagesFilterPinned {}.into(),
+        false => tl::types::InputMessagesFilterEmpty {}.into(),
+    };
+    tl::functions::messages::Search {
+        peer: request.peer.clone(),
+        q: query.to_string(),
+        from_id: None,
+        top_msg_id: topic,
+        filter,
+        min_date: 0,
+        max_date: 0,
+        offset_id: request.offset_id,
+        add_offset: 0,
+        limit: request.limit,
+        max_id: 0,
+        min_id: request.min_id,
+        hash: 0,
+    }
+}
+
+/// Whether a page of `messages`, newest first, is the last one. If the
+/// highest fetched id is within the requested limit, the oldest message
+/// (id 1) has been reached. Searches end with a short page.
+fn is_last_page(messages: &[tl::enums::Message], limit: i32, searching: bool) -> bool {
+    messages.first().is_none_or(|first| match searching {
+        true => messages.len() < limit as usize,
+        false => message_id(first) <= limit,
+    })
+}
+
+/// Ids of the next page when reading by id, right after the last message
+/// read and never at or below `min_id`.
+fn next_ids(offset_id: i32, min_id: i32, limit: i32) -> Range<i32> {
+    let first = offset_id.max(min_id) + 1;
+    first..first + limit
+}
+
+/// Ids a history page requested with this offset and limit may contain.
+fn page_ids(offset_id: i32, limit: i32) -> Range<i32> {
+    (offset_id - limit).max(1)..offset_id
+}
+
+pub fn message_id(message: &tl::enums::Message) -> i32 {
+    match message {
+        tl::enums::Message::Empty(m) => m.id,
+        tl::enums::Message::Message(m) => m.id,
+        tl::enums::Message::Service(m) => m.id,
+    }
+}
+
+/// Unix timestamp the message was sent at, if it wasn't deleted.
+pub fn message_date(message: &tl::enums::Message) -> Option<i64> {
+    match message {
+        tl::enums::Message::Empty(_) => None,
+        tl::enums::Message::Message(m) => Some(m.date as i64),
+        tl::enums::Message::Service(m) => Some(m.date as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_size_adapts() {
+        let mut page_size = PageSize::new(100, true);
+
+        page_size.record_response(Duration::from_secs(5));
+        assert_eq!(page_size.get(), 50);
+
+        page_size.shrink();
+        page_size.shrink();
+        page_size.shrink();
+        assert_eq!(page_size.get(), MIN_PAGE_SIZE);
+
+        page_size.record_response(Duration::from_millis(100));
+        assert_eq!(page_size.get(), MIN_PAGE_SIZE + PAGE_SIZE_STEP);
+    }
+
+    #[test]
+    fn test_page_ids() {
+        assert_eq!(page_ids(500, 100), 400..500);
+        assert_eq!(page_ids(42, 100), 1..42);
+    }
+
+    fn history_request(offset_id: i32, limit: i32) -> tl::functions::messages::GetHistory {
+        tl::functions::messages::GetHistory {
+            peer: tl::types::InputPeerSelf {}.into(),
+            offset_id,
+            offset_date: 0,
+            add_offset: 0,
+            limit,
+            max_id: 0,
+            min_id: 20,
+            hash: 0,
+        }
+    }
+
+    fn page(ids: Range<i32>) -> Vec<tl::enums::Message> {
+        ids.rev()
+            .map(|id| tl::types::MessageEmpty { id, peer_id: None }.into())
+            .collect()
+    }
+
+    #[test]
+    fn test_pinned_only() {
+        let search = search_request(&history_request(500, 100), "", true, None);
+        assert_eq!(
+            search.filter,
+            tl::types::InputMessagesFilterPinned {}.into()
+        );
+        assert_eq!(
+            (search.offset_id, search.limit, search.min_id),
+            (500, 100, 20)
+        );
+
+        // Pinned messages are far apart, so low ids don't end the search,
+        // only a page shorter than asked for does.
+        assert!(!is_last_page(&page(1..101), 100, true));
+        assert!(is_last_page(&page(1..4), 100, true));
+        assert!(is_last_page(&page(1..101), 100, false));
+        assert!(!is_last_page(&page(400..500), 100, false));
+    }
+
+    #[test]
+    fn test_search() {
+        let search = search_request(&history_request(0, 50), "t.me/joinchat", false, None);
+        assert_eq!(search.q, "t.me/joinchat");
+        assert_eq!(search.filter, tl::types::InputMessagesFilterEmpty {}.into());
+        assert_eq!((search.offset_id, search.limit), (0, 50));
+
+        // Both narrow the search down when given together.
+        let search = search_request(&history_request(0, 50), "t.me", true, None);
+        assert_eq!(search.q, "t.me");
+        assert_eq!(
+            search.filter,
+            tl::types::InputMessagesFilterPinned {}.into()
+        );
+
+        assert!(!is_last_page(&page(900..950), 50, true));
+        assert!(is_last_page(&page(900..910), 50, true));
+        assert!(is_last_page(&[], 50, true));
+    }
+
+    #[test]
+    fn test_next_ids() {
+        assert_eq!(next_ids(0, 0, 100), 1..101);
+        assert_eq!(next_ids(250, 0, 100), 251..351);
+        assert_eq!(next_ids(0, 1000, 50), 1001..1051);
+    }
+
+    #[test]
+    fn test_mentioned_users() {
+        let mut user = tl::types::User {
+            is_self: false,
+            contact: false,
+            mutual_contact: false,
+            deleted: false,
+            bot: false,
+            bot_chat_history: false,
+            bot_nochats: false,
+            verified: false,
+            restricted: false,
+            min: false,
+            bot_inline_geo: false,
+            support: false,
+            scam: false,
+            apply_min_photo: false,
+            fake: false,
+            bot_attach_menu: false,
+            premium: false,
+            attach_menu_enabled: false,
+            id: 777000,
+            access_hash: Some(1234),
+            first_name: Some("Lonami".to_string()),
+            last_name: Some("Exo".to_string()),
+            username: None,
+            phone: None,
+            photo: None,
+            status: None,
+            bot_info_version: None,
+            restriction_reason: None,
+            bot_inline_placeholder: None,
+            lang_code: None,
+            emoji_status: None,
+        };
+
+        assert_eq!(access_hash(&user), Some(1234));
+        assert_eq!(
+            Profile::of(&user),
+            Profile {
+                name: "Lonami Exo".to_string(),
+                username: None,
+            }
+        );
+
+        user.min = true;
+        assert_eq!(access_hash(&user), None);
+    }
+
+    #[test]
+    fn test_link_base() {
+        assert_eq!(
+            link_base(Some("rustlang"), 1234, true).as_deref(),
+            Some("https://t.me/rustlang")
+        );
+        assert_eq!(
+            link_base(None, 1234, true).as_deref(),
+            Some("https://t.me/c/1234")
+        );
+        assert_eq!(link_base(Some("someone"), 1234, false), None);
+    }
+
+    #[test]
+    fn test_page_size_fixed() {
+        let mut page_size = PageSize::new(500, false);
+        assert_eq!(page_size.get(), MAX_PAGE_SIZE);
+
+        page_size.record_response(Duration::from_secs(5));
+        page_size.shrink();
+        assert_eq!(page_size.get(), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_migration() {
+        let service = |action| -> tl::enums::Message {
+            tl::types::MessageService {
+                out: false,
+                mentioned: false,
+                media_unread: false,
+                silent: false,
+                post: false,
+                legacy: false,
+                id: 1,
+                from_id: None,
+                peer_id: tl::types::PeerChannel { channel_id: 2 }.into(),
+                reply_to: None,
+                date: 0,
+                action,
+                ttl_period: None,
+            }
+            .into()
+        };
+
+        let from = service(
+            tl::types::MessageActionChannelMigrateFrom {
+                title: "Rust".to_string(),
+                chat_id: 1,
+            }
+            .into(),
+        );
+        let followed = migration(&from, &[]).unwrap();
+        assert!(matches!(
+            followed.peer,
+            tl::enums::InputPeer::Chat(tl::types::InputPeerChat { chat_id: 1 })
+        ));
+        assert!(followed.channel.is_none());
+        assert!(followed.permalink.is_none());
+
+        // Without the channel among the chats there's no access hash to
+        // read it with.
+        let to = service(tl::types::MessageActionChatMigrateTo { channel_id: 2 }.into());
+        assert!(migration(&to, &[]).is_none());
+
+        let joined = service(tl::enums::MessageAction::ChatJoinedByRequest);
+        assert!(migration(&joined, &[]).is_none());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Subcommand;
+use color_eyre::eyre::Result;
+use grammers_client::{client::messages::InvocationError, Client};
+use grammers_tl_types as tl;
+use indicatif::ProgressBar;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::{flood, locale::Formatter, UsernameMetadata, UsernameType};
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Check a file of invite hashes or links, one per line, and store the
+    /// title and member count of every valid one
+    Check {
+        file: PathBuf,
+
+        /// Seconds to wait between two checks
+        #[arg(long, default_value_t = 3)]
+        delay: u64,
+    },
+}
+
+/// Result of checking one invite hash.
+#[derive(Deserialize, Serialize)]
+struct Invite {
+    hash: String,
+    valid: bool,
+    /// Id of the chat, only known if the account is a member or can peek
+    /// into it.
+    id: Option<i64>,
+    title: Option<String>,
+    members: Option<usize>,
+    #[serde(rename = "type")]
+    type_: Option<UsernameType>,
+    /// Why Telegram rejected the hash, e.g. `INVITE_HASH_EXPIRED`.
+    error: Option<String>,
+}
+
+impl Invite {
+    fn new(hash: &str, invite: tl::enums::ChatInvite) -> Self {
+        let ChatInfo {
+            id,
+            title,
+            members,
+            type_,
+        } = match invite {
+            tl::enums::ChatInvite::Invite(invite) => {
+                let type_ = if invite.broadcast {
+                    UsernameType::Channel
+                } else {
+                    UsernameType::Group
+                };
+                ChatInfo {
+                    id: None,
+                    title: Some(invite.title),
+                    members: Some(invite.participants_count as usize),
+                    type_: Some(type_),
+                }
+            }
+            tl::enums::ChatInvite::Already(already) => chat_info(already.chat),
+            tl::enums::ChatInvite::Peek(peek) => chat_info(peek.chat),
+        };
+
+        Self {
+            hash: hash.to_string(),
+            valid: true,
+            id,
+            title,
+            members,
+            type_,
+            error: None,
+        }
+    }
+
+    fn invalid(hash: &str, error: String) -> Self {
+        Self {
+            hash: hash.to_string(),
+            valid: false,
+            id: None,
+            title: None,
+            members: None,
+            type_: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChatInfo {
+    id: Option<i64>,
+    title: Option<String>,
+    members: Option<usize>,
+    type_: Option<UsernameType>,
+}
+
+fn chat_info(chat: tl::enums::Chat) -> ChatInfo {
+    match chat {
+        tl::enums::Chat::Chat(chat) => ChatInfo {
+            id: Some(chat.id),
+            title: Some(chat.title),
+            members: Some(chat.participants_count as usize),
+            type_: Some(UsernameType::Group),
+        },
+        tl::enums::Chat::Channel(channel) => {
+            let type_ = if channel.broadcast {
+                UsernameType::Channel
+            } else {
+                UsernameType::Group
+            };
+            ChatInfo {
+                id: Some(channel.id),
+                title: Some(channel.title),
+                members: channel.participants_count.map(|count| count as usize),
+                type_: Some(type_),
+            }
+        }
+        tl::enums::Chat::Forbidden(chat) => ChatInfo {
+            id: Some(chat.id),
+            title: Some(chat.title),
+            type_: Some(UsernameType::Group),
+            ..Default::default()
+        },
+        tl::enums::Chat::ChannelForbidden(channel) => ChatInfo {
+            id: Some(channel.id),
+            title: Some(channel.title),
+            ..Default::default()
+        },
+        tl::enums::Chat::Empty(chat) => ChatInfo {
+            id: Some(chat.id),
+            ..Default::default()
+        },
+    }
+}
+
+/// Looks up the chat behind an invite hash found while crawling, for the
+/// `check-invite` resolution strategy.
+pub async fn metadata(client: &Client, hash: &str) -> Option<UsernameMetadata> {
+    let invite = match check_hash(client, hash).await {
+        Ok(invite) => invite,
+        Err(e) => {
+            tracing::warn!(hash, error = %e, "could not check invite");
+            return None;
+        }
+    };
+
+    Some(UsernameMetadata {
+        id: invite.id.unwrap_or_default(),
+        name: invite.title?,
+        type_: invite.type_?,
+        about: None,
+        members: invite.members,
+        date: None,
+        restriction: None,
+        language: None,
+        label: None,
+        risk: None,
+        linked_chat: None,
+        admins: None,
+    })
+}
+
+pub async fn run(client: &Client, command: &Command, fmt: Formatter) -> Result<()> {
+    match command {
+        Command::Check { file, delay } => {
+            check(client, file, Duration::from_secs(*delay), fmt).await
+        }
+    }
+}
+
+/// Checks every hash in `path`, appending the results to
+/// `<name>.invites.jsonl`. Hashes already in that file are skipped, so an
+/// aborted check continues where it stopped.
+async fn check(client: &Client, path: &Path, delay: Duration, fmt: Formatter) -> Result<()> {
+    let hashes = parse_hashes(&fs::read_to_string(path)?);
+    let out = results_file(path);
+    let done = checked_hashes(&out)?;
+
+    let pending: Vec<_> = hashes.iter().filter(|h| !done.contains(*h)).collect();
+    status!(
+        "Checking {} invite hashes ({} already checked)...",
+        fmt.number(pending.len()),
+        fmt.number(hashes.len() - pending.len())
+    );
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&out)?;
+    let progress_bar = ProgressBar::new(pending.len() as u64);
+    let mut valid = 0;
+    for (i, hash) in pending.iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(delay).await;
+        }
+
+        let invite = check_hash(client, hash).await?;
+        if invite.valid {
+            valid += 1;
+        }
+        writeln!(file, "{}", serde_json::to_string(&invite)?)?;
+        progress_bar.inc(1);
+    }
+    progress_bar.finish();
+
+    status!(
+        "{} of {} hashes are valid, saved to {}",
+        fmt.number(valid),
+        fmt.number(pending.len()),
+        out.display()
+    );
+
+    Ok(())
+}
+
+async fn check_hash(client: &Client, hash: &str) -> Result<Invite, InvocationError> {
+    let request = tl::functions::messages::CheckChatInvite {
+        hash: hash.to_string(),
+    };
+
+    match flood::invoke(client, &request).await {
+        Ok(invite) => Ok(Invite::new(hash, invite)),
+        Err(InvocationError::Rpc(e)) if e.name.starts_with("INVITE_HASH") => {
+            Ok(Invite::invalid(hash, e.name))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads one hash per line, accepting full invite links as well. Blank lines
+/// and lines starting with `#` are ignored.
+fn parse_hashes(contents: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let hash = rampilo::extract_all(line)
+                .into_iter()
+                .find_map(|link| match link {
+                    LinkType::Hash(hash) => Some(hash),
+                    _ => None,
+                });
+
+            match hash {
+                Some(hash) => Some(hash),
+                None if line
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+                {
+                    Some(line.to_string())
+                }
+                None => {
+                    tracing::warn!(line, "ignoring line, it is not an invite hash or link");
+                    None
+                }
+            }
+        })
+        .filter(|hash| seen.insert(hash.clone()))
+        .collect()
+}
+
+fn results_file(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    PathBuf::from(format!("{stem}.invites.jsonl"))
+}
+
+fn checked_hashes(out: &Path) -> Result<HashSet<String>> {
+    let contents = match fs::read_to_string(out) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str::<Invite>(line)?.hash))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hashes() {
+        let hashes = parse_hashes(
+            "# invites\n\
+             USpx-sviNKIj408g\n\
+             \n\
+             https://t.me/+_DGX2NIt9IhkNTVk\n\
+             https://t.me/joinchat/USpx-sviNKIj408g\n\
+             not a hash\n",
+        );
+        assert_eq!(hashes, vec!["USpx-sviNKIj408g", "_DGX2NIt9IhkNTVk"]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+use crate::{
+    locale::Formatter,
+    output::{Destination, Format},
+};
+
+/// Least share of the messages a language needs to count as one of the
+/// languages of a chat.
+const DOMINANT_SHARE: f64 = 0.1;
+
+/// Languages the messages of a chat are written in, for `--languages`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Languages {
+    /// Whether messages are run through detection at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    /// Messages by the ISO 639-3 code of their language, for the ones long
+    /// enough to tell.
+    pub messages: BTreeMap<String, usize>,
+}
+
+impl Languages {
+    pub fn record(&mut self, text: &str) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(code) = detect(text) {
+            *self.messages.entry(code.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Codes of the languages of at least a tenth of the messages, with
+    /// their share, most used first.
+    pub fn dominant(&self) -> Vec<(&str, f64)> {
+        let total: usize = self.messages.values().sum();
+        let mut dominant: Vec<_> = self
+            .messages
+            .iter()
+            .map(|(code, &count)| (code.as_str(), count as f64 / total as f64))
+            .filter(|&(_, share)| share >= DOMINANT_SHARE)
+            .collect();
+        dominant.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        dominant
+    }
+
+    /// One line naming the dominant languages and their shares.
+    pub fn summary(&self, fmt: Formatter) -> String {
+        let dominant = self.dominant();
+        if dominant.is_empty() {
+            return "no language could be detected".to_string();
+        }
+
+        let languages: Vec<_> = dominant
+            .iter()
+            .map(|&(code, share)| format!("{} ({}%)", name(code), fmt.decimal(share * 100.0)))
+            .collect();
+        format!("written in {}", languages.join(", "))
+    }
+
+    /// Writes the counts and dominant languages next to the results as
+    /// `<chat>.languages.json`, or whatever `--out-name` makes of that name.
+    pub fn write(&self, chat: &str, destination: &Destination) -> Result<String> {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            messages: &'a BTreeMap<String, usize>,
+            dominant: Vec<&'a str>,
+        }
+
+        let report = Report {
+            messages: &self.messages,
+            dominant: self.dominant().into_iter().map(|(code, _)| code).collect(),
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let path = destination.path(&format!("{chat}.languages"), Format::Json, now);
+        fs::create_dir_all(&destination.out_dir)?;
+        destination.write(&path, serde_json::to_string_pretty(&report)?)?;
+        Ok(path.display().to_string())
+    }
+}
+
+/// ISO 639-3 code of the language of `text`, if it can be told reliably.
+/// Short messages, like a lone link, usually can't.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().code())
+}
+
+/// English name of the language with the ISO 639-3 `code`.
+fn name(code: &str) -> &str {
+    Lang::from_code(code).map_or(code, |lang| lang.eng_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_languages() {
+        let mut languages = Languages {
+            enabled: true,
+            ..Default::default()
+        };
+        for _ in 0..8 {
+            languages.record("The weekly coding night starts at seven, bring your laptop and a project you are working on.");
+        }
+        for _ in 0..2 {
+            languages.record(
+                "Der wöchentliche Programmierabend beginnt um sieben Uhr, bringt euren Laptop mit.",
+            );
+        }
+        languages.record("t.me/codenight");
+
+        assert_eq!(languages.messages.values().sum::<usize>(), 10);
+        assert_eq!(languages.dominant(), [("eng", 0.8), ("deu", 0.2)]);
+        assert_eq!(
+            languages.summary(Formatter::default()),
+            "written in English (80.0%), German (20.0%)"
+        );
+
+        let mut disabled = Languages::default();
+        disabled.record("The weekly coding night starts at seven.");
+        assert!(disabled.messages.is_empty());
+    }
+}
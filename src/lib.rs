@@ -0,0 +1,143 @@
+//! Telegram link parsing used by the rampilo crawler.
+//!
+//! [`extract_all`] finds every Telegram link in a piece of text. The
+//! [`extractors`] module gives finer control over what is extracted.
+
+use std::{fmt, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+pub mod extractors;
+mod tme;
+
+use extractors::{Kind, MessageText, Pipeline};
+
+/// Something found in a message.
+#[derive(Deserialize, Serialize, Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub enum LinkType {
+    /// `t.me/<username>` or `tg://resolve?domain=<username>`
+    Username(String),
+    /// Invite hash of a `t.me/+<hash>`, `t.me/joinchat/<hash>` or
+    /// `tg://join?invite=<hash>` link
+    Hash(String),
+    /// `@username` mention
+    Mention(String),
+    /// `#hashtag`, without the `#`
+    Hashtag(String),
+    /// Link to a site other than t.me
+    Url(String),
+    /// Slug of a `t.me/addlist/<slug>` chat folder link
+    Folder(String),
+    /// `<server>:<port>` of a `t.me/proxy` or `t.me/socks` link
+    Proxy(String),
+    /// Slug of a `t.me/invoice/<slug>`, `t.me/$<slug>` or
+    /// `tg://invoice?slug=<slug>` payment link
+    Invoice(String),
+    /// Name of a `t.me/addstickers/<name>` or `tg://addstickers?set=<name>`
+    /// sticker set
+    StickerSet(String),
+    /// Name of a `t.me/addemoji/<name>` or `tg://addemoji?set=<name>` custom
+    /// emoji set
+    EmojiSet(String),
+    /// Slug of a `t.me/addtheme/<slug>` or `tg://addtheme?slug=<slug>` theme
+    Theme(String),
+    /// Id of a user mentioned by name, who may have no username
+    UserId(i64),
+    /// Phone number, as `+` and digits if it was written with a country code
+    Phone(String),
+    /// `<username>/<id>` of a `t.me/<username>/s/<id>` or
+    /// `tg://resolve?domain=<username>&story=<id>` story link
+    Story(String),
+    /// Channel of a `t.me/boost/<username>` or `tg://boost?domain=<username>`
+    /// link, as shared to gather boosts for giveaways
+    Boost(String),
+}
+
+impl LinkType {
+    /// Key the link is counted under. Usernames are case-insensitive while
+    /// invite hashes are not.
+    pub fn key(&self) -> String {
+        match self {
+            LinkType::Username(username) => format!("username:{}", username.to_lowercase()),
+            LinkType::Hash(hash) => format!("hash:{hash}"),
+            LinkType::Mention(username) => format!("mention:{}", username.to_lowercase()),
+            LinkType::Hashtag(hashtag) => format!("hashtag:{}", hashtag.to_lowercase()),
+            LinkType::Url(url) => format!("url:{url}"),
+            LinkType::Folder(slug) => format!("folder:{slug}"),
+            LinkType::Proxy(proxy) => format!("proxy:{}", proxy.to_lowercase()),
+            LinkType::Invoice(slug) => format!("invoice:{slug}"),
+            LinkType::StickerSet(name) => format!("stickers:{}", name.to_lowercase()),
+            LinkType::EmojiSet(name) => format!("emoji:{}", name.to_lowercase()),
+            LinkType::Theme(slug) => format!("theme:{slug}"),
+            LinkType::UserId(id) => format!("user:{id}"),
+            LinkType::Phone(number) => format!("phone:{number}"),
+            LinkType::Story(story) => format!("story:{}", story.to_lowercase()),
+            LinkType::Boost(username) => format!("boost:{}", username.to_lowercase()),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LinkType::Username(_) => "Username",
+            LinkType::Hash(_) => "Hash",
+            LinkType::Mention(_) => "Mention",
+            LinkType::Hashtag(_) => "Hashtag",
+            LinkType::Url(_) => "Url",
+            LinkType::Folder(_) => "Folder",
+            LinkType::Proxy(_) => "Proxy",
+            LinkType::Invoice(_) => "Invoice",
+            LinkType::StickerSet(_) => "StickerSet",
+            LinkType::EmojiSet(_) => "EmojiSet",
+            LinkType::Theme(_) => "Theme",
+            LinkType::UserId(_) => "UserId",
+            LinkType::Phone(_) => "Phone",
+            LinkType::Story(_) => "Story",
+            LinkType::Boost(_) => "Boost",
+        }
+    }
+}
+
+impl fmt::Display for LinkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkType::Username(username) => write!(f, "{username}"),
+            LinkType::Hash(hash) => write!(f, "{hash}"),
+            LinkType::Mention(username) => write!(f, "{username}"),
+            LinkType::Hashtag(hashtag) => write!(f, "#{hashtag}"),
+            LinkType::Url(url) => write!(f, "{url}"),
+            LinkType::Folder(slug) => write!(f, "{slug}"),
+            LinkType::Proxy(proxy) => write!(f, "{proxy}"),
+            LinkType::Invoice(slug) => write!(f, "{slug}"),
+            LinkType::StickerSet(name) | LinkType::EmojiSet(name) => write!(f, "{name}"),
+            LinkType::Theme(slug) => write!(f, "{slug}"),
+            LinkType::UserId(id) => write!(f, "{id}"),
+            LinkType::Phone(number) => write!(f, "{number}"),
+            LinkType::Story(story) => write!(f, "{story}"),
+            LinkType::Boost(username) => write!(f, "{username}"),
+        }
+    }
+}
+
+/// Finds every Telegram link in `text`: usernames, invite hashes, chat
+/// folders, proxies, invoices, sticker sets, themes, stories and boosts, in
+/// both their `https://t.me/` and `tg://` forms.
+///
+/// ```
+/// use rampilo::{extract_all, LinkType};
+///
+/// let links = extract_all("join https://t.me/+AbC or tg://resolve?domain=grammers");
+/// assert_eq!(
+///     links,
+///     vec![
+///         LinkType::Username("grammers".to_string()),
+///         LinkType::Hash("AbC".to_string()),
+///     ]
+/// );
+/// ```
+pub fn extract_all(text: &str) -> Vec<LinkType> {
+    static PIPELINE: OnceLock<Pipeline> = OnceLock::new();
+
+    PIPELINE
+        .get_or_init(|| Pipeline::new(&Kind::TELEGRAM))
+        .extract(&MessageText::plain(text))
+}
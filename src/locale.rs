@@ -0,0 +1,93 @@
+use num_format::{Locale, ToFormattedString};
+
+use crate::output;
+
+/// Formats numbers and dates for the human-facing parts of the output
+/// according to `--locale`, since reports are often shared with teams that
+/// don't read `1,234,567` the English way.
+#[derive(Clone, Copy, Debug)]
+pub struct Formatter {
+    locale: Locale,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self { locale: Locale::en }
+    }
+}
+
+impl Formatter {
+    pub fn number(&self, n: usize) -> String {
+        n.to_formatted_string(&self.locale)
+    }
+
+    /// Formats a non-negative number with one decimal.
+    pub fn decimal(&self, n: f64) -> String {
+        let tenths = (n * 10.0).round() as usize;
+        let whole = self.number(tenths / 10);
+        format!("{whole}{}{}", self.locale.decimal(), tenths % 10)
+    }
+
+    /// Formats a Unix timestamp as a UTC date, in the order and with the
+    /// separator the locale's country writes dates with. The locales that
+    /// put the year first keep `YYYY-MM-DD`, and so does the default `en`,
+    /// which isn't tied to a country.
+    pub fn date(&self, timestamp: i64) -> String {
+        let (year, month, day) = output::civil(timestamp.div_euclid(86400));
+        let name = self.locale.name();
+        let (language, region) = name.split_once('-').unwrap_or((name, ""));
+        match (language, region) {
+            (_, "US" | "PH") => format!("{month}/{day}/{year}"),
+            ("en", "") | (_, "CA") | ("zh" | "ja" | "ko" | "hu" | "lt" | "sv" | "mn", _) => {
+                output::date(timestamp)
+            }
+            ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "nn" | "da" | "tr" | "uk", _)
+            | ("ro" | "bg" | "hr" | "sl" | "sr" | "et" | "lv" | "kk" | "be" | "az" | "hy", _) => {
+                format!("{day:02}.{month:02}.{year}")
+            }
+            ("nl" | "fy", _) => format!("{day:02}-{month:02}-{year}"),
+            _ => format!("{day:02}/{month:02}/{year}"),
+        }
+    }
+}
+
+/// Parses a locale name such as `en`, `de` or `fr-CA`.
+pub fn parse(name: &str) -> Result<Formatter, String> {
+    Locale::from_name(name)
+        .map(|locale| Formatter { locale })
+        .map_err(|_| format!("unknown locale {name:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number() {
+        assert_eq!(Formatter::default().number(1234567), "1,234,567");
+        assert_eq!(parse("de").unwrap().number(1234567), "1.234.567");
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(Formatter::default().decimal(1234.56), "1,234.6");
+        assert_eq!(parse("de").unwrap().decimal(1234.56), "1.234,6");
+    }
+
+    #[test]
+    fn test_date() {
+        // 2024-01-31
+        let timestamp = 1706659200;
+        assert_eq!(Formatter::default().date(timestamp), "2024-01-31");
+        assert_eq!(parse("es-US").unwrap().date(timestamp), "1/31/2024");
+        assert_eq!(parse("en-GB").unwrap().date(timestamp), "31/01/2024");
+        assert_eq!(parse("de").unwrap().date(timestamp), "31.01.2024");
+        assert_eq!(parse("fr").unwrap().date(timestamp), "31/01/2024");
+        assert_eq!(parse("ja").unwrap().date(timestamp), "2024-01-31");
+    }
+
+    #[test]
+    fn test_unknown_locale() {
+        assert!(parse("xx-nope").is_err());
+    }
+}
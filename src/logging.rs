@@ -0,0 +1,87 @@
+use clap::ValueEnum;
+use tracing::Subscriber;
+use tracing_subscriber::{fmt::MakeWriter, util::SubscriberInitExt, EnvFilter};
+
+/// How log records are written to stderr.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the record and its spans
+    Json,
+}
+
+/// Sets up logging, filtered with `RUST_LOG` and only showing errors by
+/// default. Records of the `log` crate, like grammers', are included.
+pub fn init(format: Format) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error"));
+    subscriber(format, filter, std::io::stderr).init();
+}
+
+fn subscriber<W>(format: Format, filter: EnvFilter, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+
+    match format {
+        Format::Text => Box::new(builder.finish()),
+        Format::Json => Box::new(
+            builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(true)
+                .finish(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let buffer = buffer.clone();
+            move || Buffer(buffer.clone())
+        };
+
+        let subscriber = subscriber(Format::Json, EnvFilter::new("info"), writer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("crawl", chat = "rustlang").entered();
+            tracing::info!(messages = 42, "fetched history page");
+            tracing::debug!("left out by the filter");
+        });
+
+        let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+
+        let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["message"], "fetched history page");
+        assert_eq!(record["messages"], 42);
+        assert_eq!(record["span"]["name"], "crawl");
+        assert_eq!(record["span"]["chat"], "rustlang");
+    }
+}
@@ -1,68 +1,817 @@
-use std::{collections::HashMap, fs, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use clap::{Parser, Subcommand, ValueEnum};
 use color_eyre::eyre::{self, Result};
-use grammers_client::{
-    types::{chat::Chat, Message},
-    Client, Config, SignInError,
-};
-use grammers_session::Session;
-use grammers_tl_types::enums::MessageEntity;
-use indicatif::ProgressBar;
-use inquire::{Password, Text};
+use futures_util::{stream, StreamExt};
+use grammers_client::{client::messages::InvocationError, types::chat::Chat, Client};
+use grammers_tl_types as tl;
+use indicatif::{MultiProgress, ProgressBar};
+use inquire::Text;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tracing::Instrument;
 
+/// Prints a message for people, to stderr instead when the results go to
+/// stdout, with `--out -` or `--storage stdout://`, so it stays out of
+/// pipelines.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::stdout_taken() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod admins;
+mod anonymize;
+mod auth;
+mod autojoin;
+mod browse;
+mod cache;
+mod compress;
+mod config;
+mod cooccurrence;
 mod credentials;
-use credentials::ApiCredentials;
+mod crosspost;
+mod details;
+mod dialogs;
+mod diff;
+mod discover;
+mod enrich;
+mod events;
+mod export;
+mod filter;
+mod flood;
+mod folder;
+mod history;
+mod invites;
+mod language;
+mod locale;
+mod logging;
+mod members;
+mod metrics;
+#[cfg(feature = "proxy")]
+mod mtproxy;
+mod notify;
+mod output;
+mod passive;
+mod paths;
+mod polls;
+#[cfg(feature = "http")]
+mod probe;
+mod provenance;
+mod proxy;
+mod ratelimit;
+mod resume;
+mod retry;
+mod risk;
+mod sample;
+mod schedule;
+mod seen;
+mod senders;
+#[cfg(feature = "server")]
+mod server;
+mod shutdown;
+mod snippet;
+mod spill;
+mod stats;
+mod stop;
+mod storage;
+mod summary;
+mod target;
+mod timeseries;
+mod topics;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "http")]
+mod unshorten;
+mod usage;
+mod watch;
+#[cfg(feature = "webhook")]
+mod webhook;
+use anonymize::Anonymizer;
+use config::Strategy;
+use filter::Filter;
+use provenance::Provenance;
+use rampilo::{
+    extractors::{self, MessageText, Pipeline, UsernameFilter},
+    LinkType,
+};
+use resume::ResumeToken;
+use stats::Stats;
+use storage::Storage;
+
+/// Optional cargo features, listed by `--version` so users can check what a
+/// binary is able to do.
+const FEATURES: &[(&str, bool)] = &[
+    ("parquet", cfg!(feature = "parquet")),
+    ("tui", cfg!(feature = "tui")),
+    ("keyring", cfg!(feature = "keyring")),
+    ("sqlite", cfg!(feature = "sqlite")),
+    ("postgres", cfg!(feature = "postgres")),
+    ("http", cfg!(feature = "http")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("webhook", cfg!(feature = "webhook")),
+    ("server", cfg!(feature = "server")),
+    ("proxy", cfg!(feature = "proxy")),
+];
+
+fn long_version() -> &'static str {
+    static LONG_VERSION: OnceLock<String> = OnceLock::new();
+    LONG_VERSION.get_or_init(|| {
+        let features: Vec<_> = FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+
+        let features = if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        };
+
+        format!("{}\nfeatures: {features}", env!("CARGO_PKG_VERSION"))
+    })
+}
+
+/// A simple telegram crawler that extracts usernames and telegram links from a chat.
+#[derive(Parser)]
+#[command(version, long_version = long_version(), about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Chats to crawl, as usernames, t.me links, invite links or ids of chats
+    /// the account is in, asked for if none are given
+    #[arg(value_name = "CHAT")]
+    chats: Vec<target::Target>,
+
+    /// Join the chats given as invite links that the account isn't in and
+    /// can't peek into. Joining shows in the chat and is refused with
+    /// `--strict-passive`
+    #[arg(long)]
+    join: bool,
+
+    #[command(flatten)]
+    auto_join: autojoin::Options,
+
+    /// Also crawl the chats in the account's chat folder with this name
+    #[arg(long, value_name = "FOLDER")]
+    from_folder: Option<String>,
+
+    #[command(flatten)]
+    dialogs: dialogs::Options,
+
+    /// Also count the joins, leaves, pins and upgrades announced by service
+    /// messages and write them to `<chat>.events.json`
+    #[arg(long)]
+    events: bool,
+
+    /// Also detect the languages the messages are written in, writing them
+    /// to `<chat>.languages.json`, and the language of every description
+    #[arg(long)]
+    languages: bool,
+
+    /// Also write the participants of the crawled groups, with their roles,
+    /// to `<chat>.members.json`. Channels only list them to their admins
+    #[arg(long)]
+    export_members: bool,
+
+    /// List the admins of the resolved groups and channels where Telegram
+    /// shows them, and write the ones running several of them to
+    /// `admins.json`
+    #[arg(long)]
+    admins: bool,
+
+    /// Fingerprint the messages of the crawled chats and write the identical
+    /// or near-identical posts found in more than one of them to
+    /// `crossposts.json`
+    #[arg(long)]
+    crossposts: bool,
+
+    /// Also collect the polls and quizzes with their options and votes and
+    /// write them to `<chat>.polls.json`
+    #[arg(long)]
+    polls: bool,
+
+    /// Attribute the links found in groups to the members who posted them
+    /// and write who promotes what to `<chat>.senders.json`
+    #[arg(long)]
+    senders: bool,
+
+    /// Count which links are posted together in the same messages and write
+    /// the pairs found together more than once to `<chat>.cooccurrence.json`
+    #[arg(long)]
+    cooccurrence: bool,
+
+    /// Count the mentions of every link by day, week or month and write
+    /// them to `<chat>.timeseries.json`
+    #[arg(long, value_enum, value_name = "INTERVAL")]
+    timeseries: Option<timeseries::Interval>,
+
+    /// Extract from a Telegram Desktop export (`result.json` or a directory of
+    /// exported HTML files) without connecting to Telegram
+    #[arg(long, value_name = "PATH")]
+    from_export: Option<PathBuf>,
+
+    /// Comma separated list of the formats to write the results in, one
+    /// file each
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [output::Format::Json]
+    )]
+    format: Vec<output::Format>,
+
+    #[command(flatten)]
+    destination: output::Destination,
+
+    #[command(flatten)]
+    stop: stop::Conditions,
+
+    /// Print a table of the 20 most found links, the totals of every link
+    /// type, the messages scanned and how long it took after the crawl
+    #[arg(long)]
+    summary: bool,
+
+    /// Only print the `--summary` table instead of writing a results file
+    #[arg(long)]
+    summary_only: bool,
+
+    /// Print the requests made to Telegram by type, with the bytes sent and
+    /// received and the flood waits, after the crawl
+    #[arg(long)]
+    usage: bool,
+
+    /// Page through the results after the crawl, marking entries as
+    /// interesting or ignored and exporting the interesting ones
+    #[arg(long)]
+    browse: bool,
+
+    /// Replace the links, chat names and ids in the results files with
+    /// hashes keyed with KEY, to share reports without exposing who is in them
+    #[arg(
+        long,
+        value_name = "KEY",
+        env = "RAMPILO_ANONYMIZE_KEY",
+        hide_env_values = true
+    )]
+    anonymize: Option<String>,
+
+    /// Number of messages requested per history page (at most 100)
+    #[arg(long, default_value_t = history::MAX_PAGE_SIZE)]
+    page_size: usize,
+
+    /// Always request `--page-size` messages instead of shrinking pages when
+    /// Telegram slows down or asks to wait
+    #[arg(long)]
+    fixed_page_size: bool,
+
+    /// Follow the redirects of links of url shorteners like bit.ly and count
+    /// the ones that lead to Telegram as the Telegram link
+    #[cfg(feature = "http")]
+    #[arg(long)]
+    unshorten: bool,
+
+    /// Count `@foo` mentions and `t.me/foo` links as a single entry
+    #[arg(long)]
+    merge_duplicates: bool,
+
+    /// Comma separated list of the extractors to run on every message
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = extractors::Kind::DEFAULT
+    )]
+    extract: Vec<extractors::Kind>,
+
+    /// File of usernames to leave out of the results, one a line, such as
+    /// your own channels or common bots
+    #[arg(long, value_name = "PATH", value_parser = parse_username_list)]
+    ignore_usernames: Option<HashSet<String>>,
+
+    /// File of the only usernames to count, one a line, other links are
+    /// counted as usual
+    #[arg(long, value_name = "PATH", value_parser = parse_username_list)]
+    only_usernames: Option<HashSet<String>>,
+
+    /// Only scan messages containing this keyword (ignoring case), can be
+    /// given more than once
+    #[arg(long, value_name = "KEYWORD")]
+    filter_keyword: Vec<String>,
+
+    /// Only scan messages matching this regular expression, can be given more
+    /// than once
+    #[arg(long, value_name = "REGEX")]
+    filter_regex: Vec<Regex>,
+
+    /// Also crawl the groups and channels linked from the crawled chat, and
+    /// the ones linked from those, up to this many hops away
+    #[arg(long, default_value_t = 0)]
+    depth: usize,
+
+    /// Weight of a link for every hop between the crawled chat and the chat
+    /// it was found in, e.g. 0.5 counts links two hops away a quarter
+    #[arg(long, default_value_t = 1.0)]
+    decay: f64,
+
+    /// Only scan the pinned messages of the crawled chats, which tend to hold
+    /// their most important links
+    #[arg(long)]
+    pinned_only: bool,
+
+    /// Only scan the pinned messages of the chats crawled with `--depth` too
+    #[arg(long)]
+    pinned_nested: bool,
+
+    /// Only scan the messages of this topic of a forum group, given by the
+    /// id in its `t.me/<chat>/<id>` link
+    #[arg(long, value_name = "ID")]
+    topic: Option<i32>,
+
+    /// Sort the messages of forum groups into their topics and write the
+    /// links found in every topic to `<chat>.topics.json`
+    #[arg(long)]
+    topics: bool,
+
+    /// Only scan the messages Telegram's own search finds for this query in
+    /// the crawled chats, instead of their whole history
+    #[arg(long, value_name = "QUERY")]
+    search: Option<String>,
+
+    /// Sign in as a bot instead of a user account. Bots can only crawl channels
+    /// and supergroups they are a member of
+    #[arg(long, env = "RAMPILO_BOT_TOKEN", hide_env_values = true)]
+    bot_token: Option<String>,
+
+    /// Encrypt the session file with a passphrase, asked for on startup or
+    /// taken from `RAMPILO_SESSION_PASSPHRASE`. Once encrypted, it is always
+    /// read and saved with the passphrase
+    #[arg(long)]
+    encrypt_session: bool,
 
-const SESSION_FILE: &str = "crawler.session";
+    /// Reach Telegram through this proxy, `socks5://[USER:PASSWORD@]HOST:PORT`
+    /// or a `t.me/proxy?server=...` link of an MTProto proxy, instead of the
+    /// `proxy` of `rampilo.toml`
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "RAMPILO_PROXY",
+        hide_env_values = true
+    )]
+    proxy: Option<proxy::Proxy>,
 
-#[derive(Debug, Deserialize, Serialize)]
+    /// Save the results gathered so far every this many messages, so a crash
+    /// of a long crawl can be resumed from the last save. 0 only saves them
+    /// when the crawl aborts
+    #[arg(long, value_name = "MESSAGES", default_value_t = 10_000)]
+    flush_every: usize,
+
+    /// Keep at most 100,000 entries in memory while reading the history of a
+    /// chat and move the others to disk, for chats with millions of
+    /// distinct links
+    #[arg(long)]
+    low_memory: bool,
+
+    /// Scan only this share of the messages, e.g. 0.1 for one in ten, for a
+    /// quick estimate of what a very large chat links to
+    #[arg(long, value_name = "RATE", value_parser = sample::parse_rate)]
+    sample: Option<f64>,
+
+    /// Continue an aborted crawl from the token it printed
+    #[arg(long, value_name = "TOKEN")]
+    resume_token: Option<ResumeToken>,
+
+    /// Database of the links found by earlier crawls, used to flag new ones,
+    /// `seen.json` in the data directory by default
+    #[arg(long, value_name = "PATH")]
+    seen_db: Option<PathBuf>,
+
+    /// Usernames resolved by earlier runs, looked up again once they are
+    /// older than `--cache-ttl`, `resolve-cache.json` in the cache directory
+    /// by default
+    #[arg(long, value_name = "PATH")]
+    resolve_cache: Option<PathBuf>,
+
+    /// Usernames whose lookup failed, looked up again by `rampilo retry`,
+    /// `retry-queue.json` in the data directory by default
+    #[arg(long, value_name = "PATH")]
+    retry_queue: Option<PathBuf>,
+
+    /// Hours a cached username stays valid, 0 to resolve every one again
+    #[arg(long, value_name = "HOURS", value_parser = cache::parse_ttl, default_value = "168")]
+    cache_ttl: Duration,
+
+    /// Locale used for numbers in human-facing output, e.g. `en`, `de` or `fr-CA`
+    #[arg(long, value_parser = locale::parse, default_value = "en")]
+    locale: locale::Formatter,
+
+    /// Also store the results in `json://`, `ndjson://`, `stdout://`,
+    /// `sqlite://`, `postgres://` or `webhook+https://` storage
+    #[arg(long, value_name = "URL")]
+    storage: Option<storage::Location>,
+
+    /// Settings file, see the README for what it can hold, `rampilo.toml`
+    /// in the config directory by default
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Keep the session, settings and caches apart from the ones of other
+    /// profiles, e.g. to crawl with several accounts
+    #[arg(
+        long,
+        env = "RAMPILO_PROFILE",
+        value_parser = paths::parse_profile,
+        default_value = paths::DEFAULT_PROFILE
+    )]
+    profile: String,
+
+    /// Format of the log records written to stderr, see `RUST_LOG`
+    #[arg(long, value_enum, default_value_t)]
+    log_format: logging::Format,
+
+    /// Fail instead of sending any request that isn't a read-only one, such
+    /// as marking messages as read or joining a chat
+    #[arg(long)]
+    strict_passive: bool,
+
+    /// POST a JSON payload to this url when a crawl finishes, or when
+    /// `watch` finds a link for the first time
+    #[cfg(feature = "webhook")]
+    #[arg(long, value_name = "URL")]
+    notify_url: Option<String>,
+
+    /// Comma separated list of the chat types to keep in the results
+    #[arg(long, value_enum, value_delimiter = ',')]
+    only: Vec<UsernameType>,
+
+    /// Score how much the resolved chats look like scam or spam, from
+    /// Telegram's warning labels, their age and their descriptions
+    #[arg(long)]
+    risk_score: bool,
+
+    /// Keep the entries that couldn't be resolved, such as deleted usernames
+    /// and invite links, instead of dropping them
+    #[arg(long)]
+    unresolved: bool,
+
+    /// Keep the text around the first mentions of every link, to see how it
+    /// was mentioned without opening Telegram
+    #[arg(long)]
+    snippets: bool,
+
+    /// Keep up to N snippets of every link with `--snippets`
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "snippets")]
+    max_snippets: usize,
+
+    /// Drop the entries found fewer than N times from the results
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    min_count: usize,
+
+    /// Only keep the K highest scored entries in the results
+    #[arg(long, value_name = "K")]
+    top: Option<usize>,
+
+    /// Number of chats crawled at the same time
+    #[arg(long, default_value_t = 3)]
+    concurrency: usize,
+
+    /// Requests per second, shared by all chats crawled at the same time
+    #[arg(
+        long,
+        alias = "rate-limit",
+        value_parser = ratelimit::parse_rate,
+        default_value = "10"
+    )]
+    requests_per_second: f64,
+
+    /// Least time between two usernames resolved, on top of
+    /// `--requests-per-second`, for accounts that get flood waits easily
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    resolve_delay_ms: u64,
+
+    /// Show a live dashboard while crawling
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+}
+
+impl Args {
+    fn seen_db(&self) -> PathBuf {
+        self.seen_db
+            .clone()
+            .unwrap_or_else(|| paths::data_file("seen.json"))
+    }
+
+    fn resolve_cache(&self) -> PathBuf {
+        self.resolve_cache
+            .clone()
+            .unwrap_or_else(|| paths::cache_file("resolve-cache.json"))
+    }
+
+    fn retry_queue(&self) -> PathBuf {
+        self.retry_queue
+            .clone()
+            .unwrap_or_else(|| paths::data_file("retry-queue.json"))
+    }
+
+    /// Extractors of `--extract`, leaving out the usernames of
+    /// `--ignore-usernames` and `--only-usernames`.
+    fn pipeline(&self) -> Pipeline {
+        let mut usernames = UsernameFilter::default();
+        if let Some(ref ignore) = self.ignore_usernames {
+            usernames = usernames.ignore(ignore.clone());
+        }
+        if let Some(ref only) = self.only_usernames {
+            usernames = usernames.only(only.clone());
+        }
+        Pipeline::new(&self.extract).usernames(usernames)
+    }
+
+    fn config(&self) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| paths::config_file("rampilo.toml"))
+    }
+}
+
+/// Reads a file of usernames for `--ignore-usernames` or `--only-usernames`.
+fn parse_username_list(path: &str) -> Result<HashSet<String>, String> {
+    let list = std::fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    Ok(UsernameFilter::parse_list(&list))
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Work with invite links without crawling a chat
+    #[command(subcommand)]
+    Invites(invites::Command),
+    /// Sign in and save the session, e.g. after it was revoked while
+    /// watching chats
+    Login,
+    /// Log the saved session out, revoking it on Telegram's side too, and
+    /// delete it
+    Logout,
+    /// Tell which account the saved session is signed in as, without
+    /// signing in
+    Whoami,
+    /// Move the saved session to another machine, e.g. a server or a
+    /// container where signing in isn't possible
+    #[command(subcommand)]
+    Session(credentials::Command),
+    /// Keep checking chats for new messages and report the new links in them
+    Watch(watch::Options),
+    /// Crawl chats on a schedule, writing a dated snapshot of the links in
+    /// the messages since the last run
+    Schedule(schedule::Options),
+    /// Search Telegram for the public groups and channels matching keywords,
+    /// and crawl them with `--crawl`
+    Discover(discover::Options),
+    /// Serve an HTTP API that runs crawls in the background
+    #[cfg(feature = "server")]
+    Serve(server::Options),
+    /// Compare the JSON results of two crawls, listing the links that
+    /// appeared, disappeared or changed count
+    Diff(diff::Options),
+    /// Look up the entries of an earlier results file that have no metadata,
+    /// e.g. after the resolution of a crawl was interrupted
+    Enrich(enrich::Options),
+    /// Look up again the usernames whose lookup failed in earlier crawls and
+    /// update the JSON results they were left out of
+    Retry,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct Username {
     username: LinkType,
     count: usize,
     metadata: Option<UsernameMetadata>,
+    /// Whether no earlier crawl found this link.
+    #[serde(default)]
+    new: bool,
+    /// Other links that resolved to the same chat and were merged into this one.
+    #[serde(default)]
+    aliases: Vec<LinkType>,
+    /// Fewest hops between the crawled chat and a chat the link was found
+    /// in, 0 for the crawled chat itself.
+    #[serde(default)]
+    depth: usize,
+    /// Count weighted by `--decay` for every hop away from the crawled chat,
+    /// which results are ranked by.
+    #[serde(default)]
+    score: f64,
+    /// Unix timestamp of the earliest message the link appeared in.
+    #[serde(default)]
+    first_seen: Option<i64>,
+    /// Unix timestamp of the most recent message the link appeared in.
+    #[serde(default)]
+    last_seen: Option<i64>,
+    /// Reactions on the messages the link appeared in, summed, to rank links
+    /// by engagement rather than frequency.
+    #[serde(default)]
+    reactions: usize,
+    /// Views of the channel posts the link appeared in, summed, to rank links
+    /// by how many people they reached.
+    #[serde(default)]
+    views: usize,
+    /// Links to the first few messages the link appeared in, to jump to its
+    /// context.
+    #[serde(default)]
+    messages: Vec<String>,
+    /// Text around the first few mentions of the link, to see how it was
+    /// mentioned.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    snippets: Vec<String>,
+    /// Why looking up the link gave no metadata, if it was looked up.
+    #[serde(default)]
+    unresolved: Option<Unresolved>,
 }
 
+/// Most message links kept for every entry.
+const MAX_MESSAGES: usize = 3;
+
 impl Username {
     fn new(username: LinkType) -> Self {
         Self {
             username,
             count: 1,
             metadata: None,
+            new: false,
+            aliases: Vec::new(),
+            depth: 0,
+            score: 1.0,
+            first_seen: None,
+            last_seen: None,
+            reactions: 0,
+            views: 0,
+            messages: Vec::new(),
+            snippets: Vec::new(),
+            unresolved: None,
         }
     }
-}
 
-#[derive(Deserialize, Serialize, Hash, PartialEq, Eq, Debug)]
-enum LinkType {
-    Username(String),
-    Hash(String),
-    Mention(String),
-}
+    /// Fills in what looking up the link gave.
+    fn resolved(&mut self, resolution: Resolution) {
+        (self.metadata, self.unresolved) = match resolution {
+            Ok(metadata) => (Some(metadata), None),
+            Err(reason) => (None, Some(reason)),
+        };
+    }
 
-impl ToString for LinkType {
-    fn to_string(&self) -> String {
-        match self {
-            LinkType::Username(username) => username.to_string(),
-            LinkType::Hash(hash) => hash.to_string(),
-            LinkType::Mention(username) => username.to_string(),
+    /// Keeps the link of a message the link appeared in, unless there are
+    /// enough already.
+    fn seen_in(&mut self, message: Option<&str>) {
+        let Some(message) = message else {
+            return;
+        };
+        if self.messages.len() < MAX_MESSAGES && !self.messages.iter().any(|m| m == message) {
+            self.messages.push(message.to_string());
+        }
+    }
+
+    /// Keeps the text around the link in `text`, up to `--max-snippets` of
+    /// them.
+    fn quoted(&mut self, text: &str) {
+        if self.snippets.len() >= snippet::limit() {
+            return;
+        }
+        if let Some(snippet) = snippet::around(text, &self.username) {
+            if !self.snippets.contains(&snippet) {
+                self.snippets.push(snippet);
+            }
+        }
+    }
+
+    /// Widens the first and last seen dates to include `date`.
+    fn seen_at(&mut self, date: Option<i64>) {
+        let Some(date) = date else {
+            return;
+        };
+        self.first_seen = Some(self.first_seen.map_or(date, |first| first.min(date)));
+        self.last_seen = Some(self.last_seen.map_or(date, |last| last.max(date)));
+    }
+
+    /// Adds the counts of another entry for the same link to this one.
+    fn absorb(&mut self, other: &Username) {
+        self.count += other.count;
+        self.score += other.score;
+        self.reactions += other.reactions;
+        self.views += other.views;
+        self.depth = self.depth.min(other.depth);
+        self.new &= other.new;
+        self.seen_at(other.first_seen);
+        self.seen_at(other.last_seen);
+        for message in &other.messages {
+            self.seen_in(Some(message));
+        }
+        for snippet in &other.snippets {
+            if self.snippets.len() < snippet::limit() && !self.snippets.contains(snippet) {
+                self.snippets.push(snippet.clone());
+            }
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct UsernameMetadata {
+    /// Telegram id of the chat the link resolved to, 0 if it isn't known.
+    #[serde(default)]
+    id: i64,
     name: String,
     #[serde(rename = "type")]
     type_: UsernameType,
+    /// Description of the group or channel, or the bio of the user.
+    #[serde(default)]
+    about: Option<String>,
+    #[serde(default)]
+    members: Option<usize>,
+    /// Unix timestamp of when the chat was created, or joined by the
+    /// crawling account.
+    #[serde(default)]
+    date: Option<i64>,
+    /// Why Telegram restricts the chat, e.g. after a takedown, as the
+    /// reasons and explanations it gives.
+    #[serde(default)]
+    restriction: Option<String>,
+    /// ISO 639-3 code of the language of `about`, with `--languages`.
+    #[serde(default)]
+    language: Option<String>,
+    /// Warning label Telegram shows on the chat.
+    #[serde(default)]
+    label: Option<risk::Label>,
+    /// How suspicious the chat looks, with `--risk-score`.
+    #[serde(default)]
+    risk: Option<risk::Risk>,
+    /// Id of the discussion group of a channel, or of the channel a group
+    /// discusses.
+    #[serde(default)]
+    linked_chat: Option<i64>,
+    /// Admins and creator of the group or channel, with `--admins`. Empty
+    /// when Telegram doesn't list them.
+    #[serde(default)]
+    admins: Option<Vec<admins::Admin>>,
+}
+
+/// Why a username or user couldn't be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Unresolved {
+    /// No chat has the username.
+    NotFound,
+    /// The user deleted their account.
+    Deleted,
+    /// The chat is private or the crawling account is banned from it.
+    Inaccessible,
+    /// The lookup failed, a later crawl may resolve it.
+    Failed,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Unresolved {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Unresolved::NotFound => "not found",
+            Unresolved::Deleted => "deleted",
+            Unresolved::Inaccessible => "inaccessible",
+            Unresolved::Failed => "failed",
+        }
+    }
+}
+
+/// What looking up a username or user gave.
+type Resolution = Result<UsernameMetadata, Unresolved>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
 enum UsernameType {
+    #[value(name = "users", alias = "user")]
     User,
+    #[value(name = "groups", alias = "group")]
     Group,
+    #[value(name = "channels", alias = "channel")]
     Channel,
+    /// A web page found by probing a url.
+    #[value(name = "websites", alias = "website")]
+    Website,
+}
+
+impl UsernameType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UsernameType::User => "User",
+            UsernameType::Group => "Group",
+            UsernameType::Channel => "Channel",
+            UsernameType::Website => "Website",
+        }
+    }
 }
 
 impl From<&Chat> for UsernameMetadata {
@@ -74,8 +823,18 @@ impl From<&Chat> for UsernameMetadata {
         };
 
         Self {
+            id: chat.id(),
             name: chat.name().to_string(),
             type_,
+            about: None,
+            members: None,
+            date: None,
+            restriction: None,
+            language: None,
+            label: None,
+            risk: None,
+            linked_chat: None,
+            admins: None,
         }
     }
 }
@@ -84,186 +843,1723 @@ type Usernames = HashMap<String, Username>;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let started = std::time::Instant::now();
     color_eyre::install()?;
-    pretty_env_logger::init();
 
-    println!("Connecting to Telegram servers...");
-    let session = Session::load_file_or_create("crawler.session")?;
+    let args = Args::parse();
+    logging::init(args.log_format);
+    paths::set_profile(&args.profile);
+    passive::set_strict(args.strict_passive);
+    admins::set(args.admins);
+    let storage_stdout = args.storage.as_ref().is_some_and(|s| s.is_stdout());
+    if storage_stdout && args.destination.out.is_some() {
+        eyre::bail!("--out - and --storage stdout:// can't both write to stdout");
+    }
+    output::set_stdout(args.destination.out.is_some());
+    output::set_stdout_events(storage_stdout);
+    sample::set_rate(args.sample.unwrap_or(1.0));
+    snippet::set_limit(if args.snippets { args.max_snippets } else { 0 });
+    if output::to_stdout() {
+        match args.format[..] {
+            [format] if format.is_streamable() => {}
+            _ => eyre::bail!("--out - writes one of the json, jsonl and csv formats"),
+        }
+    }
+
+    if let Some(ref path) = args.from_export {
+        return crawl_export(path, &args).await;
+    }
+    if let Some(Command::Diff(ref options)) = args.command {
+        return diff::run(options, args.locale);
+    }
 
-    let credentials = ApiCredentials::load()?;
+    let config = config::load(&args.config())?;
+    let proxy = match (&args.proxy, &config.proxy) {
+        (Some(proxy), _) => Some(proxy.clone()),
+        (None, Some(proxy)) => Some(proxy.parse().map_err(|e| eyre::eyre!("proxy: {e}"))?),
+        (None, None) => None,
+    };
+    if let Some(proxy) = proxy {
+        proxy::set(proxy);
+    }
 
-    let client = Client::connect(Config {
-        session,
-        api_id: credentials.api_id(),
-        api_hash: credentials.api_hash().to_owned(),
-        params: Default::default(),
-    })
-    .await?;
-    println!("Connected!");
+    let bot = args.bot_token.is_some();
+    match args.command {
+        Some(Command::Login) => {
+            return auth::login(args.bot_token.as_deref(), args.encrypt_session).await;
+        }
+        Some(Command::Logout) => return auth::logout(bot, args.encrypt_session).await,
+        Some(Command::Whoami) => return auth::whoami(bot, args.encrypt_session).await,
+        Some(Command::Session(ref command)) => {
+            return credentials::run(command, bot, args.encrypt_session);
+        }
+        _ => {}
+    }
 
-    let is_authorized = client.is_authorized().await?;
+    let client = auth::connect(args.bot_token.as_deref(), args.encrypt_session).await?;
+    let mut seeds = Vec::new();
+    match args.command {
+        Some(Command::Invites(ref command)) => {
+            return invites::run(&client, command, args.locale).await;
+        }
+        Some(Command::Watch(ref options)) => {
+            return watch::run(client, &args, options, &config).await;
+        }
+        Some(Command::Schedule(ref options)) => {
+            return schedule::run(&client, &args, options, &config).await;
+        }
+        #[cfg(feature = "server")]
+        Some(Command::Serve(ref options)) => {
+            return server::run(&client, &args, options, &config).await;
+        }
+        Some(Command::Enrich(ref options)) => {
+            return enrich::run(&client, &args, options, &config).await;
+        }
+        Some(Command::Retry) => return retry::run(&client, &args, &config).await,
+        Some(Command::Discover(ref options)) => {
+            seeds = discover::run(&client, options, &args.destination, args.locale).await?;
+            if !options.crawl || seeds.is_empty() {
+                return Ok(());
+            }
+        }
+        // Diffs don't need Telegram and the session is managed without
+        // signing in, both are handled before connecting.
+        Some(
+            Command::Diff(_)
+            | Command::Login
+            | Command::Logout
+            | Command::Whoami
+            | Command::Session(_),
+        )
+        | None => {}
+    }
 
-    if !is_authorized {
-        sign_in(&client, credentials.api_id(), credentials.api_hash()).await?;
-        credentials.save()?;
+    let mut listed = Vec::new();
+    if let Some(ref title) = args.from_folder {
+        let chats = folder::chats(&client, title).await?;
+        status!(
+            "Crawling the {} chats of the folder {title}",
+            args.locale.number(chats.len())
+        );
+        listed.extend(chats);
+    }
+    if args.dialogs.all_dialogs {
+        if args.bot_token.is_some() {
+            eyre::bail!("Bots can't list the chats they are in");
+        }
+        let chats = dialogs::chats(&client, &args.dialogs).await?;
+        status!(
+            "Crawling {} of the chats the account is in",
+            args.locale.number(chats.len())
+        );
+        listed.extend(chats);
+    }
+    if args.from_folder.is_some() || args.dialogs.all_dialogs {
+        seeds.extend(args.chats.iter().cloned());
+        seeds.extend(listed);
+        seeds.sort();
+        seeds.dedup();
+        if seeds.is_empty() {
+            eyre::bail!("There are no chats to crawl");
+        }
     }
 
-    let client_handle = client.clone();
+    let storage = Mutex::new(open_storage(&args).await?);
+    ratelimit::init(
+        args.requests_per_second,
+        Duration::from_millis(args.resolve_delay_ms),
+    );
+    shutdown::listen();
+    args.stop.start();
 
-    let username = Text::new("Enter the username: ").prompt()?;
-    let maybe_chat = client_handle.resolve_username(&username).await?;
+    let chats = match args.resume_token {
+        Some(ref token) => vec![token.chat.parse()?],
+        None if !seeds.is_empty() => seeds,
+        None if !args.chats.is_empty() => args.chats.clone(),
+        None => vec![Text::new("Enter the chat: ").prompt()?.parse()?],
+    };
 
-    let chat = maybe_chat
-        .ok_or_else(|| eyre::eyre!("Could not find a chat with the username {}", username))?;
+    #[cfg(feature = "tui")]
+    if args.tui && chats.len() > 1 {
+        eyre::bail!("The dashboard can only follow a single chat");
+    }
 
-    let mut usernames: Usernames = HashMap::new();
+    #[cfg(feature = "tui")]
+    let progress = if args.tui {
+        MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    #[cfg(not(feature = "tui"))]
+    let progress = MultiProgress::new();
 
-    let mut count = 0;
-    let mut messages = client_handle.iter_messages(&chat);
+    let ctx = Context {
+        client: &client,
+        args: &args,
+        config: &config,
+        storage: &storage,
+        seen: &Mutex::new(seen::SeenDb::load(&args.seen_db())?),
+        cache: &Mutex::new(cache::ResolveCache::load(
+            &args.resolve_cache(),
+            args.cache_ttl,
+        )?),
+        crossposts: &Mutex::default(),
+        progress,
+        snapshot: None,
+    };
 
-    let progress_bar = ProgressBar::new_spinner();
-    progress_bar.enable_steady_tick(Duration::from_millis(100));
-    while let Some(message) = messages.next().await? {
-        extract_link(&message, &mut usernames);
-        extract_mentions(&message, &mut usernames);
-        count += 1;
-        progress_bar.set_message(message.id().to_string());
+    let results: Vec<_> = stream::iter(&chats)
+        .map(|chat| {
+            let ctx = &ctx;
+            async move { (chat, crawl_chat(ctx, chat, 0).await) }
+        })
+        .buffer_unordered(args.concurrency.max(1))
+        .collect()
+        .await;
+    ctx.seen.lock().unwrap().save()?;
+    ctx.cache.lock().unwrap().save()?;
+    if args.crossposts {
+        let mut clusters = ctx.crossposts.lock().unwrap().clusters();
+        if let Some(ref key) = args.anonymize {
+            Anonymizer::new(key).apply_crossposts(&mut clusters);
+        }
+        let filename = crosspost::write(&clusters, &args.destination)?;
+        status!(
+            "{} posts were cross-posted to several chats, saved to {filename}",
+            args.locale.number(clusters.len())
+        );
+    }
+    if args.admins {
+        let crawled = results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok());
+        let mut shared = admins::shared(crawled.flat_map(|c| c.usernames.iter()));
+        if let Some(ref key) = args.anonymize {
+            Anonymizer::new(key).apply_shared_admins(&mut shared);
+        }
+        let filename = admins::write(&shared, &args.destination)?;
+        status!(
+            "{} people admin several of the chats found, saved to {filename}",
+            args.locale.number(shared.len())
+        );
     }
+
+    if args.dialogs.all_dialogs {
+        let crawled = results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok());
+        let mut usernames = dialogs::aggregate(crawled.map(|c| c.usernames.as_slice()));
+        trim(&mut usernames, args.min_count, args.top);
+        let anonymizer = args.anonymize.as_deref().map(Anonymizer::new);
+        let provenance = Provenance::new(dialogs::RESULTS_NAME).sample(args.sample);
+        let files = write_results(
+            &mut usernames,
+            dialogs::RESULTS_NAME,
+            dialogs::RESULTS_NAME,
+            anonymizer.as_ref(),
+            provenance,
+            &mut storage::Discard,
+            &args,
+        )?;
+        if !files.is_empty() {
+            status!(
+                "Saved {} usernames found across all chats to {}",
+                args.locale.number(usernames.len()),
+                files.join(", ")
+            );
+        }
+    }
+
+    if args.stop.max_requests.is_some() {
+        let (sent, resolved) = ratelimit::sent();
+        status!(
+            "Sent {} requests to Telegram, resolving {} usernames and users",
+            args.locale.number(sent),
+            args.locale.number(resolved)
+        );
+    }
+    if args.usage {
+        status!("{}", usage::report(started, args.locale));
+    }
+
+    if args.browse {
+        let crawled = chats
+            .iter()
+            .filter_map(|chat| results.iter().find(|(c, _)| *c == chat))
+            .filter_map(|(_, result)| result.as_ref().ok());
+        for crawled in crawled {
+            browse::run(&crawled.chat, &crawled.usernames, &args)?;
+        }
+    }
+
+    let mut errors: Vec<_> = results
+        .into_iter()
+        .filter_map(|(chat, result)| result.err().map(|e| (chat, e)))
+        .collect();
+    match errors.len() {
+        0 => Ok(()),
+        _ if chats.len() == 1 => Err(errors.remove(0).1),
+        failed => {
+            for (chat, e) in &errors {
+                eprintln!("Could not crawl {chat}: {e}");
+            }
+            eyre::bail!("{failed} of {} chats could not be crawled", chats.len())
+        }
+    }
+}
+
+/// What every chat crawled in parallel shares.
+struct Context<'a> {
+    client: &'a Client,
+    args: &'a Args,
+    config: &'a config::Config,
+    storage: &'a Mutex<Box<dyn Storage>>,
+    seen: &'a Mutex<seen::SeenDb>,
+    cache: &'a Mutex<cache::ResolveCache>,
+    /// Fingerprints of the messages of every chat crawled, for `--crossposts`.
+    crossposts: &'a Mutex<crosspost::Index>,
+    progress: MultiProgress,
+    /// Time of the scheduled run, added to the names of the results files.
+    snapshot: Option<String>,
+}
+
+impl Context<'_> {
+    /// Prints above the progress bars of the other chats, if there are any.
+    fn println(&self, message: impl AsRef<str>) {
+        if self.progress.is_hidden() {
+            status!("{}", message.as_ref());
+        } else {
+            let _ = self.progress.println(message);
+        }
+    }
+}
+
+/// What crawling a chat gave.
+struct Crawled {
+    /// Name of the chat in the results.
+    chat: String,
+    /// Id of the newest message read.
+    newest: i32,
+    /// Results files written, one for every format.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    files: Vec<String>,
+    usernames: Vec<Username>,
+}
+
+/// Crawls the messages of one chat newer than `min_id`, 0 for all of them,
+/// and writes their results.
+async fn crawl_chat(ctx: &Context<'_>, target: &target::Target, min_id: i32) -> Result<Crawled> {
+    let args = ctx.args;
+    let client = ctx.client;
+
+    if shutdown::requested() {
+        eyre::bail!("Interrupted before {target} was crawled");
+    }
+    if args.stop.out_of_time() {
+        eyre::bail!("Out of time before {target} was crawled");
+    }
+    if ratelimit::over_budget() {
+        eyre::bail!("Request budget used up before {target} was crawled");
+    }
+
+    let chat = target.resolve(client, args.join).await?;
+    let username = target.label(&chat);
+
+    // Bots can only read messages by id, which needs a channel's own ids.
+    if args.bot_token.is_some() && !chat.pack().is_channel() {
+        eyre::bail!("Bots can only crawl channels and supergroups");
+    }
+    if args.bot_token.is_some()
+        && (args.pinned_only || args.pinned_nested || args.search.is_some() || args.topic.is_some())
+    {
+        eyre::bail!("Bots can't search for messages");
+    }
+
+    let mut token = match args.resume_token {
+        Some(ref token) => token.clone(),
+        None => ResumeToken {
+            chat: username.clone(),
+            offset_id: 0,
+            run_id: resume::new_run_id(),
+        },
+    };
+
+    let partial = match args.resume_token {
+        Some(_) => token.load_partial()?,
+        None => Default::default(),
+    };
+    if let Some(offset_id) = partial.offset_id {
+        token.offset_id = offset_id;
+    }
+    let mut usernames = partial.usernames;
+    let mut count = partial.messages;
+    // Entries an aborted run moved to disk are picked up even without
+    // `--low-memory`.
+    let mut spill = match (args.low_memory, partial.spilled_at) {
+        (false, None) => None,
+        (_, spilled_at) => {
+            let spill = spill::Spill::open(token.spill_dir())?;
+            if spill.messages()? != spilled_at {
+                eyre::bail!(
+                    "The entries moved to disk don't match the partial results of {token}, start the crawl over"
+                );
+            }
+            Some(spill)
+        }
+    };
+    let mut tally = partial.tally;
+    tally.languages.enabled = args.languages;
+    tally.polls.enabled = args.polls;
+    tally.senders.enabled = args.senders;
+    tally.cooccurrence.enabled = args.cooccurrence;
+    tally.timeseries.interval = args.timeseries;
+    tally.topics.enabled = args.topics;
+    tally.crossposts.enabled = args.crossposts;
+
+    let stats = Arc::new(Stats::default());
+    let pipeline = args.pipeline();
+    let filter = Filter::new(&args.filter_keyword, &args.filter_regex);
+    let page_size = history::PageSize::new(args.page_size, !args.fixed_page_size);
+    let mut messages = history::History::new(client, &chat, page_size)
+        .offset_id(token.offset_id)
+        .min_id(min_id)
+        .skipped(partial.skipped)
+        .by_id(args.bot_token.is_some())
+        .pinned_only(args.pinned_only)
+        .search(args.search.as_deref())
+        .topic(args.topic)
+        .min_date(args.stop.min_date())
+        .stats(stats.clone());
+
+    #[cfg(feature = "tui")]
+    let dashboard = if args.tui {
+        Some(tui::Dashboard::start(stats.clone(), username.clone())?)
+    } else {
+        None
+    };
+
+    let progress_bar = ctx.progress.add(ProgressBar::new_spinner());
+
+    progress_bar.enable_steady_tick(Duration::from_millis(100));
+    let mut newest = min_id;
+    let mut message_ids: Option<(i32, i32)> = None;
+    let mut stop_reason = None;
+    let mut followed = false;
+    let result = read_history(
+        &mut messages,
+        &pipeline,
+        &filter,
+        &mut usernames,
+        &stats,
+        &mut count,
+        &mut tally,
+        spill.as_mut(),
+        |id, discovered, gathered| {
+            if gathered.migrated && !followed {
+                followed = true;
+                ctx.println(format!(
+                    "{username} was upgraded from or to another group, reading its history too"
+                ));
+            }
+            // Resuming only goes back into the history of the chat itself.
+            let id = id.filter(|_| !gathered.migrated);
+            if let Some(id) = id {
+                token.offset_id = id;
+                newest = newest.max(id);
+                message_ids = Some(message_ids.map_or((id, id), |(first, last)| {
+                    (first.min(id), last.max(id))
+                }));
+                progress_bar.set_message(format!("{username}: {id}"));
+            }
+            discovered
+                .iter()
+                .try_for_each(|link| ctx.storage.lock().unwrap().put_discovery(&username, link))?;
+            if stop_reason.is_none() {
+                let found = gathered.usernames.len() + gathered.on_disk;
+                stop_reason = args.stop.reached(gathered.count, found);
+                if stop_reason.is_some() {
+                    stats.stop();
+                }
+            }
+
+            // Retried messages are only saved with the rest once they are
+            // all scanned, as they are no longer among the skipped ones.
+            // Moving entries to disk always saves, to keep the two in step.
+            let spilled = gathered.spilled_at == Some(gathered.count);
+            let due = flush_due(args.flush_every, gathered.count);
+            if id.is_none() || !(spilled || due) {
+                return Ok(());
+            }
+            if gathered.count == args.flush_every {
+                ctx.println(format!(
+                    "Saving the results of {username} every {} messages, continue a crashed crawl with: --resume-token {token}",
+                    args.locale.number(args.flush_every)
+                ));
+            }
+            token.save_partial(
+                gathered.usernames,
+                gathered.count,
+                gathered.skipped,
+                gathered.tally,
+                gathered.spilled_at,
+            )?;
+            // Checkpoints would only hold the entries still in memory.
+            if gathered.spilled_at.is_none() {
+                ctx.storage
+                    .lock()
+                    .unwrap()
+                    .checkpoint(&username, gathered.usernames)?;
+            }
+            tracing::debug!(messages = gathered.count, "saved partial results");
+            Ok(())
+        },
+    )
+    .instrument(tracing::info_span!(
+        "history",
+        chat = username,
+        chat_id = chat.id()
+    ))
+    .await;
     progress_bar.finish_and_clear();
 
+    // An interrupted crawl is saved like an aborted one, so it can be resumed.
+    let result = result.and_then(|()| match shutdown::requested() {
+        true => Err(eyre::eyre!("Interrupted")),
+        false => Ok(()),
+    });
+
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        dashboard.finish()?;
+    }
+
+    if let Err(e) = result {
+        // The partial results can't tell which chat they stopped in.
+        if messages.migrated() {
+            ctx.println(format!(
+                "Crawl of {username} aborted while reading the group it was upgraded from or to, start it over"
+            ));
+            return Err(e);
+        }
+        let spilled_at = match spill {
+            Some(ref spill) => spill.messages()?,
+            None => None,
+        };
+        token.save_partial(
+            &usernames,
+            count,
+            messages.skipped_ids(),
+            &tally,
+            spilled_at,
+        )?;
+        if spilled_at.is_none() {
+            ctx.storage
+                .lock()
+                .unwrap()
+                .checkpoint(&username, &usernames)?;
+        }
+        ctx.progress.suspend(|| {
+            eprintln!("Crawl of {username} aborted, continue it with: --resume-token {token}")
+        });
+        return Err(e);
+    }
+    token.remove_partial();
+    if let Some(spill) = spill {
+        spill.restore(&mut usernames)?;
+    }
+
+    let mut users = messages.users().clone();
+    if args.depth > 0 {
+        crawl_nested(
+            ctx,
+            &username,
+            &mut usernames,
+            &mut users,
+            &mut count,
+            &stats,
+        )
+        .await;
+    }
+
+    if stats.is_stopped() {
+        ctx.println(format!(
+            "Stopped {username} early before message {}{}",
+            token.offset_id,
+            stop_reason.map_or(String::new(), |reason| format!(" ({reason})"))
+        ));
+    }
+
+    let fmt = args.locale;
+    if !messages.skipped_ids().is_empty() {
+        ctx.println(format!(
+            "Could not fetch {} messages of {username}, the results may be incomplete",
+            fmt.number(messages.skipped_ids().len())
+        ));
+    }
+
+    #[cfg(feature = "http")]
+    if args.unshorten {
+        let folded = unshorten::fold(&mut usernames, &pipeline).await;
+        ctx.println(format!(
+            "{username}: {} shortened links led to Telegram",
+            fmt.number(folded)
+        ));
+    }
+
+    if args.merge_duplicates {
+        merge_duplicates(&mut usernames);
+    }
+
     let mut usernames: Vec<_> = usernames.into_values().collect();
-    usernames.sort_by(|a, b| b.count.cmp(&a.count));
+    output::sort(&mut usernames);
+
+    ctx.seen.lock().unwrap().record(&mut usernames, &username);
+
+    ctx.println(format!(
+        "Found {} usernames from {} messages of {username} in {}s ({} messages/s), {} new since the last crawl",
+        fmt.number(usernames.len()),
+        fmt.number(count),
+        fmt.decimal(stats.elapsed().as_secs_f64()),
+        fmt.decimal(stats.rate()),
+        fmt.number(usernames.iter().filter(|u| u.new).count())
+    ));
+    if tally.unsupported_media > 0 {
+        ctx.println(format!(
+            "{username}: {} messages held giveaways, shared stories or other media too new to be read, the channels they name are left out",
+            fmt.number(tally.unsupported_media)
+        ));
+    }
+    let anonymizer = args.anonymize.as_deref().map(Anonymizer::new);
+    let file_name = results_name(&username, anonymizer.as_ref());
+    let file_name = match ctx.snapshot {
+        Some(ref stamp) => format!("{file_name}-{stamp}"),
+        None => file_name,
+    };
+    if args.events {
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_events(&mut tally.events);
+        }
+        let filename = tally.events.write(&file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {}, saved to {filename}",
+            tally.events.summary(fmt)
+        ));
+    }
+    if args.languages {
+        let filename = tally.languages.write(&file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {}, saved to {filename}",
+            tally.languages.summary(fmt)
+        ));
+    }
+    if args.polls {
+        if anonymizer.is_some() {
+            tally.polls.unlink();
+        }
+        let filename = tally.polls.write(&file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {} polls, saved to {filename}",
+            fmt.number(tally.polls.polls.len())
+        ));
+    }
+    if args.senders {
+        let mut senders = tally.senders.rows(messages.profiles());
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_senders(&mut senders);
+        }
+        let filename = senders::write(&senders, &file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {} senders posted links, {} of them by more than one, saved to {filename}",
+            fmt.number(senders.len()),
+            fmt.number(senders::shared_links(&senders))
+        ));
+    }
+    if args.cooccurrence {
+        let mut edges = tally.cooccurrence.edges();
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_cooccurrence(&mut edges);
+        }
+        let filename = cooccurrence::write(&edges, &file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {} pairs of links posted together, between {} links, saved to {filename}",
+            fmt.number(edges.len()),
+            fmt.number(cooccurrence::linked(&edges))
+        ));
+    }
+    if args.timeseries.is_some() {
+        let mut series = tally.timeseries.series();
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_timeseries(&mut series);
+        }
+        let filename = timeseries::write(&series, &file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: mentions of {} links over time, saved to {filename}",
+            fmt.number(series.len())
+        ));
+    }
+    if args.topics {
+        let mut topics = tally.topics.topics(|id| messages.permalink(id));
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_topics(&mut topics);
+        }
+        let filename = topics::write(&topics, &file_name, &args.destination)?;
+        ctx.println(format!(
+            "{username}: {} topics, saved to {filename}",
+            fmt.number(topics.len())
+        ));
+    }
+    if args.export_members {
+        match members::fetch(client, &chat).await {
+            Ok(mut members) => {
+                if let Some(ref anonymizer) = anonymizer {
+                    anonymizer.apply_members(&mut members);
+                }
+                let filename = members::write(&mut members, &file_name, &args.destination)?;
+                ctx.println(format!(
+                    "{username}: {} members, saved to {filename}",
+                    fmt.number(members.len())
+                ));
+            }
+            Err(e) => ctx.println(format!("{username}: could not list the members: {e}")),
+        }
+    }
+    ctx.crossposts
+        .lock()
+        .unwrap()
+        .add(&username, std::mem::take(&mut tally.crossposts));
+    ctx.println(format!("Resolving the usernames found in {username}..."));
+
+    let progress_bar = ctx.progress.add(ProgressBar::new(usernames.len() as u64));
+    resolve_all(
+        client,
+        &mut usernames,
+        &ctx.config.resolve,
+        &users,
+        ctx.cache,
+        progress_bar,
+    )
+    .instrument(tracing::info_span!("resolve", chat = username))
+    .await;
+    if args.admins {
+        admins::fill(client, &mut usernames).await;
+    }
+    if shutdown::requested() {
+        ctx.println(format!(
+            "Interrupted while resolving, some entries of {username} are left unresolved"
+        ));
+    } else if ratelimit::over_budget() {
+        ctx.println(format!(
+            "Request budget used up while resolving, some entries of {username} are left unresolved"
+        ));
+    }
+    if args.languages {
+        detect_languages(&mut usernames);
+    }
+    for username in usernames.iter().filter(|u| u.metadata.is_some()) {
+        ctx.storage.lock().unwrap().put_metadata(username)?;
+    }
+
+    let failed = retry::failed(&usernames, &ctx.config.resolve);
+    usernames.retain(|u| keep(u, &args.only, args.unresolved));
+    let mut usernames = merge_resolved(usernames);
+    if args.risk_score {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        risk::score(&mut usernames, now);
+    }
+
+    trim(&mut usernames, args.min_count, args.top);
+
+    let provenance = Provenance::new(&username)
+        .chat_id(chat.id())
+        .messages(count, message_ids)
+        .sample(args.sample);
+    let files = write_results(
+        &mut usernames,
+        &username,
+        &file_name,
+        anonymizer.as_ref(),
+        provenance,
+        &mut **ctx.storage.lock().unwrap(),
+        args,
+    )?;
+    if !failed.is_empty() {
+        // Anonymized results can't be matched with the lookups, they are
+        // only looked up again for the cache.
+        let written = if anonymizer.is_none() {
+            &files[..]
+        } else {
+            &[]
+        };
+        let queued = failed.len();
+        retry::record(&args.retry_queue(), &username, failed, written)?;
+        ctx.println(format!(
+            "{username}: {} lookups failed, run `rampilo retry` to look them up again",
+            fmt.number(queued)
+        ));
+    }
+
+    if !files.is_empty() {
+        ctx.println(format!(
+            "Saved {} usernames from {} messages to {}",
+            fmt.number(usernames.len()),
+            fmt.number(count),
+            files.join(", ")
+        ));
+    }
+    if args.summary || args.summary_only {
+        ctx.println(summary::render(
+            &username,
+            &usernames,
+            count,
+            stats.elapsed(),
+            fmt,
+        ));
+    }
+
+    #[cfg(feature = "webhook")]
+    if let Some(ref url) = args.notify_url {
+        let event = webhook::Event::CrawlFinished {
+            chat: &username,
+            messages: count,
+            links: usernames.len(),
+            new: new_links(&usernames),
+            files: &files,
+        };
+        webhook::send(url, &event).await;
+    }
+
+    Ok(Crawled {
+        chat: username,
+        newest,
+        files,
+        usernames,
+    })
+}
+
+/// Name of the results of `chat`, hashed like the chat's username when
+/// anonymizing, so it matches the entries of the chat in other results.
+fn results_name(chat: &str, anonymizer: Option<&Anonymizer>) -> String {
+    match anonymizer {
+        Some(anonymizer) => anonymizer.hash(&chat.to_lowercase()),
+        None => chat.to_string(),
+    }
+}
 
-    println!(
-        "Found {} usernames from {} messages",
-        usernames.len(),
-        count
+/// Finalizes the results of `chat` in every sink: the results files, in
+/// every `--format` and with their manifest, named `name`, and `storage`.
+/// Every sink gets the same results, and the files are anonymized like
+/// `storage` if asked to. Returns the files written, none with
+/// `--summary-only`.
+fn write_results(
+    usernames: &mut [Username],
+    chat: &str,
+    name: &str,
+    anonymizer: Option<&Anonymizer>,
+    mut provenance: Provenance,
+    storage: &mut dyn Storage,
+    args: &Args,
+) -> Result<Vec<String>> {
+    output::sort(usernames);
+    if anonymizer.is_some() {
+        provenance.anonymize(name);
+    }
+    let mut files = output::Files::new(
+        name,
+        &args.format,
+        &args.destination,
+        provenance,
+        args.locale,
     );
-    println!("Resolving usernames...");
+    let mut anonymized;
+    let mut sinks: Vec<&mut dyn Storage> = vec![storage];
+    if !args.summary_only {
+        match anonymizer {
+            Some(anonymizer) => {
+                anonymized = storage::Anonymized::new(&mut files, anonymizer.clone());
+                sinks.push(&mut anonymized);
+            }
+            None => sinks.push(&mut files),
+        }
+    }
+    storage::Tee(sinks).finalize(chat, usernames)?;
+    Ok(files.written)
+}
 
-    let progress_bar = ProgressBar::new(usernames.len() as u64);
-    for username in usernames.iter_mut() {
-        let entity_username = match username.username {
-            LinkType::Username(ref username) => username.as_str(),
-            LinkType::Mention(ref username) => username.as_str(),
-            LinkType::Hash(_) => continue,
+/// Keys of the results no earlier crawl found.
+#[cfg(feature = "webhook")]
+fn new_links(usernames: &[Username]) -> Vec<String> {
+    usernames
+        .iter()
+        .filter(|u| u.new)
+        .map(|u| u.username.key())
+        .collect()
+}
+
+/// Opens `--storage`, which only ever gets anonymized results with
+/// `--anonymize`.
+async fn open_storage(args: &Args) -> Result<Box<dyn Storage>> {
+    let storage = match args.storage {
+        Some(ref location) => location.open(args.destination.compress).await?,
+        None => Box::new(storage::Discard),
+    };
+    Ok(match args.anonymize {
+        Some(ref key) => Box::new(storage::Anonymized::new(storage, Anonymizer::new(key))),
+        None => storage,
+    })
+}
+
+async fn crawl_export(path: &Path, args: &Args) -> Result<()> {
+    status!("Reading export from {}...", path.display());
+    let started = std::time::Instant::now();
+    let messages = export::load(path)?;
+
+    let pipeline = args.pipeline();
+    let filter = Filter::new(&args.filter_keyword, &args.filter_regex);
+    let name = export::name(path);
+    let mut storage = open_storage(args).await?;
+    let mut usernames: Usernames = HashMap::new();
+    let mut languages = language::Languages {
+        enabled: args.languages,
+        ..Default::default()
+    };
+    let mut cooccurrence = cooccurrence::Cooccurrence {
+        enabled: args.cooccurrence,
+        ..Default::default()
+    };
+    let mut timeseries = timeseries::Timeseries {
+        interval: args.timeseries,
+        ..Default::default()
+    };
+    // Exported messages have no ids, they are sampled by their place in the
+    // export.
+    let sampled = messages
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| sample::keep(i as u64))
+        .map(|(_, message)| message);
+    for message in sampled.filter(|m| filter.matches(&m.text)) {
+        languages.record(&message.text);
+        let links = pipeline.extract(&MessageText {
+            text: &message.text,
+            entities: &message.entities,
+            preview: None,
+        });
+        cooccurrence.record(&links);
+        timeseries.record(&links, message.date);
+        for link in add_links(
+            links,
+            message.date,
+            message.reactions,
+            0,
+            None,
+            &message.text,
+            &mut usernames,
+        ) {
+            storage.put_discovery(&name, &link)?;
+        }
+    }
+
+    #[cfg(feature = "http")]
+    if args.unshorten {
+        let folded = unshorten::fold(&mut usernames, &pipeline).await;
+        status!(
+            "{} shortened links led to Telegram",
+            args.locale.number(folded)
+        );
+    }
+
+    if args.merge_duplicates {
+        merge_duplicates(&mut usernames);
+    }
+
+    let mut usernames: Vec<_> = usernames.into_values().collect();
+    let mut seen = seen::SeenDb::load(&args.seen_db())?;
+    seen.record(&mut usernames, &name);
+    trim(&mut usernames, args.min_count, args.top);
+
+    let anonymizer = args.anonymize.as_deref().map(Anonymizer::new);
+    let file_name = results_name(&name, anonymizer.as_ref());
+    if args.languages {
+        let filename = languages.write(&file_name, &args.destination)?;
+        status!(
+            "{name}: {}, saved to {filename}",
+            languages.summary(args.locale)
+        );
+    }
+    if args.cooccurrence {
+        let mut edges = cooccurrence.edges();
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_cooccurrence(&mut edges);
+        }
+        let filename = cooccurrence::write(&edges, &file_name, &args.destination)?;
+        status!(
+            "{name}: {} pairs of links posted together, between {} links, saved to {filename}",
+            args.locale.number(edges.len()),
+            args.locale.number(cooccurrence::linked(&edges))
+        );
+    }
+    if args.timeseries.is_some() {
+        let mut series = timeseries.series();
+        if let Some(ref anonymizer) = anonymizer {
+            anonymizer.apply_timeseries(&mut series);
+        }
+        let filename = timeseries::write(&series, &file_name, &args.destination)?;
+        status!(
+            "{name}: mentions of {} links over time, saved to {filename}",
+            args.locale.number(series.len())
+        );
+    }
+    let provenance = Provenance::new(&name)
+        .messages(messages.len(), None)
+        .sample(args.sample);
+    let files = write_results(
+        &mut usernames,
+        &name,
+        &file_name,
+        anonymizer.as_ref(),
+        provenance,
+        &mut *storage,
+        args,
+    )?;
+    seen.save()?;
+
+    if !files.is_empty() {
+        status!(
+            "Saved {} usernames ({} new) from {} messages to {}",
+            args.locale.number(usernames.len()),
+            args.locale
+                .number(usernames.iter().filter(|u| u.new).count()),
+            args.locale.number(messages.len()),
+            files.join(", ")
+        );
+    }
+    if args.summary || args.summary_only {
+        status!(
+            "{}",
+            summary::render(
+                &name,
+                &usernames,
+                messages.len(),
+                started.elapsed(),
+                args.locale
+            )
+        );
+    }
+
+    #[cfg(feature = "webhook")]
+    if let Some(ref url) = args.notify_url {
+        let event = webhook::Event::CrawlFinished {
+            chat: &name,
+            messages: messages.len(),
+            links: usernames.len(),
+            new: new_links(&usernames),
+            files: &files,
         };
+        webhook::send(url, &event).await;
+    }
+
+    Ok(())
+}
+
+/// What is collected of a chat besides its links, each written to a file of
+/// its own when asked for.
+#[derive(Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+struct Tally {
+    events: events::Events,
+    languages: language::Languages,
+    polls: polls::Polls,
+    senders: senders::Senders,
+    cooccurrence: cooccurrence::Cooccurrence,
+    timeseries: timeseries::Timeseries,
+    topics: topics::Topics,
+    crossposts: crosspost::Fingerprints,
+    /// Messages whose media is newer than the layer the Telegram library
+    /// speaks, like giveaways, their results and shared stories. Telegram
+    /// sends them without the channels they name.
+    unsupported_media: usize,
+}
+
+/// Whether the results are saved after this many messages with
+/// `--flush-every`, where 0 never saves them on the way.
+fn flush_due(flush_every: usize, messages: usize) -> bool {
+    flush_every != 0 && messages.is_multiple_of(flush_every)
+}
 
-        let maybe_user = client_handle
-            .resolve_username(entity_username)
-            .await
-            .ok()
-            .flatten();
-        if let Some(ref chat) = maybe_user {
-            username.metadata = Some(chat.into());
+/// What `read_history` has gathered so far.
+struct Gathered<'a> {
+    usernames: &'a Usernames,
+    /// Links of the message just read that passed the filter.
+    links: &'a [LinkType],
+    /// Messages read, including the ones of earlier runs.
+    count: usize,
+    tally: &'a Tally,
+    /// Ids of the messages still waiting to be retried.
+    skipped: &'a [i32],
+    /// Entries moved to disk with `--low-memory`, which aren't in
+    /// `usernames`.
+    on_disk: usize,
+    /// Messages read when entries were last moved to disk.
+    spilled_at: Option<usize>,
+    /// Whether the message came from the group the chat was upgraded from,
+    /// or the supergroup it was upgraded to.
+    migrated: bool,
+}
+
+/// Reads the whole history of a chat into `usernames`, unless the crawl is
+/// stopped or a request fails. Skipped messages are retried at the end.
+///
+/// `on_message` is given the id of every message, or `None` for retried
+/// ones, the links seen for the first time in it and everything gathered
+/// up to it. With a `spill`, entries are moved to disk whenever there are
+/// too many in memory, except while retrying.
+#[allow(clippy::too_many_arguments)]
+async fn read_history(
+    messages: &mut history::History,
+    pipeline: &Pipeline,
+    filter: &Filter,
+    usernames: &mut Usernames,
+    stats: &Stats,
+    count: &mut usize,
+    tally: &mut Tally,
+    mut spill: Option<&mut spill::Spill>,
+    mut on_message: impl FnMut(Option<i32>, &[LinkType], &Gathered) -> Result<()>,
+) -> Result<()> {
+    loop {
+        let stopped = || stats.is_stopped() || shutdown::requested();
+        while stats.is_paused() && !stopped() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        if stopped() {
+            return Ok(());
         }
 
-        progress_bar.inc(1);
+        let Some(message) = messages.next().await? else {
+            break;
+        };
+
+        let message_id = history::message_id(&message);
+        tracing::trace!(message_id, "scanning message");
+
+        *count += 1;
+        let (links, mut discovered) = if sample::keep(message_id as u64) {
+            let permalink = messages.permalink(message_id);
+            scan_message(
+                message,
+                permalink.as_deref(),
+                pipeline,
+                filter,
+                usernames,
+                stats,
+                tally,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        if let Some(ref mut spill) = spill {
+            spill.forget_known(&mut discovered)?;
+            if usernames.len() >= spill::MAX_IN_MEMORY {
+                spill.store(usernames, *count)?;
+            }
+        }
+        record_discoveries(&discovered, stats);
+        let gathered = Gathered {
+            usernames,
+            links: &links,
+            count: *count,
+            tally,
+            skipped: messages.skipped_ids(),
+            on_disk: spill.as_ref().map_or(0, |spill| spill.len()),
+            spilled_at: spill
+                .as_ref()
+                .map(|spill| spill.messages())
+                .transpose()?
+                .flatten(),
+            migrated: messages.migrated(),
+        };
+        on_message(Some(message_id), &discovered, &gathered)?;
     }
-    progress_bar.finish();
 
-    usernames.retain(|u| u.metadata.is_some());
+    if !messages.skipped_ids().is_empty() {
+        for message in messages.retry_skipped(history::RETRY_ATTEMPTS).await {
+            *count += 1;
+            let message_id = history::message_id(&message);
+            let (links, mut discovered) = if sample::keep(message_id as u64) {
+                let permalink = messages.permalink(message_id);
+                scan_message(
+                    message,
+                    permalink.as_deref(),
+                    pipeline,
+                    filter,
+                    usernames,
+                    stats,
+                    tally,
+                )
+            } else {
+                (Vec::new(), Vec::new())
+            };
+            if let Some(ref spill) = spill {
+                spill.forget_known(&mut discovered)?;
+            }
+            record_discoveries(&discovered, stats);
+            let gathered = Gathered {
+                usernames,
+                links: &links,
+                count: *count,
+                tally,
+                skipped: messages.skipped_ids(),
+                on_disk: spill.as_ref().map_or(0, |spill| spill.len()),
+                spilled_at: spill
+                    .as_ref()
+                    .map(|spill| spill.messages())
+                    .transpose()?
+                    .flatten(),
+                migrated: messages.migrated(),
+            };
+            on_message(None, &discovered, &gathered)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts the links seen for the first time in the stats and metrics.
+fn record_discoveries(discovered: &[LinkType], stats: &Stats) {
+    for link in discovered {
+        stats.record_discovery(link.to_string());
+    }
+    metrics::record_links(discovered.len());
+}
+
+/// Runs a fetched message through the pipeline, if it passes the filter,
+/// and counts what it finds, returning its links and the ones seen for the
+/// first time. Service messages are counted in `tally` instead, as are polls.
+fn scan_message(
+    message: tl::enums::Message,
+    permalink: Option<&str>,
+    pipeline: &Pipeline,
+    filter: &Filter,
+    usernames: &mut Usernames,
+    stats: &Stats,
+    tally: &mut Tally,
+) -> (Vec<LinkType>, Vec<LinkType>) {
+    stats.record_message();
+    metrics::record_message();
 
-    let json = serde_json::to_string_pretty(&usernames)?;
-    let filename = format!("{}.json", username);
-    fs::write(filename, json)?;
+    let mut message = match message {
+        tl::enums::Message::Message(message) => message,
+        tl::enums::Message::Service(service) => {
+            tally.events.record(&service);
+            tally.topics.record_service(&service);
+            return (Vec::new(), Vec::new());
+        }
+        tl::enums::Message::Empty(_) => return (Vec::new(), Vec::new()),
+    };
+    tally.polls.record(&message, permalink);
+    if let Some(tl::enums::MessageMedia::Unsupported) = message.media {
+        tracing::debug!(id = message.id, "skipped media of an unsupported type");
+        tally.unsupported_media += 1;
+    }
+    if !filter.matches(&message.message) {
+        return (Vec::new(), Vec::new());
+    }
+    tally.languages.record(&message.message);
+    tally
+        .crossposts
+        .record(&message.message, message.date as i64, permalink);
 
-    println!(
-        "Saved {} usernames from {count} messages to {username}.json",
-        usernames.len(),
+    let reactions = reaction_count(&message);
+    // Only channel posts count their views.
+    let views = message.views.unwrap_or(0).max(0) as usize;
+    let entities = message.entities.take().unwrap_or_default();
+    let links = pipeline.extract(&MessageText {
+        text: &message.message,
+        entities: &entities,
+        preview: preview_url(&message),
+    });
+    tally.senders.record(message.from_id.as_ref(), &links);
+    tally.cooccurrence.record(&links);
+    tally.timeseries.record(&links, Some(message.date as i64));
+    tally.topics.record(&message, &links);
+    let discovered = add_links(
+        links.clone(),
+        Some(message.date as i64),
+        reactions,
+        views,
+        permalink,
+        &message.message,
+        usernames,
     );
+    (links, discovered)
+}
 
-    Ok(())
+/// Url of the link preview of the message, which the sender may have
+/// removed from the text.
+fn preview_url(message: &tl::types::Message) -> Option<&str> {
+    match message.media {
+        Some(tl::enums::MessageMedia::WebPage(tl::types::MessageMediaWebPage {
+            webpage: tl::enums::WebPage::Page(ref page),
+        })) => Some(&page.url),
+        _ => None,
+    }
 }
 
-fn extract_link(message: &Message, usernames: &mut Usernames) {
-    let text = message.text();
-    if let Some(username) = extract(text) {
-        usernames
-            .entry(username.to_string().to_lowercase())
+/// Total of every reaction on the message.
+fn reaction_count(message: &tl::types::Message) -> usize {
+    let Some(tl::enums::MessageReactions::Reactions(ref reactions)) = message.reactions else {
+        return 0;
+    };
+
+    reactions
+        .results
+        .iter()
+        .map(|tl::enums::ReactionCount::Count(reaction)| reaction.count.max(0) as usize)
+        .sum()
+}
+
+/// Counts the links of a message sent at `date` with `reactions`
+/// reactions and `views` views, linked to by `permalink` and reading `text`,
+/// returning the ones seen for the first time.
+fn add_links(
+    links: Vec<LinkType>,
+    date: Option<i64>,
+    reactions: usize,
+    views: usize,
+    permalink: Option<&str>,
+    text: &str,
+    usernames: &mut Usernames,
+) -> Vec<LinkType> {
+    let mut discovered = Vec::new();
+
+    for link in links {
+        let username = usernames
+            .entry(link.key())
             .and_modify(|u| {
                 u.count += 1;
+                u.score += 1.0;
             })
-            .or_insert_with(|| Username::new(username));
+            .or_insert_with(|| {
+                discovered.push(link.clone());
+                Username::new(link)
+            });
+        username.seen_at(date);
+        username.seen_in(permalink);
+        username.quoted(text);
+        username.reactions += reactions;
+        username.views += views;
     }
-}
-
-fn extract_mentions(message: &Message, usernames: &mut Usernames) {
-    let text = message.text();
-    let empty = Vec::<MessageEntity>::new();
-    let entities: &Vec<MessageEntity> = message.fmt_entities().unwrap_or(&empty);
 
-    for entity in entities {
-        if let MessageEntity::Mention(e) = entity {
-            let offset = e.offset as usize;
-            let length = e.length as usize;
+    discovered
+}
 
-            let points = text.encode_utf16().collect::<Vec<_>>();
+/// Adds the links found `depth` hops away from the crawled chat, weighting
+/// their counts by `decay` for every hop.
+fn add_nested(usernames: &mut Usernames, nested: Usernames, depth: usize, decay: f64) {
+    let weight = decay.powi(depth as i32);
 
-            let username = &points[offset..offset + length];
-            let username = String::from_utf16_lossy(username);
-            let username = username.trim_start_matches('@').trim().to_lowercase();
-            let username = LinkType::Mention(username.to_string());
+    for (key, mut username) in nested {
+        username.depth = depth;
+        username.score = username.count as f64 * weight;
 
-            usernames
-                .entry(username.to_string())
-                .and_modify(|u| {
-                    u.count += 1;
-                })
-                .or_insert_with(|| Username::new(username));
+        match usernames.get_mut(&key) {
+            Some(existing) => existing.absorb(&username),
+            None => {
+                usernames.insert(key, username);
+            }
         }
     }
 }
 
-fn extract(link: &str) -> Option<LinkType> {
-    extract_username(link)
-        .map(LinkType::Username)
-        .or_else(|| extract_hash(link).map(LinkType::Hash))
+/// The chats linked or mentioned in `usernames`, by username, and by invite
+/// link with `invites`.
+fn linked_chats(usernames: &Usernames, invites: bool) -> Vec<target::Target> {
+    let mut chats: Vec<_> = usernames
+        .values()
+        .filter_map(|u| match u.username {
+            LinkType::Username(ref name) | LinkType::Mention(ref name) => {
+                Some(target::Target::Username(name.to_lowercase()))
+            }
+            LinkType::Hash(ref hash) if invites => Some(target::Target::Invite(hash.clone())),
+            _ => None,
+        })
+        .collect();
+    chats.sort();
+    chats.dedup();
+    chats
 }
 
-fn extract_username(link: &str) -> Option<String> {
-    let regex = Regex::new(r"https://t.me/([a-zA-Z0-9_]+)").unwrap();
-    let captures = regex.captures(link)?;
-    let group_name = captures.get(1)?.as_str();
-    match group_name {
-        "joinchat" | "addstickers" | "addemoji" | "addtheme" | "share" | "socks" | "proxy"
-        | "bg" | "login" | "invoice" | "setlanguage" | "confirmphone" | "path" | "c" => None,
-        _ => Some(group_name.to_string()),
+/// Crawls the groups and channels linked from the crawled chat, then the
+/// ones linked from those, up to `--depth` hops away. With `--auto-join`,
+/// private chats are joined through their invite links and left after.
+async fn crawl_nested(
+    ctx: &Context<'_>,
+    root: &str,
+    usernames: &mut Usernames,
+    users: &mut HashMap<i64, i64>,
+    count: &mut usize,
+    stats: &Arc<Stats>,
+) {
+    let (client, args) = (ctx.client, ctx.args);
+    let pipeline = args.pipeline();
+    let filter = Filter::new(&args.filter_keyword, &args.filter_regex);
+    let bot = args.bot_token.is_some();
+    // Bots can't join chats through invite links.
+    let invites = args.auto_join.auto_join && !bot;
+
+    let mut crawled = HashSet::from([target::Target::Username(root.to_lowercase())]);
+    let mut chats = linked_chats(usernames, invites);
+    for depth in 1..=args.depth {
+        let mut next = Vec::new();
+
+        for target in chats {
+            if stats.is_stopped() || shutdown::requested() || ratelimit::over_budget() {
+                return;
+            }
+            if !crawled.insert(target.clone()) {
+                continue;
+            }
+
+            let (chat, joined) = match target {
+                target::Target::Username(ref name) => {
+                    match flood::resolve_username(client, name).await {
+                        Ok(Some(chat)) => (chat, false),
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::warn!(chat = name, error = %e, "could not resolve chat");
+                            continue;
+                        }
+                    }
+                }
+                target::Target::Invite(ref hash) => {
+                    let confirm = |invite: &tl::types::ChatInvite| {
+                        ctx.progress
+                            .suspend(|| args.auto_join.confirm(hash, invite))
+                    };
+                    match target::open_invite(client, hash, confirm).await {
+                        Ok(opened) => opened,
+                        Err(e) => {
+                            tracing::warn!(%target, error = %e, "could not open invite link");
+                            continue;
+                        }
+                    }
+                }
+                target::Target::Id(_) => continue,
+            };
+            let name = target.label(&chat);
+            if matches!(chat, Chat::User(_)) || (bot && !chat.pack().is_channel()) {
+                continue;
+            }
+            if joined {
+                ctx.println(format!("Joined {name} through {target} to crawl it"));
+            }
+
+            let progress_bar = ctx.progress.add(ProgressBar::new_spinner());
+            progress_bar.enable_steady_tick(Duration::from_millis(100));
+
+            let page_size = history::PageSize::new(args.page_size, !args.fixed_page_size);
+            let mut messages = history::History::new(client, &chat, page_size)
+                .by_id(bot)
+                .pinned_only(args.pinned_nested)
+                .min_date(args.stop.min_date())
+                .stats(stats.clone());
+            let mut nested = Usernames::new();
+            let result = read_history(
+                &mut messages,
+                &pipeline,
+                &filter,
+                &mut nested,
+                stats,
+                count,
+                &mut Default::default(),
+                None,
+                |id, discovered, gathered| {
+                    if let Some(id) = id {
+                        progress_bar.set_message(format!("{name} ({depth} hops away): {id}"));
+                    }
+                    let found = usernames.len() + gathered.usernames.len();
+                    if let Some(reason) = args.stop.reached(gathered.count, found) {
+                        tracing::info!(chat = name, reason, "stopping early");
+                        stats.stop();
+                    }
+                    discovered
+                        .iter()
+                        .try_for_each(|link| ctx.storage.lock().unwrap().put_discovery(&name, link))
+                },
+            )
+            .instrument(tracing::info_span!(
+                "history",
+                chat = name,
+                chat_id = chat.id(),
+                depth
+            ))
+            .await;
+            progress_bar.finish_and_clear();
+            users.extend(messages.users());
+            if joined {
+                match autojoin::leave(client, &chat).await {
+                    Ok(()) => ctx.println(format!("Left {name} again")),
+                    Err(e) => ctx.println(format!("Could not leave {name}: {e}")),
+                }
+            }
+
+            if let Err(e) = result {
+                tracing::warn!(
+                    chat = name,
+                    error = %e,
+                    "could not finish crawling, keeping what was read"
+                );
+            }
+
+            next.extend(linked_chats(&nested, invites));
+            add_nested(usernames, nested, depth, args.decay);
+            if let Err(e) = ctx.storage.lock().unwrap().checkpoint(root, usernames) {
+                tracing::warn!(error = %e, "could not checkpoint results");
+            }
+        }
+
+        chats = next;
     }
 }
 
-fn extract_hash(link: &str) -> Option<String> {
-    let regex = Regex::new(r"https://t.me/(joinchat/|\+)([a-zA-Z0-9_-]+)").unwrap();
-    let captures = regex.captures(link)?;
-    let group_name = captures.get(2)?.as_str();
-    Some(group_name.to_string())
+/// Folds every mention into the link entry of the same username, if any.
+fn merge_duplicates(usernames: &mut Usernames) {
+    let mentions: Vec<_> = usernames
+        .keys()
+        .filter(|key| key.starts_with("mention:"))
+        .cloned()
+        .collect();
+
+    for mention in mentions {
+        let link = mention.replacen("mention:", "username:", 1);
+        if !usernames.contains_key(&link) {
+            continue;
+        }
+
+        if let Some(merged) = usernames.remove(&mention) {
+            if let Some(username) = usernames.get_mut(&link) {
+                username.absorb(&merged);
+            }
+        }
+    }
 }
 
-async fn sign_in(client: &Client, api_id: i32, app_hash: &str) -> Result<()> {
-    println!("Signing in...");
+/// Looks up every entry as its strategy says, `users` holding the access
+/// hashes of the users that may be mentioned by id.
+///
+/// Usernames in `cache` are filled in first, so only the others wait on the
+/// rate limit, and every username resolved is added to it.
+async fn resolve_all(
+    client: &Client,
+    usernames: &mut [Username],
+    strategies: &config::Strategies,
+    users: &HashMap<i64, i64>,
+    cache: &Mutex<cache::ResolveCache>,
+    progress_bar: ProgressBar,
+) {
+    let mut pending = Vec::new();
+    for username in usernames.iter_mut() {
+        let cached = match (strategies.for_link(&username.username), &username.username) {
+            (Strategy::Resolve, LinkType::Username(name) | LinkType::Mention(name)) => {
+                cache.lock().unwrap().get(name)
+            }
+            _ => None,
+        };
+        match cached {
+            Some(resolution) => {
+                username.resolved(resolution);
+                progress_bar.inc(1);
+            }
+            None => pending.push(username),
+        }
+    }
+    tracing::debug!(
+        cached = progress_bar.position(),
+        pending = pending.len(),
+        "resolving"
+    );
+
+    for username in pending {
+        if shutdown::requested() || ratelimit::over_budget() {
+            break;
+        }
+
+        match (strategies.for_link(&username.username), &username.username) {
+            (Strategy::Resolve, LinkType::Username(name) | LinkType::Mention(name)) => {
+                // The same username may be both linked and mentioned.
+                let cached = cache.lock().unwrap().get(name);
+                let resolution = match cached {
+                    Some(resolution) => resolution,
+                    None => {
+                        let resolution = resolve_chat(client, name).await;
+                        // Failed lookups are tried again by the next crawl.
+                        if !matches!(resolution, Err(Unresolved::Failed)) {
+                            cache.lock().unwrap().insert(name, &resolution);
+                        }
+                        resolution
+                    }
+                };
+                username.resolved(resolution);
+            }
+            (Strategy::Resolve, LinkType::UserId(id)) => match users.get(id) {
+                Some(&access_hash) => {
+                    username.resolved(resolve_user(client, *id, access_hash).await);
+                }
+                None => username.metadata = None,
+            },
+            (Strategy::CheckInvite, LinkType::Hash(hash)) => {
+                ratelimit::acquire().await;
+                username.metadata = invites::metadata(client, hash).await;
+            }
+            #[cfg(feature = "http")]
+            (Strategy::Probe, LinkType::Url(url)) => username.metadata = probe::probe(url).await,
+            _ => username.metadata = None,
+        }
 
-    let phone = Text::new("Enter your phone number: ").prompt()?;
-    let token = client.request_login_code(&phone, api_id, app_hash).await?;
-    let code = Text::new("Enter the code: ").prompt()?;
-    let sign_in = client.sign_in(&token, &code).await;
+        progress_bar.inc(1);
+    }
+    progress_bar.finish();
+}
 
-    match sign_in {
-        Ok(user) => {
-            println!("Signed in as {}!", user.first_name());
+/// Looks up a user mentioned by name, with the access hash that came with
+/// the crawled messages.
+async fn resolve_user(client: &Client, id: i64, access_hash: i64) -> Resolution {
+    tracing::debug!(user_id = id, "resolving");
+    ratelimit::acquire_resolve().await;
+    usage::record_resolve();
+    match details::fetch_user(client, id, access_hash).await {
+        Ok(Some((_, details))) if details.deleted => Err(Unresolved::Deleted),
+        Ok(Some((name, details))) => Ok(UsernameMetadata {
+            id,
+            name,
+            type_: UsernameType::User,
+            about: details.about,
+            members: None,
+            date: None,
+            restriction: details.restriction,
+            language: None,
+            label: details.label,
+            risk: None,
+            linked_chat: None,
+            admins: None,
+        }),
+        Ok(None) => Err(Unresolved::NotFound),
+        Err(e) => {
+            tracing::warn!(user_id = id, error = %e, "could not resolve user");
+            Err(Unresolved::Failed)
         }
-        Err(SignInError::PasswordRequired(password_token)) => {
-            let password = Password::new("Enter the password: ").prompt()?;
+    }
+}
 
-            client
-                .check_password(password_token, password.trim())
-                .await?;
+/// Resolves a username to its chat and fetches the chat's details.
+async fn resolve_chat(client: &Client, name: &str) -> Resolution {
+    tracing::debug!(username = name, "resolving");
+    let chat = match flood::resolve_username(client, name).await {
+        Ok(Some(chat)) => chat,
+        Ok(None) => return Err(Unresolved::NotFound),
+        Err(e) if e.is("USERNAME_INVALID") => return Err(Unresolved::NotFound),
+        Err(e) if is_inaccessible(&e) => return Err(Unresolved::Inaccessible),
+        Err(e) => {
+            tracing::warn!(username = name, error = %e, "could not resolve username");
+            return Err(Unresolved::Failed);
         }
-        Err(e) => return Err(e.into()),
     };
+    if let Chat::User(ref user) = chat {
+        if user.deleted() {
+            return Err(Unresolved::Deleted);
+        }
+    }
+
+    let mut metadata = UsernameMetadata::from(&chat);
+    match details::fetch(client, &chat).await {
+        Ok(details) => {
+            metadata.about = details.about;
+            metadata.members = details.members;
+            metadata.date = details.date;
+            metadata.restriction = details.restriction;
+            metadata.label = details.label;
+            metadata.linked_chat = details.linked_chat;
+        }
+        Err(e) if is_inaccessible(&e) => return Err(Unresolved::Inaccessible),
+        Err(e) => {
+            tracing::warn!(chat_id = chat.id(), error = %e, "could not fetch chat details")
+        }
+    }
+    if admins::enabled() && metadata.type_ != UsernameType::User {
+        metadata.admins = Some(admins::fetch(client, &chat).await);
+    }
+    Ok(metadata)
+}
 
-    client.session().save_to_file(SESSION_FILE)?;
+/// Detects the language of the description of every resolved entry.
+fn detect_languages(usernames: &mut [Username]) {
+    for metadata in usernames.iter_mut().filter_map(|u| u.metadata.as_mut()) {
+        metadata.language = metadata
+            .about
+            .as_deref()
+            .and_then(language::detect)
+            .map(str::to_string);
+    }
+}
 
-    Ok(())
+/// Whether Telegram refused the request because the chat is private or the
+/// crawling account is banned from it.
+fn is_inaccessible(e: &InvocationError) -> bool {
+    e.is("CHANNEL_PRIVATE") || e.is("CHANNEL_PUBLIC_GROUP_NA") || e.is("CHAT_FORBIDDEN")
+}
+
+/// Whether a resolved entry belongs in the results, given `--only` and
+/// `--unresolved`.
+fn keep(username: &Username, only: &[UsernameType], unresolved: bool) -> bool {
+    match username.metadata {
+        Some(ref metadata) => only.is_empty() || only.contains(&metadata.type_),
+        None => unresolved,
+    }
+}
+
+/// Drops the entries found fewer than `min_count` times and keeps the `top`
+/// highest scored of the rest, given `--min-count` and `--top`.
+fn trim(usernames: &mut Vec<Username>, min_count: usize, top: Option<usize>) {
+    usernames.retain(|u| u.count >= min_count);
+    if let Some(top) = top {
+        output::sort(usernames);
+        usernames.truncate(top);
+    }
+}
+
+/// Merges the entries that resolved to the same chat, e.g. a username and an
+/// old alias of a channel, into the entry with the highest count.
+fn merge_resolved(mut usernames: Vec<Username>) -> Vec<Username> {
+    output::sort(&mut usernames);
+
+    let mut merged: Vec<Username> = Vec::with_capacity(usernames.len());
+    let mut by_id = HashMap::new();
+    for username in usernames {
+        // Websites and some invites have no id to merge by.
+        let id = username
+            .metadata
+            .as_ref()
+            .map(|m| m.id)
+            .filter(|&id| id != 0);
+        match id.and_then(|id| by_id.get(&id)) {
+            Some(&index) => {
+                let primary: &mut Username = &mut merged[index];
+                primary.absorb(&username);
+                primary.aliases.push(username.username);
+                primary.aliases.extend(username.aliases);
+            }
+            None => {
+                if let Some(id) = id {
+                    by_id.insert(id, merged.len());
+                }
+                merged.push(username);
+            }
+        }
+    }
+
+    output::sort(&mut merged);
+    merged
 }
 
 #[cfg(test)]
@@ -271,36 +2567,218 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_extract_username() {
-        let link = "https://t.me/grammers";
-        let username = extract(link);
-        assert_eq!(username, Some(LinkType::Username("grammers".to_string())));
+    fn test_args() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_auth_commands() {
+        let args = Args::try_parse_from(["rampilo", "--bot-token", "123:abc", "logout"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Logout)));
+        assert!(args.bot_token.is_some());
+        assert!(matches!(
+            Args::try_parse_from(["rampilo", "whoami"]).unwrap().command,
+            Some(Command::Whoami)
+        ));
+        assert!(matches!(
+            Args::try_parse_from(["rampilo", "login"]).unwrap().command,
+            Some(Command::Login)
+        ));
     }
 
     #[test]
-    fn test_extract_username_with_query() {
-        let link = "https://t.me/grammers?start=123";
-        let username = extract(link);
-        assert_eq!(username, Some(LinkType::Username("grammers".to_string())));
+    fn test_rate_limit_args() {
+        let args =
+            Args::try_parse_from(["rampilo", "--rate-limit", "2", "--resolve-delay-ms", "1500"])
+                .unwrap();
+        assert_eq!(args.requests_per_second, 2.0);
+        assert_eq!(args.resolve_delay_ms, 1500);
+        assert!(Args::try_parse_from(["rampilo", "--requests-per-second", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_flush_due() {
+        assert!(flush_due(10_000, 10_000));
+        assert!(flush_due(10_000, 30_000));
+        assert!(!flush_due(10_000, 15_000));
+        assert!(!flush_due(0, 10_000));
+    }
+
+    #[test]
+    fn test_merge_duplicates() {
+        let mut usernames = Usernames::new();
+        add_links(
+            vec![
+                LinkType::Username("Grammers".to_string()),
+                LinkType::Mention("grammers".to_string()),
+                LinkType::Mention("grammers".to_string()),
+                LinkType::Mention("rampilo".to_string()),
+            ],
+            None,
+            0,
+            0,
+            None,
+            "",
+            &mut usernames,
+        );
+        assert_eq!(usernames.len(), 3);
+
+        merge_duplicates(&mut usernames);
+        assert_eq!(usernames.len(), 2);
+        assert_eq!(usernames["username:grammers"].count, 3);
+        assert_eq!(usernames["mention:rampilo"].count, 1);
     }
 
     #[test]
-    fn test_joined_username() {
-        let link = "https://t.me/joinchat/USpx-sviNKIj408g";
-        let username = extract(link);
+    fn test_add_nested() {
+        let mut usernames = Usernames::new();
+        add_links(
+            vec![LinkType::Username("grammers".to_string())],
+            Some(200),
+            7,
+            40,
+            Some("https://t.me/codenight/2"),
+            "grammers is great: https://t.me/grammers",
+            &mut usernames,
+        );
+
+        let mut nested = Usernames::new();
+        add_links(
+            vec![
+                LinkType::Username("grammers".to_string()),
+                LinkType::Username("rampilo".to_string()),
+                LinkType::Username("rampilo".to_string()),
+            ],
+            Some(100),
+            3,
+            25,
+            Some("https://t.me/rustlang/1"),
+            "see t.me/grammers and t.me/rampilo",
+            &mut nested,
+        );
+        add_nested(&mut usernames, nested, 2, 0.5);
+
+        let grammers = &usernames["username:grammers"];
         assert_eq!(
-            username,
-            Some(LinkType::Hash("USpx-sviNKIj408g".to_string()))
+            (grammers.count, grammers.depth, grammers.score),
+            (2, 0, 1.25)
+        );
+        assert_eq!(
+            (grammers.first_seen, grammers.last_seen),
+            (Some(100), Some(200))
+        );
+        assert_eq!((grammers.reactions, grammers.views), (10, 65));
+        assert_eq!(
+            grammers.messages,
+            ["https://t.me/codenight/2", "https://t.me/rustlang/1"]
         );
+        assert_eq!(
+            grammers.snippets,
+            [
+                "grammers is great: https://t.me/grammers",
+                "see t.me/grammers and t.me/rampilo"
+            ]
+        );
+        let rampilo = &usernames["username:rampilo"];
+        assert_eq!((rampilo.count, rampilo.depth, rampilo.score), (2, 2, 0.5));
+        assert_eq!((rampilo.reactions, rampilo.views), (6, 50));
+        assert_eq!(rampilo.messages, ["https://t.me/rustlang/1"]);
+        assert_eq!(rampilo.snippets.len(), 1);
+    }
+
+    #[test]
+    fn test_keep() {
+        let channel = Username {
+            metadata: Some(UsernameMetadata {
+                id: 1,
+                name: "Grammers".to_string(),
+                type_: UsernameType::Channel,
+                about: None,
+                members: None,
+                date: None,
+                restriction: None,
+                language: None,
+                label: None,
+                risk: None,
+                linked_chat: None,
+                admins: None,
+            }),
+            ..Username::new(LinkType::Username("grammers".to_string()))
+        };
+        let unresolved = Username::new(LinkType::Hash("USpx-sviNKIj408g".to_string()));
+
+        assert!(keep(&channel, &[], false));
+        assert!(keep(&channel, &[UsernameType::Channel], false));
+        assert!(!keep(
+            &channel,
+            &[UsernameType::User, UsernameType::Group],
+            true
+        ));
+        assert!(!keep(&unresolved, &[], false));
+        assert!(keep(&unresolved, &[UsernameType::Channel], true));
     }
 
     #[test]
-    fn test_invite_link() {
-        let link = "https://t.me/+_DGX2NIt9IhkNTVk";
-        let username = extract(link);
+    fn test_merge_resolved() {
+        let resolved = |link: LinkType, count: usize, id: i64| Username {
+            count,
+            metadata: Some(UsernameMetadata {
+                id,
+                name: "Grammers".to_string(),
+                type_: UsernameType::Channel,
+                about: None,
+                members: None,
+                date: None,
+                restriction: None,
+                language: None,
+                label: None,
+                risk: None,
+                linked_chat: None,
+                admins: None,
+            }),
+            ..Username::new(link)
+        };
+
+        let merged = merge_resolved(vec![
+            resolved(LinkType::Mention("grammers".to_string()), 2, 1),
+            resolved(LinkType::Username("grammers".to_string()), 5, 1),
+            resolved(LinkType::Username("grammers_old".to_string()), 1, 1),
+            resolved(LinkType::Username("rampilo".to_string()), 3, 2),
+        ]);
+
+        assert_eq!(merged.len(), 2);
         assert_eq!(
-            username,
-            Some(LinkType::Hash("_DGX2NIt9IhkNTVk".to_string()))
+            merged[0].username,
+            LinkType::Username("grammers".to_string())
         );
+        assert_eq!(merged[0].count, 8);
+        assert_eq!(
+            merged[0].aliases,
+            vec![
+                LinkType::Mention("grammers".to_string()),
+                LinkType::Username("grammers_old".to_string()),
+            ]
+        );
+        assert_eq!(merged[1].count, 3);
+    }
+
+    #[test]
+    fn test_trim() {
+        let username = |name: &str, count: usize| Username {
+            count,
+            score: count as f64,
+            ..Username::new(LinkType::Username(name.to_string()))
+        };
+        let mut usernames = vec![
+            username("once", 1),
+            username("grammers", 5),
+            username("tokio", 2),
+            username("rampilo", 3),
+        ];
+
+        trim(&mut usernames, 2, Some(2));
+        let names: Vec<_> = usernames.iter().map(|u| u.username.to_string()).collect();
+        assert_eq!(names, ["grammers", "rampilo"]);
     }
 }
@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_client::{
+    types::{chat::Chat, participant, Participant},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flood,
+    output::{Destination, Format},
+    ratelimit,
+};
+
+/// A participant of a crawled group, for `--export-members`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Member {
+    pub id: i64,
+    pub username: Option<String>,
+    pub name: String,
+    pub bot: bool,
+    pub role: Role,
+    /// Custom title of an admin or the creator.
+    pub rank: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Member,
+    Admin,
+    Creator,
+    Banned,
+    Left,
+}
+
+impl From<Participant> for Member {
+    fn from(participant: Participant) -> Self {
+        let (role, rank) = match participant.role {
+            participant::Role::Admin(ref admin) => (Role::Admin, admin.rank()),
+            participant::Role::Creator(ref creator) => (Role::Creator, creator.rank()),
+            participant::Role::Banned(_) => (Role::Banned, None),
+            participant::Role::Left(_) => (Role::Left, None),
+            _ => (Role::Member, None),
+        };
+        let user = &participant.user;
+
+        Self {
+            id: user.id(),
+            username: user.username().map(str::to_string),
+            name: user.full_name(),
+            bot: user.is_bot(),
+            role,
+            rank: rank.map(str::to_string),
+        }
+    }
+}
+
+/// Lists the participants of the chat. Channels only list them to their
+/// admins, and large groups may hide them from members.
+pub async fn fetch(client: &Client, chat: &Chat) -> Result<Vec<Member>> {
+    let mut participants = flood::iter_participants(client, chat)?;
+    ratelimit::acquire().await;
+
+    let mut members = Vec::new();
+    while let Some(participant) = participants.next().await? {
+        members.push(participant.into());
+    }
+    tracing::debug!(members = members.len(), "listed participants");
+
+    Ok(members)
+}
+
+/// Writes the members next to the results as `<chat>.members.json`, or
+/// whatever `--out-name` makes of that name, admins first.
+pub fn write(members: &mut [Member], chat: &str, destination: &Destination) -> Result<String> {
+    members.sort_by_key(|member| (rank(member.role), member.id));
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(&format!("{chat}.members"), Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(&members)?)?;
+    Ok(path.display().to_string())
+}
+
+fn rank(role: Role) -> u8 {
+    match role {
+        Role::Creator => 0,
+        Role::Admin => 1,
+        Role::Member => 2,
+        Role::Banned => 3,
+        Role::Left => 4,
+    }
+}
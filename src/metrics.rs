@@ -0,0 +1,145 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Totals since the process started, served on `/metrics` by builds with the
+/// `metrics` feature.
+#[derive(Default)]
+struct Counters {
+    messages: AtomicU64,
+    links: AtomicU64,
+    rpc_errors: AtomicU64,
+    flood_wait_seconds: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            messages: AtomicU64::new(0),
+            links: AtomicU64::new(0),
+            rpc_errors: AtomicU64::new(0),
+            flood_wait_seconds: AtomicU64::new(0),
+        }
+    }
+
+    /// Formats the counters in the Prometheus text format.
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn render(&self) -> String {
+        let counters = [
+            ("rampilo_messages_total", "Messages scanned", &self.messages),
+            (
+                "rampilo_links_discovered_total",
+                "Links found for the first time in a crawl or check",
+                &self.links,
+            ),
+            (
+                "rampilo_rpc_errors_total",
+                "Requests Telegram answered with an error",
+                &self.rpc_errors,
+            ),
+            (
+                "rampilo_flood_wait_seconds_total",
+                "Seconds spent sleeping off flood waits",
+                &self.flood_wait_seconds,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, help, value) in counters {
+            let _ = writeln!(text, "# HELP {name} {help}.");
+            let _ = writeln!(text, "# TYPE {name} counter");
+            let _ = writeln!(text, "{name} {}", value.load(Ordering::Relaxed));
+        }
+        text
+    }
+}
+
+static COUNTERS: Counters = Counters::new();
+
+pub fn record_message() {
+    COUNTERS.messages.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_links(count: usize) {
+    COUNTERS.links.fetch_add(count as u64, Ordering::Relaxed);
+}
+
+pub fn record_rpc_error() {
+    COUNTERS.rpc_errors.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_flood_wait(wait: Duration) {
+    COUNTERS
+        .flood_wait_seconds
+        .fetch_add(wait.as_secs(), Ordering::Relaxed);
+}
+
+/// Serves the counters on `http://<addr>/metrics` until the process exits.
+/// Binding happens right away so a taken port is reported before watching
+/// starts.
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: std::net::SocketAddr) -> color_eyre::eyre::Result<()> {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(%addr, "serving metrics");
+
+    tokio::spawn(async move {
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not accept metrics connection");
+                    continue;
+                }
+            };
+
+            tokio::spawn(async move {
+                let mut request = [0; 1024];
+                let Ok(read) = stream.read(&mut request).await else {
+                    return;
+                };
+                let request = String::from_utf8_lossy(&request[..read]);
+
+                let response = match request.split_whitespace().nth(1) {
+                    Some("/metrics") => {
+                        let body = COUNTERS.render();
+                        format!(
+                            "HTTP/1.1 200 OK\r\n\
+                             Content-Type: text/plain; version=0.0.4\r\n\
+                             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        )
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let counters = Counters::default();
+        counters.messages.fetch_add(3, Ordering::Relaxed);
+        counters.flood_wait_seconds.fetch_add(42, Ordering::Relaxed);
+
+        let text = counters.render();
+        assert!(text.contains("# TYPE rampilo_messages_total counter\nrampilo_messages_total 3\n"));
+        assert!(text.contains("rampilo_flood_wait_seconds_total 42\n"));
+        assert!(text.contains("rampilo_rpc_errors_total 0\n"));
+    }
+}
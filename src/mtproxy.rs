@@ -0,0 +1,479 @@
+//! Connections through MTProto proxies.
+//!
+//! The Telegram library only speaks the full transport, straight to a
+//! datacenter or through a SOCKS5 proxy. MTProto proxies want the
+//! obfuscated intermediate transport instead, and the datacenter's id
+//! rather than its address. So the client is pointed at a SOCKS5 proxy on
+//! localhost, which opens an obfuscated connection to the MTProto proxy for
+//! every connection it is asked for and converts the packets between both
+//! transports.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::OnceLock,
+};
+
+use aes::{Aes256, Block, BlockEncrypt, NewBlockCipher};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use crc32fast::Hasher;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::proxy::Secret;
+
+/// Addresses of the datacenters the Telegram library connects to, the
+/// index being the datacenter's id.
+const DC_ADDRESSES: [(Ipv4Addr, u16); 6] = [
+    (Ipv4Addr::UNSPECIFIED, 0),
+    (Ipv4Addr::new(149, 154, 175, 53), 443),
+    (Ipv4Addr::new(149, 154, 167, 51), 443),
+    (Ipv4Addr::new(149, 154, 175, 100), 443),
+    (Ipv4Addr::new(149, 154, 167, 92), 443),
+    (Ipv4Addr::new(91, 108, 56, 190), 443),
+];
+
+/// Tags of the intermediate transport, plain and padded.
+const INTERMEDIATE: [u8; 4] = [0xee; 4];
+const PADDED_INTERMEDIATE: [u8; 4] = [0xdd; 4];
+
+/// Starts of the first packet that would make it look like another
+/// protocol, which proxies refuse.
+const RESERVED: [[u8; 4]; 7] = [
+    *b"HEAD",
+    *b"POST",
+    *b"GET ",
+    *b"OPTI",
+    [0x16, 0x03, 0x01, 0x02],
+    INTERMEDIATE,
+    PADDED_INTERMEDIATE,
+];
+
+/// Largest packet passed on, well above what Telegram sends.
+const MAX_PACKET: usize = 16 * 1024 * 1024;
+
+/// URL of the local SOCKS5 proxy, once started.
+static LOCAL: OnceLock<String> = OnceLock::new();
+
+/// Starts the local SOCKS5 proxy forwarding to the MTProto proxy at
+/// `server:port`, unless it already runs, and returns its URL.
+pub fn start(server: &str, port: u16, secret: &Secret) -> io::Result<String> {
+    if let Some(url) = LOCAL.get() {
+        return Ok(url.clone());
+    }
+
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let url = format!("socks5://{}", listener.local_addr()?);
+    let listener = TcpListener::from_std(listener)?;
+    tokio::spawn(serve(listener, format!("{server}:{port}"), secret.clone()));
+
+    Ok(LOCAL.get_or_init(|| url).clone())
+}
+
+async fn serve(listener: TcpListener, proxy: String, secret: Secret) {
+    loop {
+        let local = match listener.accept().await {
+            Ok((local, _)) => local,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not accept a connection for the MTProto proxy");
+                continue;
+            }
+        };
+        let (proxy, secret) = (proxy.clone(), secret.clone());
+        tokio::spawn(async move {
+            if let Err(e) = forward(local, &proxy, &secret).await {
+                tracing::warn!(proxy, error = %e, "connection through the MTProto proxy failed");
+            }
+        });
+    }
+}
+
+/// Forwards one connection of the client to the proxy.
+async fn forward(mut local: TcpStream, proxy: &str, secret: &Secret) -> io::Result<()> {
+    let dc = accept_socks(&mut local).await?;
+    tracing::debug!(proxy, dc, "connecting through the MTProto proxy");
+
+    let mut remote = TcpStream::connect(proxy).await?;
+    remote.set_nodelay(true)?;
+    let tag = if secret.padded {
+        PADDED_INTERMEDIATE
+    } else {
+        INTERMEDIATE
+    };
+    let (header, encrypt, decrypt) = obfuscate(random_header(tag, dc), &secret.key);
+    remote.write_all(&header).await?;
+
+    let (local_read, local_write) = local.split();
+    let (remote_read, remote_write) = remote.split();
+    tokio::select! {
+        result = upload(local_read, remote_write, encrypt, secret.padded) => result,
+        result = download(remote_read, local_write, decrypt) => result,
+    }
+}
+
+/// Answers the SOCKS5 handshake of the client, returning the id of the
+/// datacenter it asks for.
+async fn accept_socks(local: &mut TcpStream) -> io::Result<i16> {
+    let mut greeting = [0; 2];
+    local.read_exact(&mut greeting).await?;
+    if greeting[0] != 5 {
+        return Err(invalid("not a SOCKS5 client"));
+    }
+    let mut methods = vec![0; greeting[1] as usize];
+    local.read_exact(&mut methods).await?;
+    local.write_all(&[5, 0]).await?;
+
+    // Only IPv4 addresses are asked for, as those are what the library
+    // knows the datacenters by.
+    let mut request = [0; 10];
+    local.read_exact(&mut request[..4]).await?;
+    if request[..4] != [5, 1, 0, 1] {
+        local.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+        return Err(invalid("unsupported SOCKS5 request"));
+    }
+    local.read_exact(&mut request[4..]).await?;
+    let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+    let address = SocketAddr::from((ip, u16::from_be_bytes([request[8], request[9]])));
+
+    let Some(dc) = dc_id(address) else {
+        local.write_all(&[5, 4, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+        return Err(invalid(&format!("{address} is not a known datacenter")));
+    };
+    local.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    Ok(dc)
+}
+
+fn dc_id(address: SocketAddr) -> Option<i16> {
+    DC_ADDRESSES
+        .iter()
+        .skip(1)
+        .position(|&dc| SocketAddr::from(dc) == address)
+        .map(|i| i as i16 + 1)
+}
+
+/// Passes the packets of the client's full transport on to the proxy, with
+/// the sequence number and checksum stripped and up to 3 random bytes of
+/// padding added when asked for.
+async fn upload(
+    mut local: impl AsyncRead + Unpin,
+    mut remote: impl AsyncWrite + Unpin,
+    mut encrypt: Ctr,
+    padded: bool,
+) -> io::Result<()> {
+    let mut packet = Vec::new();
+    loop {
+        let mut len = [0; 4];
+        match local.read_exact(&mut len).await {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        };
+        let len = u32::from_le_bytes(len) as usize;
+        if !(12..=MAX_PACKET).contains(&len) {
+            return Err(invalid(&format!("bad packet length {len}")));
+        }
+        let mut full = vec![0; len - 4];
+        local.read_exact(&mut full).await?;
+
+        let padding = if padded {
+            OsRng.next_u32() as usize % 4
+        } else {
+            0
+        };
+        packet.clear();
+        intermediate(&full[4..full.len() - 4], padding, &mut packet);
+        encrypt.apply(&mut packet);
+        remote.write_all(&packet).await?;
+    }
+}
+
+/// Passes the packets of the proxy on to the client in the full transport,
+/// numbered from 0 like the library expects.
+async fn download(
+    mut remote: impl AsyncRead + Unpin,
+    mut local: impl AsyncWrite + Unpin,
+    mut decrypt: Ctr,
+) -> io::Result<()> {
+    let mut packet = Vec::new();
+    for seq in 0.. {
+        let mut len = [0; 4];
+        match remote.read_exact(&mut len).await {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        };
+        decrypt.apply(&mut len);
+        // The highest bit marks quick acknowledgements, which are never
+        // asked for.
+        let len = (u32::from_le_bytes(len) & 0x7fff_ffff) as usize;
+        if len > MAX_PACKET {
+            return Err(invalid(&format!("bad packet length {len}")));
+        }
+        let mut payload = vec![0; len];
+        remote.read_exact(&mut payload).await?;
+        decrypt.apply(&mut payload);
+
+        // Payloads are multiples of 4 bytes, anything past that is padding.
+        payload.truncate(len - len % 4);
+        packet.clear();
+        full(&payload, seq, &mut packet);
+        local.write_all(&packet).await?;
+    }
+    Ok(())
+}
+
+/// Packet of the intermediate transport: the length, the payload and
+/// `padding` random bytes.
+fn intermediate(payload: &[u8], padding: usize, packet: &mut Vec<u8>) {
+    packet.extend_from_slice(&((payload.len() + padding) as u32).to_le_bytes());
+    packet.extend_from_slice(payload);
+    let mut random = [0; 3];
+    OsRng.fill_bytes(&mut random);
+    packet.extend_from_slice(&random[..padding]);
+}
+
+/// Packet of the full transport: the length, the sequence number, the
+/// payload and a checksum of them.
+fn full(payload: &[u8], seq: u32, packet: &mut Vec<u8>) {
+    packet.extend_from_slice(&((payload.len() + 12) as u32).to_le_bytes());
+    packet.extend_from_slice(&seq.to_le_bytes());
+    packet.extend_from_slice(payload);
+    let mut hasher = Hasher::new();
+    hasher.update(packet);
+    packet.extend_from_slice(&hasher.finalize().to_le_bytes());
+}
+
+/// The 64 random bytes starting an obfuscated connection, holding the tag
+/// of the transport and the datacenter's id.
+fn random_header(tag: [u8; 4], dc: i16) -> [u8; 64] {
+    let mut header = [0; 64];
+    loop {
+        OsRng.fill_bytes(&mut header);
+        let start: [u8; 4] = header[..4].try_into().unwrap();
+        if header[0] != 0xef && !RESERVED.contains(&start) && header[4..8] != [0; 4] {
+            break;
+        }
+    }
+    header[56..60].copy_from_slice(&tag);
+    header[60..62].copy_from_slice(&dc.to_le_bytes());
+    header
+}
+
+/// The header as it is sent, with its end encrypted, and the ciphers of
+/// what is sent and received after it. Keys are taken from the header, the
+/// ones of what is received reversed, and mixed with the secret.
+fn obfuscate(header: [u8; 64], secret: &[u8; 16]) -> ([u8; 64], Ctr, Ctr) {
+    let mut reversed = header;
+    reversed[8..56].reverse();
+    let mut encrypt = Ctr::keyed(&header[8..40], &header[40..56], secret);
+    let decrypt = Ctr::keyed(&reversed[8..40], &reversed[40..56], secret);
+
+    let mut sent = header;
+    encrypt.apply(&mut sent);
+    sent[..56].copy_from_slice(&header[..56]);
+    (sent, encrypt, decrypt)
+}
+
+/// AES-256 in counter mode, as obfuscated connections are encrypted with.
+struct Ctr {
+    cipher: Aes256,
+    counter: u128,
+    keystream: [u8; 16],
+    used: usize,
+}
+
+impl Ctr {
+    /// The cipher of `key` mixed with the proxy's secret.
+    fn keyed(key: &[u8], iv: &[u8], secret: &[u8; 16]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        hasher.update(secret);
+        Self::new(&hasher.finalize(), iv)
+    }
+
+    fn new(key: &[u8], iv: &[u8]) -> Self {
+        Self {
+            cipher: Aes256::new_from_slice(key).expect("AES-256 keys are 32 bytes"),
+            counter: u128::from_be_bytes(iv.try_into().expect("AES blocks are 16 bytes")),
+            keystream: [0; 16],
+            used: 16,
+        }
+    }
+
+    /// Encrypts or decrypts `data` in place.
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            if self.used == 16 {
+                let mut block = Block::clone_from_slice(&self.counter.to_be_bytes());
+                self.cipher.encrypt_block(&mut block);
+                self.keystream.copy_from_slice(&block);
+                self.counter = self.counter.wrapping_add(1);
+                self.used = 0;
+            }
+            *byte ^= self.keystream[self.used];
+            self.used += 1;
+        }
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 16] = [7; 16];
+
+    /// What the proxy makes of a header: the tag, the datacenter's id and
+    /// its ciphers of what it receives and sends.
+    fn accept(sent: &[u8; 64]) -> ([u8; 4], i16, Ctr, Ctr) {
+        let mut reversed = *sent;
+        reversed[8..56].reverse();
+        let mut decrypt = Ctr::keyed(&sent[8..40], &sent[40..56], &SECRET);
+        let encrypt = Ctr::keyed(&reversed[8..40], &reversed[40..56], &SECRET);
+
+        let mut header = *sent;
+        decrypt.apply(&mut header);
+        let tag = header[56..60].try_into().unwrap();
+        let dc = i16::from_le_bytes([header[60], header[61]]);
+        (tag, dc, decrypt, encrypt)
+    }
+
+    #[test]
+    fn test_obfuscate() {
+        let header = random_header(PADDED_INTERMEDIATE, 2);
+        assert!(!RESERVED.contains(&header[..4].try_into().unwrap()));
+        let (sent, mut encrypt, mut decrypt) = obfuscate(header, &SECRET);
+        assert_eq!(sent[..56], header[..56]);
+        assert_ne!(sent[56..], header[56..]);
+
+        let (tag, dc, mut proxy_decrypt, mut proxy_encrypt) = accept(&sent);
+        assert_eq!((tag, dc), (PADDED_INTERMEDIATE, 2));
+
+        let mut upload = *b"both ways, across several AES blocks of keystream";
+        encrypt.apply(&mut upload);
+        proxy_decrypt.apply(&mut upload);
+        assert_eq!(
+            &upload,
+            b"both ways, across several AES blocks of keystream"
+        );
+
+        let mut download = *b"and back";
+        proxy_encrypt.apply(&mut download);
+        decrypt.apply(&mut download);
+        assert_eq!(&download, b"and back");
+    }
+
+    #[test]
+    fn test_ctr() {
+        // F.5.5 of NIST SP 800-38A.
+        let key: Vec<u8> = (0..32)
+            .map(|i| {
+                u8::from_str_radix(
+                    &"603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"
+                        [i * 2..i * 2 + 2],
+                    16,
+                )
+                .unwrap()
+            })
+            .collect();
+        let iv = [
+            0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd,
+            0xfe, 0xff,
+        ];
+        let mut data = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a,
+        ];
+        Ctr::new(&key, &iv).apply(&mut data);
+        assert_eq!(
+            data,
+            [
+                0x60, 0x1e, 0xc3, 0x13, 0x77, 0x57, 0x89, 0xa5, 0xb7, 0xa7, 0xf5, 0x04, 0xbb, 0xf3,
+                0xd2, 0x28
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packets() {
+        let mut packet = Vec::new();
+        intermediate(b"abcdefgh", 3, &mut packet);
+        assert_eq!(packet.len(), 4 + 8 + 3);
+        assert_eq!(packet[..4], 11u32.to_le_bytes());
+        assert_eq!(&packet[4..12], b"abcdefgh");
+
+        packet.clear();
+        full(b"abcd", 5, &mut packet);
+        assert_eq!(packet[..8], [16, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(&packet[8..12], b"abcd");
+        assert_eq!(packet.len(), 16);
+
+        assert_eq!(dc_id(SocketAddr::from(DC_ADDRESSES[4])), Some(4));
+        assert_eq!(dc_id("127.0.0.1:443".parse().unwrap()), None);
+    }
+
+    /// A client connecting through the local proxy to a fake MTProto proxy
+    /// that echoes every packet back.
+    #[tokio::test]
+    async fn test_forward() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = proxy.local_addr().unwrap().to_string();
+        let echo = tokio::spawn(async move {
+            let (mut stream, _) = proxy.accept().await.unwrap();
+            let mut header = [0; 64];
+            stream.read_exact(&mut header).await.unwrap();
+            let (tag, dc, mut decrypt, mut encrypt) = accept(&header);
+            assert_eq!((tag, dc), (INTERMEDIATE, 2));
+
+            let mut len = [0; 4];
+            stream.read_exact(&mut len).await.unwrap();
+            decrypt.apply(&mut len);
+            let mut payload = vec![0; u32::from_le_bytes(len) as usize];
+            stream.read_exact(&mut payload).await.unwrap();
+            decrypt.apply(&mut payload);
+
+            let mut packet = Vec::new();
+            intermediate(&payload, 0, &mut packet);
+            encrypt.apply(&mut packet);
+            stream.write_all(&packet).await.unwrap();
+        });
+
+        let local = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local_address = local.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = local.accept().await.unwrap();
+            let secret = Secret {
+                key: SECRET,
+                padded: false,
+            };
+            forward(stream, &proxy_address, &secret).await.unwrap();
+        });
+
+        let mut client = TcpStream::connect(local_address).await.unwrap();
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        let mut reply = [0; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, [5, 0]);
+        let (ip, port) = DC_ADDRESSES[2];
+        let mut request = vec![5, 1, 0, 1];
+        request.extend_from_slice(&ip.octets());
+        request.extend_from_slice(&port.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], 0);
+
+        let mut packet = Vec::new();
+        full(b"ping-pong!!!", 0, &mut packet);
+        client.write_all(&packet).await.unwrap();
+        let mut echoed = vec![0; packet.len()];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, packet);
+        echo.await.unwrap();
+    }
+}
@@ -0,0 +1,17 @@
+use tokio::process::Command;
+
+use crate::config::Notify;
+
+/// Alerts the operator with the configured command, and always in the log.
+pub async fn send(notify: &Notify, message: &str) {
+    tracing::error!(message, "notifying operator");
+
+    let Some((program, args)) = notify.command.split_first() else {
+        return;
+    };
+    match Command::new(program).args(args).arg(message).status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::warn!(program, %status, "notification command failed"),
+        Err(e) => tracing::warn!(program, error = %e, "could not run notification command"),
+    }
+}
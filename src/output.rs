@@ -0,0 +1,1365 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+
+use crate::{
+    compress::Compression, locale::Formatter, provenance::Provenance, storage::Storage, Username,
+};
+
+/// File format of the crawl results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Json,
+    /// One JSON entry per line, for jq and duckdb
+    Jsonl,
+    /// Comma separated values, one row per entry
+    Csv,
+    /// Self-contained HTML page with sortable tables, for sharing
+    Html,
+    Yaml,
+    /// TOML, with the entries in a `usernames` array of tables
+    Toml,
+    /// Markdown report with a summary and tables of the top links, for
+    /// pasting into issues, wikis and chats
+    Markdown,
+    /// Apache Parquet, for pandas/polars/duckdb
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+            Format::Html => "html",
+            Format::Yaml => "yaml",
+            Format::Toml => "toml",
+            Format::Markdown => "md",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "parquet",
+        }
+    }
+
+    /// Whether the format can be written to stdout with `--out -`.
+    pub fn is_streamable(&self) -> bool {
+        matches!(self, Format::Json | Format::Jsonl | Format::Csv)
+    }
+}
+
+/// Where the results file is written.
+#[derive(Debug, clap::Args)]
+pub struct Destination {
+    /// Directory to write the results file to, created if it doesn't exist
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub out_dir: PathBuf,
+
+    /// Name of the results file, where `{chat}` is replaced by the chat,
+    /// `{date}` by the day of the crawl and `{ext}` by the format's extension
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{chat}.{ext}",
+        value_parser = parse_template
+    )]
+    out_name: String,
+
+    /// Compress the results and the other files written next to them, adding
+    /// `.gz` or `.zst` to their names
+    #[arg(long, value_enum, value_name = "ALGORITHM", conflicts_with = "out")]
+    pub compress: Option<Compression>,
+
+    /// `-` to write the results to stdout instead, with every message on
+    /// stderr, for pipelines. The other files are still written to
+    /// `--out-dir`
+    #[arg(long, value_name = "-", value_parser = parse_out)]
+    pub out: Option<String>,
+}
+
+/// Whether the results go to stdout, with `--out -`.
+static STDOUT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_stdout(stdout: bool) {
+    STDOUT.store(stdout, Ordering::Relaxed);
+}
+
+pub fn to_stdout() -> bool {
+    STDOUT.load(Ordering::Relaxed)
+}
+
+/// Whether the storage events go to stdout, with `--storage stdout://`.
+static STDOUT_EVENTS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_stdout_events(stdout: bool) {
+    STDOUT_EVENTS.store(stdout, Ordering::Relaxed);
+}
+
+/// Whether the results or the storage events go to stdout, leaving stderr
+/// for the messages for people.
+pub fn stdout_taken() -> bool {
+    to_stdout() || STDOUT_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Whether the header of the CSV written to stdout is out, as the results
+/// of every chat follow each other there.
+static CSV_HEADER: AtomicBool = AtomicBool::new(false);
+
+fn parse_out(out: &str) -> Result<String, String> {
+    match out {
+        "-" => Ok(out.to_string()),
+        _ => Err("only `-` is supported, use --out-dir and --out-name to name files".to_string()),
+    }
+}
+
+impl Destination {
+    pub fn path(&self, chat: &str, format: Format, now: i64) -> PathBuf {
+        let path = self.uncompressed_path(chat, format, now);
+        match self.compress {
+            Some(compression) => compression.path(path),
+            None => path,
+        }
+    }
+
+    /// Writes a file named by [`Destination::path`], compressed with
+    /// `--compress`.
+    pub fn write(&self, path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+        crate::compress::write(path, contents, self.compress)?;
+        Ok(())
+    }
+
+    fn uncompressed_path(&self, chat: &str, format: Format, now: i64) -> PathBuf {
+        let name = self
+            .out_name
+            .replace("{chat}", &sanitize(chat))
+            .replace("{date}", &date(now))
+            .replace("{ext}", format.extension());
+
+        // Keeps the `./` off the names printed for the default directory.
+        if self.out_dir == Path::new(".") {
+            PathBuf::from(name)
+        } else {
+            self.out_dir.join(name)
+        }
+    }
+}
+
+/// Checks that a file name template only uses the known placeholders and
+/// doesn't reach into other directories.
+fn parse_template(template: &str) -> Result<String, String> {
+    if template.contains(['/', '\\']) {
+        return Err("must be a file name, use --out-dir to pick the directory".to_string());
+    }
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in {template:?}"))?;
+        let placeholder = &rest[start..start + end + 1];
+        if !["{chat}", "{date}", "{ext}"].contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {placeholder}, expected {{chat}}, {{date}} or {{ext}}"
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+
+    Ok(template.to_string())
+}
+
+/// Makes a chat name safe to use in a file name, as export names come from
+/// arbitrary paths.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    // Leading dots would hide the file or, as `..`, leave the directory.
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "chat".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Formats a Unix timestamp as a UTC `YYYY-MM-DD` date, which also sorts
+/// correctly as text.
+pub fn date(timestamp: i64) -> String {
+    let (year, month, day) = civil(timestamp.div_euclid(86400));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Year, month and day of the day `days` days after 1970-01-01.
+pub fn civil(days: i64) -> (i64, i64, i64) {
+    let era = (days + 719468).div_euclid(146097);
+    let day_of_era = days + 719468 - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Sorts the results by descending score and count, breaking ties by link
+/// type and username, so identical crawls produce byte-identical files.
+pub fn sort(usernames: &mut [Username]) {
+    usernames.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.count.cmp(&a.count))
+            .then_with(|| a.username.cmp(&b.username))
+    });
+}
+
+/// Comma separated keys of the links merged into `username`, for formats
+/// without lists.
+fn aliases(username: &Username) -> String {
+    let keys: Vec<_> = username.aliases.iter().map(|alias| alias.key()).collect();
+    keys.join(", ")
+}
+
+/// Why the entry is restricted, looks suspicious or couldn't be resolved,
+/// for formats without nesting.
+fn status(username: &Username) -> Option<String> {
+    let Some(ref metadata) = username.metadata else {
+        return username
+            .unresolved
+            .map(|reason| reason.as_str().to_string());
+    };
+
+    let restriction = metadata
+        .restriction
+        .as_ref()
+        .map(|restriction| format!("restricted: {restriction}"));
+    let risk = metadata
+        .risk
+        .as_ref()
+        .filter(|risk| risk.score > 0)
+        .map(|risk| {
+            let signals: Vec<_> = risk.signals.iter().map(|s| s.as_str()).collect();
+            format!("risk {}: {}", risk.score, signals.join(", "))
+        });
+    match (restriction, risk) {
+        (Some(restriction), Some(risk)) => Some(format!("{restriction}; {risk}")),
+        (restriction, risk) => restriction.or(risk),
+    }
+}
+
+/// The results files of a chat in every `--format`, and their manifest,
+/// written as one more [`Storage`] sink so they get the same final results
+/// as `--storage`.
+pub struct Files<'a> {
+    name: &'a str,
+    formats: Vec<Format>,
+    destination: &'a Destination,
+    provenance: Provenance,
+    fmt: Formatter,
+    /// The files written, once finalized.
+    pub written: Vec<String>,
+}
+
+impl<'a> Files<'a> {
+    /// Results files named after `name` rather than the chat the crawl
+    /// calls them by, as it may be hashed or stamped.
+    pub fn new(
+        name: &'a str,
+        formats: &[Format],
+        destination: &'a Destination,
+        provenance: Provenance,
+        fmt: Formatter,
+    ) -> Self {
+        let mut unique = Vec::new();
+        for format in formats {
+            if !unique.contains(format) {
+                unique.push(*format);
+            }
+        }
+        Self {
+            name,
+            formats: unique,
+            destination,
+            provenance,
+            fmt,
+            written: Vec::new(),
+        }
+    }
+}
+
+impl Storage for Files<'_> {
+    fn finalize(&mut self, _chat: &str, usernames: &[Username]) -> Result<()> {
+        for &format in &self.formats {
+            let file = write(
+                usernames,
+                self.name,
+                format,
+                self.destination,
+                &self.provenance,
+                self.fmt,
+            )?;
+            self.written.push(file);
+        }
+        // A manifest would be the only file a pipeline has to clean up after.
+        if !to_stdout() {
+            self.provenance.write(self.name, self.destination)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the results of the chat `name`, already in the order [`sort`]
+/// puts them in, to the file `destination` names and returns the file
+/// name. The formats that can hold it carry the provenance of the results
+/// as well, and the reports format their numbers and dates with `fmt`.
+pub fn write(
+    usernames: &[Username],
+    name: &str,
+    format: Format,
+    destination: &Destination,
+    provenance: &Provenance,
+    fmt: Formatter,
+) -> Result<String> {
+    if to_stdout() {
+        let header = !CSV_HEADER.swap(true, Ordering::Relaxed);
+        let contents = match format {
+            Format::Json => serde_json::to_string_pretty(usernames)? + "\n",
+            Format::Jsonl => to_jsonl(usernames)?,
+            Format::Csv => csv::render(usernames, header),
+            _ => unreachable!("only streamable formats are written to stdout"),
+        };
+        // One chat's results at a time, when several are crawled at once.
+        std::io::stdout().lock().write_all(contents.as_bytes())?;
+        return Ok("stdout".to_string());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(name, format, now);
+    fs::create_dir_all(&destination.out_dir)?;
+
+    let contents = match format {
+        Format::Json => serde_json::to_string_pretty(usernames)?.into_bytes(),
+        Format::Jsonl => to_jsonl(usernames)?.into_bytes(),
+        Format::Csv => csv::render(usernames, true).into_bytes(),
+        Format::Html => html::render(usernames, name, provenance, fmt).into_bytes(),
+        Format::Yaml => serde_yaml::to_string(usernames)?.into_bytes(),
+        Format::Toml => to_toml(usernames, provenance)?.into_bytes(),
+        Format::Markdown => markdown::render(usernames, name, provenance, fmt).into_bytes(),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => {
+            let mut contents = Vec::new();
+            parquet::write(usernames, &mut contents)?;
+            contents
+        }
+    };
+    destination.write(&path, contents)?;
+
+    Ok(path.display().to_string())
+}
+
+fn to_jsonl(usernames: &[Username]) -> Result<String> {
+    let mut jsonl = String::new();
+    for username in usernames {
+        jsonl.push_str(&serde_json::to_string(username)?);
+        jsonl.push('\n');
+    }
+    Ok(jsonl)
+}
+
+/// TOML files have to be a table, so the entries go in a `usernames` array
+/// of tables, next to a `provenance` table.
+fn to_toml(usernames: &[Username], provenance: &Provenance) -> Result<String> {
+    #[derive(serde::Serialize)]
+    struct Results<'a> {
+        provenance: &'a Provenance,
+        usernames: &'a [Username],
+    }
+
+    Ok(toml::to_string(&Results {
+        provenance,
+        usernames,
+    })?)
+}
+
+mod html {
+    use std::fmt::Write;
+
+    use super::date;
+    use crate::{locale::Formatter, provenance::Provenance, LinkType, Username};
+
+    const STYLE: &str = "
+        body { font-family: sans-serif; margin: 2em; color: #222; }
+        table { border-collapse: collapse; width: 100%; }
+        th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+        th { cursor: pointer; background: #f4f4f4; user-select: none; }
+        th:hover { background: #e8e8e8; }
+        td.number { text-align: right; }
+        .new { color: #1a7f37; font-weight: bold; }
+        .provenance { color: #666; font-size: 0.9em; }
+    ";
+
+    /// Sorts a table by the clicked column, numerically when the cells hold
+    /// numbers, and in reverse when clicked again.
+    const SCRIPT: &str = "
+        document.querySelectorAll('th').forEach(th => th.addEventListener('click', () => {
+            const column = th.cellIndex;
+            const body = th.closest('table').tBodies[0];
+            const ascending = th.dataset.order !== 'asc';
+            th.dataset.order = ascending ? 'asc' : 'desc';
+            const value = row => row.cells[column].dataset.value ?? row.cells[column].innerText;
+            const rows = Array.from(body.rows).sort((a, b) => {
+                const [x, y] = [value(a), value(b)];
+                const order = !isNaN(x) && !isNaN(y) && x !== '' && y !== ''
+                    ? x - y
+                    : x.localeCompare(y);
+                return ascending ? order : -order;
+            });
+            body.append(...rows);
+        }));
+    ";
+
+    const COLUMNS: &[&str] = &[
+        "Username",
+        "Link type",
+        "Count",
+        "Reactions",
+        "Views",
+        "Score",
+        "Depth",
+        "Name",
+        "Chat type",
+        "Members",
+        "About",
+        "Aliases",
+        "First seen",
+        "Last seen",
+        "New",
+        "Messages",
+        "Status",
+    ];
+
+    pub fn render(
+        usernames: &[Username],
+        name: &str,
+        provenance: &Provenance,
+        fmt: Formatter,
+    ) -> String {
+        let mut html = String::new();
+        let title = escape(name);
+        let (customizations, links): (Vec<&Username>, Vec<&Username>) = usernames
+            .iter()
+            .partition(|username| is_customization(&username.username));
+
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>rampilo: {title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+             <h1>{title}</h1>\n<p>{} entries, click a column to sort.</p>\n",
+            fmt.number(usernames.len())
+        );
+        table(&mut html, &links, fmt);
+        if !customizations.is_empty() {
+            html.push_str("<h2>Sticker sets, emoji and themes</h2>\n");
+            table(&mut html, &customizations, fmt);
+        }
+
+        let _ = write!(
+            html,
+            "<p class=\"provenance\">{}</p>\n<script>{SCRIPT}</script>\n</body>\n</html>\n",
+            escape(&provenance.describe(fmt))
+        );
+        html
+    }
+
+    /// Sticker sets, custom emoji and themes, which get their own section as
+    /// they aren't chats.
+    fn is_customization(link: &LinkType) -> bool {
+        matches!(
+            link,
+            LinkType::StickerSet(_) | LinkType::EmojiSet(_) | LinkType::Theme(_)
+        )
+    }
+
+    fn table(html: &mut String, usernames: &[&Username], fmt: Formatter) {
+        html.push_str("<table>\n<thead><tr>");
+        for column in COLUMNS {
+            let _ = write!(html, "<th>{column}</th>");
+        }
+        html.push_str("</tr></thead>\n<tbody>\n");
+
+        for username in usernames {
+            let metadata = username.metadata.as_ref();
+            let text = escape(&username.username.to_string());
+            let link = match url(&username.username) {
+                Some(url) => format!("<a href=\"{}\">{text}</a>", escape(&url)),
+                None => text,
+            };
+            let members = metadata.and_then(|m| m.members);
+
+            let _ = writeln!(
+                html,
+                "<tr><td>{link}</td><td>{}</td>{}{}{}<td class=\"number\">{:.2}</td>\
+                 <td class=\"number\">{}</td><td>{}</td><td>{}</td>{}<td>{}</td><td>{}</td>\
+                 {}{}<td>{}</td><td>{}</td><td>{}</td></tr>",
+                username.username.kind(),
+                number(Some(username.count), fmt),
+                number(Some(username.reactions), fmt),
+                number(Some(username.views), fmt),
+                username.score,
+                username.depth,
+                metadata.map(|m| escape(&m.name)).unwrap_or_default(),
+                metadata.map(|m| m.type_.as_str()).unwrap_or_default(),
+                number(members, fmt),
+                metadata
+                    .and_then(|m| m.about.as_deref())
+                    .map(escape)
+                    .unwrap_or_default(),
+                escape(&super::aliases(username)),
+                day(username.first_seen, fmt),
+                day(username.last_seen, fmt),
+                if username.new {
+                    "<span class=\"new\">new</span>"
+                } else {
+                    ""
+                },
+                messages(username),
+                super::status(username)
+                    .map(|status| escape(&status))
+                    .unwrap_or_default(),
+            );
+        }
+
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    /// Cell of a number formatted for the locale, sorted by its value.
+    fn number(value: Option<usize>, fmt: Formatter) -> String {
+        match value {
+            Some(value) => format!(
+                "<td class=\"number\" data-value=\"{value}\">{}</td>",
+                fmt.number(value)
+            ),
+            None => "<td class=\"number\"></td>".to_string(),
+        }
+    }
+
+    /// Cell of a date formatted for the locale, sorted as `YYYY-MM-DD`.
+    fn day(timestamp: Option<i64>, fmt: Formatter) -> String {
+        match timestamp {
+            Some(timestamp) => format!(
+                "<td data-value=\"{}\">{}</td>",
+                date(timestamp),
+                fmt.date(timestamp)
+            ),
+            None => "<td></td>".to_string(),
+        }
+    }
+
+    /// Numbered links to the messages the link appeared in.
+    fn messages(username: &Username) -> String {
+        let links: Vec<_> = username
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| format!("<a href=\"{}\">{}</a>", escape(message), i + 1))
+            .collect();
+        links.join(" ")
+    }
+
+    /// Where the link can be opened, if it can.
+    pub fn url(link: &LinkType) -> Option<String> {
+        match link {
+            LinkType::Username(username) | LinkType::Mention(username) => {
+                Some(format!("https://t.me/{username}"))
+            }
+            LinkType::Hash(hash) => Some(format!("https://t.me/+{hash}")),
+            LinkType::Folder(slug) => Some(format!("https://t.me/addlist/{slug}")),
+            LinkType::Invoice(slug) => Some(format!("https://t.me/${slug}")),
+            LinkType::StickerSet(name) => Some(format!("https://t.me/addstickers/{name}")),
+            LinkType::EmojiSet(name) => Some(format!("https://t.me/addemoji/{name}")),
+            LinkType::Theme(slug) => Some(format!("https://t.me/addtheme/{slug}")),
+            LinkType::UserId(id) => Some(format!("tg://user?id={id}")),
+            LinkType::Phone(number) => Some(format!("tel:{number}")),
+            LinkType::Story(story) => {
+                let (username, id) = story.split_once('/')?;
+                Some(format!("https://t.me/{username}/s/{id}"))
+            }
+            LinkType::Boost(username) => Some(format!("https://t.me/boost/{username}")),
+            LinkType::Url(url) => Some(url.clone()),
+            LinkType::Hashtag(_) | LinkType::Proxy(_) => None,
+        }
+    }
+
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+mod markdown {
+    use std::fmt::Write;
+
+    use super::html::url;
+    use crate::{locale::Formatter, provenance::Provenance, Username};
+
+    /// Entries listed in the top links and in the section of every link
+    /// type, the rest are only counted.
+    const TOP: usize = 25;
+
+    pub fn render(
+        usernames: &[Username],
+        name: &str,
+        provenance: &Provenance,
+        fmt: Formatter,
+    ) -> String {
+        let mut markdown = String::new();
+        let _ = writeln!(
+            markdown,
+            "# rampilo: {}\n\n_{}_\n",
+            escape(name),
+            escape(&provenance.describe(fmt))
+        );
+
+        // Link types in the order of their best entry.
+        let mut kinds: Vec<(&str, Vec<&Username>)> = Vec::new();
+        for username in usernames {
+            let kind = username.username.kind();
+            match kinds.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, entries)) => entries.push(username),
+                None => kinds.push((kind, vec![username])),
+            }
+        }
+
+        let resolved = usernames.iter().filter(|u| u.metadata.is_some()).count();
+        let new = usernames.iter().filter(|u| u.new).count();
+        let _ = writeln!(
+            markdown,
+            "## Summary\n\n{} entries, {} of them resolved and {} new.\n",
+            fmt.number(usernames.len()),
+            fmt.number(resolved),
+            fmt.number(new)
+        );
+        markdown.push_str("| Link type | Entries | Count |\n| --- | ---: | ---: |\n");
+        for (kind, entries) in &kinds {
+            let count: usize = entries.iter().map(|u| u.count).sum();
+            let _ = writeln!(
+                markdown,
+                "| {kind} | {} | {} |",
+                fmt.number(entries.len()),
+                fmt.number(count)
+            );
+        }
+
+        if usernames.is_empty() {
+            return markdown;
+        }
+        markdown.push_str("\n## Top links\n\n");
+        let top: Vec<_> = usernames.iter().take(TOP).collect();
+        table(&mut markdown, &top, true, fmt);
+
+        for (kind, entries) in &kinds {
+            let _ = writeln!(markdown, "\n## {kind}\n");
+            table(
+                &mut markdown,
+                &entries[..entries.len().min(TOP)],
+                false,
+                fmt,
+            );
+            if entries.len() > TOP {
+                let _ = writeln!(markdown, "\nand {} more.", fmt.number(entries.len() - TOP));
+            }
+        }
+        markdown
+    }
+
+    fn table(markdown: &mut String, usernames: &[&Username], with_kind: bool, fmt: Formatter) {
+        let kind = if with_kind { " Link type |" } else { "" };
+        let align = if with_kind { " --- |" } else { "" };
+        let _ = writeln!(
+            markdown,
+            "| # | Link |{kind} Count | Score | Name | Members | Status |\n\
+             | ---: | --- |{align} ---: | ---: | --- | ---: | --- |"
+        );
+
+        for (i, username) in usernames.iter().enumerate() {
+            let metadata = username.metadata.as_ref();
+            let text = escape(&username.username.to_string());
+            let link = match url(&username.username) {
+                Some(url) => format!(
+                    "[{text}]({})",
+                    url.replace(' ', "%20")
+                        .replace(')', "%29")
+                        .replace('|', "%7C")
+                ),
+                None => text,
+            };
+            let kind = if with_kind {
+                format!(" | {}", username.username.kind())
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                markdown,
+                "| {} | {link}{}{kind} | {} | {:.2} | {} | {} | {} |",
+                i + 1,
+                if username.new { " **new**" } else { "" },
+                fmt.number(username.count),
+                username.score,
+                metadata.map(|m| escape(&m.name)).unwrap_or_default(),
+                metadata
+                    .and_then(|m| m.members)
+                    .map(|m| fmt.number(m))
+                    .unwrap_or_default(),
+                super::status(username)
+                    .map(|status| escape(&status))
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    /// Escapes the characters that would end a table cell or start
+    /// formatting, and keeps the text on one line.
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\\' | '|' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                '\n' | '\r' => escaped.push(' '),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+mod csv {
+    use crate::Username;
+
+    /// The columns of the Parquet files, in the same order.
+    const COLUMNS: &[&str] = &[
+        "username",
+        "link_type",
+        "count",
+        "name",
+        "type",
+        "about",
+        "members",
+        "date",
+        "new",
+        "aliases",
+        "depth",
+        "score",
+        "first_seen",
+        "last_seen",
+        "reactions",
+        "messages",
+        "status",
+        "views",
+    ];
+
+    pub fn render(usernames: &[Username], header: bool) -> String {
+        let mut csv = String::new();
+        if header {
+            csv.push_str(&COLUMNS.join(","));
+            csv.push_str("\r\n");
+        }
+        let number = |n: Option<i64>| n.map(|n| n.to_string()).unwrap_or_default();
+        for username in usernames {
+            let metadata = username.metadata.as_ref();
+            let row = [
+                username.username.to_string(),
+                username.username.kind().to_string(),
+                username.count.to_string(),
+                metadata.map(|m| m.name.clone()).unwrap_or_default(),
+                metadata
+                    .map(|m| m.type_.as_str().to_string())
+                    .unwrap_or_default(),
+                metadata.and_then(|m| m.about.clone()).unwrap_or_default(),
+                number(metadata.and_then(|m| m.members).map(|n| n as i64)),
+                number(metadata.and_then(|m| m.date)),
+                username.new.to_string(),
+                super::aliases(username),
+                username.depth.to_string(),
+                username.score.to_string(),
+                number(username.first_seen),
+                number(username.last_seen),
+                username.reactions.to_string(),
+                username.messages.join(" "),
+                super::status(username).unwrap_or_default(),
+                username.views.to_string(),
+            ];
+            let row: Vec<_> = row.iter().map(|field| quote(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    /// Quotes a field holding a separator, quote or line break, doubling
+    /// its quotes, as RFC 4180 has it.
+    fn quote(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use std::{io::Write, sync::Arc};
+
+    use color_eyre::eyre::Result;
+    use parquet::{
+        basic::Compression,
+        data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+
+    use crate::Username;
+
+    const SCHEMA: &str = "
+        message username {
+            REQUIRED BYTE_ARRAY username (UTF8);
+            REQUIRED BYTE_ARRAY link_type (UTF8);
+            REQUIRED INT64 count;
+            OPTIONAL BYTE_ARRAY name (UTF8);
+            OPTIONAL BYTE_ARRAY type (UTF8);
+            OPTIONAL BYTE_ARRAY about (UTF8);
+            OPTIONAL INT64 members;
+            OPTIONAL INT64 date;
+            REQUIRED BOOLEAN new;
+            OPTIONAL BYTE_ARRAY aliases (UTF8);
+            REQUIRED INT64 depth;
+            REQUIRED DOUBLE score;
+            OPTIONAL INT64 first_seen;
+            OPTIONAL INT64 last_seen;
+            REQUIRED INT64 reactions;
+            OPTIONAL BYTE_ARRAY messages (UTF8);
+            OPTIONAL BYTE_ARRAY status (UTF8);
+            REQUIRED INT64 views;
+        }
+    ";
+
+    pub fn write<W: Write + Send>(usernames: &[Username], out: W) -> Result<()> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = SerializedFileWriter::new(out, schema, Arc::new(properties))?;
+
+        let usernames_column = usernames
+            .iter()
+            .map(|u| ByteArray::from(u.username.to_string().as_str()))
+            .collect::<Vec<_>>();
+        let link_types = usernames
+            .iter()
+            .map(|u| ByteArray::from(u.username.kind()))
+            .collect::<Vec<_>>();
+        let counts = usernames.iter().map(|u| u.count as i64).collect::<Vec<_>>();
+
+        let metadata = usernames
+            .iter()
+            .map(|u| u.metadata.as_ref())
+            .collect::<Vec<_>>();
+        let definition_levels = metadata
+            .iter()
+            .map(|m| i16::from(m.is_some()))
+            .collect::<Vec<_>>();
+        let names = metadata
+            .iter()
+            .flatten()
+            .map(|m| ByteArray::from(m.name.as_str()))
+            .collect::<Vec<_>>();
+        let types = metadata
+            .iter()
+            .flatten()
+            .map(|m| ByteArray::from(m.type_.as_str()))
+            .collect::<Vec<_>>();
+
+        let abouts = metadata
+            .iter()
+            .map(|m| m.and_then(|m| m.about.as_deref()))
+            .collect::<Vec<_>>();
+        let about_levels = abouts
+            .iter()
+            .map(|a| i16::from(a.is_some()))
+            .collect::<Vec<_>>();
+        let abouts = abouts
+            .into_iter()
+            .flatten()
+            .map(ByteArray::from)
+            .collect::<Vec<_>>();
+
+        let members = metadata
+            .iter()
+            .map(|m| m.and_then(|m| m.members).map(|n| n as i64))
+            .collect::<Vec<_>>();
+        let members_levels = members
+            .iter()
+            .map(|n| i16::from(n.is_some()))
+            .collect::<Vec<_>>();
+        let members = members.into_iter().flatten().collect::<Vec<_>>();
+
+        let dates = metadata
+            .iter()
+            .map(|m| m.and_then(|m| m.date))
+            .collect::<Vec<_>>();
+        let date_levels = dates
+            .iter()
+            .map(|d| i16::from(d.is_some()))
+            .collect::<Vec<_>>();
+        let dates = dates.into_iter().flatten().collect::<Vec<_>>();
+
+        let new = usernames.iter().map(|u| u.new).collect::<Vec<_>>();
+
+        let alias_levels = usernames
+            .iter()
+            .map(|u| i16::from(!u.aliases.is_empty()))
+            .collect::<Vec<_>>();
+        let aliases = usernames
+            .iter()
+            .filter(|u| !u.aliases.is_empty())
+            .map(|u| ByteArray::from(super::aliases(u).as_str()))
+            .collect::<Vec<_>>();
+
+        let depths = usernames.iter().map(|u| u.depth as i64).collect::<Vec<_>>();
+        let scores = usernames.iter().map(|u| u.score).collect::<Vec<_>>();
+
+        let seen_levels = usernames
+            .iter()
+            .map(|u| i16::from(u.first_seen.is_some()))
+            .collect::<Vec<_>>();
+        let first_seen = usernames
+            .iter()
+            .filter_map(|u| u.first_seen)
+            .collect::<Vec<_>>();
+        let last_seen = usernames
+            .iter()
+            .filter_map(|u| u.last_seen)
+            .collect::<Vec<_>>();
+        let reactions = usernames
+            .iter()
+            .map(|u| u.reactions as i64)
+            .collect::<Vec<_>>();
+        let views = usernames.iter().map(|u| u.views as i64).collect::<Vec<_>>();
+
+        let message_levels = usernames
+            .iter()
+            .map(|u| i16::from(!u.messages.is_empty()))
+            .collect::<Vec<_>>();
+        let messages = usernames
+            .iter()
+            .filter(|u| !u.messages.is_empty())
+            .map(|u| ByteArray::from(u.messages.join(" ").as_str()))
+            .collect::<Vec<_>>();
+
+        let statuses = usernames.iter().map(super::status).collect::<Vec<_>>();
+        let status_levels = statuses
+            .iter()
+            .map(|s| i16::from(s.is_some()))
+            .collect::<Vec<_>>();
+        let statuses = statuses
+            .iter()
+            .flatten()
+            .map(|s| ByteArray::from(s.as_str()))
+            .collect::<Vec<_>>();
+
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&usernames_column, None, None)?,
+                1 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&link_types, None, None)?,
+                2 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&counts, None, None)?,
+                3 => column.typed::<ByteArrayType>().write_batch(
+                    &names,
+                    Some(&definition_levels),
+                    None,
+                )?,
+                4 => column.typed::<ByteArrayType>().write_batch(
+                    &types,
+                    Some(&definition_levels),
+                    None,
+                )?,
+                5 => column.typed::<ByteArrayType>().write_batch(
+                    &abouts,
+                    Some(&about_levels),
+                    None,
+                )?,
+                6 => column.typed::<Int64Type>().write_batch(
+                    &members,
+                    Some(&members_levels),
+                    None,
+                )?,
+                7 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&dates, Some(&date_levels), None)?,
+                8 => column.typed::<BoolType>().write_batch(&new, None, None)?,
+                9 => column.typed::<ByteArrayType>().write_batch(
+                    &aliases,
+                    Some(&alias_levels),
+                    None,
+                )?,
+                10 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&depths, None, None)?,
+                11 => column
+                    .typed::<DoubleType>()
+                    .write_batch(&scores, None, None)?,
+                12 => column.typed::<Int64Type>().write_batch(
+                    &first_seen,
+                    Some(&seen_levels),
+                    None,
+                )?,
+                13 => {
+                    column
+                        .typed::<Int64Type>()
+                        .write_batch(&last_seen, Some(&seen_levels), None)?
+                }
+                14 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&reactions, None, None)?,
+                15 => column.typed::<ByteArrayType>().write_batch(
+                    &messages,
+                    Some(&message_levels),
+                    None,
+                )?,
+                16 => column.typed::<ByteArrayType>().write_batch(
+                    &statuses,
+                    Some(&status_levels),
+                    None,
+                )?,
+                _ => column
+                    .typed::<Int64Type>()
+                    .write_batch(&views, None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        writer.close()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LinkType;
+
+    #[test]
+    fn test_canonical_order() {
+        let mut usernames = vec![
+            Username::new(LinkType::Mention("b".to_string())),
+            Username::new(LinkType::Username("b".to_string())),
+            Username::new(LinkType::Username("a".to_string())),
+            Username {
+                count: 2,
+                ..Username::new(LinkType::Hash("z".to_string()))
+            },
+        ];
+        sort(&mut usernames);
+
+        let order: Vec<_> = usernames.iter().map(|u| u.username.clone()).collect();
+        assert_eq!(
+            order,
+            vec![
+                LinkType::Hash("z".to_string()),
+                LinkType::Username("a".to_string()),
+                LinkType::Username("b".to_string()),
+                LinkType::Mention("b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_destination() {
+        let destination = Destination {
+            out_dir: PathBuf::from("results"),
+            out_name: parse_template("{chat}-{date}.{ext}").unwrap(),
+            compress: None,
+            out: None,
+        };
+        assert_eq!(
+            destination.path("../etc/pass:wd", Format::Html, 1709164800),
+            Path::new("results/_etc_pass_wd-2024-02-29.html")
+        );
+        let destination = Destination {
+            compress: Some(Compression::Zstd),
+            ..destination
+        };
+        assert_eq!(
+            destination.path("codenight", Format::Json, 1709164800),
+            Path::new("results/codenight-2024-02-29.json.zst")
+        );
+
+        assert!(parse_template("out/{chat}.{ext}").is_err());
+        assert!(parse_template("{chat}-{time}.{ext}").is_err());
+        assert!(parse_template("{chat.{ext}").is_err());
+        assert_eq!(sanitize(" .. "), "chat");
+    }
+
+    #[test]
+    fn test_files_formats() {
+        let destination = Destination {
+            out_dir: PathBuf::from("results"),
+            out_name: parse_template("{chat}.{ext}").unwrap(),
+            compress: None,
+            out: None,
+        };
+        let files = Files::new(
+            "codenight",
+            &[
+                Format::Csv,
+                Format::Json,
+                Format::Csv,
+                Format::Html,
+                Format::Json,
+            ],
+            &destination,
+            Provenance::new("codenight"),
+            Formatter::default(),
+        );
+        assert_eq!(files.formats, [Format::Csv, Format::Json, Format::Html]);
+    }
+
+    #[test]
+    fn test_html_report() {
+        let usernames = vec![
+            Username {
+                first_seen: Some(1612137600),
+                last_seen: Some(1709164800),
+                messages: vec!["https://t.me/codenight/7".to_string()],
+                unresolved: Some(crate::Unresolved::NotFound),
+                ..Username::new(LinkType::Mention("grammers".to_string()))
+            },
+            Username::new(LinkType::Url("https://example.com/?a=<b>".to_string())),
+            Username::new(LinkType::Hashtag("rust".to_string())),
+        ];
+        let html = html::render(
+            &usernames,
+            "codenight",
+            &Provenance::new("codenight"),
+            Formatter::default(),
+        );
+
+        assert_eq!(html.matches("<tr><td>").count(), 3);
+        assert!(html.contains("<a href=\"https://t.me/grammers\">grammers</a>"));
+        assert!(html.contains("https://example.com/?a=&lt;b&gt;"));
+        assert!(html.contains("<tr><td>#rust</td>"));
+        assert!(html.contains(
+            "<td data-value=\"2021-02-01\">2021-02-01</td><td data-value=\"2024-02-29\">2024-02-29</td>"
+        ));
+        assert!(
+            html.contains("<td><a href=\"https://t.me/codenight/7\">1</a></td><td>not found</td>")
+        );
+        assert!(!html.contains("<h2>"));
+
+        let usernames = vec![Username {
+            count: 1234,
+            last_seen: Some(1709164800),
+            ..Username::new(LinkType::Username("grammers".to_string()))
+        }];
+        let html = html::render(
+            &usernames,
+            "codenight",
+            &Provenance::new("codenight"),
+            crate::locale::parse("de").unwrap(),
+        );
+        assert!(html.contains("<td class=\"number\" data-value=\"1234\">1.234</td>"));
+        assert!(html.contains("<td data-value=\"2024-02-29\">29.02.2024</td>"));
+
+        let usernames = vec![
+            Username::new(LinkType::StickerSet("Cats".to_string())),
+            Username::new(LinkType::Username("grammers".to_string())),
+        ];
+        let html = html::render(
+            &usernames,
+            "codenight",
+            &Provenance::new("codenight"),
+            Formatter::default(),
+        );
+        let (links, customizations) = html.split_once("<h2>").unwrap();
+        assert!(links.contains("https://t.me/grammers"));
+        assert!(customizations.contains("<a href=\"https://t.me/addstickers/Cats\">Cats</a>"));
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let usernames = vec![
+            Username {
+                count: 3,
+                new: true,
+                ..Username::new(LinkType::Username("rust_beginners".to_string()))
+            },
+            Username::new(LinkType::Url("https://example.com/a|b".to_string())),
+            Username::new(LinkType::Username("grammers".to_string())),
+        ];
+        let markdown = markdown::render(
+            &usernames,
+            "codenight",
+            &Provenance::new("codenight"),
+            Formatter::default(),
+        );
+
+        assert!(markdown.starts_with("# rampilo: codenight\n\n_Crawled by rampilo "));
+        assert!(markdown.contains("| Username | 2 | 4 |\n| Url | 1 | 1 |\n"));
+        assert!(markdown.contains(
+            "| 1 | [rust\\_beginners](https://t.me/rust_beginners) **new** | Username | 3 |"
+        ));
+        assert!(markdown.contains("[https://example.com/a\\|b](https://example.com/a%7Cb)"));
+        assert_eq!(markdown.matches("\n## ").count(), 4);
+
+        let usernames = vec![Username {
+            count: 1234,
+            ..Username::new(LinkType::Username("grammers".to_string()))
+        }];
+        let markdown = markdown::render(
+            &usernames,
+            "codenight",
+            &Provenance::new("codenight"),
+            crate::locale::parse("de").unwrap(),
+        );
+        assert!(markdown.contains("| Username | 1 | 1.234 |\n"));
+        assert!(markdown.contains("| 1 | [grammers](https://t.me/grammers) | Username | 1.234 |"));
+    }
+
+    #[test]
+    fn test_csv_and_jsonl() {
+        let usernames = vec![
+            Username {
+                count: 2,
+                ..Username::new(LinkType::Url(
+                    "https://example.com/?a=1,b=\"2\"".to_string(),
+                ))
+            },
+            Username::new(LinkType::Username("grammers".to_string())),
+        ];
+
+        let csv = csv::render(&usernames, true);
+        let lines: Vec<_> = csv.split("\r\n").collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("username,link_type,count,name,"));
+        assert!(lines[1].starts_with("\"https://example.com/?a=1,b=\"\"2\"\"\",Url,2,,"));
+        assert!(lines[2].starts_with("grammers,Username,1,"));
+        assert!(!csv::render(&usernames, false).starts_with("username,"));
+
+        let jsonl = to_jsonl(&usernames).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        let first: Username = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(first.count, 2);
+    }
+
+    #[test]
+    fn test_yaml_and_toml() {
+        let usernames = vec![
+            Username {
+                metadata: Some(crate::UsernameMetadata {
+                    id: 1,
+                    name: "grammers".to_string(),
+                    type_: crate::UsernameType::Group,
+                    about: None,
+                    members: Some(42),
+                    date: None,
+                    restriction: None,
+                    language: None,
+                    label: None,
+                    risk: None,
+                    linked_chat: None,
+                    admins: None,
+                }),
+                ..Username::new(LinkType::Username("grammers".to_string()))
+            },
+            Username::new(LinkType::Hashtag("rust".to_string())),
+        ];
+
+        let yaml: Vec<Username> =
+            serde_yaml::from_str(&serde_yaml::to_string(&usernames).unwrap()).unwrap();
+        assert_eq!(yaml.len(), 2);
+
+        let provenance = Provenance::new("codenight");
+        let toml: toml::Table = to_toml(&usernames, &provenance).unwrap().parse().unwrap();
+        assert_eq!(toml["provenance"]["chat"].as_str(), Some("codenight"));
+        let entries = toml["usernames"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["metadata"]["members"].as_integer(), Some(42));
+        assert!(entries[1].get("metadata").is_none());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_row_count() {
+        let usernames = vec![
+            Username {
+                metadata: Some(crate::UsernameMetadata {
+                    id: 1,
+                    name: "grammers".to_string(),
+                    type_: crate::UsernameType::Group,
+                    about: None,
+                    members: Some(12),
+                    date: Some(1612137600),
+                    restriction: None,
+                    language: None,
+                    label: None,
+                    risk: None,
+                    linked_chat: None,
+                    admins: None,
+                }),
+                ..Username::new(LinkType::Username("grammers".to_string()))
+            },
+            Username::new(LinkType::Hash("USpx-sviNKIj408g".to_string())),
+        ];
+
+        let path = crate::storage::temp_path("parquet", "parquet");
+        parquet::write(&usernames, fs::File::create(&path).unwrap()).unwrap();
+
+        use ::parquet::file::reader::{FileReader, SerializedFileReader};
+        let reader = SerializedFileReader::new(fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        drop(reader);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use grammers_client::client::messages::InvocationError;
+use grammers_mtproto::mtp::RpcError;
+
+use crate::stats;
+
+/// Requests the crawler is allowed to make. None of them mark messages as
+/// read, join a chat or send a typing event, so crawled chats can't tell
+/// they are being read.
+const READ_ONLY: &[&str] = &[
+    "messages::GetHistory",
+    "messages::Search",
+    "messages::GetMessages",
+    "channels::GetMessages",
+    "channels::GetFullChannel",
+    "channels::GetParticipants",
+    "messages::GetFullChat",
+    "users::GetFullUser",
+    "messages::CheckChatInvite",
+    "contacts::Search",
+    "messages::GetDialogFilters",
+    "contacts::ResolveUsername",
+    "messages::GetDialogs",
+    "users::GetUsers",
+    "messages::GetChats",
+    "channels::GetChannels",
+    "updates::GetState",
+];
+
+/// Error returned for a refused request in `--strict-passive` mode.
+const ERROR_NAME: &str = "STRICT_PASSIVE_VIOLATION";
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Refuses every request outside of [`READ_ONLY`] from now on, instead of
+/// only logging it.
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Checks that `R` is a read-only request before it is sent.
+pub fn check<R>() -> Result<(), InvocationError> {
+    check_method(stats::method_name::<R>(), STRICT.load(Ordering::Relaxed))
+}
+
+/// Refuses `method` if it isn't read-only and `strict` is set, and only
+/// logs it otherwise.
+fn check_method(method: &str, strict: bool) -> Result<(), InvocationError> {
+    if is_read_only(method) {
+        return Ok(());
+    }
+
+    if strict {
+        tracing::error!(method, "refusing request that is not read-only");
+        return Err(InvocationError::Rpc(RpcError {
+            code: 403,
+            name: ERROR_NAME.to_string(),
+            value: None,
+            caused_by: None,
+        }));
+    }
+
+    tracing::warn!(method, "sending request that is not read-only");
+    Ok(())
+}
+
+fn is_read_only(method: &str) -> bool {
+    READ_ONLY.contains(&method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_tl_types::functions;
+
+    #[test]
+    fn test_read_only() {
+        assert!(is_read_only(stats::method_name::<
+            functions::messages::GetHistory,
+        >()));
+        assert!(is_read_only(stats::method_name::<
+            functions::channels::GetFullChannel,
+        >()));
+        assert!(is_read_only(stats::method_name::<
+            functions::messages::Search,
+        >()));
+        assert!(is_read_only(stats::method_name::<
+            functions::contacts::Search,
+        >()));
+        assert!(!is_read_only(stats::method_name::<
+            functions::messages::ReadHistory,
+        >()));
+        assert!(!is_read_only(stats::method_name::<
+            functions::channels::JoinChannel,
+        >()));
+        assert!(!is_read_only(stats::method_name::<
+            functions::messages::SetTyping,
+        >()));
+    }
+
+    #[test]
+    fn test_strict() {
+        let import = stats::method_name::<functions::messages::ImportChatInvite>();
+        let leave = stats::method_name::<functions::channels::LeaveChannel>();
+        let resolve = stats::method_name::<functions::contacts::ResolveUsername>();
+
+        for refused in [import, leave] {
+            assert!(matches!(
+                check_method(refused, true),
+                Err(InvocationError::Rpc(RpcError { code: 403, ref name, .. })) if name == ERROR_NAME
+            ));
+            assert!(check_method(refused, false).is_ok());
+        }
+        assert!(check_method(resolve, true).is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use directories::ProjectDirs;
+
+/// Profile used when `--profile` isn't given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Profile the files of this run belong to.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Keeps the files of this run apart from the ones of other profiles, e.g.
+/// another account.
+pub fn set_profile(profile: &str) {
+    let _ = PROFILE.set(profile.to_string());
+}
+
+/// Profile set with [`set_profile`], or the default one.
+pub fn profile() -> &'static str {
+    PROFILE.get().map_or(DEFAULT_PROFILE, String::as_str)
+}
+
+/// Path of a settings or credentials file, such as the session.
+pub fn config_file(name: &str) -> PathBuf {
+    locate(name, ProjectDirs::config_dir)
+}
+
+/// Path of a file that keeps what earlier runs found.
+pub fn data_file(name: &str) -> PathBuf {
+    locate(name, ProjectDirs::data_dir)
+}
+
+/// Path of a file that can be rebuilt by looking things up again.
+pub fn cache_file(name: &str) -> PathBuf {
+    locate(name, ProjectDirs::cache_dir)
+}
+
+/// `name` in the profile's directory of the platform's `dir`, which is
+/// created if needed.
+fn locate(name: &str, dir: fn(&ProjectDirs) -> &Path) -> PathBuf {
+    let dirs = ProjectDirs::from("", "", "rampilo");
+    resolve(name, profile(), Path::new(""), dirs.as_ref().map(dir))
+}
+
+/// `name` in `profile`'s directory under `base`. The default profile keeps
+/// using `name` in `cwd` if an older version left it there, and without a
+/// home directory to find `base` in, the profiles' directories go in `cwd`.
+/// Other profiles never read the file an older version left.
+fn resolve(name: &str, profile: &str, cwd: &Path, base: Option<&Path>) -> PathBuf {
+    let legacy = cwd.join(name);
+    if profile == DEFAULT_PROFILE && (legacy.exists() || base.is_none()) {
+        return legacy;
+    }
+
+    let dir = base.unwrap_or(cwd).join(profile);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        tracing::warn!(path = %dir.display(), error = %e, "could not create directory");
+        if profile == DEFAULT_PROFILE {
+            return legacy;
+        }
+    }
+    dir.join(name)
+}
+
+/// Parses a profile name, which names a directory.
+pub fn parse_profile(profile: &str) -> Result<String, String> {
+    let valid = profile
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if profile.is_empty() || profile.starts_with('.') || !valid {
+        return Err("may only hold letters, digits, `-`, `_` and `.`".to_string());
+    }
+    Ok(profile.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profile() {
+        assert_eq!(parse_profile("work-2").as_deref(), Ok("work-2"));
+        assert!(parse_profile("").is_err());
+        assert!(parse_profile("..").is_err());
+        assert!(parse_profile("a/b").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("rampilo-paths-{}", std::process::id()));
+        let (cwd, base) = (root.join("cwd"), root.join("config"));
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(cwd.join("crawler.session"), "").unwrap();
+
+        let work = resolve("crawler.session", "work", &cwd, Some(&base));
+        assert_eq!(work, base.join("work").join("crawler.session"));
+        assert!(base.join("work").is_dir());
+        let default = resolve("crawler.session", DEFAULT_PROFILE, &cwd, Some(&base));
+        assert_eq!(default, cwd.join("crawler.session"));
+        let fresh = resolve("api_info.json", DEFAULT_PROFILE, &cwd, Some(&base));
+        assert_eq!(fresh, base.join(DEFAULT_PROFILE).join("api_info.json"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
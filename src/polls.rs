@@ -0,0 +1,222 @@
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_tl_types as tl;
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Destination, Format};
+
+/// The polls and quizzes posted in a chat, for `--polls`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Polls {
+    /// Whether polls are collected at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    pub polls: Vec<Poll>,
+}
+
+/// A poll with its votes as they were when it was crawled.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Poll {
+    /// Unix timestamp of the message the poll was posted in.
+    pub date: i64,
+    /// Link to the message, if the chat can be linked to.
+    pub message: Option<String>,
+    pub question: String,
+    pub quiz: bool,
+    pub multiple_choice: bool,
+    pub closed: bool,
+    /// `None` when Telegram doesn't show the results, e.g. to accounts that
+    /// haven't voted yet.
+    pub total_voters: Option<usize>,
+    pub options: Vec<PollOption>,
+    /// Explanation of the right answer of a quiz.
+    pub solution: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PollOption {
+    pub text: String,
+    pub voters: Option<usize>,
+    /// Whether this is the right answer of a quiz, only known once the
+    /// account answered or the quiz closed.
+    pub correct: bool,
+}
+
+impl Polls {
+    /// Keeps the poll of the message, if it has one.
+    pub fn record(&mut self, message: &tl::types::Message, permalink: Option<&str>) {
+        if !self.enabled {
+            return;
+        }
+        let Some(tl::enums::MessageMedia::Poll(ref media)) = message.media else {
+            return;
+        };
+        let tl::enums::Poll::Poll(ref poll) = media.poll;
+        let tl::enums::PollResults::Results(ref results) = media.results;
+
+        let voters = |option: &[u8]| {
+            results.results.iter().flatten().find_map(|voters| {
+                let tl::enums::PollAnswerVoters::Voters(voters) = voters;
+                (voters.option == option).then_some(voters)
+            })
+        };
+        let options = poll
+            .answers
+            .iter()
+            .map(|tl::enums::PollAnswer::Answer(answer)| {
+                let voters = voters(&answer.option);
+                PollOption {
+                    text: answer.text.clone(),
+                    voters: voters.map(|v| v.voters.max(0) as usize),
+                    correct: voters.is_some_and(|v| v.correct),
+                }
+            })
+            .collect();
+
+        self.polls.push(Poll {
+            date: message.date as i64,
+            message: permalink.map(str::to_string),
+            question: poll.question.clone(),
+            quiz: poll.quiz,
+            multiple_choice: poll.multiple_choice,
+            closed: poll.closed,
+            total_voters: results.total_voters.map(|n| n.max(0) as usize),
+            options,
+            solution: results.solution.clone(),
+        });
+    }
+
+    /// Drops the links to the messages, which name the chat, for
+    /// `--anonymize`.
+    pub fn unlink(&mut self) {
+        for poll in &mut self.polls {
+            poll.message = None;
+        }
+    }
+
+    /// Writes the polls next to the results as `<chat>.polls.json`, or
+    /// whatever `--out-name` makes of that name, oldest first.
+    pub fn write(&mut self, chat: &str, destination: &Destination) -> Result<String> {
+        self.polls.sort_by_key(|poll| poll.date);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let path = destination.path(&format!("{chat}.polls"), Format::Json, now);
+        fs::create_dir_all(&destination.out_dir)?;
+        destination.write(&path, serde_json::to_string_pretty(&self.polls)?)?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(media: tl::enums::MessageMedia) -> tl::types::Message {
+        tl::types::Message {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            pinned: false,
+            noforwards: false,
+            id: 7,
+            from_id: None,
+            peer_id: tl::types::PeerChannel { channel_id: 1 }.into(),
+            fwd_from: None,
+            via_bot_id: None,
+            reply_to: None,
+            date: 1697414400,
+            message: String::new(),
+            media: Some(media),
+            reply_markup: None,
+            entities: None,
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            reactions: None,
+            restriction_reason: None,
+            ttl_period: None,
+        }
+    }
+
+    #[test]
+    fn test_record_poll() {
+        let answer = |text: &str, option: u8| {
+            tl::enums::PollAnswer::Answer(tl::types::PollAnswer {
+                text: text.to_string(),
+                option: vec![option],
+            })
+        };
+        let voters = |option: u8, voters, correct| {
+            tl::enums::PollAnswerVoters::Voters(tl::types::PollAnswerVoters {
+                chosen: false,
+                correct,
+                option: vec![option],
+                voters,
+            })
+        };
+        let media = tl::types::MessageMediaPoll {
+            poll: tl::types::Poll {
+                id: 1,
+                closed: true,
+                public_voters: false,
+                multiple_choice: false,
+                quiz: true,
+                question: "Which language?".to_string(),
+                answers: vec![answer("Rust", 0), answer("Go", 1)],
+                close_period: None,
+                close_date: None,
+            }
+            .into(),
+            results: tl::types::PollResults {
+                min: false,
+                results: Some(vec![voters(0, 30, true), voters(1, 12, false)]),
+                total_voters: Some(42),
+                recent_voters: None,
+                solution: Some("Obviously".to_string()),
+                solution_entities: None,
+            }
+            .into(),
+        };
+
+        let mut polls = Polls {
+            enabled: true,
+            ..Default::default()
+        };
+        polls.record(&message(media.into()), Some("https://t.me/codenight/7"));
+
+        let poll = &polls.polls[0];
+        assert_eq!(poll.question, "Which language?");
+        assert!(poll.quiz && poll.closed);
+        assert_eq!(poll.total_voters, Some(42));
+        assert_eq!(
+            poll.options,
+            [
+                PollOption {
+                    text: "Rust".to_string(),
+                    voters: Some(30),
+                    correct: true
+                },
+                PollOption {
+                    text: "Go".to_string(),
+                    voters: Some(12),
+                    correct: false
+                },
+            ]
+        );
+        assert_eq!(poll.message.as_deref(), Some("https://t.me/codenight/7"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::{sync::OnceLock, time::Duration};
+
+use regex::Regex;
+
+use crate::{export::unescape_html, UsernameMetadata, UsernameType};
+
+/// How much of a page is read looking for its title.
+const MAX_BODY: usize = 256 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fetches a page to get its title, for the
+/// `probe` resolution strategy. Pages that can't be fetched or answer with
+/// an error status count as unresolved.
+pub async fn probe(url: &str) -> Option<UsernameMetadata> {
+    match fetch(url).await {
+        Ok(Some(body)) => Some(UsernameMetadata {
+            id: 0,
+            name: title(&body).unwrap_or_else(|| url.to_string()),
+            type_: UsernameType::Website,
+            about: None,
+            members: None,
+            date: None,
+            restriction: None,
+            language: None,
+            label: None,
+            risk: None,
+            linked_chat: None,
+            admins: None,
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!(url, error = %e, "could not probe url");
+            None
+        }
+    }
+}
+
+/// Reads the start of the page, or `None` if the server answered with an
+/// error status.
+async fn fetch(url: &str) -> reqwest::Result<Option<String>> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .expect("the HTTP client builds")
+    });
+
+    let mut response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    // A page longer than `MAX_BODY` is cut off, which is not an error.
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BODY {
+            body.truncate(MAX_BODY);
+            break;
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn title(html: &str) -> Option<String> {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    let regex = TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+    let title = regex.captures(html)?[1]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!title.is_empty()).then(|| unescape_html(&title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title() {
+        assert_eq!(
+            title("<html><head><TITLE>\n  Rust &amp; Go\n</TITLE></head>").as_deref(),
+            Some("Rust & Go")
+        );
+        assert_eq!(title("<title> </title>"), None);
+        assert_eq!(title("no title"), None);
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    locale::Formatter,
+    output::{Destination, Format},
+};
+
+/// Options whose values are credentials or may hold some, like the password
+/// of a database url.
+const SECRET_OPTIONS: &[&str] = &[
+    "--bot-token",
+    "--anonymize",
+    "--storage",
+    "--notify-url",
+    "--proxy",
+];
+
+/// Where a results file came from, so it can be understood and the crawl
+/// repeated long after it was written.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Provenance {
+    /// `rampilo <version>`
+    pub tool: String,
+    /// Unix timestamp of when the results were written.
+    pub crawled_at: i64,
+    pub chat: String,
+    /// Telegram id of the crawled chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<i64>,
+    /// Messages read, including the ones of the runs a resumed crawl
+    /// continues.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages: Option<usize>,
+    /// Ids of the oldest and newest message read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_ids: Option<(i32, i32)>,
+    /// Share of the messages scanned with `--sample`. The counts of the
+    /// results are of the scanned messages only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<f64>,
+    /// Command line arguments of the run, with secrets left out.
+    pub options: Vec<String>,
+}
+
+impl Provenance {
+    pub fn new(chat: &str) -> Self {
+        Self {
+            tool: format!("rampilo {}", env!("CARGO_PKG_VERSION")),
+            crawled_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as i64),
+            chat: chat.to_string(),
+            chat_id: None,
+            messages: None,
+            message_ids: None,
+            sample: None,
+            options: redact(std::env::args().skip(1)),
+        }
+    }
+
+    pub fn chat_id(mut self, chat_id: i64) -> Self {
+        self.chat_id = Some(chat_id);
+        self
+    }
+
+    pub fn messages(mut self, messages: usize, ids: Option<(i32, i32)>) -> Self {
+        self.messages = Some(messages);
+        self.message_ids = ids;
+        self
+    }
+
+    pub fn sample(mut self, rate: Option<f64>) -> Self {
+        self.sample = rate;
+        self
+    }
+
+    /// Leaves out what would tell which chat was crawled, for results
+    /// anonymized as `chat`.
+    pub fn anonymize(&mut self, chat: &str) {
+        self.chat = chat.to_string();
+        self.chat_id = None;
+        self.options.clear();
+    }
+
+    /// One line account of the crawl, for the human readable formats.
+    pub fn describe(&self, fmt: Formatter) -> String {
+        let mut line = format!(
+            "Crawled by {} on {} UTC from {}",
+            self.tool,
+            timestamp(self.crawled_at, fmt),
+            self.chat
+        );
+        if let Some(chat_id) = self.chat_id {
+            line.push_str(&format!(" (id {chat_id})"));
+        }
+        if let Some(messages) = self.messages {
+            line.push_str(&format!(", {} messages", fmt.number(messages)));
+        }
+        if let Some((first, last)) = self.message_ids {
+            line.push_str(&format!(" with ids {first} to {last}"));
+        }
+        if let Some(rate) = self.sample {
+            line.push_str(&format!(", {}% of them scanned", rate * 100.0));
+        }
+        if !self.options.is_empty() {
+            line.push_str(&format!(", options: {}", self.options.join(" ")));
+        }
+        line
+    }
+
+    /// Writes the manifest of the results file `name` to
+    /// `<name>.manifest.json` next to it, and returns the file name.
+    pub fn write(&self, name: &str, destination: &Destination) -> Result<String> {
+        let path = destination.path(&format!("{name}.manifest"), Format::Json, self.crawled_at);
+        fs::create_dir_all(&destination.out_dir)?;
+        destination.write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path.display().to_string())
+    }
+}
+
+/// The date of a Unix timestamp as the locale writes it, and its `HH:MM`.
+fn timestamp(timestamp: i64, fmt: Formatter) -> String {
+    let minutes = timestamp.rem_euclid(86400) / 60;
+    format!(
+        "{} {:02}:{:02}",
+        fmt.date(timestamp),
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Replaces the values of [`SECRET_OPTIONS`] with `<redacted>`.
+fn redact(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut redacted = Vec::new();
+    let mut secret_next = false;
+    for arg in args {
+        if std::mem::take(&mut secret_next) {
+            redacted.push("<redacted>".to_string());
+            continue;
+        }
+        match arg.split_once('=') {
+            Some((option, _)) if SECRET_OPTIONS.contains(&option) => {
+                redacted.push(format!("{option}=<redacted>"));
+            }
+            _ => {
+                secret_next = SECRET_OPTIONS.contains(&arg.as_str());
+                redacted.push(arg);
+            }
+        }
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let args = [
+            "codenight",
+            "--bot-token",
+            "123:abc",
+            "--storage=postgres://me:pw@db/rampilo",
+            "--format",
+            "html",
+        ];
+        assert_eq!(
+            redact(args.into_iter().map(String::from)),
+            [
+                "codenight",
+                "--bot-token",
+                "<redacted>",
+                "--storage=<redacted>",
+                "--format",
+                "html"
+            ]
+        );
+        assert_eq!(
+            timestamp(1709215380, Formatter::default()),
+            "2024-02-29 14:03"
+        );
+        assert_eq!(
+            timestamp(1709215380, crate::locale::parse("de").unwrap()),
+            "29.02.2024 14:03"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use color_eyre::eyre::Result;
+use grammers_client::InitParams;
+
+#[cfg(feature = "proxy")]
+use crate::mtproxy;
+
+/// A proxy Telegram is reached through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proxy {
+    /// `socks5://[<user>:<password>@]<host>:<port>`
+    Socks5(String),
+    /// MTProto proxy of a `t.me/proxy` or `tg://proxy` link.
+    MtProto {
+        server: String,
+        port: u16,
+        secret: Secret,
+    },
+}
+
+/// Secret of an MTProto proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Secret {
+    pub key: [u8; 16],
+    /// Whether the secret was prefixed by `dd`, asking for packets padded
+    /// to random lengths.
+    pub padded: bool,
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    fn from_str(proxy: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = proxy.strip_prefix("socks5://") {
+            let address = rest.rsplit_once('@').map_or(rest, |(_, address)| address);
+            let valid = address
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+            if !valid {
+                return Err(format!("{proxy} has no host and port"));
+            }
+            return Ok(Proxy::Socks5(proxy.to_string()));
+        }
+
+        let query = ["https://t.me/proxy?", "t.me/proxy?", "tg://proxy?"]
+            .iter()
+            .find_map(|prefix| proxy.strip_prefix(prefix))
+            .ok_or("expected socks5://<host>:<port> or a t.me/proxy link")?;
+        let param = |name: &str| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+                .ok_or(format!("the proxy link has no {name}"))
+        };
+        let port = param("port")?;
+        Ok(Proxy::MtProto {
+            server: param("server")?.to_string(),
+            port: port.parse().map_err(|_| format!("invalid port {port}"))?,
+            secret: param("secret")?.parse()?,
+        })
+    }
+}
+
+impl FromStr for Secret {
+    type Err = String;
+
+    /// Decodes the hex secret of a proxy link: 16 bytes, prefixed by `dd`
+    /// for padded connections. Secrets prefixed by `ee` belong to proxies
+    /// that disguise themselves as TLS, which aren't supported.
+    fn from_str(secret: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid proxy secret {secret}");
+        if !secret.is_ascii() || !secret.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let bytes = (0..secret.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&secret[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+
+        let (key, padded) = match (bytes.len(), bytes.first()) {
+            (16, _) => (&bytes[..], false),
+            (17, Some(0xdd)) => (&bytes[1..], true),
+            (len, Some(0xee)) if len > 17 => {
+                return Err(
+                    "proxies disguised as TLS (secrets starting with ee) are not supported"
+                        .to_string(),
+                )
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Secret {
+            key: key.try_into().map_err(|_| invalid())?,
+            padded,
+        })
+    }
+}
+
+/// Proxy every connection of this run goes through.
+static PROXY: OnceLock<Proxy> = OnceLock::new();
+
+pub fn set(proxy: Proxy) {
+    let _ = PROXY.set(proxy);
+}
+
+/// Connection settings of the client, through the proxy if there is one.
+pub fn params() -> Result<InitParams> {
+    match PROXY.get() {
+        None => Ok(InitParams::default()),
+        #[cfg(feature = "proxy")]
+        Some(proxy) => Ok(InitParams {
+            proxy_url: Some(proxy.url()?),
+            ..Default::default()
+        }),
+        #[cfg(not(feature = "proxy"))]
+        Some(_) => {
+            color_eyre::eyre::bail!(
+                "Connecting through a proxy needs a build with the `proxy` feature"
+            )
+        }
+    }
+}
+
+#[cfg(feature = "proxy")]
+impl Proxy {
+    /// URL of the SOCKS5 proxy the client connects through. MTProto proxies
+    /// are reached through a local one that forwards the connections to
+    /// them.
+    fn url(&self) -> Result<String> {
+        match self {
+            Proxy::Socks5(url) => Ok(url.clone()),
+            Proxy::MtProto {
+                server,
+                port,
+                secret,
+            } => Ok(mtproxy::start(server, *port, secret)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "socks5://me:pw@127.0.0.1:1080".parse(),
+            Ok(Proxy::Socks5("socks5://me:pw@127.0.0.1:1080".to_string()))
+        );
+        assert!("socks5://localhost".parse::<Proxy>().is_err());
+        assert!("socks5://localhost:port".parse::<Proxy>().is_err());
+
+        let proxy: Proxy =
+            "tg://proxy?server=proxy.example&port=443&secret=dd0123456789abcdef0123456789abcdef"
+                .parse()
+                .unwrap();
+        let Proxy::MtProto {
+            server,
+            port,
+            secret,
+        } = proxy
+        else {
+            panic!("not an MTProto proxy");
+        };
+        assert_eq!((server.as_str(), port), ("proxy.example", 443));
+        assert!(secret.padded);
+        assert_eq!(secret.key[..2], [0x01, 0x23]);
+
+        let plain: Secret = "0123456789abcdef0123456789abcdef".parse().unwrap();
+        assert!(!plain.padded);
+        assert!("https://t.me/proxy?server=a&port=443&secret=ff"
+            .parse::<Proxy>()
+            .is_err());
+        assert!("ee0123456789abcdef0123456789abcdef6578616d706c652e636f6d"
+            .parse::<Secret>()
+            .is_err());
+        assert!("http://127.0.0.1:8080".parse::<Proxy>().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Spaces out the requests of every chat crawled at once, so crawling more
+/// chats in parallel doesn't mean sending requests faster.
+struct RateLimiter {
+    interval: Duration,
+    /// When the next request may be sent.
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Lets `per_second` requests through every second.
+    fn per_second(per_second: f64) -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / per_second))
+    }
+
+    /// Reserves the next free slot, returning how long to wait for it.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut next = self.next.lock().unwrap();
+        let at = (*next).max(now);
+        *next = at + self.interval;
+        at - now
+    }
+
+    fn pause(&self, until: Instant) {
+        let mut next = self.next.lock().unwrap();
+        *next = (*next).max(until);
+    }
+}
+
+/// Most requests a run may send, from `--max-requests`.
+struct Budget {
+    max: usize,
+    /// How long a budget lasts before the next one starts, with
+    /// `--budget-window`. Without one, a used up budget stops the run.
+    window: Option<Duration>,
+    /// When the current budget started, and the requests sent before it.
+    started: Mutex<(Instant, usize)>,
+}
+
+impl Budget {
+    fn new(max: usize, window: Option<Duration>) -> Self {
+        Self {
+            max,
+            window,
+            started: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Whether `sent` requests used up the budget, with no next one to wait
+    /// for.
+    fn used_up(&self, sent: usize) -> bool {
+        self.window.is_none() && sent >= self.max
+    }
+
+    /// Waits for the next budget if this one is used up and there is a
+    /// next one.
+    async fn wait(&self, sent: &AtomicUsize) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let (started, before) = *self.started.lock().unwrap();
+        if sent.load(Ordering::Relaxed) - before < self.max {
+            return;
+        }
+
+        let until = started + window;
+        let wait = until.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            tracing::warn!(
+                sent = self.max,
+                wait_secs = wait.as_secs(),
+                "request budget used up, waiting for the next one"
+            );
+            tokio::time::sleep(wait).await;
+        }
+
+        // Only the first request to wake up starts the next budget.
+        let mut started = self.started.lock().unwrap();
+        if started.0 < until {
+            *started = (Instant::now(), sent.load(Ordering::Relaxed));
+        }
+    }
+}
+
+static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+/// Spaces out the usernames resolved, on top of [`LIMITER`].
+static RESOLVER: OnceLock<RateLimiter> = OnceLock::new();
+static BUDGET: OnceLock<Budget> = OnceLock::new();
+/// Requests sent in the run.
+static SENT: AtomicUsize = AtomicUsize::new(0);
+/// Usernames and users resolved in the run.
+static RESOLVED: AtomicUsize = AtomicUsize::new(0);
+
+/// Limits every request made from now on to `per_second`, and the
+/// resolutions of usernames to one every `resolve_delay`.
+pub fn init(per_second: f64, resolve_delay: Duration) {
+    let _ = LIMITER.set(RateLimiter::per_second(per_second));
+    if !resolve_delay.is_zero() {
+        let _ = RESOLVER.set(RateLimiter::new(resolve_delay));
+    }
+}
+
+/// Caps the requests of a run at `max`, or of every `window` if given,
+/// and starts counting the requests of a new run.
+pub fn budget(max: usize, window: Option<Duration>) {
+    let budget = BUDGET.get_or_init(|| Budget::new(max, window));
+    *budget.started.lock().unwrap() = (Instant::now(), 0);
+    SENT.store(0, Ordering::Relaxed);
+    RESOLVED.store(0, Ordering::Relaxed);
+}
+
+/// Whether the run used up its request budget and has no next one to wait
+/// for.
+pub fn over_budget() -> bool {
+    BUDGET
+        .get()
+        .is_some_and(|budget| budget.used_up(SENT.load(Ordering::Relaxed)))
+}
+
+/// Requests sent, and usernames and users resolved, in the run.
+pub fn sent() -> (usize, usize) {
+    (
+        SENT.load(Ordering::Relaxed),
+        RESOLVED.load(Ordering::Relaxed),
+    )
+}
+
+/// Waits until the next request may be sent, and counts it.
+pub async fn acquire() {
+    if let Some(budget) = BUDGET.get() {
+        budget.wait(&SENT).await;
+    }
+    SENT.fetch_add(1, Ordering::Relaxed);
+    wait(&LIMITER).await;
+}
+
+/// Waits until the next username may be resolved. The request that
+/// resolves it still has to [`acquire`] a slot as well.
+pub async fn acquire_resolve() {
+    RESOLVED.fetch_add(1, Ordering::Relaxed);
+    wait(&RESOLVER).await;
+}
+
+async fn wait(limiter: &OnceLock<RateLimiter>) {
+    let Some(limiter) = limiter.get() else {
+        return;
+    };
+
+    let wait = limiter.reserve(Instant::now());
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Holds back every request for `wait`, after Telegram asked one of them to
+/// slow down.
+pub fn pause(wait: Duration) {
+    if let Some(limiter) = LIMITER.get() {
+        limiter.pause(Instant::now() + wait);
+    }
+}
+
+/// Parses a positive number of requests per second.
+pub fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("{rate:?} is not a positive number")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_per_second() {
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("inf").is_err());
+        assert!(parse_rate("fast").is_err());
+
+        assert_eq!(
+            RateLimiter::per_second(4.0).interval,
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            RateLimiter::per_second(0.5).interval,
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(3, None);
+        assert!(!budget.used_up(2));
+        assert!(budget.used_up(3));
+
+        // With a window, a used up budget is waited out instead.
+        assert!(!Budget::new(3, Some(Duration::from_secs(60))).used_up(10));
+    }
+
+    #[tokio::test]
+    async fn test_budget_window() {
+        let sent = AtomicUsize::new(2);
+        let budget = Budget::new(2, Some(Duration::from_millis(50)));
+
+        let start = Instant::now();
+        budget.wait(&sent).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(budget.started.lock().unwrap().1, 2);
+
+        // The next budget starts with the requests sent before it.
+        sent.store(3, Ordering::Relaxed);
+        let start = Instant::now();
+        budget.wait(&sent).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let limiter = RateLimiter::new(Duration::from_millis(250));
+        let now = Instant::now();
+        *limiter.next.lock().unwrap() = now;
+
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::from_millis(250));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(500));
+
+        limiter.pause(now + Duration::from_secs(10));
+        assert_eq!(limiter.reserve(now), Duration::from_secs(10));
+        assert_eq!(
+            limiter.reserve(now + Duration::from_secs(20)),
+            Duration::ZERO
+        );
+    }
+}
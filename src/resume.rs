@@ -0,0 +1,186 @@
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Tally, Usernames};
+
+/// Everything needed to continue an aborted crawl: the chat, the id of the
+/// last processed message and the id of the run whose partial results should
+/// be picked up again.
+///
+/// It is printed as `<chat>:<offset id>:<run id>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeToken {
+    pub chat: String,
+    pub offset_id: i32,
+    pub run_id: String,
+}
+
+impl ResumeToken {
+    fn partial_file(&self) -> String {
+        format!("{}.{}.partial.json", self.chat, self.run_id)
+    }
+
+    /// Where `--low-memory` moves entries to, next to the partial results.
+    pub fn spill_dir(&self) -> PathBuf {
+        format!("{}.{}.spill", self.chat, self.run_id).into()
+    }
+
+    /// Stores the results gathered so far so that the resumed run can add to
+    /// them. The file is replaced at once, so a crash while writing it keeps
+    /// the previous one.
+    pub fn save_partial(
+        &self,
+        usernames: &Usernames,
+        messages: usize,
+        skipped: &[i32],
+        tally: &Tally,
+        spilled_at: Option<usize>,
+    ) -> Result<()> {
+        let partial = Partial {
+            offset_id: Some(self.offset_id),
+            messages,
+            usernames: usernames.clone(),
+            skipped: skipped.to_vec(),
+            tally: tally.clone(),
+            spilled_at,
+        };
+        let tmp = format!("{}.tmp", self.partial_file());
+        fs::write(&tmp, serde_json::to_string(&partial)?)?;
+        fs::rename(tmp, self.partial_file())?;
+        Ok(())
+    }
+
+    /// Loads the results of the aborted run.
+    pub fn load_partial(&self) -> Result<Partial> {
+        match fs::read_to_string(self.partial_file()) {
+            Ok(contents) => {
+                let mut partial: Partial = serde_json::from_str(&contents)?;
+                // Partials only hold the crawled chat itself, where every
+                // link scores its count. Older ones have no score at all.
+                for username in partial.usernames.values_mut() {
+                    username.score = username.count as f64;
+                }
+                Ok(partial)
+            }
+            Err(_) => {
+                tracing::warn!(path = self.partial_file(), "no partial results found");
+                Ok(Default::default())
+            }
+        }
+    }
+
+    pub fn remove_partial(&self) {
+        let _ = fs::remove_file(self.partial_file());
+    }
+}
+
+impl fmt::Display for ResumeToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.chat, self.offset_id, self.run_id)
+    }
+}
+
+impl FromStr for ResumeToken {
+    type Err = eyre::Report;
+
+    fn from_str(token: &str) -> Result<Self> {
+        let invalid =
+            || eyre::eyre!("Invalid resume token {token:?}, expected <chat>:<offset id>:<run id>");
+
+        let mut parts = token.split(':');
+        let (Some(chat), Some(offset_id), Some(run_id), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+
+        if chat.is_empty() || run_id.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            chat: chat.to_string(),
+            offset_id: offset_id.parse().map_err(|_| invalid())?,
+            run_id: run_id.to_string(),
+        })
+    }
+}
+
+/// Results of an aborted run.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Partial {
+    /// Id of the last processed message, which the resumed run continues
+    /// from whatever the token says. Older partials don't have it.
+    #[serde(default)]
+    pub offset_id: Option<i32>,
+    /// Number of messages processed.
+    pub messages: usize,
+    pub usernames: Usernames,
+    /// Ids of messages in skipped pages that still have to be fetched.
+    #[serde(default)]
+    pub skipped: Vec<i32>,
+    /// Service messages, languages and polls gathered so far, which older
+    /// partials kept as fields of their own, under the same names.
+    #[serde(flatten)]
+    pub tally: Tally,
+    /// Messages read when entries were last moved to disk with
+    /// `--low-memory`, which `usernames` doesn't include.
+    #[serde(default)]
+    pub spilled_at: Option<usize>,
+}
+
+/// Short id distinguishing one crawl run from another.
+pub fn new_run_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{:08x}", nanos as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_token_roundtrip() {
+        let token = ResumeToken {
+            chat: "grammers".to_string(),
+            offset_id: 4213,
+            run_id: "0a1b2c3d".to_string(),
+        };
+
+        assert_eq!(token.to_string(), "grammers:4213:0a1b2c3d");
+        assert_eq!(token.to_string().parse::<ResumeToken>().unwrap(), token);
+    }
+
+    #[test]
+    fn test_partial_offset_id() {
+        let partial: Partial =
+            serde_json::from_str(r#"{"messages":10000,"usernames":{}}"#).unwrap();
+        assert_eq!(partial.offset_id, None);
+
+        let partial = Partial {
+            offset_id: Some(4213),
+            ..partial
+        };
+        let partial: Partial =
+            serde_json::from_str(&serde_json::to_string(&partial).unwrap()).unwrap();
+        assert_eq!(partial.offset_id, Some(4213));
+        assert_eq!(partial.messages, 10000);
+    }
+
+    #[test]
+    fn test_invalid_resume_token() {
+        assert!("grammers".parse::<ResumeToken>().is_err());
+        assert!("grammers:abc:0a1b2c3d".parse::<ResumeToken>().is_err());
+        assert!("grammers:1:2:3".parse::<ResumeToken>().is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{Result, WrapErr};
+use grammers_client::Client;
+use indicatif::ProgressBar;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::ResolveCache,
+    compress::{self, Compression},
+    config::{Config, Strategies, Strategy},
+    keep, output, ratelimit, resolve_all, shutdown, Unresolved, Username,
+};
+
+/// Serializes the crawls of several chats adding to the queue at once.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Usernames whose lookup failed or was cut short, kept across runs so
+/// `rampilo retry` can look them up again.
+pub struct RetryQueue {
+    path: PathBuf,
+    entries: Vec<Queued>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Queued {
+    /// Chat the link was found in.
+    chat: String,
+    /// JSON results file to update once the link is looked up, if one was
+    /// written.
+    file: Option<PathBuf>,
+    /// Unix timestamp of the crawl the lookup failed in.
+    queued_at: i64,
+    /// Times `rampilo retry` failed to look the link up.
+    #[serde(default)]
+    attempts: u32,
+    username: Username,
+}
+
+impl Queued {
+    fn matches(&self, other: &Queued) -> bool {
+        self.chat == other.chat
+            && self.file == other.file
+            && self.username.username.key() == other.username.username.key()
+    }
+}
+
+impl RetryQueue {
+    /// Loads the queue, starting an empty one if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .wrap_err_with(|| format!("{} is not a retry queue", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    /// Queues the usernames of `chat` that weren't looked up, replacing the
+    /// ones an earlier crawl of the chat queued for the same file.
+    fn add(&mut self, chat: &str, file: Option<&Path>, usernames: Vec<Username>, now: i64) {
+        for username in usernames {
+            let queued = Queued {
+                chat: chat.to_string(),
+                file: file.map(Path::to_path_buf),
+                queued_at: now,
+                attempts: 0,
+                username,
+            };
+            self.entries.retain(|entry| !entry.matches(&queued));
+            self.entries.push(queued);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        // Written next to the queue and renamed over it, like the seen
+        // database.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.entries)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Whether looking the entry up again may give its metadata: usernames and
+/// mentions whose lookup failed or never happened because the crawl was
+/// interrupted or ran out of requests.
+fn should_retry(username: &Username, strategies: &Strategies) -> bool {
+    matches!(
+        username.username,
+        LinkType::Username(_) | LinkType::Mention(_)
+    ) && strategies.for_link(&username.username) == Strategy::Resolve
+        && username.metadata.is_none()
+        && matches!(username.unresolved, None | Some(Unresolved::Failed))
+}
+
+/// The entries of a crawl that should be looked up again, before the
+/// unresolved ones are left out of the results.
+pub fn failed(usernames: &[Username], strategies: &Strategies) -> Vec<Username> {
+    usernames
+        .iter()
+        .filter(|u| should_retry(u, strategies))
+        .cloned()
+        .collect()
+}
+
+/// Adds the `failed` entries of a crawl of `chat` to the queue at `path`.
+/// `files` are the results files written, of which the JSON one is updated
+/// by `rampilo retry`.
+pub fn record(path: &Path, chat: &str, failed: Vec<Username>, files: &[String]) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let file = files
+        .iter()
+        .map(Path::new)
+        .find(|file| is_json(file))
+        .and_then(|file| fs::canonicalize(file).ok());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+
+    let _lock = LOCK.lock().unwrap();
+    let mut queue = RetryQueue::load(path)?;
+    queue.add(chat, file.as_deref(), failed, now);
+    queue.save()
+}
+
+/// Whether a results file is JSON, compressed or not.
+fn is_json(file: &Path) -> bool {
+    let file = match Compression::from_path(file) {
+        Some(_) => file.with_extension(""),
+        None => file.to_path_buf(),
+    };
+    file.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+/// Looks up the queued usernames again, updates the results files they
+/// were left out of or unresolved in, and keeps the ones that failed again
+/// for the next retry.
+pub async fn run(client: &Client, args: &crate::Args, config: &Config) -> Result<()> {
+    let mut queue = RetryQueue::load(&args.retry_queue())?;
+    if queue.entries.is_empty() {
+        status!("No failed lookups to retry");
+        return Ok(());
+    }
+    let cache = Mutex::new(ResolveCache::load(&args.resolve_cache(), args.cache_ttl)?);
+    ratelimit::init(
+        args.requests_per_second,
+        Duration::from_millis(args.resolve_delay_ms),
+    );
+    shutdown::listen();
+    args.stop.start();
+
+    // Every link is looked up once, however many chats it was queued for.
+    let mut lookups: BTreeMap<String, Username> = BTreeMap::new();
+    for entry in &queue.entries {
+        lookups
+            .entry(entry.username.username.key())
+            .or_insert_with(|| Username {
+                unresolved: None,
+                ..entry.username.clone()
+            });
+    }
+    let mut lookups: Vec<_> = lookups.into_values().collect();
+    status!(
+        "Retrying {} usernames queued by {} crawls...",
+        args.locale.number(lookups.len()),
+        args.locale.number(queue.entries.len())
+    );
+    let progress_bar = ProgressBar::new(lookups.len() as u64);
+    resolve_all(
+        client,
+        &mut lookups,
+        &config.resolve,
+        &HashMap::new(),
+        &cache,
+        progress_bar,
+    )
+    .await;
+    cache.lock().unwrap().save()?;
+
+    let lookups: HashMap<_, _> = lookups
+        .into_iter()
+        .map(|username| (username.username.key(), username))
+        .collect();
+    let (done, mut pending): (Vec<_>, Vec<_>) = queue.entries.drain(..).partition(|entry| {
+        let lookup = &lookups[&entry.username.username.key()];
+        lookup.metadata.is_some()
+            || matches!(lookup.unresolved, Some(reason) if reason != Unresolved::Failed)
+    });
+
+    let mut by_file: BTreeMap<PathBuf, Vec<Queued>> = BTreeMap::new();
+    for entry in done {
+        if let Some(file) = entry.file.clone() {
+            by_file.entry(file).or_default().push(entry);
+        }
+    }
+    for (file, entries) in by_file {
+        match update(&file, entries, &lookups, args) {
+            Ok(updated) => status!(
+                "Updated {} entries of {}",
+                args.locale.number(updated),
+                file.display()
+            ),
+            Err(e) => status!("Could not update {}: {e}", file.display()),
+        }
+    }
+
+    for entry in &mut pending {
+        entry.attempts += 1;
+    }
+    let resolved = lookups.values().filter(|u| u.metadata.is_some()).count();
+    status!(
+        "Resolved {} usernames, {} lookups are left to retry",
+        args.locale.number(resolved),
+        args.locale.number(pending.len())
+    );
+    queue.entries = pending;
+    queue.save()
+}
+
+/// Fills in what the lookups gave in a results file, adding the entries the
+/// crawl left out for being unresolved if `--only` and `--unresolved` keep
+/// them now. Returns how many entries changed.
+fn update(
+    file: &Path,
+    entries: Vec<Queued>,
+    lookups: &HashMap<String, Username>,
+    args: &crate::Args,
+) -> Result<usize> {
+    let contents = compress::read_to_string(file)?;
+    let mut usernames: Vec<Username> = serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("{} is not a JSON results file", file.display()))?;
+
+    let mut updated = 0;
+    for entry in entries {
+        let key = entry.username.username.key();
+        let lookup = &lookups[&key];
+        match usernames.iter_mut().find(|u| u.username.key() == key) {
+            Some(username) => {
+                username.metadata.clone_from(&lookup.metadata);
+                username.unresolved = lookup.unresolved;
+            }
+            None => {
+                let username = Username {
+                    metadata: lookup.metadata.clone(),
+                    unresolved: lookup.unresolved,
+                    ..entry.username
+                };
+                if !keep(&username, &args.only, args.unresolved) {
+                    continue;
+                }
+                usernames.push(username);
+            }
+        }
+        updated += 1;
+    }
+
+    output::sort(&mut usernames);
+    compress::write(
+        file,
+        serde_json::to_string_pretty(&usernames)?,
+        Compression::from_path(file),
+    )?;
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let strategies = Strategies::default();
+        let username = |name: &str, unresolved| Username {
+            unresolved,
+            ..Username::new(LinkType::Username(name.to_string()))
+        };
+        let usernames = [
+            username("grammers", Some(Unresolved::Failed)),
+            username("rampilo", None),
+            username("gone", Some(Unresolved::NotFound)),
+            Username::new(LinkType::Hashtag("rust".to_string())),
+        ];
+        let names: Vec<_> = failed(&usernames, &strategies)
+            .iter()
+            .map(|u| u.username.to_string())
+            .collect();
+        assert_eq!(names, ["grammers", "rampilo"]);
+
+        let mut queue = RetryQueue {
+            path: PathBuf::new(),
+            entries: Vec::new(),
+        };
+        queue.add("codenight", None, usernames[..2].to_vec(), 1);
+        queue.add("codenight", None, usernames[..1].to_vec(), 2);
+        queue.add("rustlang", None, usernames[..1].to_vec(), 3);
+        let queued: Vec<_> = queue
+            .entries
+            .iter()
+            .map(|e| (e.chat.as_str(), e.queued_at))
+            .collect();
+        assert_eq!(
+            queued,
+            [("codenight", 1), ("codenight", 2), ("rustlang", 3)]
+        );
+
+        assert!(is_json(Path::new("out/codenight.json.zst")));
+        assert!(!is_json(Path::new("codenight.html")));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Username;
+
+/// Chats younger than this many days count as new.
+const NEW_DAYS: i64 = 30;
+/// Fewest links a description needs to count as a link dump.
+const MIN_LINKS: usize = 3;
+/// Share of the words of a description that may be links.
+const MAX_LINK_DENSITY: f64 = 0.25;
+/// Phrases of the get-rich-quick and giveaway promotions scam chats copy
+/// around, lowercase.
+const PROMOTION: &[&str] = &[
+    "guaranteed profit",
+    "double your",
+    "free signals",
+    "vip signals",
+    "investment opportunity",
+    "passive income",
+    "100% profit",
+    "no risk",
+    "airdrop",
+    "giveaway",
+    "dm for promo",
+    "send to receive",
+];
+
+/// Warning label Telegram shows on a chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Label {
+    /// Reported by many users for scams.
+    Scam,
+    /// Impersonates another chat or person.
+    Fake,
+}
+
+/// Something suspicious about a resolved chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Signal {
+    Scam,
+    Fake,
+    /// Created less than [`NEW_DAYS`] days ago.
+    New,
+    /// The description is mostly links.
+    LinkDensity,
+    /// The description uses well known promotion phrases.
+    Promotion,
+    /// Other chats in the results have the same description.
+    CopiedDescription,
+}
+
+impl Signal {
+    /// Points the signal adds to the score, which is capped at 100.
+    fn weight(self) -> u32 {
+        match self {
+            Signal::Scam => 70,
+            Signal::Fake => 60,
+            Signal::New => 15,
+            Signal::LinkDensity => 20,
+            Signal::Promotion => 20,
+            Signal::CopiedDescription => 25,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Signal::Scam => "scam",
+            Signal::Fake => "fake",
+            Signal::New => "new",
+            Signal::LinkDensity => "link density",
+            Signal::Promotion => "promotion",
+            Signal::CopiedDescription => "copied description",
+        }
+    }
+}
+
+/// How suspicious a resolved chat looks, with `--risk-score`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Risk {
+    /// 0 when nothing is suspicious, up to 100.
+    pub score: u32,
+    pub signals: Vec<Signal>,
+}
+
+/// Scores every resolved entry at the time `now`. Descriptions are compared
+/// across the whole results, so they all have to be scored at once.
+pub fn score(usernames: &mut [Username], now: i64) {
+    let mut descriptions: HashMap<String, usize> = HashMap::new();
+    for about in usernames
+        .iter()
+        .filter_map(|u| normalize(u.metadata.as_ref()?.about.as_deref()?))
+    {
+        *descriptions.entry(about).or_default() += 1;
+    }
+
+    for metadata in usernames.iter_mut().filter_map(|u| u.metadata.as_mut()) {
+        let mut signals = Vec::new();
+        match metadata.label {
+            Some(Label::Scam) => signals.push(Signal::Scam),
+            Some(Label::Fake) => signals.push(Signal::Fake),
+            None => {}
+        }
+        if metadata
+            .date
+            .is_some_and(|date| now - date < NEW_DAYS * 24 * 60 * 60)
+        {
+            signals.push(Signal::New);
+        }
+        if let Some(ref about) = metadata.about {
+            if is_link_dump(about) {
+                signals.push(Signal::LinkDensity);
+            }
+            let lowercase = about.to_lowercase();
+            if PROMOTION.iter().any(|phrase| lowercase.contains(phrase)) {
+                signals.push(Signal::Promotion);
+            }
+            let copies = normalize(about).and_then(|about| descriptions.get(&about));
+            if copies.is_some_and(|&n| n > 1) {
+                signals.push(Signal::CopiedDescription);
+            }
+        }
+
+        metadata.risk = Some(Risk {
+            score: signals.iter().map(|s| s.weight()).sum::<u32>().min(100),
+            signals,
+        });
+    }
+}
+
+fn is_link_dump(text: &str) -> bool {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let links = words
+        .iter()
+        .filter(|word| word.starts_with('@') || word.contains("t.me/") || word.contains("://"))
+        .count();
+    links >= MIN_LINKS && links as f64 / words.len() as f64 > MAX_LINK_DENSITY
+}
+
+/// The description without case, punctuation or spacing differences.
+/// `None` for ones too short to tell copies apart, like "Welcome!".
+fn normalize(about: &str) -> Option<String> {
+    let words: Vec<_> = about
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    (words.len() > 4).then(|| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use rampilo::LinkType;
+
+    use super::*;
+    use crate::{UsernameMetadata, UsernameType};
+
+    fn chat(name: &str, about: &str, date: i64, label: Option<Label>) -> Username {
+        let mut username = Username::new(LinkType::Username(name.to_string()));
+        username.metadata = Some(UsernameMetadata {
+            id: 1,
+            name: name.to_string(),
+            type_: UsernameType::Channel,
+            about: Some(about.to_string()),
+            members: None,
+            date: Some(date),
+            restriction: None,
+            language: None,
+            label,
+            risk: None,
+            linked_chat: None,
+            admins: None,
+        });
+        username
+    }
+
+    #[test]
+    fn test_score() {
+        let day = 24 * 60 * 60;
+        let now = 1000 * day;
+        let promo = "Free signals every day, guaranteed profit! Join @cryptopump";
+        let mut usernames = vec![
+            chat(
+                "rustlang",
+                "News about the Rust programming language",
+                10 * day,
+                None,
+            ),
+            chat("pump1", promo, now - day, Some(Label::Scam)),
+            chat("pump2", &promo.to_uppercase(), 10 * day, None),
+            chat("links", "@a @b @c t.me/d and more", 10 * day, None),
+            chat("hello1", "Welcome!", 10 * day, None),
+            chat("hello2", "Welcome!", 10 * day, None),
+        ];
+        score(&mut usernames, now);
+
+        let risk = |i: usize| {
+            usernames[i]
+                .metadata
+                .as_ref()
+                .unwrap()
+                .risk
+                .clone()
+                .unwrap()
+        };
+        assert_eq!(risk(0).score, 0);
+        assert_eq!(
+            risk(1).signals,
+            [
+                Signal::Scam,
+                Signal::New,
+                Signal::Promotion,
+                Signal::CopiedDescription
+            ]
+        );
+        assert_eq!(risk(1).score, 100);
+        assert_eq!(
+            risk(2).signals,
+            [Signal::Promotion, Signal::CopiedDescription]
+        );
+        assert_eq!(risk(3).signals, [Signal::LinkDensity]);
+        assert_eq!(risk(4).signals, []);
+    }
+}
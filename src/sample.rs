@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Share of the messages scanned, as the bits of an `f64`. 1 scans them all.
+static RATE: AtomicU64 = AtomicU64::new(0x3ff0_0000_0000_0000);
+
+pub fn set_rate(rate: f64) {
+    RATE.store(rate.to_bits(), Ordering::Relaxed);
+}
+
+pub fn rate() -> f64 {
+    f64::from_bits(RATE.load(Ordering::Relaxed))
+}
+
+/// Whether the message with this id is scanned. The choice looks random but
+/// depends only on the id, so a resumed or repeated crawl samples the same
+/// messages.
+pub fn keep(id: u64) -> bool {
+    let rate = rate();
+    rate >= 1.0 || (mix(id) as f64 / u64::MAX as f64) < rate
+}
+
+/// SplitMix64 finalizer, spreading consecutive ids over the whole range.
+fn mix(id: u64) -> u64 {
+    let mut z = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Parses `--sample`, a share of the messages between 0 and 1.
+pub fn parse_rate(rate: &str) -> Result<f64, String> {
+    let rate: f64 = rate
+        .parse()
+        .map_err(|_| format!("{rate:?} is not a number"))?;
+    if !(rate > 0.0 && rate <= 1.0) {
+        return Err("must be more than 0 and at most 1".to_string());
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(rate(), 1.0);
+        assert!((0..1000).all(keep));
+
+        let kept = (0..100_000).filter(|&id| mix(id) < u64::MAX / 10).count();
+        assert!((9_000..11_000).contains(&kept), "kept {kept}");
+
+        assert_eq!(parse_rate("0.1"), Ok(0.1));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("1.5").is_err());
+        assert!(parse_rate("ten").is_err());
+    }
+}
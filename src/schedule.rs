@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Result};
+use grammers_client::Client;
+use indicatif::MultiProgress;
+
+use crate::{
+    cache::ResolveCache, config::Config, crawl_chat, open_storage, output, ratelimit, seen::SeenDb,
+    shutdown, target::Target, Context,
+};
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// When to crawl, as a cron expression of the minute, hour, day of the
+    /// month, month and day of the week in UTC, e.g. `"0 */6 * * *"`
+    #[arg(value_name = "CRON")]
+    cron: Cron,
+
+    /// Chats to crawl, like the ones of a single crawl
+    #[arg(required = true, value_name = "CHAT")]
+    chats: Vec<Target>,
+}
+
+/// Crawls the chats whenever `options.cron` says, each run only reading the
+/// messages sent since the one before and writing them to a new snapshot
+/// named after the time of the run.
+pub async fn run(
+    client: &Client,
+    args: &crate::Args,
+    options: &Options,
+    config: &Config,
+) -> Result<()> {
+    if args.resume_token.is_some() {
+        eyre::bail!("Scheduled crawls can't be resumed, every run starts where the last one ended");
+    }
+
+    let storage = Mutex::new(open_storage(args).await?);
+    let seen = Mutex::new(SeenDb::load(&args.seen_db())?);
+    let cache = Mutex::new(ResolveCache::load(&args.resolve_cache(), args.cache_ttl)?);
+    ratelimit::init(
+        args.requests_per_second,
+        Duration::from_millis(args.resolve_delay_ms),
+    );
+    shutdown::listen();
+
+    // Newest message of every chat read so far, the first run reads the
+    // whole history.
+    let mut newest: HashMap<&Target, i32> = HashMap::new();
+    loop {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let Some(next) = options.cron.next_after(now) else {
+            eyre::bail!("{} never matches a date", options.cron);
+        };
+        status!("Next crawl at {} UTC", stamp(next).replace('T', " "));
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs((next - now) as u64)) => {}
+            _ = shutdown::wait() => break,
+        }
+
+        let ctx = Context {
+            client,
+            args,
+            config,
+            storage: &storage,
+            seen: &seen,
+            cache: &cache,
+            crossposts: &Mutex::default(),
+            progress: MultiProgress::new(),
+            snapshot: Some(stamp(next)),
+        };
+        args.stop.start();
+        for chat in &options.chats {
+            if shutdown::requested() {
+                break;
+            }
+
+            let min_id = newest.get(chat).copied().unwrap_or(0);
+            match crawl_chat(&ctx, chat, min_id).await {
+                Ok(crawled) => {
+                    newest.insert(chat, crawled.newest.max(min_id));
+                }
+                Err(e) => eprintln!("Could not crawl {chat}: {e}"),
+            }
+        }
+        seen.lock().unwrap().save()?;
+        cache.lock().unwrap().save()?;
+
+        if shutdown::requested() {
+            break;
+        }
+    }
+
+    status!("Stopped the schedule");
+    Ok(())
+}
+
+/// `YYYY-MM-DDTHHMM` of a Unix timestamp, which snapshots are named with.
+fn stamp(timestamp: i64) -> String {
+    let minutes = timestamp.rem_euclid(86400) / 60;
+    format!(
+        "{}T{:02}{:02}",
+        output::date(timestamp),
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// A five field cron expression. Every field is `*`, a number, a range
+/// `a-b`, any of them stepped with `/n`, or a comma separated list of those.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month or the week is `*`. When neither is, a
+    /// day matching either of them matches, as in cron.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, String> {
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "{expression:?} must have five fields: minute, hour, day of the month, month and day of the week"
+            ));
+        };
+
+        // Sunday can be given as 0 or 7.
+        let weekdays_field = field(weekdays, 0, 7)?;
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: (weekdays_field | weekdays_field >> 7) & 0x7f,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Cron {
+    /// First minute after `timestamp` the expression matches, if one does in
+    /// the next eight years.
+    pub fn next_after(&self, timestamp: i64) -> Option<i64> {
+        let end = timestamp + 8 * 366 * 86400;
+        let mut time = (timestamp.div_euclid(60) + 1) * 60;
+
+        while time < end {
+            let days = time.div_euclid(86400);
+            let (_, month, day) = output::civil(days);
+            // 1970-01-01 was a Thursday.
+            let weekday = (days + 4).rem_euclid(7);
+            let minute_of_day = time.rem_euclid(86400) / 60;
+
+            if !self.matches_day(month, day, weekday) {
+                time = (days + 1) * 86400;
+            } else if !has(self.hours, minute_of_day / 60) {
+                time = (time.div_euclid(3600) + 1) * 3600;
+            } else if !has(self.minutes, minute_of_day % 60) {
+                time += 60;
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, month: i64, day: i64, weekday: i64) -> bool {
+        if !has(self.months, month) {
+            return false;
+        }
+
+        let day = has(self.days, day);
+        let weekday = has(self.weekdays, weekday);
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn has(set: u64, value: i64) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses one field into a set of the values between `min` and `max`.
+fn field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| format!("invalid step in {part:?}"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let number = |n: &str| {
+            n.parse::<u32>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("{n:?} is not a number from {min} to {max}"))
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // `5/15` runs from 5 to the end.
+            None if step > 1 => (number(range)?, max),
+            None => {
+                let n = number(range)?;
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(format!("{part:?} ends before it starts"));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cron() {
+        let cron: Cron = "*/15 9-17 * * 1-5".parse().unwrap();
+        assert_eq!(cron.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(cron.weekdays, 0b0111110);
+
+        let sunday: Cron = "0 0 * * 7".parse().unwrap();
+        assert_eq!(sunday.weekdays, 1);
+
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("5-1 * * * *".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Thursday 2024-02-29 10:20:30 UTC
+        let now = 1709202030;
+        let next = |expression: &str| {
+            let cron: Cron = expression.parse().unwrap();
+            cron.next_after(now).map(stamp)
+        };
+
+        assert_eq!(next("* * * * *").unwrap(), "2024-02-29T1021");
+        assert_eq!(next("0 */6 * * *").unwrap(), "2024-02-29T1200");
+        assert_eq!(next("30 3 * * *").unwrap(), "2024-03-01T0330");
+        assert_eq!(next("0 9 * * 1").unwrap(), "2024-03-04T0900");
+        assert_eq!(next("0 0 29 2 *").unwrap(), "2028-02-29T0000");
+        // Either the 1st of the month or a Saturday.
+        assert_eq!(next("0 0 1 * 6").unwrap(), "2024-03-01T0000");
+        assert_eq!(next("0 0 31 2 *"), None);
+    }
+}
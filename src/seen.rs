@@ -0,0 +1,105 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::Username;
+
+/// Every link discovered by earlier crawls, kept across runs so a report can
+/// tell which of its entries are new.
+pub struct SeenDb {
+    path: PathBuf,
+    links: HashMap<String, Seen>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Seen {
+    /// Unix timestamp of the crawl that first found the link.
+    first_seen: u64,
+    last_seen: u64,
+    /// Chats the link was found in.
+    chats: BTreeSet<String>,
+}
+
+impl SeenDb {
+    /// Loads the database, starting an empty one if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let links = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            links,
+        })
+    }
+
+    /// Records the results of a crawl of `chat`, flagging the links no
+    /// earlier crawl has found as new.
+    pub fn record(&mut self, usernames: &mut [Username], chat: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        for username in usernames {
+            let seen = self
+                .links
+                .entry(username.username.key())
+                .or_insert_with(|| {
+                    username.new = true;
+                    Seen {
+                        first_seen: now,
+                        last_seen: now,
+                        chats: BTreeSet::new(),
+                    }
+                });
+            seen.last_seen = now;
+            seen.chats.insert(chat.to_string());
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        // Written next to the database and renamed over it, so an interrupted
+        // save never loses the links of earlier crawls.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&self.links)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LinkType;
+
+    #[test]
+    fn test_new_since_last_crawl() {
+        let path = std::env::temp_dir().join("rampilo-test-seen.json");
+        let _ = fs::remove_file(&path);
+
+        let mut first = vec![Username::new(LinkType::Username("grammers".to_string()))];
+        let mut db = SeenDb::load(&path).unwrap();
+        db.record(&mut first, "codenight");
+        db.save().unwrap();
+        assert!(first[0].new);
+
+        let mut second = vec![
+            Username::new(LinkType::Username("Grammers".to_string())),
+            Username::new(LinkType::Mention("grammers".to_string())),
+        ];
+        let mut db = SeenDb::load(&path).unwrap();
+        db.record(&mut second, "rustlang");
+        assert!(!second[0].new);
+        assert!(second[1].new);
+        assert_eq!(db.links["username:grammers"].chats.len(), 2);
+    }
+}
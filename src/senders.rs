@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_tl_types as tl;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    history::Profile,
+    output::{Destination, Format},
+};
+
+/// Who posted which links in a group, for `--senders`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Senders {
+    /// Whether the links are attributed at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    /// By `<type>:<id>`, as ids of users and chats can be the same.
+    pub senders: HashMap<String, Sender>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SenderType {
+    User,
+    /// A group posting as itself, e.g. its anonymous admins.
+    Group,
+    Channel,
+}
+
+/// A member of the chat and the links they posted.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Sender {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub type_: SenderType,
+    /// Only known for users.
+    pub name: Option<String>,
+    pub username: Option<String>,
+    /// Messages with links they sent.
+    pub messages: usize,
+    /// Most posted first once written.
+    pub links: Vec<Promoted>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Promoted {
+    pub link: LinkType,
+    pub count: usize,
+}
+
+impl Senders {
+    /// Counts the links of a message towards its sender. Channel posts and
+    /// private messages come without one and are left out.
+    pub fn record(&mut self, from: Option<&tl::enums::Peer>, links: &[LinkType]) {
+        if !self.enabled || links.is_empty() {
+            return;
+        }
+        let (type_, id) = match from {
+            Some(tl::enums::Peer::User(peer)) => (SenderType::User, peer.user_id),
+            Some(tl::enums::Peer::Chat(peer)) => (SenderType::Group, peer.chat_id),
+            Some(tl::enums::Peer::Channel(peer)) => (SenderType::Channel, peer.channel_id),
+            None => return,
+        };
+
+        let key = format!("{}:{id}", type_.as_str());
+        let sender = self.senders.entry(key).or_insert_with(|| Sender {
+            id,
+            type_,
+            name: None,
+            username: None,
+            messages: 0,
+            links: Vec::new(),
+        });
+        sender.messages += 1;
+        for link in links {
+            let key = link.key();
+            match sender.links.iter_mut().find(|p| p.link.key() == key) {
+                Some(promoted) => promoted.count += 1,
+                None => sender.links.push(Promoted {
+                    link: link.clone(),
+                    count: 1,
+                }),
+            }
+        }
+    }
+
+    /// The senders with the names of the users among them found in
+    /// `profiles`, the ones who posted the most links first.
+    pub fn rows(&self, profiles: &HashMap<i64, Profile>) -> Vec<Sender> {
+        let mut rows: Vec<_> = self.senders.values().cloned().collect();
+        for sender in &mut rows {
+            if let (SenderType::User, Some(profile)) = (sender.type_, profiles.get(&sender.id)) {
+                sender.name = Some(profile.name.clone());
+                sender.username.clone_from(&profile.username);
+            }
+            sender
+                .links
+                .sort_by(|a, b| b.count.cmp(&a.count).then(a.link.cmp(&b.link)));
+        }
+        rows.sort_by_key(|sender| {
+            let total: usize = sender.links.iter().map(|p| p.count).sum();
+            (std::cmp::Reverse(total), sender.id)
+        });
+        rows
+    }
+}
+
+impl SenderType {
+    fn as_str(self) -> &'static str {
+        match self {
+            SenderType::User => "user",
+            SenderType::Group => "group",
+            SenderType::Channel => "channel",
+        }
+    }
+}
+
+/// Number of links posted by more than one of the senders, which is how
+/// rings of accounts promoting the same chats show up.
+pub fn shared_links(senders: &[Sender]) -> usize {
+    let mut posters: HashMap<String, usize> = HashMap::new();
+    for promoted in senders.iter().flat_map(|s| &s.links) {
+        *posters.entry(promoted.link.key()).or_default() += 1;
+    }
+    posters.values().filter(|&&n| n > 1).count()
+}
+
+/// Writes the senders next to the results as `<chat>.senders.json`, or
+/// whatever `--out-name` makes of that name.
+pub fn write(senders: &[Sender], chat: &str, destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(&format!("{chat}.senders"), Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(&senders)?)?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let user = |user_id| tl::enums::Peer::User(tl::types::PeerUser { user_id });
+        let link = |name: &str| LinkType::Username(name.to_string());
+        let mut senders = Senders {
+            enabled: true,
+            ..Default::default()
+        };
+        senders.record(Some(&user(1)), &[link("pump"), link("Pump")]);
+        senders.record(Some(&user(1)), &[link("rustlang")]);
+        senders.record(Some(&user(2)), &[link("pump")]);
+        senders.record(None, &[link("pump")]);
+        senders.record(Some(&user(3)), &[]);
+
+        let profiles = HashMap::from([(
+            1,
+            Profile {
+                name: "Spammer".to_string(),
+                username: Some("spammer".to_string()),
+            },
+        )]);
+        let rows = senders.rows(&profiles);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name.as_deref(), Some("Spammer"));
+        assert_eq!(rows[0].messages, 2);
+        assert_eq!(
+            rows[0].links,
+            [
+                Promoted {
+                    link: link("pump"),
+                    count: 2
+                },
+                Promoted {
+                    link: link("rustlang"),
+                    count: 1
+                }
+            ]
+        );
+        assert_eq!(rows[1].name, None);
+        assert_eq!(shared_links(&rows), 1);
+    }
+}
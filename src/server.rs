@@ -0,0 +1,449 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Result};
+use futures_util::{stream, StreamExt};
+use grammers_client::Client;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use crate::{
+    cache::ResolveCache, compress, config::Config, crawl_chat, open_storage, output, ratelimit,
+    seen::SeenDb, shutdown, target::Target, Context,
+};
+
+/// Largest request accepted, headers included.
+const MAX_REQUEST: usize = 64 * 1024;
+/// How long a client has to send its whole request, so a slow one can't
+/// hold a connection forever.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// Address to serve the API on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+}
+
+/// A crawl asked for with `POST /crawls`.
+#[derive(Debug, Clone, Serialize)]
+struct Crawl {
+    id: u64,
+    state: State,
+    chats: Vec<String>,
+    /// Unix timestamp of the request.
+    created: i64,
+    /// Unix timestamp of when the last chat was done.
+    finished: Option<i64>,
+    /// Outcome of every chat crawled so far.
+    results: Vec<ChatResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Queued,
+    Running,
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ChatResult {
+    chat: String,
+    /// Results file written for the chat.
+    file: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewCrawl {
+    chats: Vec<String>,
+}
+
+/// The crawls asked for since the server started, by id.
+#[derive(Default)]
+struct Crawls {
+    crawls: BTreeMap<u64, Crawl>,
+    next_id: u64,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Serves the crawl API on `--listen` and runs the crawls it is asked for
+/// one after the other, the chats of each `--concurrency` at a time.
+pub async fn run(
+    client: &Client,
+    args: &crate::Args,
+    options: &Options,
+    config: &Config,
+) -> Result<()> {
+    if args.resume_token.is_some() {
+        eyre::bail!("Served crawls can't be resumed");
+    }
+    if !matches!(args.format[..], [output::Format::Json]) || args.summary_only {
+        eyre::bail!("The API serves JSON results, which needs `--format json`");
+    }
+
+    let storage = Mutex::new(open_storage(args).await?);
+    let seen = Mutex::new(SeenDb::load(&args.seen_db())?);
+    let cache = Mutex::new(ResolveCache::load(&args.resolve_cache(), args.cache_ttl)?);
+    ratelimit::init(
+        args.requests_per_second,
+        std::time::Duration::from_millis(args.resolve_delay_ms),
+    );
+    shutdown::listen();
+
+    let crawls = Arc::new(Mutex::new(Crawls::default()));
+    let (queue, mut queued) = mpsc::unbounded_channel();
+    let listener = TcpListener::bind(options.listen).await?;
+    status!("Serving the crawl API on http://{}", options.listen);
+
+    let accepting = crawls.clone();
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not accept API connection");
+                    continue;
+                }
+            };
+            tokio::spawn(handle(stream, accepting.clone(), queue.clone()));
+        }
+    });
+
+    loop {
+        let (id, targets): (u64, Vec<Target>) = tokio::select! {
+            Some(crawl) = queued.recv() => crawl,
+            _ = shutdown::wait() => break,
+        };
+        update(&crawls, id, |crawl| crawl.state = State::Running);
+        tracing::info!(id, chats = targets.len(), "starting crawl");
+
+        let ctx = Context {
+            client,
+            args,
+            config,
+            storage: &storage,
+            seen: &seen,
+            cache: &cache,
+            crossposts: &Mutex::default(),
+            progress: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            snapshot: Some(format!("crawl-{id}")),
+        };
+        args.stop.start();
+        stream::iter(&targets)
+            .map(|target| {
+                let ctx = &ctx;
+                async move { (target, crawl_chat(ctx, target, 0).await) }
+            })
+            .buffer_unordered(args.concurrency.max(1))
+            .for_each(|(target, result)| {
+                let result = match result {
+                    Ok(crawled) => ChatResult {
+                        chat: target.to_string(),
+                        file: crawled.files.into_iter().next(),
+                        error: None,
+                    },
+                    Err(e) => ChatResult {
+                        chat: target.to_string(),
+                        file: None,
+                        error: Some(e.to_string()),
+                    },
+                };
+                update(&crawls, id, |crawl| crawl.results.push(result));
+                async {}
+            })
+            .await;
+
+        seen.lock().unwrap().save()?;
+        cache.lock().unwrap().save()?;
+        update(&crawls, id, |crawl| {
+            crawl.state = State::Finished;
+            crawl.finished = Some(now());
+        });
+        tracing::info!(id, "finished crawl");
+    }
+
+    status!("Stopped serving");
+    Ok(())
+}
+
+fn update(crawls: &Mutex<Crawls>, id: u64, f: impl FnOnce(&mut Crawl)) {
+    if let Some(crawl) = crawls.lock().unwrap().crawls.get_mut(&id) {
+        f(crawl);
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    crawls: Arc<Mutex<Crawls>>,
+    queue: mpsc::UnboundedSender<(u64, Vec<Target>)>,
+) {
+    let request = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Some(request)) => request,
+        Ok(None) => return,
+        Err(_) => Err(408),
+    };
+
+    let (status, body) = match request {
+        Ok(request) => route(&crawls, &queue, &request),
+        Err(status) => (status, json!({ "error": reason(status) })),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        reason(status),
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// Reads a request off the stream, or the status to refuse it with. `None`
+/// if the client went away first.
+async fn read_request(stream: &mut TcpStream) -> Option<Result<Request, u16>> {
+    let mut buffer = Vec::new();
+    loop {
+        let mut chunk = [0; 4096];
+        let read = match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => read,
+        };
+        buffer.extend_from_slice(&chunk[..read]);
+        match parse_request(&buffer) {
+            Ok(None) if buffer.len() > MAX_REQUEST => return Some(Err(413)),
+            Ok(None) => {}
+            Ok(Some(request)) => return Some(Ok(request)),
+            Err(status) => return Some(Err(status)),
+        }
+    }
+}
+
+/// The request in `buffer`, once its headers and whole body were read, or
+/// the status to refuse it with. Bodies larger than [`MAX_REQUEST`] are
+/// refused before they are read.
+fn parse_request(buffer: &[u8]) -> Result<Option<Request>, u16> {
+    let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = String::from_utf8_lossy(&buffer[..end]);
+    let mut lines = head.lines();
+    let mut start = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(path)) = (start.next(), start.next()) else {
+        return Err(400);
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let length = match lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+    {
+        Some((_, value)) => value.trim().parse::<usize>().map_err(|_| 400u16)?,
+        None => 0,
+    };
+    if length > MAX_REQUEST {
+        return Err(413);
+    }
+    let body_start = end + 4;
+    let body_end = body_start.checked_add(length).ok_or(413u16)?;
+    let Some(body) = buffer.get(body_start..body_end) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Request {
+        method,
+        path,
+        body: body.to_vec(),
+    }))
+}
+
+fn route(
+    crawls: &Mutex<Crawls>,
+    queue: &mpsc::UnboundedSender<(u64, Vec<Target>)>,
+    request: &Request,
+) -> (u16, Value) {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["crawls"]) => {
+            let targets = match parse_chats(&request.body) {
+                Ok(targets) => targets,
+                Err(e) => return (400, json!({ "error": e })),
+            };
+            let mut crawls = crawls.lock().unwrap();
+            crawls.next_id += 1;
+            let id = crawls.next_id;
+            let crawl = Crawl {
+                id,
+                state: State::Queued,
+                chats: targets.iter().map(Target::to_string).collect(),
+                created: now(),
+                finished: None,
+                results: Vec::new(),
+            };
+            let body = json!(crawl);
+            crawls.crawls.insert(id, crawl);
+            if queue.send((id, targets)).is_err() {
+                return (503, json!({ "error": "the server is shutting down" }));
+            }
+            (202, body)
+        }
+        ("GET", ["crawls", id]) => match find(crawls, id) {
+            Some(crawl) => (200, json!(crawl)),
+            None => (404, json!({ "error": "no such crawl" })),
+        },
+        ("GET", ["crawls", id, "results"]) => match find(crawls, id) {
+            Some(crawl) if crawl.state != State::Finished => (
+                409,
+                json!({ "error": "the crawl hasn't finished", "state": crawl.state }),
+            ),
+            Some(crawl) => (200, results(&crawl)),
+            None => (404, json!({ "error": "no such crawl" })),
+        },
+        (_, ["crawls", ..]) => (405, json!({ "error": "method not allowed" })),
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+fn parse_chats(body: &[u8]) -> Result<Vec<Target>, String> {
+    let new: NewCrawl =
+        serde_json::from_slice(body).map_err(|e| format!("expected {{\"chats\": [...]}}: {e}"))?;
+    if new.chats.is_empty() {
+        return Err("no chats to crawl".to_string());
+    }
+    new.chats
+        .iter()
+        .map(|chat| chat.parse().map_err(|e| format!("{chat}: {e}")))
+        .collect()
+}
+
+fn find(crawls: &Mutex<Crawls>, id: &str) -> Option<Crawl> {
+    let id = id.parse().ok()?;
+    crawls.lock().unwrap().crawls.get(&id).cloned()
+}
+
+/// The results of every chat of the crawl that was crawled, by chat.
+fn results(crawl: &Crawl) -> Value {
+    let results: serde_json::Map<_, _> = crawl
+        .results
+        .iter()
+        .filter_map(|result| {
+            let file = result.file.as_ref()?;
+            let results = compress::read_to_string(Path::new(file))
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())?;
+            Some((result.chat.clone(), results))
+        })
+        .collect();
+    Value::Object(results)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        let raw = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        parse_request(raw.as_bytes()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let raw = b"POST /crawls HTTP/1.1\r\ncontent-length: 10\r\n\r\n{\"chats\":";
+        assert_eq!(parse_request(raw).map(|r| r.is_none()), Ok(true));
+        let raw = b"POST /crawls HTTP/1.1\r\ncontent-length: 18446744073709551615\r\n\r\n";
+        assert!(matches!(parse_request(raw), Err(413)));
+        let raw = b"POST /crawls HTTP/1.1\r\ncontent-length: 65537\r\n\r\n";
+        assert!(matches!(parse_request(raw), Err(413)));
+        let raw = b"POST /crawls HTTP/1.1\r\ncontent-length: lots\r\n\r\n";
+        assert!(matches!(parse_request(raw), Err(400)));
+
+        let request = request("POST", "/crawls", r#"{"chats": []}"#);
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/crawls");
+        assert_eq!(request.body, br#"{"chats": []}"#);
+    }
+
+    #[test]
+    fn test_route() {
+        let crawls = Mutex::new(Crawls::default());
+        let (queue, mut queued) = mpsc::unbounded_channel();
+
+        let (status, body) = route(
+            &crawls,
+            &queue,
+            &request(
+                "POST",
+                "/crawls",
+                r#"{"chats": ["codenight", "https://t.me/rampilo"]}"#,
+            ),
+        );
+        assert_eq!(status, 202);
+        assert_eq!(body["id"], 1);
+        assert_eq!(body["state"], "queued");
+        assert_eq!(queued.try_recv().unwrap().1.len(), 2);
+
+        let (status, body) = route(&crawls, &queue, &request("GET", "/crawls/1", ""));
+        assert_eq!(status, 200);
+        assert_eq!(body["chats"].as_array().unwrap().len(), 2);
+
+        let (status, _) = route(&crawls, &queue, &request("GET", "/crawls/1/results", ""));
+        assert_eq!(status, 409);
+
+        update(&crawls, 1, |crawl| crawl.state = State::Finished);
+        let (status, body) = route(&crawls, &queue, &request("GET", "/crawls/1/results", ""));
+        assert_eq!(status, 200);
+        assert_eq!(body, json!({}));
+
+        let (status, _) = route(&crawls, &queue, &request("GET", "/crawls/2", ""));
+        assert_eq!(status, 404);
+        let (status, _) = route(
+            &crawls,
+            &queue,
+            &request("POST", "/crawls", r#"{"chats": []}"#),
+        );
+        assert_eq!(status, 400);
+        let (status, _) = route(&crawls, &queue, &request("DELETE", "/crawls/1", ""));
+        assert_eq!(status, 405);
+    }
+}
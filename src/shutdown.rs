@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::Notify;
+
+static SHUTDOWN: Shutdown = Shutdown::new();
+
+struct Shutdown {
+    requested: AtomicBool,
+    signal: Notify,
+}
+
+impl Shutdown {
+    const fn new() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            signal: Notify::const_new(),
+        }
+    }
+
+    fn requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    async fn wait(&self) {
+        let notified = self.signal.notified();
+        if self.requested() {
+            return;
+        }
+        notified.await;
+    }
+
+    fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        self.signal.notify_waiters();
+    }
+}
+
+/// Traps Ctrl-C and SIGTERM so a crawl can finish the current message and
+/// save its partial results. A second signal quits right away.
+pub fn listen() {
+    tokio::spawn(async {
+        signal().await;
+        tracing::info!("shutdown requested, finishing the current message");
+        eprintln!("Stopping after the current message, press Ctrl-C again to quit right away");
+        SHUTDOWN.request();
+
+        signal().await;
+        std::process::exit(130);
+    });
+}
+
+/// Whether the crawls should stop and save what they have.
+pub fn requested() -> bool {
+    SHUTDOWN.requested()
+}
+
+/// Waits until a shutdown is requested.
+pub async fn wait() {
+    SHUTDOWN.wait().await
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        },
+        Err(e) => {
+            tracing::warn!(error = %e, "could not trap SIGTERM");
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn test_request() {
+        let shutdown = Arc::new(Shutdown::new());
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!shutdown.requested());
+        assert!(!waiting.is_finished());
+
+        shutdown.request();
+        assert!(shutdown.requested());
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Crawls that start waiting after the request stop right away.
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rampilo::LinkType;
+
+/// Characters kept on either side of a link.
+const CONTEXT: usize = 80;
+
+/// Most snippets kept for every entry, 0 to keep none, as runs without
+/// `--snippets` do.
+static LIMIT: AtomicUsize = AtomicUsize::new(3);
+
+pub fn set_limit(limit: usize) {
+    LIMIT.store(limit, Ordering::Relaxed);
+}
+
+pub fn limit() -> usize {
+    LIMIT.load(Ordering::Relaxed)
+}
+
+/// The text around the first place `link` appears in `text`, on one line,
+/// with `…` where it was cut. `None` when the link isn't written out, like a
+/// user mentioned by name.
+pub fn around(text: &str, link: &LinkType) -> Option<String> {
+    let needle = match link {
+        LinkType::UserId(_) => return None,
+        LinkType::Story(story) => story.split_once('/').map_or(story.as_str(), |(u, _)| u),
+        LinkType::Proxy(proxy) => proxy.split_once(':').map_or(proxy.as_str(), |(s, _)| s),
+        _ => return find(text, &link.to_string()),
+    };
+    find(text, needle)
+}
+
+fn find(text: &str, needle: &str) -> Option<String> {
+    // ASCII lowercasing keeps the byte offsets of the text.
+    let start = text
+        .to_ascii_lowercase()
+        .find(&needle.to_ascii_lowercase())?;
+    let end = start + needle.len();
+
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let to = text[end..]
+        .char_indices()
+        .nth(CONTEXT)
+        .map_or(text.len(), |(i, _)| end + i);
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(
+        &text[from..to]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    if to < text.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_around() {
+        let link = LinkType::Username("Grammers".to_string());
+        assert_eq!(
+            around("Try\nhttps://t.me/grammers  for bots", &link).as_deref(),
+            Some("Try https://t.me/grammers for bots")
+        );
+
+        let text = format!("{}é @grammers {}", "a".repeat(100), "b".repeat(100));
+        let snippet = around(&text, &LinkType::Mention("grammers".to_string())).unwrap();
+        assert_eq!(
+            snippet,
+            format!("…{}é @grammers {}…", "a".repeat(77), "b".repeat(79))
+        );
+        assert_eq!(around("hello", &LinkType::UserId(1)), None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fs, path::PathBuf};
+
+use color_eyre::eyre::Result;
+use rampilo::LinkType;
+
+use crate::{Username, Usernames};
+
+/// Entries kept in memory with `--low-memory` before they are moved to disk.
+pub const MAX_IN_MEMORY: usize = 100_000;
+/// Key of the number of messages read when entries were last moved. The keys
+/// of entries all have a `<type>:` prefix, so it can't be taken by one.
+const MESSAGES_KEY: &str = "messages";
+
+/// Entries of a crawl moved to disk with `--low-memory`, kept next to the
+/// partial results so an aborted crawl can be resumed with them.
+pub struct Spill {
+    db: sled::Db,
+    path: PathBuf,
+    len: usize,
+}
+
+impl Spill {
+    /// Opens the entries an earlier run of the crawl moved to `path`, if
+    /// there are any.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let db = sled::open(&path)?;
+        let len = db.len() - usize::from(db.contains_key(MESSAGES_KEY)?);
+        Ok(Self { db, path, len })
+    }
+
+    /// Number of entries on disk.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of messages read when entries were last moved to disk, `None`
+    /// if none were.
+    pub fn messages(&self) -> Result<Option<usize>> {
+        let Some(messages) = self.db.get(MESSAGES_KEY)? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&messages)?))
+    }
+
+    /// Drops the links that were found before their entries were moved to
+    /// disk from the ones seen for the first time.
+    pub fn forget_known(&self, discovered: &mut Vec<LinkType>) -> Result<()> {
+        let mut unknown = Vec::with_capacity(discovered.len());
+        for link in discovered.drain(..) {
+            if !self.db.contains_key(link.key())? {
+                unknown.push(link);
+            }
+        }
+        *discovered = unknown;
+        Ok(())
+    }
+
+    /// Moves every entry of `usernames` to disk, adding it to the one of the
+    /// same link already there, after `messages` messages were read. The
+    /// entries and the message count are written at once.
+    pub fn store(&mut self, usernames: &mut Usernames, messages: usize) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (key, username) in usernames.drain() {
+            let merged = match self.db.get(&key)? {
+                Some(stored) => {
+                    let mut stored: Username = serde_json::from_slice(&stored)?;
+                    stored.absorb(&username);
+                    stored
+                }
+                None => {
+                    self.len += 1;
+                    username
+                }
+            };
+            batch.insert(key.as_bytes(), serde_json::to_vec(&merged)?);
+        }
+        batch.insert(MESSAGES_KEY, serde_json::to_vec(&messages)?);
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Moves the entries on disk back into `usernames` and deletes them.
+    pub fn restore(self, usernames: &mut Usernames) -> Result<()> {
+        for item in self.db.iter() {
+            let (key, stored) = item?;
+            if *key == *MESSAGES_KEY.as_bytes() {
+                continue;
+            }
+            let stored: Username = serde_json::from_slice(&stored)?;
+            let key = String::from_utf8_lossy(&key).into_owned();
+            match usernames.get_mut(&key) {
+                Some(username) => username.absorb(&stored),
+                None => {
+                    usernames.insert(key, stored);
+                }
+            }
+        }
+        drop(self.db);
+        fs::remove_dir_all(self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_restore() {
+        let path = crate::storage::temp_path("spill", "sled");
+        let _ = fs::remove_dir_all(&path);
+        let link = |name: &str| LinkType::Username(name.to_string());
+        let add = |usernames: &mut Usernames, name: &str| {
+            usernames
+                .entry(link(name).key())
+                .and_modify(|u| u.count += 1)
+                .or_insert_with(|| Username::new(link(name)));
+        };
+
+        let mut spill = Spill::open(path.clone()).unwrap();
+        let mut usernames = Usernames::new();
+        add(&mut usernames, "grammers");
+        add(&mut usernames, "grammers");
+        add(&mut usernames, "rampilo");
+        spill.store(&mut usernames, 3).unwrap();
+        assert!(usernames.is_empty());
+
+        add(&mut usernames, "grammers");
+        add(&mut usernames, "codenight");
+        let mut discovered = vec![link("grammers"), link("codenight")];
+        spill.forget_known(&mut discovered).unwrap();
+        assert_eq!(discovered, [link("codenight")]);
+        drop(spill);
+
+        // Reopened like a resumed crawl would, once sled's flusher thread
+        // lets go of the lock.
+        let mut attempts = 0;
+        let spill = loop {
+            match Spill::open(path.clone()) {
+                Err(_) if attempts < 100 => attempts += 1,
+                result => break result.unwrap(),
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!((spill.len(), spill.messages().unwrap()), (2, Some(3)));
+        spill.restore(&mut usernames).unwrap();
+        let count = |name: &str| usernames[&link(name).key()].count;
+        assert_eq!(
+            (count("grammers"), count("rampilo"), count("codenight")),
+            (3, 1, 1)
+        );
+        assert!(!path.exists());
+    }
+}
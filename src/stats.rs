@@ -0,0 +1,209 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How many of the latest discoveries are kept for display.
+const RECENT_LIMIT: usize = 50;
+
+/// Live counters of a running crawl, shared between the crawl loop and
+/// anything displaying its progress.
+pub struct Stats {
+    started: Instant,
+    messages: AtomicUsize,
+    links: AtomicUsize,
+    flood_wait_until: Mutex<Option<Instant>>,
+    recent: Mutex<VecDeque<String>>,
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    requests: Mutex<BTreeMap<&'static str, usize>>,
+    bytes_sent: AtomicUsize,
+    bytes_received: AtomicUsize,
+    page_size: AtomicUsize,
+    max_page_size: AtomicUsize,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            messages: AtomicUsize::new(0),
+            links: AtomicUsize::new(0),
+            flood_wait_until: Mutex::new(None),
+            recent: Mutex::new(VecDeque::new()),
+            paused: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            requests: Mutex::new(BTreeMap::new()),
+            bytes_sent: AtomicUsize::new(0),
+            bytes_received: AtomicUsize::new(0),
+            page_size: AtomicUsize::new(0),
+            max_page_size: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Stats {
+    pub fn record_message(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_discovery(&self, link: String) {
+        self.links.fetch_add(1, Ordering::Relaxed);
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_front(link);
+        recent.truncate(RECENT_LIMIT);
+    }
+
+    pub fn record_flood_wait(&self, duration: Duration) {
+        *self.flood_wait_until.lock().unwrap() = Some(Instant::now() + duration);
+    }
+
+    /// Counts an API request of `method` and the size of its serialized
+    /// request and response.
+    pub fn record_request(&self, method: &'static str, sent: usize, received: usize) {
+        *self.requests.lock().unwrap().entry(method).or_default() += 1;
+        self.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+        self.bytes_received.fetch_add(received, Ordering::Relaxed);
+    }
+
+    pub fn record_page_size(&self, current: usize, max: usize) {
+        self.page_size.store(current, Ordering::Relaxed);
+        self.max_page_size.store(max, Ordering::Relaxed);
+    }
+
+    pub fn messages(&self) -> usize {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn links(&self) -> usize {
+        self.links.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Messages scanned per second since the crawl started.
+    pub fn rate(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.messages() as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Time left on the current flood wait, if any.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn flood_wait(&self) -> Option<Duration> {
+        let until = (*self.flood_wait_until.lock().unwrap())?;
+        until.checked_duration_since(Instant::now())
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Number of requests made so far, by method.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn requests(&self) -> Vec<(&'static str, usize)> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|(method, count)| (*method, *count))
+            .collect()
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Current and maximum history page size.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn page_size(&self) -> (usize, usize) {
+        (
+            self.page_size.load(Ordering::Relaxed),
+            self.max_page_size.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Asks the crawl to stop early and save what it has so far.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Short name of a request type, e.g. `messages::GetHistory`.
+pub fn method_name<R>() -> &'static str {
+    let name = std::any::type_name::<R>();
+    let start = name
+        .rmatch_indices("::")
+        .nth(1)
+        .map(|(i, _)| i + 2)
+        .unwrap_or(0);
+    &name[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_is_bounded() {
+        let stats = Stats::default();
+        for i in 0..RECENT_LIMIT + 10 {
+            stats.record_discovery(i.to_string());
+        }
+
+        let recent = stats.recent();
+        assert_eq!(recent.len(), RECENT_LIMIT);
+        assert_eq!(recent[0], (RECENT_LIMIT + 9).to_string());
+        assert_eq!(stats.links(), RECENT_LIMIT + 10);
+    }
+
+    #[test]
+    fn test_requests_by_method() {
+        let stats = Stats::default();
+        stats.record_request("messages::GetHistory", 40, 1000);
+        stats.record_request("channels::GetMessages", 30, 500);
+        stats.record_request("messages::GetHistory", 40, 800);
+
+        assert_eq!(
+            stats.requests(),
+            vec![("channels::GetMessages", 1), ("messages::GetHistory", 2)]
+        );
+        assert_eq!(stats.bytes_sent(), 110);
+        assert_eq!(stats.bytes_received(), 2300);
+        assert_eq!(
+            method_name::<grammers_tl_types::functions::messages::GetHistory>(),
+            "messages::GetHistory"
+        );
+    }
+}
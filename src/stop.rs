@@ -0,0 +1,118 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::ratelimit;
+
+/// When the run started, which `--time-budget` counts from.
+static STARTED: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Bounds on a crawl besides the end of the history.
+#[derive(Debug, Default, clap::Args)]
+pub struct Conditions {
+    /// Stop a chat after scanning this many messages, counting the ones of
+    /// the chats crawled with `--depth`
+    #[arg(long, value_name = "N")]
+    pub max_messages: Option<usize>,
+
+    /// Stop a chat once this many distinct links were found in it
+    #[arg(long, value_name = "N")]
+    pub max_usernames: Option<usize>,
+
+    /// Stop reading a chat at the first message older than this many days
+    #[arg(long, value_name = "DAYS")]
+    pub max_age_days: Option<u64>,
+
+    /// Stop every chat once the run has taken this many minutes, and skip
+    /// the ones that haven't started
+    #[arg(long, value_name = "MINUTES")]
+    pub time_budget: Option<u64>,
+
+    /// Stop every chat once the run has sent this many requests to Telegram,
+    /// resolving included, and skip the ones that haven't started
+    #[arg(long, value_name = "N")]
+    pub max_requests: Option<usize>,
+
+    /// Wait for a new `--max-requests` budget every this many minutes
+    /// instead of stopping when it is used up
+    #[arg(long, value_name = "MINUTES", requires = "max_requests")]
+    pub budget_window: Option<u64>,
+}
+
+impl Conditions {
+    /// Starts the clock of `--time-budget` and the count of
+    /// `--max-requests`, again for every scheduled run.
+    pub fn start(&self) {
+        *STARTED.lock().unwrap() = Some(Instant::now());
+        if let Some(max) = self.max_requests {
+            let window = self
+                .budget_window
+                .map(|minutes| Duration::from_secs(minutes.saturating_mul(60)));
+            ratelimit::budget(max, window);
+        }
+    }
+
+    /// Unix timestamp of the oldest message to read, if there's a limit.
+    pub fn min_date(&self) -> Option<i64> {
+        let days = self.max_age_days?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        Some(now.saturating_sub(days.saturating_mul(24 * 60 * 60) as i64))
+    }
+
+    /// Whether `--time-budget` is used up.
+    pub fn out_of_time(&self) -> bool {
+        let Some(minutes) = self.time_budget else {
+            return false;
+        };
+        let started = *STARTED.lock().unwrap().get_or_insert_with(Instant::now);
+        started.elapsed() >= Duration::from_secs(minutes.saturating_mul(60))
+    }
+
+    /// Why a crawl that scanned `messages` messages and found `usernames`
+    /// links should stop, if it should.
+    pub fn reached(&self, messages: usize, usernames: usize) -> Option<String> {
+        if self.max_messages.is_some_and(|max| messages >= max) {
+            return Some(format!("{messages} messages scanned"));
+        }
+        if self.max_usernames.is_some_and(|max| usernames >= max) {
+            return Some(format!("{usernames} links found"));
+        }
+        if ratelimit::over_budget() {
+            return Some("request budget used up".to_string());
+        }
+        if self.out_of_time() {
+            return Some("out of time".to_string());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reached() {
+        let conditions = Conditions {
+            max_messages: Some(100),
+            max_usernames: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(conditions.reached(99, 9), None);
+        assert_eq!(
+            conditions.reached(100, 3).as_deref(),
+            Some("100 messages scanned")
+        );
+        assert_eq!(conditions.reached(5, 10).as_deref(), Some("10 links found"));
+
+        let conditions = Conditions {
+            time_budget: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(conditions.reached(0, 0).as_deref(), Some("out of time"));
+        assert_eq!(Conditions::default().min_date(), None);
+    }
+}
@@ -0,0 +1,1103 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Result};
+use rampilo::LinkType;
+use serde::Serialize;
+
+use crate::{
+    anonymize::Anonymizer,
+    compress::{self, Compression},
+    Username, UsernameMetadata, Usernames,
+};
+
+/// A sink for the results of a crawl, picked with `--storage`.
+///
+/// The crawl reports every step to it and the sink decides what to keep, so
+/// adding a new one only means implementing this trait and a URL scheme.
+pub trait Storage {
+    /// Called when a link is found in `chat` for the first time.
+    fn put_discovery(&mut self, _chat: &str, _link: &LinkType) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for every entry that resolved to a chat.
+    fn put_metadata(&mut self, _username: &Username) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called with the results gathered so far every `--flush-every`
+    /// messages, after every nested chat and when a crawl is aborted.
+    fn checkpoint(&mut self, _chat: &str, _usernames: &Usernames) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once with the final results of the crawl of `chat`.
+    fn finalize(&mut self, _chat: &str, _usernames: &[Username]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Used when no `--storage` is given.
+pub struct Discard;
+
+impl Storage for Discard {}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+        (**self).put_discovery(chat, link)
+    }
+
+    fn put_metadata(&mut self, username: &Username) -> Result<()> {
+        (**self).put_metadata(username)
+    }
+
+    fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+        (**self).checkpoint(chat, usernames)
+    }
+
+    fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+        (**self).finalize(chat, usernames)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+        (**self).put_discovery(chat, link)
+    }
+
+    fn put_metadata(&mut self, username: &Username) -> Result<()> {
+        (**self).put_metadata(username)
+    }
+
+    fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+        (**self).checkpoint(chat, usernames)
+    }
+
+    fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+        (**self).finalize(chat, usernames)
+    }
+}
+
+/// Hashes the chats, links and names of every step before handing it to
+/// the sink, for `--anonymize`, so no sink ever gets the real names.
+pub struct Anonymized<S> {
+    sink: S,
+    anonymizer: Anonymizer,
+}
+
+impl<S: Storage> Anonymized<S> {
+    pub fn new(sink: S, anonymizer: Anonymizer) -> Self {
+        Self { sink, anonymizer }
+    }
+
+    /// Hashed like the name of the chat's results files.
+    fn chat(&self, chat: &str) -> String {
+        self.anonymizer.hash(&chat.to_lowercase())
+    }
+
+    fn usernames<'a>(&self, usernames: impl Iterator<Item = &'a Username>) -> Vec<Username> {
+        let mut usernames: Vec<_> = usernames.cloned().collect();
+        self.anonymizer.apply(&mut usernames);
+        usernames
+    }
+}
+
+impl<S: Storage> Storage for Anonymized<S> {
+    fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+        let (chat, link) = (self.chat(chat), self.anonymizer.link(link));
+        self.sink.put_discovery(&chat, &link)
+    }
+
+    fn put_metadata(&mut self, username: &Username) -> Result<()> {
+        let usernames = self.usernames(std::iter::once(username));
+        self.sink.put_metadata(&usernames[0])
+    }
+
+    fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+        let usernames = self
+            .usernames(usernames.values())
+            .into_iter()
+            .map(|username| (username.username.key(), username))
+            .collect();
+        self.sink.checkpoint(&self.chat(chat), &usernames)
+    }
+
+    fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+        let usernames = self.usernames(usernames.iter());
+        self.sink.finalize(&self.chat(chat), &usernames)
+    }
+}
+
+/// Hands every step to each of the sinks in turn, so the results files and
+/// `--storage` are given the same data.
+pub struct Tee<'a>(pub Vec<&'a mut dyn Storage>);
+
+impl Storage for Tee<'_> {
+    fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|sink| sink.put_discovery(chat, link))
+    }
+
+    fn put_metadata(&mut self, username: &Username) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|sink| sink.put_metadata(username))
+    }
+
+    fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|sink| sink.checkpoint(chat, usernames))
+    }
+
+    fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|sink| sink.finalize(chat, usernames))
+    }
+}
+
+/// Where to store the results, e.g. `json://results.json`,
+/// `ndjson://events.ndjson`, `stdout://`, `sqlite://rampilo.db`,
+/// `postgres://user@localhost/rampilo` or
+/// `webhook+https://example.com/rampilo`.
+#[derive(Debug, Clone)]
+pub enum Location {
+    Json(PathBuf),
+    Ndjson(PathBuf),
+    /// The NDJSON events, written to stdout.
+    Stdout,
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+    #[cfg(feature = "postgres")]
+    Postgres(String),
+    /// URL the events are POSTed to, without the `webhook+`.
+    #[cfg(feature = "webhook")]
+    Webhook(String),
+}
+
+impl FromStr for Location {
+    type Err = eyre::Report;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let (scheme, rest) = url.split_once("://").ok_or_else(|| {
+            eyre::eyre!("Invalid storage {url:?}, expected <scheme>://<location>")
+        })?;
+
+        match scheme {
+            "json" => Ok(Self::Json(rest.into())),
+            "ndjson" => Ok(Self::Ndjson(rest.into())),
+            "stdout" if rest.is_empty() => Ok(Self::Stdout),
+            "stdout" => Err(eyre::eyre!(
+                "stdout storage takes no location, use stdout://"
+            )),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Self::Sqlite(rest.into())),
+            #[cfg(feature = "postgres")]
+            "postgres" | "postgresql" => Ok(Self::Postgres(url.to_string())),
+            #[cfg(feature = "webhook")]
+            "webhook+http" | "webhook+https" => {
+                Ok(Self::Webhook(url["webhook+".len()..].to_string()))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err(missing_feature("sqlite")),
+            #[cfg(not(feature = "postgres"))]
+            "postgres" | "postgresql" => Err(missing_feature("postgres")),
+            #[cfg(not(feature = "webhook"))]
+            "webhook+http" | "webhook+https" => Err(missing_feature("webhook")),
+            _ => Err(eyre::eyre!("Unknown storage scheme {scheme:?}")),
+        }
+    }
+}
+
+#[cfg(not(all(feature = "sqlite", feature = "postgres", feature = "webhook")))]
+fn missing_feature(feature: &str) -> eyre::Report {
+    eyre::eyre!("{feature} storage needs rampilo to be built with the `{feature}` feature")
+}
+
+impl Location {
+    /// Whether the storage takes stdout, which then can't hold anything else.
+    pub fn is_stdout(&self) -> bool {
+        matches!(self, Self::Stdout)
+    }
+
+    /// Opens the storage. JSON and NDJSON files are compressed with
+    /// `compress`, or with what their extension says.
+    pub async fn open(&self, compress: Option<Compression>) -> Result<Box<dyn Storage>> {
+        Ok(match self {
+            Self::Json(path) => Box::new(Json::new(path, compress)),
+            Self::Ndjson(path) => Box::new(Ndjson::open(path, compress)?),
+            Self::Stdout => Box::new(Ndjson::new(Box::new(io::stdout()), None)),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(path) => Box::new(sqlite::Sqlite::open(path)?),
+            #[cfg(feature = "postgres")]
+            Self::Postgres(url) => Box::new(postgres::Postgres::connect(url).await?),
+            #[cfg(feature = "webhook")]
+            Self::Webhook(url) => Box::new(webhook::Webhook::new(url)),
+        })
+    }
+}
+
+/// Runs a query or request from the synchronous [`Storage`] methods, which
+/// are called from within the crawl's runtime.
+#[cfg(any(feature = "postgres", feature = "webhook"))]
+fn block<F: std::future::Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// The path of a storage file and how it is compressed.
+fn compressed(path: &Path, compress: Option<Compression>) -> (PathBuf, Option<Compression>) {
+    match compress {
+        Some(compression) => (compression.path(path.to_path_buf()), compress),
+        None => (path.to_path_buf(), Compression::from_path(path)),
+    }
+}
+
+/// Keeps a JSON array of the results, rewritten on every checkpoint.
+struct Json {
+    path: PathBuf,
+    compression: Option<Compression>,
+}
+
+impl Json {
+    fn new(path: &Path, compress: Option<Compression>) -> Self {
+        let (path, compression) = compressed(path, compress);
+        Self { path, compression }
+    }
+
+    fn write<'a>(&self, usernames: impl Iterator<Item = &'a Username>) -> Result<()> {
+        let usernames: Vec<_> = usernames.collect();
+        let tmp = self.path.with_extension("tmp");
+        let json = serde_json::to_string_pretty(&usernames)?;
+        compress::write(&tmp, json, self.compression)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Storage for Json {
+    fn checkpoint(&mut self, _chat: &str, usernames: &Usernames) -> Result<()> {
+        self.write(usernames.values())
+    }
+
+    fn finalize(&mut self, _chat: &str, usernames: &[Username]) -> Result<()> {
+        self.write(usernames.iter())
+    }
+}
+
+/// Appends every step of the crawl as a JSON line, for tailing a crawl or
+/// feeding it to other tools.
+///
+/// Compressed files get a new compressed stream on every checkpoint, so
+/// everything up to the last one can be read even if the crawl crashes.
+struct Ndjson {
+    out: BufWriter<Box<dyn Write>>,
+    compression: Option<Compression>,
+    /// Lines waiting for the next compressed stream.
+    pending: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Discovery {
+        chat: &'a str,
+        link: &'a LinkType,
+        at: i64,
+    },
+    Metadata {
+        link: &'a LinkType,
+        metadata: &'a UsernameMetadata,
+    },
+    Checkpoint {
+        chat: &'a str,
+        links: usize,
+        at: i64,
+    },
+    Result {
+        chat: &'a str,
+        #[serde(flatten)]
+        username: &'a Username,
+    },
+}
+
+impl Ndjson {
+    fn open(path: &Path, compress: Option<Compression>) -> Result<Self> {
+        let (path, compression) = compressed(path, compress);
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(Box::new(file), compression))
+    }
+
+    fn new(out: Box<dyn Write>, compression: Option<Compression>) -> Self {
+        Self {
+            out: BufWriter::new(out),
+            compression,
+            pending: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, event: &Event) -> Result<()> {
+        match self.compression {
+            Some(_) => {
+                serde_json::to_writer(&mut self.pending, event)?;
+                self.pending.push(b'\n');
+            }
+            None => {
+                serde_json::to_writer(&mut self.out, event)?;
+                self.out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(compression) = self.compression.filter(|_| !self.pending.is_empty()) {
+            let mut encoder = compression.encoder(&mut self.out)?;
+            encoder.write_all(&self.pending)?;
+            encoder.finish()?;
+            self.pending.clear();
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Ndjson {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Storage for Ndjson {
+    fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+        self.write(&Event::Discovery {
+            chat,
+            link,
+            at: now(),
+        })
+    }
+
+    fn put_metadata(&mut self, username: &Username) -> Result<()> {
+        match username.metadata {
+            Some(ref metadata) => self.write(&Event::Metadata {
+                link: &username.username,
+                metadata,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+        self.write(&Event::Checkpoint {
+            chat,
+            links: usernames.len(),
+            at: now(),
+        })?;
+        self.flush()
+    }
+
+    fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+        for username in usernames {
+            self.write(&Event::Result { chat, username })?;
+        }
+        self.flush()
+    }
+}
+
+/// Stores discoveries, chat metadata and the results of every crawl in
+/// SQLite tables, replacing a chat's results on every checkpoint.
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::path::Path;
+
+    use color_eyre::eyre::Result;
+    use rampilo::LinkType;
+    use rusqlite::{params, Connection};
+
+    use crate::{Username, Usernames};
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS discoveries (
+            chat TEXT NOT NULL,
+            link TEXT NOT NULL,
+            link_type TEXT NOT NULL,
+            found_at INTEGER NOT NULL,
+            PRIMARY KEY (chat, link)
+        );
+        CREATE TABLE IF NOT EXISTS metadata (
+            link TEXT PRIMARY KEY,
+            chat_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            type TEXT NOT NULL,
+            about TEXT,
+            members INTEGER,
+            date INTEGER
+        );
+        CREATE TABLE IF NOT EXISTS results (
+            chat TEXT NOT NULL,
+            link TEXT NOT NULL,
+            link_type TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            score REAL NOT NULL,
+            depth INTEGER NOT NULL,
+            new INTEGER NOT NULL,
+            first_seen INTEGER,
+            last_seen INTEGER,
+            reactions INTEGER NOT NULL,
+            complete INTEGER NOT NULL,
+            views INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (chat, link)
+        );
+    ";
+
+    pub struct Sqlite {
+        connection: Connection,
+    }
+
+    impl Sqlite {
+        pub fn open(path: &Path) -> Result<Self> {
+            let connection = Connection::open(path)?;
+            connection.execute_batch(SCHEMA)?;
+            // Databases of older builds lack the views.
+            if connection.prepare("SELECT views FROM results").is_err() {
+                connection.execute(
+                    "ALTER TABLE results ADD COLUMN views INTEGER NOT NULL DEFAULT 0",
+                    [],
+                )?;
+            }
+            Ok(Self { connection })
+        }
+
+        fn write_results<'a>(
+            &mut self,
+            chat: &str,
+            usernames: impl Iterator<Item = &'a Username>,
+            complete: bool,
+        ) -> Result<()> {
+            let transaction = self.connection.transaction()?;
+            transaction.execute("DELETE FROM results WHERE chat = ?1", [chat])?;
+            {
+                let mut insert = transaction.prepare(
+                    "INSERT INTO results (chat, link, link_type, count, score, depth, new,
+                     first_seen, last_seen, reactions, complete, views)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                )?;
+                for u in usernames {
+                    insert.execute(params![
+                        chat,
+                        u.username.key(),
+                        u.username.kind(),
+                        u.count as i64,
+                        u.score,
+                        u.depth as i64,
+                        u.new,
+                        u.first_seen,
+                        u.last_seen,
+                        u.reactions as i64,
+                        complete,
+                        u.views as i64,
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        }
+    }
+
+    impl super::Storage for Sqlite {
+        fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+            self.connection.execute(
+                "INSERT OR IGNORE INTO discoveries VALUES (?1, ?2, ?3, ?4)",
+                params![chat, link.key(), link.kind(), super::now()],
+            )?;
+            Ok(())
+        }
+
+        fn put_metadata(&mut self, username: &Username) -> Result<()> {
+            let Some(ref m) = username.metadata else {
+                return Ok(());
+            };
+            self.connection.execute(
+                "INSERT OR REPLACE INTO metadata VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    username.username.key(),
+                    m.id,
+                    m.name,
+                    m.type_.as_str(),
+                    m.about,
+                    m.members.map(|n| n as i64),
+                    m.date,
+                ],
+            )?;
+            Ok(())
+        }
+
+        fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+            self.write_results(chat, usernames.values(), false)
+        }
+
+        fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+            self.write_results(chat, usernames.iter(), true)
+        }
+    }
+}
+
+/// The same tables as the SQLite storage in a Postgres database, plus the
+/// `crawls` and `chats` tables, so several machines can report to one
+/// central database.
+#[cfg(feature = "postgres")]
+mod postgres {
+    use std::collections::HashMap;
+
+    use color_eyre::eyre::Result;
+    use rampilo::LinkType;
+    use tokio_postgres::{Client, NoTls};
+
+    use super::block;
+    use crate::{Username, Usernames};
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS crawls (
+            id BIGSERIAL PRIMARY KEY,
+            chat TEXT NOT NULL,
+            host TEXT NOT NULL,
+            started_at BIGINT NOT NULL,
+            finished_at BIGINT,
+            complete BOOLEAN NOT NULL DEFAULT FALSE
+        );
+        CREATE TABLE IF NOT EXISTS chats (
+            chat TEXT PRIMARY KEY,
+            crawls BIGINT NOT NULL,
+            last_crawl BIGINT NOT NULL REFERENCES crawls (id),
+            last_crawled_at BIGINT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS discoveries (
+            chat TEXT NOT NULL,
+            link TEXT NOT NULL,
+            link_type TEXT NOT NULL,
+            found_at BIGINT NOT NULL,
+            PRIMARY KEY (chat, link)
+        );
+        CREATE TABLE IF NOT EXISTS metadata (
+            link TEXT PRIMARY KEY,
+            chat_id BIGINT NOT NULL,
+            name TEXT NOT NULL,
+            type TEXT NOT NULL,
+            about TEXT,
+            members BIGINT,
+            date BIGINT
+        );
+        CREATE TABLE IF NOT EXISTS results (
+            chat TEXT NOT NULL,
+            link TEXT NOT NULL,
+            link_type TEXT NOT NULL,
+            count BIGINT NOT NULL,
+            score DOUBLE PRECISION NOT NULL,
+            depth BIGINT NOT NULL,
+            new BOOLEAN NOT NULL,
+            first_seen BIGINT,
+            last_seen BIGINT,
+            reactions BIGINT NOT NULL,
+            complete BOOLEAN NOT NULL
+        );
+        ALTER TABLE discoveries ADD COLUMN IF NOT EXISTS crawl_id BIGINT REFERENCES crawls (id);
+        ALTER TABLE results ADD COLUMN IF NOT EXISTS crawl_id BIGINT REFERENCES crawls (id);
+        ALTER TABLE results ADD COLUMN IF NOT EXISTS views BIGINT NOT NULL DEFAULT 0;
+        ALTER TABLE results DROP CONSTRAINT IF EXISTS results_pkey;
+        CREATE UNIQUE INDEX IF NOT EXISTS results_crawl_link ON results (crawl_id, link);
+    ";
+
+    pub struct Postgres {
+        client: Client,
+        host: String,
+        /// Row in `crawls` of every chat crawled by this run.
+        crawls: HashMap<String, i64>,
+    }
+
+    /// Name of the machine the crawl runs on, to tell apart the crawls
+    /// reported to a shared database.
+    fn host() -> String {
+        std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    impl Postgres {
+        pub async fn connect(url: &str) -> Result<Self> {
+            let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    tracing::error!(error = %e, "postgres connection closed");
+                }
+            });
+
+            client.batch_execute(SCHEMA).await?;
+            Ok(Self {
+                client,
+                host: host(),
+                crawls: HashMap::new(),
+            })
+        }
+
+        /// Id of this run's crawl of `chat`, started on first use.
+        async fn crawl_id(&mut self, chat: &str) -> Result<i64> {
+            if let Some(&id) = self.crawls.get(chat) {
+                return Ok(id);
+            }
+
+            let row = self
+                .client
+                .query_one(
+                    "INSERT INTO crawls (chat, host, started_at) VALUES ($1, $2, $3) RETURNING id",
+                    &[&chat, &self.host, &super::now()],
+                )
+                .await?;
+            let id: i64 = row.get(0);
+            self.crawls.insert(chat.to_string(), id);
+            Ok(id)
+        }
+
+        async fn write_results<'a>(
+            &mut self,
+            chat: &str,
+            usernames: impl Iterator<Item = &'a Username>,
+            complete: bool,
+        ) -> Result<()> {
+            let crawl_id = self.crawl_id(chat).await?;
+            let now = super::now();
+
+            // Rows belong to this crawl, so crawls of the same chat running
+            // elsewhere keep theirs.
+            let transaction = self.client.transaction().await?;
+            let insert = transaction
+                .prepare(
+                    "INSERT INTO results (chat, link, link_type, count, score, depth, new,
+                     first_seen, last_seen, reactions, complete, crawl_id, views)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                     ON CONFLICT (crawl_id, link) DO UPDATE SET count = $4, score = $5,
+                     depth = $6, new = $7, first_seen = $8, last_seen = $9, reactions = $10,
+                     complete = $11, views = $13",
+                )
+                .await?;
+            for u in usernames {
+                transaction
+                    .execute(
+                        &insert,
+                        &[
+                            &chat,
+                            &u.username.key(),
+                            &u.username.kind(),
+                            &(u.count as i64),
+                            &u.score,
+                            &(u.depth as i64),
+                            &u.new,
+                            &u.first_seen,
+                            &u.last_seen,
+                            &(u.reactions as i64),
+                            &complete,
+                            &crawl_id,
+                            &(u.views as i64),
+                        ],
+                    )
+                    .await?;
+            }
+
+            if complete {
+                transaction
+                    .execute(
+                        "UPDATE crawls SET finished_at = $2, complete = TRUE WHERE id = $1",
+                        &[&crawl_id, &now],
+                    )
+                    .await?;
+                transaction
+                    .execute(
+                        "INSERT INTO chats VALUES ($1, 1, $2, $3)
+                         ON CONFLICT (chat) DO UPDATE SET crawls = chats.crawls + 1,
+                         last_crawl = $2, last_crawled_at = $3",
+                        &[&chat, &crawl_id, &now],
+                    )
+                    .await?;
+            }
+            transaction.commit().await?;
+            Ok(())
+        }
+    }
+
+    impl super::Storage for Postgres {
+        fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+            let crawl_id = block(self.crawl_id(chat))?;
+            block(self.client.execute(
+                "INSERT INTO discoveries (chat, link, link_type, found_at, crawl_id)
+                 VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+                &[&chat, &link.key(), &link.kind(), &super::now(), &crawl_id],
+            ))?;
+            Ok(())
+        }
+
+        fn put_metadata(&mut self, username: &Username) -> Result<()> {
+            let Some(ref m) = username.metadata else {
+                return Ok(());
+            };
+            block(self.client.execute(
+                "INSERT INTO metadata VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (link) DO UPDATE SET chat_id = $2, name = $3, type = $4,
+                 about = $5, members = $6, date = $7",
+                &[
+                    &username.username.key(),
+                    &m.id,
+                    &m.name,
+                    &m.type_.as_str(),
+                    &m.about,
+                    &m.members.map(|n| n as i64),
+                    &m.date,
+                ],
+            ))?;
+            Ok(())
+        }
+
+        fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+            block(self.write_results(chat, usernames.values(), false))
+        }
+
+        fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+            block(self.write_results(chat, usernames.iter(), true))
+        }
+    }
+}
+
+/// POSTs the same events as the NDJSON storage to a URL, batched at every
+/// checkpoint and once a crawl is done instead of one request per link.
+#[cfg(feature = "webhook")]
+mod webhook {
+    use color_eyre::eyre::Result;
+    use rampilo::LinkType;
+    use serde::Serialize;
+
+    use super::{block, now, Event, Storage};
+    use crate::{Username, Usernames};
+
+    pub struct Webhook {
+        url: String,
+        /// Events waiting for the next batch.
+        pending: Vec<serde_json::Value>,
+    }
+
+    #[derive(Serialize)]
+    struct Batch<'a> {
+        events: &'a [serde_json::Value],
+    }
+
+    impl Webhook {
+        pub fn new(url: &str) -> Self {
+            Self {
+                url: url.to_string(),
+                pending: Vec::new(),
+            }
+        }
+
+        fn push(&mut self, event: &Event) -> Result<()> {
+            self.pending.push(serde_json::to_value(event)?);
+            Ok(())
+        }
+
+        /// Sends the pending events. A batch that can't be delivered is
+        /// logged and dropped, like the `--notify-url` webhooks.
+        fn send(&mut self) -> Result<()> {
+            if self.pending.is_empty() {
+                return Ok(());
+            }
+            let body = serde_json::to_string(&Batch {
+                events: &self.pending,
+            })?;
+            if let Err(e) = block(crate::webhook::post(&self.url, &body)) {
+                tracing::warn!(
+                    url = self.url,
+                    events = self.pending.len(),
+                    error = %e,
+                    "could not send events to the storage webhook"
+                );
+            }
+            self.pending.clear();
+            Ok(())
+        }
+    }
+
+    impl Storage for Webhook {
+        fn put_discovery(&mut self, chat: &str, link: &LinkType) -> Result<()> {
+            self.push(&Event::Discovery {
+                chat,
+                link,
+                at: now(),
+            })
+        }
+
+        fn put_metadata(&mut self, username: &Username) -> Result<()> {
+            match username.metadata {
+                Some(ref metadata) => self.push(&Event::Metadata {
+                    link: &username.username,
+                    metadata,
+                }),
+                None => Ok(()),
+            }
+        }
+
+        fn checkpoint(&mut self, chat: &str, usernames: &Usernames) -> Result<()> {
+            self.push(&Event::Checkpoint {
+                chat,
+                links: usernames.len(),
+                at: now(),
+            })?;
+            self.send()
+        }
+
+        fn finalize(&mut self, chat: &str, usernames: &[Username]) -> Result<()> {
+            for username in usernames {
+                self.push(&Event::Result { chat, username })?;
+            }
+            self.send()
+        }
+    }
+}
+
+/// A file of its own for every test and test run, so tests run at the same
+/// time don't write to each other's files.
+#[cfg(test)]
+pub(crate) fn temp_path(test: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rampilo-{test}-{}.{extension}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        assert!(matches!(
+            "json://results.json".parse::<Location>(),
+            Ok(Location::Json(path)) if path == Path::new("results.json")
+        ));
+        assert!(matches!(
+            "ndjson:///tmp/events.ndjson".parse::<Location>(),
+            Ok(Location::Ndjson(path)) if path == Path::new("/tmp/events.ndjson")
+        ));
+        assert!(matches!(
+            "stdout://".parse::<Location>(),
+            Ok(Location::Stdout)
+        ));
+        assert!("stdout://results.json".parse::<Location>().is_err());
+        assert!("results.json".parse::<Location>().is_err());
+        assert!("s3://bucket/results".parse::<Location>().is_err());
+        #[cfg(feature = "webhook")]
+        assert!(matches!(
+            "webhook+https://example.com/rampilo".parse::<Location>(),
+            Ok(Location::Webhook(url)) if url == "https://example.com/rampilo"
+        ));
+    }
+
+    #[test]
+    fn test_ndjson_events() {
+        let path = temp_path("test_ndjson_events", "ndjson");
+
+        let link = LinkType::Username("grammers".to_string());
+        let mut storage = Ndjson::open(&path, None).unwrap();
+        storage.put_discovery("codenight", &link).unwrap();
+        storage
+            .finalize("codenight", &[Username::new(link)])
+            .unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let events: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "discovery");
+        assert_eq!(events[1]["event"], "result");
+        assert_eq!(events[1]["count"], 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stdout() {
+        #[derive(Clone, Default)]
+        struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert!(Location::Stdout.is_stdout());
+        assert!(!Location::Json(PathBuf::from("results.json")).is_stdout());
+
+        // Lines reach the pipe at every checkpoint, not only once the crawl
+        // is done.
+        let out = Shared::default();
+        let mut storage = Ndjson::new(Box::new(out.clone()), None);
+        let link = LinkType::Username("grammers".to_string());
+        storage.put_discovery("codenight", &link).unwrap();
+        storage.checkpoint("codenight", &Usernames::new()).unwrap();
+
+        let contents = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "discovery");
+        assert_eq!(events[1]["event"], "checkpoint");
+    }
+
+    #[cfg(feature = "webhook")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_webhook() {
+        use std::io::{BufRead, BufReader, Read};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rampilo", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            body
+        });
+
+        let link = LinkType::Username("grammers".to_string());
+        let mut storage = webhook::Webhook::new(&url);
+        storage.put_discovery("codenight", &link).unwrap();
+        storage.checkpoint("codenight", &Usernames::new()).unwrap();
+
+        let batch: serde_json::Value = serde_json::from_slice(&server.join().unwrap()).unwrap();
+        let events = batch["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "discovery");
+        assert_eq!(events[0]["link"]["Username"], "grammers");
+        assert_eq!(events[1]["event"], "checkpoint");
+    }
+
+    #[test]
+    fn test_anonymized_ndjson() {
+        let path = temp_path("test_anonymized_ndjson", "ndjson");
+
+        let link = LinkType::Username("Grammers".to_string());
+        let username = Username {
+            metadata: Some(UsernameMetadata {
+                id: 1234,
+                name: "Grammers chat".to_string(),
+                type_: crate::UsernameType::Group,
+                about: Some("Talk about grammers".to_string()),
+                members: Some(100),
+                date: None,
+                restriction: None,
+                language: None,
+                label: None,
+                risk: None,
+                linked_chat: None,
+                admins: None,
+            }),
+            snippets: vec!["join @grammers".to_string()],
+            ..Username::new(link.clone())
+        };
+        let usernames = Usernames::from([(link.key(), username.clone())]);
+        let anonymizer = Anonymizer::new("secret");
+        let mut storage = Anonymized::new(Ndjson::open(&path, None).unwrap(), anonymizer);
+        storage.put_discovery("CodeNight", &link).unwrap();
+        storage.put_metadata(&username).unwrap();
+        storage.checkpoint("CodeNight", &usernames).unwrap();
+        storage.finalize("CodeNight", &[username]).unwrap();
+        drop(storage);
+
+        let contents = fs::read_to_string(&path).unwrap().to_lowercase();
+        assert_eq!(contents.lines().count(), 4);
+        assert!(!contents.contains("grammers"), "{contents}");
+        assert!(!contents.contains("codenight"), "{contents}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_ndjson() {
+        let path = temp_path("test_compressed_ndjson", "ndjson");
+        let compressed = Compression::Gzip.path(path.clone());
+
+        let link = LinkType::Username("grammers".to_string());
+        let mut storage = Ndjson::open(&path, Some(Compression::Gzip)).unwrap();
+        storage.put_discovery("codenight", &link).unwrap();
+        storage.checkpoint("codenight", &Usernames::new()).unwrap();
+        storage
+            .finalize("codenight", &[Username::new(link)])
+            .unwrap();
+        drop(storage);
+
+        let contents = compress::read_to_string(&compressed).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        fs::remove_file(compressed).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_results() {
+        let path = temp_path("test_sqlite_results", "db");
+
+        let mut storage = sqlite::Sqlite::open(&path).unwrap();
+        let usernames = vec![
+            Username {
+                views: 1500,
+                ..Username::new(LinkType::Username("grammers".to_string()))
+            },
+            Username::new(LinkType::Mention("rampilo".to_string())),
+        ];
+        storage.finalize("codenight", &usernames).unwrap();
+        storage.finalize("codenight", &usernames[..1]).unwrap();
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let (count, views): (i64, i64) = connection
+            .query_row("SELECT COUNT(*), SUM(views) FROM results", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, views), (1, 1500));
+        drop((storage, connection));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+use crate::{locale::Formatter, Username};
+
+/// Entries listed in the summary.
+const TOP: usize = 20;
+
+/// A console table of the most found links of a crawl and the totals of
+/// every link type, for `--summary`.
+pub fn render(
+    chat: &str,
+    usernames: &[Username],
+    messages: usize,
+    elapsed: Duration,
+    fmt: Formatter,
+) -> String {
+    let mut top: Vec<_> = usernames.iter().collect();
+    top.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.username.cmp(&b.username))
+    });
+    top.truncate(TOP);
+
+    let rows: Vec<_> = top
+        .iter()
+        .enumerate()
+        .map(|(i, username)| {
+            [
+                (i + 1).to_string(),
+                username.username.to_string(),
+                username.username.kind().to_string(),
+                fmt.number(username.count),
+                username
+                    .metadata
+                    .as_ref()
+                    .map(|m| m.name.clone())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let mut kinds: HashMap<&str, (usize, usize)> = HashMap::new();
+    for username in usernames {
+        let (entries, found) = kinds.entry(username.username.kind()).or_default();
+        *entries += 1;
+        *found += username.count;
+    }
+    let mut kinds: Vec<_> = kinds.into_iter().collect();
+    kinds.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then(a.0.cmp(b.0)));
+    let totals: Vec<_> = kinds
+        .into_iter()
+        .map(|(kind, (entries, found))| [kind.to_string(), fmt.number(entries), fmt.number(found)])
+        .collect();
+
+    let mut summary = format!(
+        "{chat}: {} messages scanned in {}s, {} links found {} times\n\n",
+        fmt.number(messages),
+        fmt.decimal(elapsed.as_secs_f64()),
+        fmt.number(usernames.len()),
+        fmt.number(usernames.iter().map(|u| u.count).sum()),
+    );
+    table(&mut summary, ["#", "Link", "Type", "Count", "Name"], &rows);
+    summary.push('\n');
+    table(&mut summary, ["Type", "Links", "Found"], &totals);
+
+    // Only channel posts count their views.
+    let mut viewed: Vec<_> = usernames.iter().filter(|u| u.views > 0).collect();
+    if !viewed.is_empty() {
+        viewed.sort_by(|a, b| {
+            b.views
+                .cmp(&a.views)
+                .then_with(|| a.username.cmp(&b.username))
+        });
+        viewed.truncate(TOP);
+        let rows: Vec<_> = viewed
+            .iter()
+            .enumerate()
+            .map(|(i, username)| {
+                [
+                    (i + 1).to_string(),
+                    username.username.to_string(),
+                    fmt.number(username.views),
+                    fmt.number(username.count),
+                ]
+            })
+            .collect();
+        summary.push('\n');
+        table(&mut summary, ["#", "Most viewed", "Views", "Count"], &rows);
+    }
+    summary
+}
+
+/// Appends the rows under the header with every column padded to its
+/// widest cell, numbers aligned to the right.
+pub fn table<const N: usize>(out: &mut String, header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(|cell| cell.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.map(str::to_string);
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::new();
+        for ((cell, width), numeric) in row.iter().zip(widths).zip(numeric_columns(rows)) {
+            let _ = match numeric {
+                true => write!(line, "{cell:>width$}  "),
+                false => write!(line, "{cell:<width$}  "),
+            };
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+}
+
+/// Whether every cell of a column is a formatted number.
+fn numeric_columns<const N: usize>(rows: &[[String; N]]) -> [bool; N] {
+    std::array::from_fn(|column| {
+        !rows.is_empty()
+            && rows.iter().all(|row| {
+                row[column]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_digit())
+                    && row[column].chars().all(|c| !c.is_alphabetic())
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rampilo::LinkType;
+
+    #[test]
+    fn test_render() {
+        let mut usernames: Vec<_> = (0..25)
+            .map(|i| Username {
+                count: i + 1,
+                ..Username::new(LinkType::Username(format!("chat{i}")))
+            })
+            .collect();
+        usernames.push(Username {
+            count: 1000,
+            ..Username::new(LinkType::Hashtag("rust".to_string()))
+        });
+
+        let summary = render(
+            "codenight",
+            &usernames,
+            48213,
+            Duration::from_secs(90),
+            Formatter::default(),
+        );
+        let lines: Vec<_> = summary.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "codenight: 48,213 messages scanned in 90.0s, 26 links found 1,325 times"
+        );
+        assert_eq!(lines[2], " #  Link    Type      Count  Name");
+        assert_eq!(lines[3], " 1  #rust   Hashtag   1,000");
+        assert_eq!(lines[4], " 2  chat24  Username     25");
+        assert_eq!(lines[22], "20  chat6   Username      7");
+        assert_eq!(lines[23], "");
+        assert!(summary.ends_with("Hashtag       1  1,000\nUsername     25    325\n"));
+
+        usernames[3].views = 12000;
+        usernames[25].views = 800;
+        let summary = render(
+            "codenight",
+            &usernames,
+            48213,
+            Duration::from_secs(90),
+            Formatter::default(),
+        );
+        assert!(summary.ends_with(
+            "#  Most viewed   Views  Count\n1  chat3        12,000      4\n2  #rust           800  1,000\n"
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{fmt, str::FromStr};
+
+use color_eyre::eyre::{self, Result};
+use grammers_client::{types::chat::Chat, Client};
+use grammers_session::{PackedChat, PackedType};
+use grammers_tl_types as tl;
+use rampilo::LinkType;
+
+use crate::flood;
+
+/// Marked ids of channels are `-100` followed by the channel's own id.
+const CHANNEL_MARK: i64 = 1_000_000_000_000;
+
+/// Domains of t.me links.
+const TELEGRAM_DOMAINS: [&str; 3] = ["t.me", "telegram.me", "telegram.dog"];
+
+/// A chat to crawl, as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Target {
+    /// `codenight`, `@codenight`, `t.me/codenight` or `tg://resolve?domain=codenight`
+    Username(String),
+    /// Id of a chat the account is in, with or without the `-100` or `-`
+    /// prefix of channel and group ids
+    Id(i64),
+    /// Hash of a `t.me/+<hash>` or `t.me/joinchat/<hash>` invite link
+    Invite(String),
+}
+
+impl FromStr for Target {
+    type Err = eyre::Report;
+
+    fn from_str(target: &str) -> Result<Self> {
+        let target = target.trim();
+        if let Ok(id) = target.parse::<i64>() {
+            return Ok(Self::Id(bare_id(id)));
+        }
+
+        // The extractors only know the `https://` form of t.me links.
+        let bare = target
+            .strip_prefix("https://")
+            .or_else(|| target.strip_prefix("http://"))
+            .unwrap_or(target);
+        let link = match bare.split_once('/') {
+            Some((domain, path)) if TELEGRAM_DOMAINS.contains(&domain.to_lowercase().as_str()) => {
+                format!("https://t.me/{path}")
+            }
+            _ => target.to_string(),
+        };
+        match rampilo::extract_all(&link).into_iter().next() {
+            Some(LinkType::Username(name)) => return Ok(Self::Username(name)),
+            Some(LinkType::Hash(hash)) => return Ok(Self::Invite(hash)),
+            _ => {}
+        }
+
+        let name = target.strip_prefix('@').unwrap_or(target);
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Ok(Self::Username(name.to_string()));
+        }
+
+        Err(eyre::eyre!(
+            "{target:?} is not a username, chat id, invite link or t.me link"
+        ))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Username(name) => write!(f, "{name}"),
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Invite(hash) => write!(f, "https://t.me/+{hash}"),
+        }
+    }
+}
+
+impl Target {
+    /// Finds the chat. Invites of chats the account isn't in and can't peek
+    /// into are only followed with `join`, as joining shows in the chat.
+    pub async fn resolve(&self, client: &Client, join: bool) -> Result<Chat> {
+        match self {
+            Self::Username(name) => flood::resolve_username(client, name)
+                .await?
+                .ok_or_else(|| eyre::eyre!("Could not find a chat with the username {name}")),
+            Self::Id(id) => {
+                // Without an access hash, only the account's own chats can be
+                // found by id.
+                let mut dialogs = flood::iter_dialogs(client)?;
+                while let Some(dialog) = dialogs.next().await? {
+                    if dialog.chat().id() == *id {
+                        return Ok(dialog.chat().clone());
+                    }
+                }
+                eyre::bail!("The account is not in a chat with the id {id}")
+            }
+            Self::Invite(hash) => {
+                let (chat, _) = open_invite(client, hash, |invite| match join {
+                    true => Ok(true),
+                    false => eyre::bail!(
+                        "The account is not in {:?}, pass --join to join it through {self}",
+                        invite.title
+                    ),
+                })
+                .await?;
+                Ok(chat)
+            }
+        }
+    }
+
+    /// Name of the crawl in file names, resume tokens and storage: the
+    /// username the chat was given by, else its own username or id.
+    pub fn label(&self, chat: &Chat) -> String {
+        match self {
+            Self::Username(name) => name.clone(),
+            _ => chat
+                .username()
+                .map_or_else(|| chat.id().to_string(), str::to_string),
+        }
+    }
+}
+
+/// Finds the chat behind the invite link with `hash`. If the account isn't
+/// in it and can't peek into it, `join` is asked whether to join it.
+/// Returns the chat and whether it was joined.
+pub async fn open_invite(
+    client: &Client,
+    hash: &str,
+    join: impl FnOnce(&tl::types::ChatInvite) -> Result<bool>,
+) -> Result<(Chat, bool)> {
+    let link = Target::Invite(hash.to_string());
+    let request = tl::functions::messages::CheckChatInvite {
+        hash: hash.to_string(),
+    };
+    let (chat, joined) = match flood::invoke(client, &request).await? {
+        tl::enums::ChatInvite::Already(already) => (already.chat, false),
+        tl::enums::ChatInvite::Peek(peek) => (peek.chat, false),
+        tl::enums::ChatInvite::Invite(invite) => {
+            if !join(&invite)? {
+                eyre::bail!("Not joining {:?} through {link}", invite.title);
+            }
+            let request = tl::functions::messages::ImportChatInvite {
+                hash: hash.to_string(),
+            };
+            let chat = joined_chat(flood::invoke(client, &request).await?)
+                .ok_or_else(|| eyre::eyre!("Joined {link} but got no chat back"))?;
+            (chat, true)
+        }
+    };
+
+    let packed =
+        pack(&chat).ok_or_else(|| eyre::eyre!("The chat behind {link} can't be accessed"))?;
+    Ok((flood::unpack_chat(client, packed).await?, joined))
+}
+
+/// Strips the `-100` and `-` prefixes Bot API style ids mark channels and
+/// groups with.
+fn bare_id(id: i64) -> i64 {
+    if id <= -CHANNEL_MARK {
+        -id - CHANNEL_MARK
+    } else {
+        id.abs()
+    }
+}
+
+fn joined_chat(updates: tl::enums::Updates) -> Option<tl::enums::Chat> {
+    let chats = match updates {
+        tl::enums::Updates::Updates(updates) => updates.chats,
+        tl::enums::Updates::Combined(updates) => updates.chats,
+        _ => return None,
+    };
+    chats.into_iter().next()
+}
+
+fn pack(chat: &tl::enums::Chat) -> Option<PackedChat> {
+    match chat {
+        tl::enums::Chat::Chat(chat) => Some(PackedChat {
+            ty: PackedType::Chat,
+            id: chat.id,
+            access_hash: None,
+        }),
+        tl::enums::Chat::Channel(channel) => {
+            let ty = if channel.gigagroup {
+                PackedType::Gigagroup
+            } else if channel.broadcast {
+                PackedType::Broadcast
+            } else {
+                PackedType::Megagroup
+            };
+            Some(PackedChat {
+                ty,
+                id: channel.id,
+                access_hash: channel.access_hash,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target = |s: &str| s.parse::<Target>().unwrap();
+        let username = Target::Username("codenight".to_string());
+
+        assert_eq!(target("codenight"), username);
+        assert_eq!(target("@codenight"), username);
+        assert_eq!(target("t.me/codenight"), username);
+        assert_eq!(target("https://t.me/codenight/1234"), username);
+        assert_eq!(target("tg://resolve?domain=codenight"), username);
+        assert_eq!(target("t.me/s/codenight"), username);
+        assert_eq!(target("telegram.me/codenight"), username);
+        assert_eq!(target("http://telegram.dog/codenight"), username);
+        assert_eq!(
+            target("https://t.me/+AbC-12"),
+            Target::Invite("AbC-12".to_string())
+        );
+        assert_eq!(
+            target("t.me/joinchat/AbC"),
+            Target::Invite("AbC".to_string())
+        );
+        assert_eq!(target("-1001234567890"), Target::Id(1234567890));
+        assert_eq!(target("-4567"), Target::Id(4567));
+        assert_eq!(target("4567"), Target::Id(4567));
+        assert!("code night".parse::<Target>().is_err());
+        assert!("https://example.com".parse::<Target>().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::output::{self, Destination, Format};
+
+/// How long the buckets of `--timeseries` are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Interval {
+    Daily,
+    /// Weeks starting on Monday.
+    Weekly,
+    Monthly,
+}
+
+impl Interval {
+    /// Index of the bucket a message sent at `timestamp` falls in: the day
+    /// since 1970-01-01 of the day or of the Monday of the week, or the
+    /// month counted from year 0.
+    fn bucket(self, timestamp: i64) -> i64 {
+        let days = timestamp.div_euclid(86400);
+        match self {
+            Interval::Daily => days,
+            // 1970-01-01 was a Thursday.
+            Interval::Weekly => days - (days + 3).rem_euclid(7),
+            Interval::Monthly => {
+                let (year, month, _) = output::civil(days);
+                year * 12 + month - 1
+            }
+        }
+    }
+
+    fn step(self) -> i64 {
+        match self {
+            Interval::Weekly => 7,
+            Interval::Daily | Interval::Monthly => 1,
+        }
+    }
+
+    /// `YYYY-MM-DD` of the first day of a bucket, or `YYYY-MM` of its month.
+    fn label(self, bucket: i64) -> String {
+        match self {
+            Interval::Daily | Interval::Weekly => output::date(bucket * 86400),
+            Interval::Monthly => format!(
+                "{:04}-{:02}",
+                bucket.div_euclid(12),
+                bucket.rem_euclid(12) + 1
+            ),
+        }
+    }
+}
+
+/// Mentions of every link over time, for `--timeseries`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Timeseries {
+    /// The buckets mentions are counted in, if they are counted at all.
+    #[serde(skip)]
+    pub interval: Option<Interval>,
+    /// By key of the link.
+    pub links: HashMap<String, Counted>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Counted {
+    pub link: LinkType,
+    /// Mentions by bucket index.
+    pub buckets: BTreeMap<i64, usize>,
+}
+
+/// The mentions of a link, bucket by bucket from its first mention to its
+/// last.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series {
+    pub link: LinkType,
+    pub mentions: usize,
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Point {
+    /// First day of the bucket, or its month when monthly.
+    pub start: String,
+    pub mentions: usize,
+}
+
+impl Timeseries {
+    /// Counts the links of a message sent at `date`. Messages without a date
+    /// are left out.
+    pub fn record(&mut self, links: &[LinkType], date: Option<i64>) {
+        let (Some(interval), Some(date)) = (self.interval, date) else {
+            return;
+        };
+        let bucket = interval.bucket(date);
+        for link in links {
+            *self
+                .links
+                .entry(link.key())
+                .or_insert_with(|| Counted {
+                    link: link.clone(),
+                    buckets: BTreeMap::new(),
+                })
+                .buckets
+                .entry(bucket)
+                .or_default() += 1;
+        }
+    }
+
+    /// The series of every link, with the buckets without mentions between
+    /// the first and last filled in, the most mentioned links first.
+    pub fn series(&self) -> Vec<Series> {
+        let Some(interval) = self.interval else {
+            return Vec::new();
+        };
+        let mut series: Vec<_> = self
+            .links
+            .values()
+            .filter_map(|counted| {
+                let (&first, _) = counted.buckets.first_key_value()?;
+                let (&last, _) = counted.buckets.last_key_value()?;
+                let points = (first..=last)
+                    .step_by(interval.step() as usize)
+                    .map(|bucket| Point {
+                        start: interval.label(bucket),
+                        mentions: counted.buckets.get(&bucket).copied().unwrap_or(0),
+                    })
+                    .collect();
+                Some(Series {
+                    link: counted.link.clone(),
+                    mentions: counted.buckets.values().sum(),
+                    points,
+                })
+            })
+            .collect();
+        series.sort_by(|a, b| b.mentions.cmp(&a.mentions).then(a.link.cmp(&b.link)));
+        series
+    }
+}
+
+/// Writes the series next to the results as `<chat>.timeseries.json`, or
+/// whatever `--out-name` makes of that name.
+pub fn write(series: &[Series], chat: &str, destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(&format!("{chat}.timeseries"), Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(series)?)?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series() {
+        // Wednesday 2024-01-03, Sunday 2024-01-07 and Tuesday 2024-01-23.
+        let (wednesday, sunday, later) = (1704240000, 1704585600, 1705968000);
+        let link = |name: &str| LinkType::Username(name.to_string());
+        let mut timeseries = Timeseries {
+            interval: Some(Interval::Weekly),
+            ..Default::default()
+        };
+        timeseries.record(&[link("pump")], Some(wednesday));
+        timeseries.record(&[link("pump"), link("rustlang")], Some(sunday));
+        timeseries.record(&[link("Pump")], Some(later));
+        timeseries.record(&[link("pump")], None);
+
+        let series = timeseries.series();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].mentions, 3);
+        let points: Vec<_> = series[0]
+            .points
+            .iter()
+            .map(|p| (p.start.as_str(), p.mentions))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                ("2024-01-01", 2),
+                ("2024-01-08", 0),
+                ("2024-01-15", 0),
+                ("2024-01-22", 1)
+            ]
+        );
+
+        assert_eq!(
+            Interval::Daily.label(Interval::Daily.bucket(sunday)),
+            "2024-01-07"
+        );
+        let month = Interval::Monthly.bucket(later);
+        assert_eq!(Interval::Monthly.label(month), "2024-01");
+        assert_eq!(Interval::Monthly.label(month + 11), "2024-12");
+        assert_eq!(Interval::Monthly.label(month + 12), "2025-01");
+    }
+}
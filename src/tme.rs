@@ -0,0 +1,235 @@
+//! Parsing of t.me and `tg://` links.
+//!
+//! Links are cut out of the text, parsed as urls and classified by their
+//! path or `tg://` action, so the case of the domain, punctuation after the
+//! link, fragments and percent-encoding don't change what is found.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use url::Url;
+
+use crate::LinkType;
+
+/// Domains of t.me. telegram.me and telegram.dog are other domains of the
+/// same site.
+pub const HOSTS: &[&str] = &["t.me", "telegram.me", "telegram.dog"];
+
+/// Paths of t.me links that aren't chats.
+const RESERVED_PATHS: &[&str] = &[
+    "joinchat",
+    "addstickers",
+    "addemoji",
+    "addtheme",
+    "addlist",
+    "share",
+    "socks",
+    "proxy",
+    "bg",
+    "login",
+    "invoice",
+    "setlanguage",
+    "boost",
+    "confirmphone",
+    "path",
+    "c",
+    "s",
+];
+
+/// Characters that end a sentence or close a bracket rather than belong to
+/// the link before them.
+const TRAILING: &[char] = &[
+    '.', ',', ';', ':', '!', '?', ')', ']', '}', '>', '"', '\'', '»', '…',
+];
+
+/// Every Telegram link in `text`, in the order they appear. A story link
+/// gives both the story and the chat it was posted in.
+pub fn links(text: &str) -> impl Iterator<Item = LinkType> + '_ {
+    static START: OnceLock<Regex> = OnceLock::new();
+    let start = START.get_or_init(|| {
+        // Only the https form of t.me links is taken, bare domains are too
+        // often just text.
+        Regex::new(r"(?i)https://(?:t\.me|telegram\.me|telegram\.dog)/|tg://").unwrap()
+    });
+
+    let starts: Vec<_> = start.find_iter(text).map(|m| m.start()).collect();
+    let ends: Vec<_> = starts.iter().skip(1).copied().chain([text.len()]).collect();
+    starts.into_iter().zip(ends).flat_map(move |(from, to)| {
+        // Links written back to back end where the next one starts.
+        let link = &text[from..to];
+        let link = link
+            .find(char::is_whitespace)
+            .map_or(link, |end| &link[..end]);
+        Url::parse(link.trim_end_matches(TRAILING))
+            .map(|url| classify(&url))
+            .unwrap_or_default()
+    })
+}
+
+/// What a parsed t.me or `tg://` link points to.
+fn classify(url: &Url) -> Vec<LinkType> {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    match url.scheme() {
+        "tg" => action(&host, url),
+        "https" if HOSTS.contains(&host.as_str()) => path(url),
+        _ => Vec::new(),
+    }
+}
+
+/// Link of a t.me path, such as `/<username>`, `/+<hash>` or
+/// `/addlist/<slug>`.
+fn path(url: &Url) -> Vec<LinkType> {
+    let segments: Vec<_> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+        .collect();
+    let segment = |i: usize| segments.get(i).map(Cow::as_ref).unwrap_or_default();
+    let first = segment(0);
+
+    if let Some(hash) = first.strip_prefix('+') {
+        return slug(hash).map(LinkType::Hash).into_iter().collect();
+    }
+    if let Some(invoice) = first.strip_prefix('$') {
+        return slug(invoice).map(LinkType::Invoice).into_iter().collect();
+    }
+    let link = match first.to_lowercase().as_str() {
+        // `t.me/s/<username>` is the web preview of a channel.
+        "s" => username(segment(1)).map(LinkType::Username),
+        "joinchat" => slug(segment(1)).map(LinkType::Hash),
+        "addlist" => slug(segment(1)).map(LinkType::Folder),
+        "invoice" => slug(segment(1)).map(LinkType::Invoice),
+        "addstickers" => slug(segment(1)).map(LinkType::StickerSet),
+        "addemoji" => slug(segment(1)).map(LinkType::EmojiSet),
+        "addtheme" => slug(segment(1)).map(LinkType::Theme),
+        "boost" => username(segment(1)).map(LinkType::Boost),
+        "proxy" | "socks" => proxy(url),
+        reserved if RESERVED_PATHS.contains(&reserved) => None,
+        _ => {
+            let Some(name) = username(first) else {
+                return Vec::new();
+            };
+            // `t.me/<username>/s/<id>` is a story of the chat.
+            let story = match (segment(1), digits(segment(2))) {
+                ("s", Some(id)) => Some(LinkType::Story(format!("{name}/{id}"))),
+                _ => None,
+            };
+            return story
+                .into_iter()
+                .chain([LinkType::Username(name)])
+                .collect();
+        }
+    };
+    link.into_iter().collect()
+}
+
+/// Link of a `tg://` action, such as `tg://resolve?domain=<username>`.
+fn action(action: &str, url: &Url) -> Vec<LinkType> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .unwrap_or_default()
+    };
+    let link = match action {
+        "resolve" => {
+            let Some(name) = username(&param("domain")) else {
+                return Vec::new();
+            };
+            let story = digits(&param("story")).map(|id| LinkType::Story(format!("{name}/{id}")));
+            return story
+                .into_iter()
+                .chain([LinkType::Username(name)])
+                .collect();
+        }
+        "join" => slug(&param("invite")).map(LinkType::Hash),
+        "addlist" => slug(&param("slug")).map(LinkType::Folder),
+        "invoice" => slug(&param("slug")).map(LinkType::Invoice),
+        "addstickers" | "addemoji" | "addtheme" => {
+            let name = slug(&param("set")).or_else(|| slug(&param("slug")));
+            name.map(match action {
+                "addstickers" => LinkType::StickerSet,
+                "addemoji" => LinkType::EmojiSet,
+                _ => LinkType::Theme,
+            })
+        }
+        "boost" => username(&param("domain")).map(LinkType::Boost),
+        "proxy" | "socks" => proxy(url),
+        _ => None,
+    };
+    link.into_iter().collect()
+}
+
+/// `<server>:<port>` of a proxy link. A proxy without a port can't be
+/// connected to.
+fn proxy(url: &Url) -> Option<LinkType> {
+    let (mut server, mut port) = (None, None);
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "server" if !value.is_empty() => server = Some(value),
+            "port" => port = digits(&value),
+            _ => {}
+        }
+    }
+    Some(LinkType::Proxy(format!("{}:{}", server?, port?)))
+}
+
+/// The leading characters of `text` that can be in a username.
+fn username(text: &str) -> Option<String> {
+    prefix(text, |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The leading characters of `text` that can be in an invite hash or slug.
+fn slug(text: &str) -> Option<String> {
+    prefix(text, |c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn digits(text: &str) -> Option<String> {
+    prefix(text, |c| c.is_ascii_digit())
+}
+
+fn prefix(text: &str, allowed: impl Fn(char) -> bool) -> Option<String> {
+    let end = text.find(|c| !allowed(c)).unwrap_or(text.len());
+    (end > 0).then(|| text[..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all(text: &str) -> Vec<LinkType> {
+        links(text).collect()
+    }
+
+    #[test]
+    fn test_links() {
+        let username = |name: &str| LinkType::Username(name.to_string());
+
+        assert_eq!(all("HTTPS://T.ME/Grammers"), [username("Grammers")]);
+        assert_eq!(all("«https://t.me/grammers»."), [username("grammers")]);
+        assert_eq!(all("https://t.me/grammers#about"), [username("grammers")]);
+        assert_eq!(all("https://t.me/%67rammers"), [username("grammers")]);
+        assert_eq!(
+            all("https://t.me/grammers,https://t.me/rampilo"),
+            [username("grammers"), username("rampilo")]
+        );
+        assert_eq!(
+            all("https://t.me/%2BAbC-1 tg://JOIN?invite=XyZ%2D2"),
+            [
+                LinkType::Hash("AbC-1".to_string()),
+                LinkType::Hash("XyZ-2".to_string())
+            ]
+        );
+        assert_eq!(
+            all("tg://resolve?domain=rust&story=3"),
+            [LinkType::Story("rust/3".to_string()), username("rust")]
+        );
+        assert_eq!(
+            all("https://t.me/proxy?server=example.org&port=443#x"),
+            [LinkType::Proxy("example.org:443".to_string())]
+        );
+        assert!(all("http://t.me/grammers https://example.com/t.me/x").is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use grammers_tl_types as tl;
+use rampilo::LinkType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    output::{Destination, Format},
+    senders::Promoted,
+};
+
+/// Id of the "General" topic of a forum, which holds the messages that
+/// aren't in any other topic.
+const GENERAL: i32 = 1;
+/// Most found links listed for every topic.
+const TOP_LINKS: usize = 10;
+
+/// The links found in every topic of a forum group, for `--topics`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Topics {
+    /// Whether messages are sorted into topics at all.
+    #[serde(skip)]
+    pub enabled: bool,
+    /// By the id of the message the thread of replies starts at.
+    pub threads: HashMap<i32, Thread>,
+    /// Ids of the service messages seen. Topics start with one, while
+    /// threads starting at other messages are replies in the General topic.
+    pub services: HashSet<i32>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Thread {
+    pub messages: usize,
+    pub links: Vec<Promoted>,
+}
+
+/// A topic and what was found in it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Topic {
+    pub id: i32,
+    /// Link to the topic, if the chat can be linked to.
+    pub link: Option<String>,
+    pub messages: usize,
+    /// Links found, counting repeats.
+    pub found: usize,
+    /// The most found links, most found first.
+    pub top: Vec<Promoted>,
+}
+
+impl Topics {
+    /// Counts the message and its links towards the thread it is in.
+    pub fn record(&mut self, message: &tl::types::Message, links: &[LinkType]) {
+        if !self.enabled {
+            return;
+        }
+        let root = match message.reply_to {
+            Some(tl::enums::MessageReplyHeader::Header(ref header)) => {
+                header.reply_to_top_id.unwrap_or(header.reply_to_msg_id)
+            }
+            None => GENERAL,
+        };
+
+        let thread = self.threads.entry(root).or_default();
+        thread.messages += 1;
+        for link in links {
+            let key = link.key();
+            match thread.links.iter_mut().find(|p| p.link.key() == key) {
+                Some(promoted) => promoted.count += 1,
+                None => thread.links.push(Promoted {
+                    link: link.clone(),
+                    count: 1,
+                }),
+            }
+        }
+    }
+
+    /// Remembers a service message, which may be the start of a topic.
+    pub fn record_service(&mut self, service: &tl::types::MessageService) {
+        if self.enabled {
+            self.services.insert(service.id);
+        }
+    }
+
+    /// The topics, linked to with `permalink`, busiest first. Replies in
+    /// the General topic are counted towards it.
+    pub fn topics(&self, permalink: impl Fn(i32) -> Option<String>) -> Vec<Topic> {
+        let mut merged: HashMap<i32, Thread> = HashMap::new();
+        for (&root, thread) in &self.threads {
+            let id = match self.services.contains(&root) {
+                true => root,
+                false => GENERAL,
+            };
+            let topic = merged.entry(id).or_default();
+            topic.messages += thread.messages;
+            for promoted in &thread.links {
+                let key = promoted.link.key();
+                match topic.links.iter_mut().find(|p| p.link.key() == key) {
+                    Some(existing) => existing.count += promoted.count,
+                    None => topic.links.push(promoted.clone()),
+                }
+            }
+        }
+
+        let mut topics: Vec<_> = merged
+            .into_iter()
+            .map(|(id, mut thread)| {
+                thread
+                    .links
+                    .sort_by(|a, b| b.count.cmp(&a.count).then(a.link.cmp(&b.link)));
+                Topic {
+                    id,
+                    link: permalink(id),
+                    messages: thread.messages,
+                    found: thread.links.iter().map(|p| p.count).sum(),
+                    top: thread.links.into_iter().take(TOP_LINKS).collect(),
+                }
+            })
+            .collect();
+        topics.sort_by_key(|topic| (std::cmp::Reverse(topic.messages), topic.id));
+        topics
+    }
+}
+
+/// Writes the topics next to the results as `<chat>.topics.json`, or
+/// whatever `--out-name` makes of that name.
+pub fn write(topics: &[Topic], chat: &str, destination: &Destination) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let path = destination.path(&format!("{chat}.topics"), Format::Json, now);
+    fs::create_dir_all(&destination.out_dir)?;
+    destination.write(&path, serde_json::to_string_pretty(&topics)?)?;
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(reply_to: Option<(i32, Option<i32>)>) -> tl::types::Message {
+        tl::types::Message {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            pinned: false,
+            noforwards: false,
+            id: 100,
+            from_id: None,
+            peer_id: tl::types::PeerChannel { channel_id: 1 }.into(),
+            fwd_from: None,
+            via_bot_id: None,
+            reply_to: reply_to.map(|(reply_to_msg_id, reply_to_top_id)| {
+                tl::types::MessageReplyHeader {
+                    reply_to_scheduled: false,
+                    reply_to_msg_id,
+                    reply_to_peer_id: None,
+                    reply_to_top_id,
+                }
+                .into()
+            }),
+            date: 1697414400,
+            message: String::new(),
+            media: None,
+            reply_markup: None,
+            entities: None,
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            reactions: None,
+            restriction_reason: None,
+            ttl_period: None,
+        }
+    }
+
+    #[test]
+    fn test_topics() {
+        let link = |name: &str| LinkType::Username(name.to_string());
+        let mut topics = Topics {
+            enabled: true,
+            ..Default::default()
+        };
+        topics.services.insert(20);
+        // Posted in topic 20, directly and as a reply to another post.
+        topics.record(&message(Some((20, None))), &[link("rustlang")]);
+        topics.record(&message(Some((25, Some(20)))), &[link("rustlang")]);
+        topics.record(&message(Some((21, Some(20)))), &[link("grammers")]);
+        // Posted in General, directly and as a reply.
+        topics.record(&message(None), &[link("grammers")]);
+        topics.record(&message(Some((30, None))), &[]);
+
+        let topics = topics.topics(|id| Some(format!("https://t.me/codenight/{id}")));
+        assert_eq!(topics.len(), 2);
+        assert_eq!(
+            (topics[0].id, topics[0].messages, topics[0].found),
+            (20, 3, 3)
+        );
+        assert_eq!(topics[0].top[0].link, link("rustlang"));
+        assert_eq!(topics[0].link.as_deref(), Some("https://t.me/codenight/20"));
+        assert_eq!(
+            (topics[1].id, topics[1].messages, topics[1].found),
+            (GENERAL, 2, 1)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, List, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{stats::Stats, usage::bytes};
+
+const REFRESH: Duration = Duration::from_millis(200);
+
+/// Full screen view of a running crawl, drawn on its own thread from the
+/// shared [`Stats`].
+pub struct Dashboard {
+    done: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Dashboard {
+    pub fn start(stats: Arc<Stats>, chat: String) -> io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        let done = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let result = run(terminal, &stats, &chat, &done);
+                ratatui::restore();
+                result
+            })
+        };
+
+        Ok(Self { done, handle })
+    }
+
+    /// Closes the dashboard and gives the terminal back.
+    pub fn finish(self) -> io::Result<()> {
+        self.done.store(true, Ordering::Relaxed);
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("dashboard thread panicked")))
+    }
+}
+
+fn run(
+    mut terminal: DefaultTerminal,
+    stats: &Stats,
+    chat: &str,
+    done: &AtomicBool,
+) -> io::Result<()> {
+    while !done.load(Ordering::Relaxed) {
+        terminal.draw(|frame| draw(frame, stats, chat))?;
+
+        if !event::poll(REFRESH)? {
+            continue;
+        }
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('p') | KeyCode::Char(' ') => stats.toggle_pause(),
+                KeyCode::Char('q') | KeyCode::Char('s') | KeyCode::Esc => stats.stop(),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, stats: &Stats, chat: &str) {
+    let [top, recent, help] = Layout::vertical([
+        Constraint::Length(9),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [counters, network] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+
+    let status = if stats.is_stopped() {
+        "stopping...".yellow()
+    } else if stats.is_paused() {
+        "paused".yellow()
+    } else {
+        "crawling".green()
+    };
+
+    let counters_text = vec![
+        Line::from(vec!["Status:   ".into(), status]),
+        Line::from(format!("Messages: {}", stats.messages())),
+        Line::from(format!("Links:    {}", stats.links())),
+        Line::from(format!("Rate:     {:.1} messages/s", stats.rate())),
+    ];
+    frame.render_widget(
+        Paragraph::new(counters_text).block(Block::bordered().title(format!(" rampilo: {chat} "))),
+        counters,
+    );
+
+    let (page_size, max_page_size) = stats.page_size();
+    let throttle = match stats.flood_wait() {
+        Some(wait) => format!("flood wait, {}s left", wait.as_secs() + 1).red(),
+        None if page_size < max_page_size => "slowed down".yellow(),
+        None => "none".into(),
+    };
+
+    let mut network_text = vec![
+        Line::from(vec!["Throttle:  ".into(), throttle]),
+        Line::from(format!("Page size: {page_size} of {max_page_size}")),
+        Line::from(format!(
+            "Traffic:   {} sent, {} received",
+            bytes(stats.bytes_sent()),
+            bytes(stats.bytes_received())
+        )),
+    ];
+    network_text.extend(
+        stats
+            .requests()
+            .into_iter()
+            .map(|(method, count)| Line::from(format!("{count:>9}  {method}"))),
+    );
+    frame.render_widget(
+        Paragraph::new(network_text).block(Block::bordered().title(" Requests ")),
+        network,
+    );
+
+    let items = stats.recent();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Latest discoveries ")),
+        recent,
+    );
+
+    frame.render_widget(
+        Paragraph::new("p: pause/resume  q: stop and save").style(Style::new().dim()),
+        help,
+    );
+}
@@ -0,0 +1,162 @@
+use std::{sync::OnceLock, time::Duration};
+
+use rampilo::{
+    extractors::{MessageText, Pipeline},
+    LinkType,
+};
+
+use crate::{Username, Usernames};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Most redirects followed from one url.
+const MAX_REDIRECTS: usize = 10;
+
+/// Hosts of the link shorteners whose links are followed.
+const SHORTENERS: &[&str] = &[
+    "bit.ly",
+    "bl.ink",
+    "buff.ly",
+    "cutt.ly",
+    "goo.gl",
+    "is.gd",
+    "lnkd.in",
+    "ow.ly",
+    "rb.gy",
+    "rebrand.ly",
+    "s.id",
+    "shorturl.at",
+    "t.co",
+    "t.ly",
+    "tiny.cc",
+    "tinyurl.com",
+    "v.gd",
+];
+
+/// Follows the redirects of every shortened url and, where one ends at a
+/// Telegram link, folds the url into the entry of that link, keeping it as
+/// an alias. Returns how many were folded.
+pub async fn fold(usernames: &mut Usernames, pipeline: &Pipeline) -> usize {
+    let short: Vec<_> = usernames
+        .iter()
+        .filter_map(|(key, username)| match username.username {
+            LinkType::Url(ref url) if is_shortened(url) => Some((key.clone(), url.clone())),
+            _ => None,
+        })
+        .collect();
+
+    let mut folded = 0;
+    for (key, url) in short {
+        let link = match follow(&url, pipeline).await {
+            Ok(Some(link)) => link,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!(url, error = %e, "could not follow url");
+                continue;
+            }
+        };
+
+        tracing::debug!(url, %link, "unshortened");
+        let Some(short) = usernames.remove(&key) else {
+            continue;
+        };
+        usernames
+            .entry(link.key())
+            .and_modify(|username| username.absorb(&short))
+            .or_insert_with(|| Username {
+                username: link.clone(),
+                ..short.clone()
+            })
+            .aliases
+            .push(short.username);
+        folded += 1;
+    }
+    folded
+}
+
+fn is_shortened(url: &str) -> bool {
+    let host = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    SHORTENERS.contains(&host)
+}
+
+/// The first link the pipeline finds in `url` that isn't a plain url.
+fn telegram_link(url: &str, pipeline: &Pipeline) -> Option<LinkType> {
+    pipeline
+        .extract(&MessageText::plain(url))
+        .into_iter()
+        .find(|link| !matches!(link, LinkType::Url(_)))
+}
+
+/// Follows the redirects of `url` one at a time until one leads to a
+/// Telegram link, which is never requested itself.
+async fn follow(url: &str, pipeline: &Pipeline) -> reqwest::Result<Option<LinkType>> {
+    let mut url = url.to_string();
+    for _ in 0..MAX_REDIRECTS {
+        let Some(target) = redirect(&url).await? else {
+            return Ok(None);
+        };
+        if let Some(link) = telegram_link(&target, pipeline) {
+            return Ok(Some(link));
+        }
+        url = target;
+    }
+    Ok(None)
+}
+
+/// Where `url` redirects to, `None` if it doesn't.
+async fn redirect(url: &str) -> reqwest::Result<Option<String>> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(TIMEOUT)
+            .build()
+            .expect("the HTTP client builds")
+    });
+
+    let response = client.get(url).send().await?;
+    if !response.status().is_redirection() {
+        return Ok(None);
+    }
+    // Relative locations are relative to the url that redirected.
+    let target = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| response.url().join(location).ok());
+    Ok(target.map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rampilo::extractors::Kind;
+
+    #[test]
+    fn test_is_shortened() {
+        assert!(is_shortened("https://bit.ly/3xYz"));
+        assert!(is_shortened("http://www.TinyURL.com/abc?x=1"));
+        assert!(!is_shortened("https://example.com/bit.ly"));
+        assert!(!is_shortened("https://bit.ly.example.com/a"));
+    }
+
+    #[test]
+    fn test_telegram_link() {
+        let pipeline = Pipeline::new(&Kind::DEFAULT);
+        assert_eq!(
+            telegram_link("https://t.me/grammers", &pipeline),
+            Some(LinkType::Username("grammers".to_string()))
+        );
+        assert_eq!(
+            telegram_link("https://t.me/+USpx-sviNKIj408g", &pipeline),
+            Some(LinkType::Hash("USpx-sviNKIj408g".to_string()))
+        );
+        assert_eq!(telegram_link("https://example.com/", &pipeline), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use grammers_client::client::messages::InvocationError;
+use grammers_tl_types::{self as tl, Serializable};
+
+use crate::{locale::Formatter, stats, summary};
+
+/// Requests made since the process started, by method, for `--usage`.
+static REQUESTS: Mutex<BTreeMap<&'static str, Calls>> = Mutex::new(BTreeMap::new());
+/// Flood waits slept off since the process started, and how long they took.
+static FLOOD_WAITS: Mutex<(usize, Duration)> = Mutex::new((0, Duration::ZERO));
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Calls {
+    count: usize,
+    errors: usize,
+    /// Size of the requests and responses serialized, leaving out the
+    /// encryption and transport around them. Unknown for the requests made
+    /// by grammers' own helpers.
+    sent: usize,
+    received: usize,
+}
+
+/// Counts a request and its size.
+pub fn record<R>(request: &R, result: &Result<R::Return, InvocationError>)
+where
+    R: tl::RemoteCall,
+    R::Return: Serializable,
+{
+    let received = result
+        .as_ref()
+        .map_or(0, |response| response.to_bytes().len());
+    add(
+        stats::method_name::<R>(),
+        Calls {
+            count: 1,
+            errors: usize::from(result.is_err()),
+            sent: request.to_bytes().len(),
+            received,
+        },
+    );
+}
+
+/// Counts a username resolved with grammers' helper, whose size isn't
+/// known.
+pub fn record_resolve() {
+    add(
+        "contacts::ResolveUsername",
+        Calls {
+            count: 1,
+            ..Default::default()
+        },
+    );
+}
+
+pub fn record_flood_wait(wait: Duration) {
+    let mut flood_waits = FLOOD_WAITS.lock().unwrap();
+    flood_waits.0 += 1;
+    flood_waits.1 += wait;
+}
+
+fn add(method: &'static str, calls: Calls) {
+    let mut requests = REQUESTS.lock().unwrap();
+    let total = requests.entry(method).or_default();
+    total.count += calls.count;
+    total.errors += calls.errors;
+    total.sent += calls.sent;
+    total.received += calls.received;
+}
+
+/// A console table of the requests made since `started`, the most made
+/// first, and their totals.
+pub fn report(started: Instant, fmt: Formatter) -> String {
+    let requests = REQUESTS.lock().unwrap().clone();
+    let (flood_waits, waited) = *FLOOD_WAITS.lock().unwrap();
+    render(&requests, flood_waits, waited, started.elapsed(), fmt)
+}
+
+fn render(
+    requests: &BTreeMap<&'static str, Calls>,
+    flood_waits: usize,
+    waited: Duration,
+    elapsed: Duration,
+    fmt: Formatter,
+) -> String {
+    let mut sorted: Vec<_> = requests.iter().collect();
+    sorted.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+    let rows: Vec<_> = sorted
+        .iter()
+        .map(|(method, calls)| {
+            [
+                method.to_string(),
+                fmt.number(calls.count),
+                fmt.number(calls.errors),
+                fmt.number(calls.sent),
+                fmt.number(calls.received),
+            ]
+        })
+        .collect();
+
+    let count: usize = requests.values().map(|calls| calls.count).sum();
+    let sent: usize = requests.values().map(|calls| calls.sent).sum();
+    let received: usize = requests.values().map(|calls| calls.received).sum();
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 {
+        count as f64 / seconds
+    } else {
+        0.0
+    };
+
+    let mut report = format!(
+        "{} requests in {}s ({} per second), {} sent and {} received, {} flood waits lasting {}s\n\n",
+        fmt.number(count),
+        fmt.decimal(seconds),
+        fmt.decimal(rate),
+        bytes(sent),
+        bytes(received),
+        fmt.number(flood_waits),
+        fmt.number(waited.as_secs() as usize),
+    );
+    summary::table(
+        &mut report,
+        ["Request", "Calls", "Errors", "Bytes sent", "Bytes received"],
+        &rows,
+    );
+    report
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+pub fn bytes(count: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = count as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{count} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let requests = BTreeMap::from([
+            (
+                "contacts::ResolveUsername",
+                Calls {
+                    count: 3,
+                    ..Default::default()
+                },
+            ),
+            (
+                "messages::GetHistory",
+                Calls {
+                    count: 1200,
+                    errors: 2,
+                    sent: 48_000,
+                    received: 3_145_728,
+                },
+            ),
+        ]);
+        let report = render(
+            &requests,
+            1,
+            Duration::from_secs(12),
+            Duration::from_secs(60),
+            Formatter::default(),
+        );
+        let lines: Vec<_> = report.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "1,203 requests in 60.0s (20.1 per second), 46.9 KiB sent and 3.0 MiB received, 1 flood waits lasting 12s"
+        );
+        assert_eq!(
+            lines[2],
+            "Request                    Calls  Errors  Bytes sent  Bytes received"
+        );
+        assert_eq!(
+            lines[3],
+            "messages::GetHistory       1,200       2      48,000       3,145,728"
+        );
+        assert_eq!(
+            lines[4],
+            "contacts::ResolveUsername      3       0           0               0"
+        );
+    }
+}
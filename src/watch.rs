@@ -0,0 +1,550 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{self, Result};
+use grammers_client::{client::messages::InvocationError, Client};
+use grammers_tl_types as tl;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use rampilo::{
+    extractors::{MessageText, Pipeline},
+    LinkType,
+};
+use serde::Serialize;
+
+use crate::{
+    add_links, auth::open_client, cache::ResolveCache, config::Config, filter::Filter, flood,
+    history, notify, open_storage, preview_url, read_history, seen::SeenDb, shutdown, stats::Stats,
+    Context, Username, Usernames,
+};
+
+#[derive(clap::Args)]
+pub struct Options {
+    /// Usernames of the chats to watch
+    #[arg(required = true, value_name = "CHAT")]
+    chats: Vec<String>,
+
+    /// Seconds between two checks for new messages
+    #[arg(long, default_value_t = 300)]
+    interval: u64,
+
+    /// Check the newest messages with links again on every check, taking
+    /// back the links of deleted messages and of edits that removed them,
+    /// and log those changes to `<CHAT>.edits.jsonl`
+    #[arg(long)]
+    track_edits: bool,
+
+    /// Serve Prometheus metrics on `http://<ADDR>/metrics`
+    #[cfg(feature = "metrics")]
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<std::net::SocketAddr>,
+}
+
+/// Errors after which the session has to be signed in again.
+const SESSION_ERRORS: &[&str] = &[
+    "AUTH_KEY_UNREGISTERED",
+    "AUTH_KEY_INVALID",
+    "AUTH_KEY_PERM_EMPTY",
+    "SESSION_REVOKED",
+    "SESSION_EXPIRED",
+    "USER_DEACTIVATED",
+    "USER_DEACTIVATED_BAN",
+];
+
+/// How often a paused watch checks whether `rampilo login` restored the
+/// session.
+const LOGIN_POLL: Duration = Duration::from_secs(30);
+
+/// Messages with links `--track-edits` checks again, for every chat.
+const MAX_TRACKED: usize = 500;
+
+/// Checks the chats for new messages every `--interval` seconds, reporting
+/// the links no earlier crawl has found.
+///
+/// When the session is revoked the watch pauses, notifies the operator and
+/// resumes by itself once `rampilo login` has signed in again.
+pub async fn run(
+    mut client: Client,
+    args: &crate::Args,
+    options: &Options,
+    config: &Config,
+) -> Result<()> {
+    if args.bot_token.is_some() {
+        eyre::bail!("Bots can't read the history of a chat, watching needs a user account");
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = options.metrics {
+        crate::metrics::serve(addr).await?;
+    }
+
+    let storage = Mutex::new(open_storage(args).await?);
+    let seen = Mutex::new(SeenDb::load(&args.seen_db())?);
+    let cache = Mutex::new(ResolveCache::load(&args.resolve_cache(), args.cache_ttl)?);
+    let pipeline = args.pipeline();
+    let filter = Filter::new(&args.filter_keyword, &args.filter_regex);
+
+    // Newest message of every chat, the first check only notes where the
+    // next one starts.
+    let mut newest: HashMap<&str, i32> = HashMap::new();
+    let mut tracked: HashMap<&str, Tracked> = HashMap::new();
+    status!(
+        "Watching {} chats every {}s...",
+        options.chats.len(),
+        options.interval
+    );
+
+    shutdown::listen();
+    'watch: while !shutdown::requested() {
+        for chat in &options.chats {
+            if shutdown::requested() {
+                break 'watch;
+            }
+
+            let ctx = Context {
+                client: &client,
+                args,
+                config,
+                storage: &storage,
+                seen: &seen,
+                cache: &cache,
+                crossposts: &Mutex::default(),
+                progress: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+                snapshot: None,
+            };
+
+            let min_id = newest.get(chat.as_str()).copied();
+            let tracked = options
+                .track_edits
+                .then(|| tracked.entry(chat.as_str()).or_default());
+            match poll(&ctx, chat, min_id, &pipeline, &filter, tracked).await {
+                Ok(id) => {
+                    newest.insert(chat, id);
+                }
+                Err(e) if is_session_error(&e) => {
+                    let message = format!(
+                        "The Telegram session stopped working ({e}), watching is paused \
+                         until `rampilo login` signs in again"
+                    );
+                    notify::send(&config.notify, &message).await;
+
+                    client = wait_for_login().await;
+                    notify::send(&config.notify, "Signed in again, watching resumes").await;
+                    continue 'watch;
+                }
+                Err(e) => tracing::warn!(chat, error = %e, "could not check for new messages"),
+            }
+        }
+
+        seen.lock().unwrap().save()?;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(options.interval)) => {}
+            _ = shutdown::wait() => {}
+        }
+    }
+
+    seen.lock().unwrap().save()?;
+    status!("Stopped watching");
+    Ok(())
+}
+
+/// Scans the messages of `name` newer than `min_id`, returning the id of the
+/// newest one. With `tracked`, the messages read earlier are checked for
+/// edits and deletions first.
+async fn poll(
+    ctx: &Context<'_>,
+    name: &str,
+    min_id: Option<i32>,
+    pipeline: &Pipeline,
+    filter: &Filter,
+    mut tracked: Option<&mut Tracked>,
+) -> Result<i32> {
+    let chat = flood::resolve_username(ctx.client, name)
+        .await?
+        .ok_or_else(|| eyre::eyre!("Could not find a chat with the username {name}"))?;
+
+    let page_size = history::PageSize::new(ctx.args.page_size, !ctx.args.fixed_page_size);
+    let messages = history::History::new(ctx.client, &chat, page_size);
+    let Some(min_id) = min_id else {
+        let mut messages = messages;
+        let newest = messages.next().await?;
+        return Ok(newest.map_or(0, |message| history::message_id(&message)));
+    };
+
+    let mut messages = messages.min_id(min_id);
+    let changes = match tracked {
+        Some(ref mut tracked) => revisit(&mut messages, tracked, pipeline, filter).await?,
+        None => Vec::new(),
+    };
+    if !changes.is_empty() {
+        let path = write_changes(&ctx.args.destination.out_dir, name, &changes)?;
+        let deleted = changes
+            .iter()
+            .filter(|c| c.change == ChangeKind::Deleted)
+            .count();
+        ctx.println(format!(
+            "{name}: {} messages with links edited, {} deleted, logged to {}",
+            ctx.args.locale.number(changes.len() - deleted),
+            ctx.args.locale.number(deleted),
+            path.display()
+        ));
+    }
+
+    let mut usernames = Usernames::new();
+    let mut count = 0;
+    let mut newest = min_id;
+    read_history(
+        &mut messages,
+        pipeline,
+        filter,
+        &mut usernames,
+        &Stats::default(),
+        &mut count,
+        &mut Default::default(),
+        None,
+        |id, discovered, gathered| {
+            if let Some(id) = id {
+                newest = newest.max(id);
+                if let Some(ref mut tracked) = tracked {
+                    tracked.record(id, gathered.links);
+                }
+            }
+            discovered
+                .iter()
+                .try_for_each(|link| ctx.storage.lock().unwrap().put_discovery(name, link))
+        },
+    )
+    .await?;
+    if count == 0 && changes.is_empty() {
+        return Ok(newest);
+    }
+
+    match tracked {
+        Some(tracked) => {
+            tracked.add(&usernames);
+            ctx.storage
+                .lock()
+                .unwrap()
+                .checkpoint(name, &tracked.usernames)?;
+        }
+        None => ctx.storage.lock().unwrap().checkpoint(name, &usernames)?,
+    }
+    if count == 0 {
+        return Ok(newest);
+    }
+    let mut found: Vec<Username> = usernames.into_values().collect();
+    ctx.seen.lock().unwrap().record(&mut found, name);
+
+    let new: Vec<_> = found
+        .iter()
+        .filter(|u| u.new)
+        .map(|u| u.username.to_string())
+        .collect();
+    ctx.println(format!(
+        "{name}: {} new messages, {} new links{}{}",
+        ctx.args.locale.number(count),
+        ctx.args.locale.number(new.len()),
+        if new.is_empty() { "" } else { ": " },
+        new.join(", ")
+    ));
+
+    #[cfg(feature = "webhook")]
+    if let Some(ref url) = ctx.args.notify_url {
+        for username in found.iter().filter(|u| u.new) {
+            let event = crate::webhook::Event::NewLink {
+                chat: name,
+                link: &username.username,
+                key: username.username.key(),
+            };
+            crate::webhook::send(url, &event).await;
+        }
+    }
+
+    Ok(newest)
+}
+
+/// Links of a watched chat since the watch started, kept up to date as its
+/// messages are edited and deleted.
+#[derive(Default)]
+struct Tracked {
+    /// Links of the newest messages that had any, by message id.
+    messages: BTreeMap<i32, Vec<LinkType>>,
+    usernames: Usernames,
+}
+
+/// A change to the links of a message, as logged to the audit trail.
+#[derive(Debug, Serialize)]
+struct Change {
+    /// Unix timestamp of when the change was noticed.
+    time: i64,
+    message_id: i32,
+    change: ChangeKind,
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ChangeKind {
+    Deleted,
+    Edited,
+}
+
+impl Tracked {
+    /// Remembers the links of a message just read, forgetting the oldest
+    /// message past [`MAX_TRACKED`].
+    fn record(&mut self, id: i32, links: &[LinkType]) {
+        if links.is_empty() {
+            return;
+        }
+        self.messages.insert(id, links.to_vec());
+        while self.messages.len() > MAX_TRACKED {
+            self.messages.pop_first();
+        }
+    }
+
+    /// Adds the links found by a check.
+    fn add(&mut self, usernames: &Usernames) {
+        for (key, username) in usernames {
+            match self.usernames.get_mut(key) {
+                Some(existing) => existing.absorb(username),
+                None => {
+                    self.usernames.insert(key.clone(), username.clone());
+                }
+            }
+        }
+    }
+
+    /// Brings the links of the message with `id` in line with `links`, or
+    /// `None` if it was deleted, returning the change if there was one.
+    fn update(
+        &mut self,
+        id: i32,
+        links: Option<Vec<LinkType>>,
+        date: Option<i64>,
+        permalink: Option<&str>,
+        now: i64,
+    ) -> Option<Change> {
+        let mut removed = self.messages.remove(&id)?;
+        let (change, links) = match links {
+            Some(links) => (ChangeKind::Edited, links),
+            None => (ChangeKind::Deleted, Vec::new()),
+        };
+        let mut added = Vec::new();
+        for link in &links {
+            match removed.iter().position(|old| old.key() == link.key()) {
+                Some(i) => {
+                    removed.swap_remove(i);
+                }
+                None => added.push(link.clone()),
+            }
+        }
+        if !links.is_empty() {
+            self.messages.insert(id, links);
+        }
+        if removed.is_empty() && added.is_empty() {
+            return None;
+        }
+
+        for link in &removed {
+            let key = link.key();
+            let Some(username) = self.usernames.get_mut(&key) else {
+                continue;
+            };
+            username.count = username.count.saturating_sub(1);
+            username.score -= 1.0;
+            if username.count == 0 {
+                self.usernames.remove(&key);
+            }
+        }
+        add_links(
+            added.clone(),
+            date,
+            0,
+            0,
+            permalink,
+            "",
+            &mut self.usernames,
+        );
+
+        Some(Change {
+            time: now,
+            message_id: id,
+            change,
+            removed: removed.iter().map(ToString::to_string).collect(),
+            added: added.iter().map(ToString::to_string).collect(),
+        })
+    }
+}
+
+/// Fetches the tracked messages again, updating their links to the edits
+/// and deletions made since they were read.
+async fn revisit(
+    messages: &mut history::History,
+    tracked: &mut Tracked,
+    pipeline: &Pipeline,
+    filter: &Filter,
+) -> Result<Vec<Change>> {
+    let ids: Vec<i32> = tracked.messages.keys().copied().collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let mut changes = Vec::new();
+    for message in messages.refetch(&ids).await? {
+        let id = history::message_id(&message);
+        let links = match message {
+            tl::enums::Message::Message(ref message) => Some(links_of(message, pipeline, filter)),
+            tl::enums::Message::Empty(_) => None,
+            tl::enums::Message::Service(_) => continue,
+        };
+        let permalink = messages.permalink(id);
+        changes.extend(tracked.update(
+            id,
+            links,
+            history::message_date(&message),
+            permalink.as_deref(),
+            now,
+        ));
+    }
+    Ok(changes)
+}
+
+/// Links of the message as it reads now, none if it no longer passes the
+/// filter.
+fn links_of(message: &tl::types::Message, pipeline: &Pipeline, filter: &Filter) -> Vec<LinkType> {
+    if !filter.matches(&message.message) {
+        return Vec::new();
+    }
+    pipeline.extract(&MessageText {
+        text: &message.message,
+        entities: message.entities.as_deref().unwrap_or_default(),
+        preview: preview_url(message),
+    })
+}
+
+/// Appends the changes to the chat's audit trail, one JSON object a line.
+fn write_changes(dir: &Path, chat: &str, changes: &[Change]) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{chat}.edits.jsonl"));
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    for change in changes {
+        writeln!(file, "{}", serde_json::to_string(change)?)?;
+    }
+    Ok(path)
+}
+
+fn is_session_error(e: &eyre::Report) -> bool {
+    match e.downcast_ref::<InvocationError>() {
+        Some(InvocationError::Rpc(e)) => e.code == 401 || SESSION_ERRORS.contains(&e.name.as_str()),
+        _ => false,
+    }
+}
+
+/// Waits until the saved session is signed in again.
+async fn wait_for_login() -> Client {
+    loop {
+        tokio::time::sleep(LOGIN_POLL).await;
+
+        let client = match open_client(false, false).await {
+            Ok((client, _)) => client,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not connect with the saved session");
+                continue;
+            }
+        };
+        match flood::is_authorized(&client).await {
+            Ok(true) => return client,
+            Ok(false) => tracing::info!("session still signed out, waiting for `rampilo login`"),
+            Err(e) => tracing::warn!(error = %e, "could not check the saved session"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_mtproto::mtp::RpcError;
+
+    #[test]
+    fn test_session_errors() {
+        let rpc = |code, name: &str| {
+            eyre::Report::new(InvocationError::Rpc(RpcError {
+                code,
+                name: name.to_string(),
+                value: None,
+                caused_by: None,
+            }))
+        };
+
+        assert!(is_session_error(&rpc(401, "AUTH_KEY_UNREGISTERED")));
+        assert!(is_session_error(&rpc(401, "SESSION_PASSWORD_NEEDED")));
+        assert!(is_session_error(&rpc(403, "USER_DEACTIVATED")));
+        assert!(!is_session_error(&rpc(420, "FLOOD_WAIT")));
+        assert!(!is_session_error(&eyre::eyre!("no chat")));
+    }
+
+    #[test]
+    fn test_tracked_update() {
+        let username = |name: &str| LinkType::Username(name.to_string());
+        let mut usernames = Usernames::new();
+        add_links(
+            vec![username("grammers"), username("rampilo")],
+            None,
+            0,
+            0,
+            None,
+            "",
+            &mut usernames,
+        );
+        add_links(
+            vec![username("grammers")],
+            None,
+            0,
+            0,
+            None,
+            "",
+            &mut usernames,
+        );
+        let mut tracked = Tracked::default();
+        tracked.add(&usernames);
+        tracked.record(1, &[username("grammers"), username("rampilo")]);
+        tracked.record(2, &[username("grammers")]);
+
+        let edit = tracked
+            .update(
+                1,
+                Some(vec![username("grammers"), username("tokio")]),
+                None,
+                None,
+                10,
+            )
+            .unwrap();
+        assert_eq!(edit.change, ChangeKind::Edited);
+        assert_eq!(edit.removed, [username("rampilo").to_string()]);
+        assert_eq!(edit.added, [username("tokio").to_string()]);
+        assert!(!tracked.usernames.contains_key(&username("rampilo").key()));
+        assert!(tracked
+            .update(
+                1,
+                Some(vec![username("grammers"), username("tokio")]),
+                None,
+                None,
+                20
+            )
+            .is_none());
+
+        let deletion = tracked.update(2, None, None, None, 30).unwrap();
+        assert_eq!(deletion.change, ChangeKind::Deleted);
+        assert_eq!(tracked.usernames[&username("grammers").key()].count, 1);
+        assert!(tracked.update(2, None, None, None, 40).is_none());
+    }
+}
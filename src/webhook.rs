@@ -0,0 +1,118 @@
+use std::{sync::OnceLock, time::Duration};
+
+use rampilo::LinkType;
+use serde::Serialize;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something worth telling `--notify-url` about.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A crawl wrote its results.
+    CrawlFinished {
+        chat: &'a str,
+        messages: usize,
+        links: usize,
+        /// Keys of the links no earlier crawl found.
+        new: Vec<String>,
+        /// Results files, one for every format and none with
+        /// `--summary-only`.
+        files: &'a [String],
+    },
+    /// A watched chat linked to something for the first time.
+    NewLink {
+        chat: &'a str,
+        link: &'a LinkType,
+        key: String,
+    },
+}
+
+impl Event<'_> {
+    fn text(&self) -> String {
+        match self {
+            Event::CrawlFinished {
+                chat,
+                messages,
+                links,
+                new,
+                ..
+            } => format!(
+                "Crawled {messages} messages of {chat}: {links} links, {} new",
+                new.len()
+            ),
+            Event::NewLink { chat, key, .. } => format!("New link in {chat}: {key}"),
+        }
+    }
+}
+
+/// The event, with its summary as `text` and `content` so Slack and Discord
+/// webhooks and the Telegram Bot API accept the payload as it is.
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+    text: String,
+    content: String,
+}
+
+fn payload(event: &Event) -> String {
+    let text = event.text();
+    let payload = Payload {
+        event,
+        content: text.clone(),
+        text,
+    };
+    serde_json::to_string(&payload).expect("payloads serialize")
+}
+
+/// POSTs the event as JSON to `url`. Failures are
+/// logged and otherwise ignored, a webhook being down shouldn't stop a crawl.
+pub async fn send(url: &str, event: &Event<'_>) {
+    if let Err(e) = post(url, &payload(event)).await {
+        tracing::warn!(url, error = %e, "could not send webhook");
+    }
+}
+
+/// POSTs the JSON `body` to `url`, failing on an error status.
+pub async fn post(url: &str, body: &str) -> reqwest::Result<()> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = CLIENT.get_or_init(reqwest::Client::new);
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .timeout(TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let link = LinkType::Username("grammers".to_string());
+        let event = Event::NewLink {
+            chat: "codenight",
+            link: &link,
+            key: link.key(),
+        };
+
+        let payload: serde_json::Value = serde_json::from_str(&payload(&event)).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "event": "new_link",
+                "chat": "codenight",
+                "link": { "Username": "grammers" },
+                "key": "username:grammers",
+                "text": "New link in codenight: username:grammers",
+                "content": "New link in codenight: username:grammers",
+            })
+        );
+    }
+}
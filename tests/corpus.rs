@@ -0,0 +1,140 @@
+//! Messages seen in the wild that extraction got wrong at some point, with
+//! the links that should be found in them.
+
+use rampilo::{
+    extractors::{Kind, MessageBuilder, Pipeline},
+    LinkType,
+};
+
+fn username(name: &str) -> LinkType {
+    LinkType::Username(name.to_string())
+}
+
+fn extract(kinds: &[Kind], message: &MessageBuilder) -> Vec<LinkType> {
+    Pipeline::new(kinds).extract(&message.message())
+}
+
+fn extract_text(kinds: &[Kind], text: &str) -> Vec<LinkType> {
+    extract(kinds, &MessageBuilder::new().text(text))
+}
+
+#[test]
+fn test_plain_text() {
+    let corpus: &[(&str, &[LinkType])] = &[
+        // Links run into the punctuation around them.
+        ("(https://t.me/grammers).", &[username("grammers")]),
+        (
+            "Подписывайтесь: https://t.me/codenight!",
+            &[username("codenight")],
+        ),
+        // Links to posts and web previews still name the chat.
+        ("https://t.me/grammers/123?single", &[username("grammers")]),
+        ("https://t.me/s/grammers", &[username("grammers")]),
+        ("https://telegram.dog/grammers", &[username("grammers")]),
+        (
+            "tg://resolve?start=1&domain=grammers",
+            &[username("grammers")],
+        ),
+        // Paths that aren't chats.
+        ("https://t.me/joinchat", &[]),
+        ("https://t.me/share?url=x", &[]),
+        // Domains are case-insensitive, fragments and percent-encoding
+        // aren't part of the username.
+        ("HTTPS://T.Me/grammers#top", &[username("grammers")]),
+        ("https://t.me/%67rammers", &[username("grammers")]),
+        // Links pasted back to back.
+        (
+            "https://t.me/grammers,https://t.me/codenight",
+            &[username("grammers"), username("codenight")],
+        ),
+        // Only the https form of t.me links is extracted.
+        ("t.me/grammers http://t.me/grammers", &[]),
+        // Mentions only come from entities, so emails aren't any.
+        ("write to admin@example.com", &[]),
+        (
+            "https://t.me/+AbC-12 and https://t.me/joinchat/XyZ",
+            &[
+                LinkType::Hash("AbC-12".to_string()),
+                LinkType::Hash("XyZ".to_string()),
+            ],
+        ),
+    ];
+
+    for (text, expected) in corpus {
+        assert_eq!(extract_text(&Kind::DEFAULT, text), *expected, "{text}");
+    }
+}
+
+#[test]
+fn test_every_kind() {
+    let corpus: &[(&str, &[LinkType])] = &[
+        (
+            "issue #1 fixed in #rust1_75",
+            &[LinkType::Hashtag("rust1_75".to_string())],
+        ),
+        // A proxy without a port can't be connected to.
+        ("https://t.me/proxy?server=1.2.3.4&secret=ee", &[]),
+        (
+            "https://t.me/socks?server=example.org&port=1080",
+            &[LinkType::Proxy("example.org:1080".to_string())],
+        ),
+        (
+            "see https://example.com/a?b=1, or https://t.me/addlist/AbC",
+            &[
+                LinkType::Folder("AbC".to_string()),
+                LinkType::Url("https://example.com/a?b=1".to_string()),
+            ],
+        ),
+    ];
+
+    let kinds = [Kind::Folders, Kind::Proxies, Kind::Hashtags, Kind::Urls];
+    for (text, expected) in corpus {
+        assert_eq!(extract_text(&kinds, text), *expected, "{text}");
+    }
+}
+
+#[test]
+fn test_entities() {
+    // Entity offsets count UTF-16 code units, which emoji take two of.
+    let message = MessageBuilder::new()
+        .text("👨‍👩‍👧 🔥 ")
+        .mention("Grammers")
+        .text(", ")
+        .mention_name("Lonami", 777000);
+    assert_eq!(
+        extract(&Kind::DEFAULT, &message),
+        [
+            LinkType::Mention("grammers".to_string()),
+            LinkType::UserId(777000),
+        ]
+    );
+
+    // Hyperlinked text hides the link, unless it's in the text as well.
+    let message = MessageBuilder::new()
+        .text_url("join us", "https://t.me/rampilo")
+        .text(" or ")
+        .text_url("https://t.me/grammers", "https://t.me/grammers");
+    assert_eq!(
+        extract(&Kind::DEFAULT, &message),
+        [username("grammers"), username("rampilo")]
+    );
+}
+
+#[test]
+fn test_previews() {
+    // The preview counts when the link was removed from the text...
+    let message = MessageBuilder::new()
+        .text("look at this")
+        .preview("https://t.me/codenight");
+    assert_eq!(extract(&Kind::DEFAULT, &message), [username("codenight")]);
+
+    // ...but not twice when it wasn't.
+    let message = MessageBuilder::new()
+        .text("https://t.me/codenight")
+        .preview("https://t.me/codenight");
+    assert_eq!(extract(&Kind::DEFAULT, &message), [username("codenight")]);
+    let message = MessageBuilder::new()
+        .text_url("here", "https://t.me/codenight")
+        .preview("https://t.me/codenight");
+    assert_eq!(extract(&Kind::DEFAULT, &message), [username("codenight")]);
+}